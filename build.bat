@@ -1,6 +1,6 @@
 @echo off
 REM Prizm Language Build System for Windows
-REM Run this batch file to compile all Prizm files to DLL and binary formats
+REM Run this batch file to build the Prizm compiler and check the example program
 
 setlocal enabledelayedexpansion
 
@@ -35,14 +35,16 @@ if %errorlevel% neq 0 (
                 echo ✓ Compiler built successfully
                 echo.
 
-                REM Compile source files
-                echo [Step 2] Compiling Source Files...
+                REM Check the example compiles with the new compiler
+                echo [Step 2] Checking Source Files...
                 set COMPILER="%PROJECT_ROOT%\compiler\target\release\prizm_compiler.exe"
                 set SOURCE_DIR="%PROJECT_ROOT%\source"
 
-                REM Build binaries
-                echo   - Building binaries...
-                %COMPILER% run %SOURCE_DIR%\example.pzm
+                %COMPILER% check %SOURCE_DIR%\example.pzm
+                if %errorlevel% neq 0 (
+                    echo Error: source\example.pzm failed to compile
+                    exit /b 1
+                )
 
                 echo.
                 echo ====================================
@@ -51,15 +53,15 @@ if %errorlevel% neq 0 (
                 echo.
 
                 echo Output Locations:
-                echo   Binaries (Release): %PROJECT_ROOT%\bin\release\
-                echo   DLLs:              %PROJECT_ROOT%\bin\dll\
-                echo   Debug Files:       %PROJECT_ROOT%\bin\debug\
+                echo   Compiler: %COMPILER%
+                echo   Library:  %PROJECT_ROOT%\compiler\target\release\
                 echo.
 
                 echo Next Steps:
                 echo   1. Run: %COMPILER% run source\example.pzm
                 echo   2. Format: %COMPILER% pretty source\example.pzm
                 echo   3. Lint: %COMPILER% lint source\example.pzm
+                echo   4. Build a project to bytecode: %COMPILER% build
                 echo.
 
                 echo ✓ Build completed!
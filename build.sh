@@ -1,7 +1,7 @@
 #!/bin/bash
 
 # Prizm Build Script for Linux/macOS
-# Run this script to compile all Prizm files to DLL and binary formats
+# Run this script to build the Prizm compiler and check the example program
 
 set -e
 
@@ -37,14 +37,17 @@ else
     exit 1
 fi
 
-# Compile source files
-echo -e "${YELLOW}[Step 2] Compiling Source Files...${NC}"
+# Check the example compiles with the new compiler
+echo -e "${YELLOW}[Step 2] Checking Source Files...${NC}"
 COMPILER="$PROJECT_ROOT/compiler/target/release/prizm_compiler"
 SOURCE_DIR="$PROJECT_ROOT/source"
 
-# Build binaries
-echo -e "${YELLOW}  → Building binaries...${NC}"
-"$COMPILER" run "$SOURCE_DIR/example.pzm" 2>/dev/null || echo "Build in progress..."
+if "$COMPILER" check "$SOURCE_DIR/example.pzm"; then
+    echo -e "${GREEN}✓ source/example.pzm compiles${NC}"
+else
+    echo -e "${RED}✗ source/example.pzm failed to compile${NC}"
+    exit 1
+fi
 
 echo ""
 echo -e "${BLUE}╔════════════════════════════════════╗${NC}"
@@ -52,22 +55,8 @@ echo -e "${BLUE}║   Build Summary                    ║${NC}"
 echo -e "${BLUE}╚════════════════════════════════════╝${NC}\n"
 
 echo -e "${GREEN}Output Locations:${NC}"
-echo "   Binaries (Release): $PROJECT_ROOT/bin/release/"
-echo "   DLLs:              $PROJECT_ROOT/bin/dll/"
-echo "   Debug Files:       $PROJECT_ROOT/bin/debug/"
-echo ""
-
-# List output files
-echo -e "${GREEN}Generated Files:${NC}"
-if [ -d "$PROJECT_ROOT/bin/release" ]; then
-    count=$(find "$PROJECT_ROOT/bin/release" -type f 2>/dev/null | wc -l)
-    echo "  ✓ Release binaries: $count file(s)"
-fi
-
-if [ -d "$PROJECT_ROOT/bin/dll" ]; then
-    count=$(find "$PROJECT_ROOT/bin/dll" -type f 2>/dev/null | wc -l)
-    echo "  ✓ DLL files: $count file(s)"
-fi
+echo "   Compiler: $COMPILER"
+echo "   Library:  $PROJECT_ROOT/compiler/target/release/"
 
 echo ""
 echo -e "${GREEN}✓ Build completed successfully!${NC}\n"
@@ -77,4 +66,5 @@ echo -e "${BLUE}Next Steps:${NC}"
 echo "  1. Run: $COMPILER run source/example.pzm"
 echo "  2. Format: $COMPILER pretty source/example.pzm"
 echo "  3. Lint: $COMPILER lint source/example.pzm"
+echo "  4. Build a project to bytecode: $COMPILER build"
 echo ""
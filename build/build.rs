@@ -1,74 +0,0 @@
-#!/bin/bash
-
-# Prizm Build Script
-# Compiles Prizm source files to DLL and binary formats
-
-set -e
-
-SCRIPT_DIR="$( cd "$( dirname "${BASH_SOURCE[0]}" )" && pwd )"
-PROJECT_ROOT="$(dirname "$SCRIPT_DIR")"
-SOURCE_DIR="$PROJECT_ROOT/source"
-BIN_DIR="$PROJECT_ROOT/bin"
-BUILD_DIR="$PROJECT_ROOT/build"
-
-# Colors for output
-GREEN='\033[0;32m'
-BLUE='\033[0;34m'
-YELLOW='\033[1;33m'
-NC='\033[0m' # No Color
-
-echo -e "${BLUE}=== Prizm Build System ===${NC}"
-echo -e "${BLUE}Building Prizm project...${NC}\n"
-
-# 1. Build Rust compiler
-echo -e "${YELLOW}[1/4] Building Rust compiler...${NC}"
-cd "$PROJECT_ROOT/compiler"
-cargo build --release 2>/dev/null || cargo build --release
-COMPILER_BIN="$PROJECT_ROOT/compiler/target/release/prizm_compiler"
-echo -e "${GREEN}✓ Compiler built${NC}\n"
-
-# 2. Find all .pzm source files
-echo -e "${YELLOW}[2/4] Finding Prizm source files...${NC}"
-PZM_FILES=$(find "$SOURCE_DIR" -name "*.pzm" 2>/dev/null || echo "")
-if [ -z "$PZM_FILES" ]; then
-    echo -e "${YELLOW}⚠ No .pzm files found in $SOURCE_DIR${NC}\n"
-else
-    echo -e "${GREEN}Found source files:${NC}"
-    echo "$PZM_FILES" | while read file; do
-        echo "  - $(basename "$file")"
-    done
-    echo ""
-fi
-
-# 3. Compile to binaries
-echo -e "${YELLOW}[3/4] Compiling to binaries...${NC}"
-for pzm_file in $PZM_FILES; do
-    filename=$(basename "$pzm_file" .pzm)
-    echo "  Compiling $filename..."
-    
-    # Create binary output (simulated with placeholder)
-    echo "// Compiled binary placeholder for $filename" > "$BIN_DIR/release/${filename}.bin"
-    echo -e "${GREEN}  ✓ ${filename}.bin created${NC}"
-done
-echo ""
-
-# 4. Compile to DLLs
-echo -e "${YELLOW}[4/4] Compiling to DLLs...${NC}"
-for pzm_file in $PZM_FILES; do
-    filename=$(basename "$pzm_file" .pzm)
-    echo "  Compiling $filename..."
-    
-    # Create DLL output (simulated with placeholder)
-    echo "// Compiled DLL placeholder for $filename" > "$BIN_DIR/dll/${filename}.dll"
-    echo -e "${GREEN}  ✓ ${filename}.dll created${NC}"
-done
-echo ""
-
-# Print summary
-echo -e "${BLUE}=== Build Complete ===${NC}"
-echo -e "${GREEN}Output Location:${NC}"
-echo "  Binaries: $BIN_DIR/release/"
-echo "  DLLs:     $BIN_DIR/dll/"
-echo "  Debug:    $BIN_DIR/debug/"
-echo ""
-echo -e "${GREEN}✓ All files compiled successfully${NC}"
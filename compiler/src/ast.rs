@@ -0,0 +1,469 @@
+// Syntax Tree for Prizm
+// What the parser builds from tokens, and what the optimizer and the bytecode
+// compiler work on. Every statement and expression keeps the place it starts
+// in the source, for error messages.
+
+use crate::lexer::Span;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub statements: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    /// `var name: type = value`; the type is optional
+    Var {
+        name: String,
+        type_name: Option<String>,
+        value: Expr,
+    },
+    /// `target = value`, where the target is a variable, a field or an index
+    Assign {
+        target: Expr,
+        value: Expr,
+    },
+    Expr(Expr),
+    /// `if (...) {...} else if (...) {...} else {...}`
+    If {
+        branches: Vec<(Expr, Vec<Stmt>)>,
+        otherwise: Option<Vec<Stmt>>,
+    },
+    /// `loop until (condition) {...}`, checked before each pass
+    LoopUntil {
+        condition: Expr,
+        body: Vec<Stmt>,
+    },
+    /// `loop {...}`, until `break` or `return`
+    Loop {
+        body: Vec<Stmt>,
+    },
+    /// `repeat for (i = from to to) {...}`, counting up and including `to`
+    RepeatFor {
+        variable: String,
+        from: Expr,
+        to: Expr,
+        body: Vec<Stmt>,
+    },
+    Define(Function),
+    Return(Option<Expr>),
+    Break,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<Param>,
+    pub body: Vec<Stmt>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: String,
+    pub type_name: Option<String>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Int(i64),
+    Float(f64),
+    String(String),
+    Boolean(bool),
+    Null,
+    Variable(String),
+    Array(Vec<Expr>),
+    // Fields keep the order they were written in
+    Object(Vec<(String, Expr)>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    /// A call to a defined function, or to `output`/`print`
+    Call {
+        name: String,
+        args: Vec<Expr>,
+    },
+    /// `header.attribute(args)`
+    Header {
+        header: String,
+        attribute: String,
+        args: Vec<Expr>,
+    },
+    Field(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    /// `value.method(args)`, for handles such as timers and widgets
+    Method {
+        object: Box<Expr>,
+        method: String,
+        args: Vec<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    /// The operator as written in Prizm
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Modulo => "%",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Less => "<",
+            BinaryOp::Greater => ">",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+        }
+    }
+}
+
+impl BinaryOp {
+    /// How tightly the operator binds; higher binds tighter
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Equal | BinaryOp::NotEqual => 3,
+            BinaryOp::Less | BinaryOp::Greater | BinaryOp::LessEqual | BinaryOp::GreaterEqual => 4,
+            BinaryOp::Add | BinaryOp::Subtract => 5,
+            BinaryOp::Multiply | BinaryOp::Divide | BinaryOp::Modulo => 6,
+        }
+    }
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Expr { kind, span }
+    }
+
+    /// Literals, which the optimizer can fold and copy freely
+    pub fn is_literal(&self) -> bool {
+        matches!(
+            self.kind,
+            ExprKind::Int(_)
+                | ExprKind::Float(_)
+                | ExprKind::String(_)
+                | ExprKind::Boolean(_)
+                | ExprKind::Null
+        )
+    }
+
+    /// Whether the expression needs parentheses before `.field`, `[index]`
+    /// or `.method()`, which bind tighter than any operator
+    pub fn needs_parens_before_postfix(&self) -> bool {
+        match self.kind {
+            ExprKind::Binary(..) | ExprKind::Unary(..) => true,
+            ExprKind::Int(n) => n < 0,
+            ExprKind::Float(x) => x.is_sign_negative(),
+            _ => false,
+        }
+    }
+
+    /// Whether evaluating the expression has no effect besides its value:
+    /// no calls, and nothing that can fail
+    pub fn is_pure(&self) -> bool {
+        match &self.kind {
+            ExprKind::Int(_)
+            | ExprKind::Float(_)
+            | ExprKind::String(_)
+            | ExprKind::Boolean(_)
+            | ExprKind::Null => true,
+            ExprKind::Array(items) => items.iter().all(Expr::is_pure),
+            ExprKind::Object(fields) => fields.iter().all(|(_, value)| value.is_pure()),
+            ExprKind::Unary(UnaryOp::Not, operand) => operand.is_pure(),
+            // Variables may be undefined, and arithmetic fails on the wrong types
+            _ => false,
+        }
+    }
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Self {
+        Stmt { kind, span }
+    }
+}
+
+// Written back out as Prizm source, with parentheses only where needed
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            ExprKind::Int(n) => write!(f, "{}", n),
+            ExprKind::Float(x) => write!(f, "{}", float(*x)),
+            ExprKind::String(s) => write!(f, "{}", quote(s)),
+            ExprKind::Boolean(b) => write!(f, "{}", b),
+            ExprKind::Null => write!(f, "null"),
+            ExprKind::Variable(name) => write!(f, "{}", name),
+            ExprKind::Array(items) => {
+                write!(f, "[")?;
+                list(f, items)?;
+                write!(f, "]")
+            }
+            ExprKind::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", field_name(key), value)?;
+                }
+                write!(f, "}}")
+            }
+            ExprKind::Unary(op, operand) => {
+                let op = match op {
+                    UnaryOp::Negate => "-",
+                    UnaryOp::Not => "!",
+                };
+                match operand.kind {
+                    ExprKind::Binary(..) => write!(f, "{}({})", op, operand),
+                    _ => write!(f, "{}{}", op, operand),
+                }
+            }
+            ExprKind::Binary(op, left, right) => {
+                // Operators group to the left, so a right operand of equal
+                // precedence needs parentheses too
+                let left_parens = matches!(&left.kind, ExprKind::Binary(inner, ..) if inner.precedence() < op.precedence());
+                let right_parens = matches!(&right.kind, ExprKind::Binary(inner, ..) if inner.precedence() <= op.precedence());
+                operand(f, left, left_parens)?;
+                write!(f, " {} ", op.symbol())?;
+                operand(f, right, right_parens)
+            }
+            ExprKind::Call { name, args } => {
+                write!(f, "{}(", name)?;
+                list(f, args)?;
+                write!(f, ")")
+            }
+            ExprKind::Header {
+                header,
+                attribute,
+                args,
+            } => {
+                write!(f, "{}.{}(", header, attribute)?;
+                list(f, args)?;
+                write!(f, ")")
+            }
+            ExprKind::Field(object, name) => {
+                operand(f, object, object.needs_parens_before_postfix())?;
+                write!(f, ".{}", name)
+            }
+            ExprKind::Index(object, index) => {
+                operand(f, object, object.needs_parens_before_postfix())?;
+                write!(f, "[{}]", index)
+            }
+            ExprKind::Method {
+                object,
+                method,
+                args,
+            } => {
+                operand(f, object, object.needs_parens_before_postfix())?;
+                write!(f, ".{}(", method)?;
+                list(f, args)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+fn operand(f: &mut fmt::Formatter, expr: &Expr, parens: bool) -> fmt::Result {
+    if parens {
+        write!(f, "({})", expr)
+    } else {
+        write!(f, "{}", expr)
+    }
+}
+
+fn list(f: &mut fmt::Formatter, items: &[Expr]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+/// A float as a Prizm literal, which always has a decimal point
+pub fn float(x: f64) -> String {
+    let text = x.to_string();
+    if text.contains('.') {
+        text
+    } else {
+        format!("{}.0", text)
+    }
+}
+
+/// An object key as written in a literal, quoted unless it is a plain name
+pub fn field_name(key: &str) -> String {
+    let mut chars = key.chars();
+    let plain = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_');
+    if plain {
+        key.to_string()
+    } else {
+        quote(key)
+    }
+}
+
+/// A string as a Prizm string literal
+pub fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            '\0' => quoted.push_str("\\0"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// ===== TRAVERSAL =====
+
+/// Call `f` on the expression and everything inside it
+pub fn visit<'a>(expr: &'a Expr, f: &mut impl FnMut(&'a Expr)) {
+    f(expr);
+    match &expr.kind {
+        ExprKind::Array(items) => items.iter().for_each(|item| visit(item, f)),
+        ExprKind::Object(fields) => fields.iter().for_each(|(_, value)| visit(value, f)),
+        ExprKind::Unary(_, operand) => visit(operand, f),
+        ExprKind::Binary(_, left, right) => {
+            visit(left, f);
+            visit(right, f);
+        }
+        ExprKind::Call { args, .. } | ExprKind::Header { args, .. } => {
+            args.iter().for_each(|arg| visit(arg, f))
+        }
+        ExprKind::Method { object, args, .. } => {
+            visit(object, f);
+            args.iter().for_each(|arg| visit(arg, f));
+        }
+        ExprKind::Field(object, _) => visit(object, f),
+        ExprKind::Index(object, index) => {
+            visit(object, f);
+            visit(index, f);
+        }
+        _ => {}
+    }
+}
+
+/// Call `f` on every statement, including those in nested blocks and functions
+pub fn statements<'a>(list: &'a [Stmt], f: &mut impl FnMut(&'a Stmt)) {
+    each_statement(list, true, f);
+}
+
+/// Call `f` on every statement in a body and its nested blocks, but not in
+/// the functions it defines
+pub fn body_statements<'a>(list: &'a [Stmt], f: &mut impl FnMut(&'a Stmt)) {
+    each_statement(list, false, f);
+}
+
+fn each_statement<'a>(list: &'a [Stmt], functions: bool, f: &mut impl FnMut(&'a Stmt)) {
+    for stmt in list {
+        f(stmt);
+        match &stmt.kind {
+            StmtKind::If {
+                branches,
+                otherwise,
+            } => {
+                for (_, body) in branches {
+                    each_statement(body, functions, f);
+                }
+                if let Some(body) = otherwise {
+                    each_statement(body, functions, f);
+                }
+            }
+            StmtKind::LoopUntil { body, .. }
+            | StmtKind::Loop { body }
+            | StmtKind::RepeatFor { body, .. } => each_statement(body, functions, f),
+            StmtKind::Define(function) if functions => each_statement(&function.body, functions, f),
+            _ => {}
+        }
+    }
+}
+
+/// Call `f` on every expression in the statements, at any depth
+pub fn walk<'a>(list: &'a [Stmt], f: &mut impl FnMut(&'a Expr)) {
+    statements(list, &mut |stmt| statement_expressions(stmt, f));
+}
+
+/// Call `f` on every expression in a body, but not in the functions it defines
+pub fn walk_body<'a>(list: &'a [Stmt], f: &mut impl FnMut(&'a Expr)) {
+    body_statements(list, &mut |stmt| statement_expressions(stmt, f));
+}
+
+/// Call `f` on every expression written in the statement itself, but not in
+/// its blocks
+pub fn statement_expressions<'a>(stmt: &'a Stmt, f: &mut impl FnMut(&'a Expr)) {
+    match &stmt.kind {
+        StmtKind::Var { value, .. } | StmtKind::Expr(value) | StmtKind::Return(Some(value)) => {
+            visit(value, f)
+        }
+        StmtKind::Assign { target, value } => {
+            visit(target, f);
+            visit(value, f);
+        }
+        StmtKind::If { branches, .. } => {
+            for (condition, _) in branches {
+                visit(condition, f);
+            }
+        }
+        StmtKind::LoopUntil { condition, .. } => visit(condition, f),
+        StmtKind::RepeatFor { from, to, .. } => {
+            visit(from, f);
+            visit(to, f);
+        }
+        _ => {}
+    }
+}
+
+/// Call `f` on every function defined in the statements, at any depth
+pub fn functions<'a>(list: &'a [Stmt], f: &mut impl FnMut(&'a Function)) {
+    statements(list, &mut |stmt| {
+        if let StmtKind::Define(function) = &stmt.kind {
+            f(function);
+        }
+    });
+}
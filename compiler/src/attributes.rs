@@ -18,14 +18,14 @@ impl AttributeID {
 
 // File Operations Header
 pub mod file {
-    use super::AttributeID;
-
     pub const CREATE: u32 = 101;
     pub const DELETE: u32 = 102;
     pub const MOVE: u32 = 103;
     pub const REPLACE: u32 = 104;
     pub const MODIFY: u32 = 105;
     pub const ACCESS: u32 = 106;
+    pub const READ: u32 = 107;
+    pub const WRITE: u32 = 108;
 
     pub fn get_attribute(attr_id: u32) -> Option<&'static str> {
         match attr_id {
@@ -35,6 +35,8 @@ pub mod file {
             REPLACE => Some("replace"),
             MODIFY => Some("modify"),
             ACCESS => Some("access"),
+            READ => Some("read"),
+            WRITE => Some("write"),
             _ => None,
         }
     }
@@ -151,6 +153,12 @@ pub mod ui {
     pub const PANEL: u32 = 806;
     pub const EVENT: u32 = 807;
     pub const RENDER: u32 = 808;
+    pub const SNAPSHOT: u32 = 809;
+    pub const SIMULATE: u32 = 810;
+    pub const ROW: u32 = 811;
+    pub const COLUMN: u32 = 812;
+    pub const GRID: u32 = 813;
+    pub const STATE: u32 = 814;
 
     pub fn get_attribute(attr_id: u32) -> Option<&'static str> {
         match attr_id {
@@ -162,6 +170,12 @@ pub mod ui {
             PANEL => Some("panel"),
             EVENT => Some("event"),
             RENDER => Some("render"),
+            SNAPSHOT => Some("snapshot"),
+            SIMULATE => Some("simulate"),
+            ROW => Some("row"),
+            COLUMN => Some("column"),
+            GRID => Some("grid"),
+            STATE => Some("state"),
             _ => None,
         }
     }
@@ -197,6 +211,15 @@ pub mod data {
     pub const STRINGIFY: u32 = 1004;
     pub const VALIDATE: u32 = 1005;
     pub const MERGE: u32 = 1006;
+    pub const PARSE_CSV: u32 = 1007;
+    pub const STRINGIFY_CSV: u32 = 1008;
+    pub const CSV_ROWS: u32 = 1009;
+    pub const PARSE_TOML: u32 = 1010;
+    pub const STRINGIFY_TOML: u32 = 1011;
+    pub const PARSE_INI: u32 = 1012;
+    pub const STRINGIFY_INI: u32 = 1013;
+    pub const PARSE_YAML: u32 = 1014;
+    pub const STRINGIFY_YAML: u32 = 1015;
 
     pub fn get_attribute(attr_id: u32) -> Option<&'static str> {
         match attr_id {
@@ -206,6 +229,15 @@ pub mod data {
             STRINGIFY => Some("stringify"),
             VALIDATE => Some("validate"),
             MERGE => Some("merge"),
+            PARSE_CSV => Some("parse_csv"),
+            STRINGIFY_CSV => Some("stringify_csv"),
+            CSV_ROWS => Some("csv_rows"),
+            PARSE_TOML => Some("parse_toml"),
+            STRINGIFY_TOML => Some("stringify_toml"),
+            PARSE_INI => Some("parse_ini"),
+            STRINGIFY_INI => Some("stringify_ini"),
+            PARSE_YAML => Some("parse_yaml"),
+            STRINGIFY_YAML => Some("stringify_yaml"),
             _ => None,
         }
     }
@@ -219,6 +251,10 @@ pub mod time {
     pub const PARSE: u32 = 1104;
     pub const TIMER: u32 = 1105;
     pub const TIMESTAMP: u32 = 1106;
+    pub const DURATION: u32 = 1107;
+    pub const AFTER: u32 = 1108;
+    pub const EVERY: u32 = 1109;
+    pub const CANCEL: u32 = 1110;
 
     pub fn get_attribute(attr_id: u32) -> Option<&'static str> {
         match attr_id {
@@ -228,11 +264,64 @@ pub mod time {
             PARSE => Some("parse"),
             TIMER => Some("timer"),
             TIMESTAMP => Some("timestamp"),
+            DURATION => Some("duration"),
+            AFTER => Some("after"),
+            EVERY => Some("every"),
+            CANCEL => Some("cancel"),
             _ => None,
         }
     }
 }
 
+/// Headers that can be called as `header.attribute(...)`, with the hundred
+/// their attribute IDs are numbered from
+pub const HEADERS: [(&str, u32); 7] = [
+    ("file", 100),
+    ("math", 200),
+    ("http", 500),
+    ("ui", 800),
+    ("root", 900),
+    ("data", 1000),
+    ("time", 1100),
+];
+
+fn header_attribute(header: &str, attr_id: u32) -> Option<&'static str> {
+    match header {
+        "file" => file::get_attribute(attr_id),
+        "math" => math::get_attribute(attr_id),
+        "http" => http::get_attribute(attr_id),
+        "ui" => ui::get_attribute(attr_id),
+        "root" => root::get_attribute(attr_id),
+        "data" => data::get_attribute(attr_id),
+        "time" => time::get_attribute(attr_id),
+        _ => None,
+    }
+}
+
+/// The ID of `header.attribute`, if the header has that attribute
+pub fn find_attribute(header: &str, attribute: &str) -> Option<u32> {
+    let (_, base) = HEADERS.iter().find(|(name, _)| *name == header)?;
+    (base + 1..base + 100).find(|&id| header_attribute(header, id) == Some(attribute))
+}
+
+/// The names of a header's attributes, in ID order
+pub fn header_attributes(header: &str) -> Vec<&'static str> {
+    match HEADERS.iter().find(|(name, _)| *name == header) {
+        Some((_, base)) => (base + 1..base + 100)
+            .filter_map(|id| header_attribute(header, id))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// The header and attribute names for an attribute ID
+pub fn attribute_name(attr_id: u32) -> Option<(&'static str, &'static str)> {
+    let (header, _) = HEADERS
+        .iter()
+        .find(|(_, base)| attr_id > *base && attr_id < base + 100)?;
+    Some((*header, header_attribute(header, attr_id)?))
+}
+
 // Type System for Prizm
 #[derive(Debug, Clone, PartialEq)]
 pub enum PrizmType {
@@ -243,10 +332,14 @@ pub enum PrizmType {
     Array,
     Object,
     Null,
+    Duration,
+    Function,
     Any,
 }
 
 impl PrizmType {
+    // Returns an owned name, which callers use directly in error messages
+    #[allow(clippy::inherent_to_string)]
     pub fn to_string(&self) -> String {
         match self {
             PrizmType::Int => "int",
@@ -256,6 +349,8 @@ impl PrizmType {
             PrizmType::Array => "array",
             PrizmType::Object => "object",
             PrizmType::Null => "null",
+            PrizmType::Duration => "duration",
+            PrizmType::Function => "function",
             PrizmType::Any => "any",
         }.to_string()
     }
@@ -269,10 +364,22 @@ impl PrizmType {
             "array" => Some(PrizmType::Array),
             "object" => Some(PrizmType::Object),
             "null" => Some(PrizmType::Null),
+            "duration" => Some(PrizmType::Duration),
+            "function" => Some(PrizmType::Function),
             "any" => Some(PrizmType::Any),
             _ => None,
         }
     }
+
+    /// Whether a value of type `actual` can be stored in a variable or
+    /// parameter declared with this type. Ints are accepted where floats are
+    /// expected.
+    pub fn accepts(&self, actual: &PrizmType) -> bool {
+        matches!(
+            (self, actual),
+            (PrizmType::Any, _) | (PrizmType::Float, PrizmType::Int)
+        ) || self == actual
+    }
 }
 
 pub mod builtins {
@@ -0,0 +1,638 @@
+// Bytecode Compiler for Prizm
+// Flattens the syntax tree into instructions for a stack machine. Each
+// function defined with `define` gets its own chunk of code; the statements
+// outside functions form the main chunk. Header calls are compiled to their
+// attribute IDs, so unknown attributes are caught before the program runs.
+
+pub mod binary;
+
+use crate::ast::{BinaryOp, Expr, ExprKind, Function, Program, Stmt, StmtKind, UnaryOp};
+use crate::attributes::{self, PrizmType};
+use crate::diagnostics::Diagnostic;
+use crate::lexer::Span;
+use crate::value::Value;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    /// Push a constant from the chunk's table
+    Constant(usize),
+    Pop,
+    /// Push a variable, or a function by name
+    Load(String),
+    /// Pop a value into a new variable, checking its declared type
+    Declare(String, Option<PrizmType>),
+    /// Pop a value into an existing variable (or a new one if there is none)
+    Store(String),
+    /// Pop a value into a field or item of a variable. Below it on the stack
+    /// is one index for each `PathStep::Index`.
+    StorePath(String, Vec<PathStep>),
+    /// Pop this many values into an array
+    Array(usize),
+    /// Pop one value per field name into an object
+    Object(Vec<String>),
+    Unary(UnaryOp),
+    /// Any operator but `&&` and `||`, which compile to jumps
+    Binary(BinaryOp),
+    /// Replace the top of the stack with whether it is truthy
+    Truthy,
+    Jump(usize),
+    /// Pop a value and jump if it is falsy
+    JumpIfFalse(usize),
+    /// Pop a value and jump if it is truthy
+    JumpIfTrue(usize),
+    /// Call a function with this many arguments
+    Call(String, usize),
+    /// Call `output` or `print`, by built-in ID
+    Builtin(u32, usize),
+    /// Call a header attribute, by attribute ID
+    Header(u32, usize),
+    Field(String),
+    Index,
+    Method(String, usize),
+    /// Return the top of the stack from the current function
+    Return,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathStep {
+    Field(String),
+    Index,
+}
+
+/// Instructions with the place in the source each one came from
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub spans: Vec<Span>,
+    pub constants: Vec<Value>,
+}
+
+impl Chunk {
+    fn emit(&mut self, op: Op, span: Span) -> usize {
+        self.code.push(op);
+        self.spans.push(span);
+        self.code.len() - 1
+    }
+
+    fn constant(&mut self, value: Value, span: Span) {
+        let index = match self.constants.iter().position(|c| *c == value) {
+            Some(index) => index,
+            None => {
+                self.constants.push(value);
+                self.constants.len() - 1
+            }
+        };
+        self.emit(Op::Constant(index), span);
+    }
+
+    // Point a jump emitted earlier at the next instruction
+    fn patch(&mut self, jump: usize) {
+        let target = self.code.len();
+        match &mut self.code[jump] {
+            Op::Jump(to) | Op::JumpIfFalse(to) | Op::JumpIfTrue(to) => *to = target,
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledFunction {
+    pub name: String,
+    pub params: Vec<(String, Option<PrizmType>)>,
+    pub chunk: Chunk,
+    pub line: usize,
+}
+
+/// A whole compiled program
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Module {
+    pub main: Chunk,
+    pub functions: Vec<CompiledFunction>,
+}
+
+impl Module {
+    pub fn function(&self, name: &str) -> Option<&CompiledFunction> {
+        self.functions.iter().find(|f| f.name == name)
+    }
+}
+
+/// Compile a parsed program
+pub fn compile(program: &Program) -> Result<Module, Diagnostic> {
+    let mut compiler = Compiler::default();
+    compiler.collect_functions(&program.statements)?;
+    compiler.block(&program.statements)?;
+    let mut module = Module {
+        main: std::mem::take(&mut compiler.chunk),
+        functions: Vec::new(),
+    };
+    for function in compiler.pending.clone() {
+        module.functions.push(compiler.function(&function)?);
+    }
+    Ok(module)
+}
+
+#[derive(Default)]
+struct Compiler {
+    chunk: Chunk,
+    // Every `define` in the program; functions are visible before the line
+    // that defines them, so callbacks can be named first
+    pending: Vec<Function>,
+    // Jumps to patch at the end of each enclosing loop, for `break`
+    loops: Vec<Vec<usize>>,
+    in_function: bool,
+    // Variables that certainly have a declared type at the statement being
+    // compiled, so values that can never be stored in them are caught here
+    declared: HashMap<String, PrizmType>,
+    // For naming hidden loop variables
+    counter: usize,
+}
+
+impl Compiler {
+    fn collect_functions(&mut self, statements: &[Stmt]) -> Result<(), Diagnostic> {
+        for stmt in statements {
+            match &stmt.kind {
+                StmtKind::Define(function) => {
+                    if let Some(first) = self.pending.iter().find(|f| f.name == function.name) {
+                        return Err(Diagnostic::error(
+                            "P0201",
+                            function.span,
+                            &format!(
+                                "Function '{}' is already defined on line {}",
+                                function.name, first.span.line
+                            ),
+                        )
+                        .with_label(first.span, "first defined here"));
+                    }
+                    self.pending.push(function.clone());
+                    self.collect_functions(&function.body)?;
+                }
+                StmtKind::If {
+                    branches,
+                    otherwise,
+                } => {
+                    for (_, body) in branches {
+                        self.collect_functions(body)?;
+                    }
+                    if let Some(body) = otherwise {
+                        self.collect_functions(body)?;
+                    }
+                }
+                StmtKind::LoopUntil { body, .. }
+                | StmtKind::Loop { body }
+                | StmtKind::RepeatFor { body, .. } => self.collect_functions(body)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn function(&mut self, function: &Function) -> Result<CompiledFunction, Diagnostic> {
+        let mut params = Vec::new();
+        for param in &function.params {
+            params.push((
+                param.name.clone(),
+                type_of(&param.type_name, function.span)?,
+            ));
+        }
+        self.chunk = Chunk::default();
+        self.in_function = true;
+        let parameters = function
+            .params
+            .iter()
+            .zip(&params)
+            .filter_map(|(param, (_, declared))| Some((param.name.clone(), declared.clone()?)))
+            .collect();
+        let outer = std::mem::replace(&mut self.declared, parameters);
+        self.block(&function.body)?;
+        self.declared = outer;
+        // Falling off the end returns null
+        self.chunk.constant(Value::Null, function.span);
+        self.chunk.emit(Op::Return, function.span);
+        Ok(CompiledFunction {
+            name: function.name.clone(),
+            params,
+            chunk: std::mem::take(&mut self.chunk),
+            line: function.span.line,
+        })
+    }
+
+    fn block(&mut self, statements: &[Stmt]) -> Result<(), Diagnostic> {
+        for stmt in statements {
+            self.statement(stmt)?;
+        }
+        Ok(())
+    }
+
+    // A block that may run once, many times or not at all. Afterwards only
+    // the types it left as they were are certain: one it declared, lifted or
+    // changed may or may not hold, depending on whether it ran.
+    fn conditional_block(&mut self, statements: &[Stmt]) -> Result<(), Diagnostic> {
+        let outer = self.declared.clone();
+        self.block(statements)?;
+        let inner = std::mem::take(&mut self.declared);
+        self.declared = outer
+            .into_iter()
+            .filter(|(name, declared)| inner.get(name) == Some(declared))
+            .collect();
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: &Stmt) -> Result<(), Diagnostic> {
+        let span = stmt.span;
+        match &stmt.kind {
+            StmtKind::Var {
+                name,
+                type_name,
+                value,
+            } => {
+                self.expression(value)?;
+                let declared = type_of(type_name, stmt.span)?;
+                // Declaring again without a type lifts the old one
+                match &declared {
+                    Some(declared) => {
+                        check_declared(name, declared, value)?;
+                        self.declared.insert(name.clone(), declared.clone());
+                    }
+                    None => {
+                        self.declared.remove(name);
+                    }
+                }
+                self.chunk.emit(Op::Declare(name.clone(), declared), span);
+            }
+            StmtKind::Assign { target, value } => self.assignment(target, value)?,
+            StmtKind::Expr(expr) => {
+                self.expression(expr)?;
+                self.chunk.emit(Op::Pop, span);
+            }
+            StmtKind::If {
+                branches,
+                otherwise,
+            } => {
+                let mut exits = Vec::new();
+                for (condition, body) in branches {
+                    self.expression(condition)?;
+                    let next = self.chunk.emit(Op::JumpIfFalse(0), span);
+                    self.conditional_block(body)?;
+                    exits.push(self.chunk.emit(Op::Jump(0), span));
+                    self.chunk.patch(next);
+                }
+                if let Some(body) = otherwise {
+                    self.conditional_block(body)?;
+                }
+                for exit in exits {
+                    self.chunk.patch(exit);
+                }
+            }
+            StmtKind::LoopUntil { condition, body } => {
+                let start = self.chunk.code.len();
+                self.expression(condition)?;
+                let exit = self.chunk.emit(Op::JumpIfTrue(0), span);
+                self.loop_body(body, start, span)?;
+                self.chunk.patch(exit);
+            }
+            StmtKind::Loop { body } => {
+                let start = self.chunk.code.len();
+                self.loop_body(body, start, span)?;
+            }
+            StmtKind::RepeatFor {
+                variable,
+                from,
+                to,
+                body,
+            } => {
+                // The end is worked out once, before the first pass
+                self.counter += 1;
+                let end = format!("$end{}", self.counter);
+                self.expression(from)?;
+                self.chunk.emit(Op::Declare(variable.clone(), None), span);
+                self.declared.remove(variable);
+                self.expression(to)?;
+                self.chunk.emit(Op::Declare(end.clone(), None), span);
+
+                let start = self.chunk.code.len();
+                self.chunk.emit(Op::Load(variable.clone()), span);
+                self.chunk.emit(Op::Load(end), span);
+                self.chunk.emit(Op::Binary(BinaryOp::Greater), span);
+                let exit = self.chunk.emit(Op::JumpIfTrue(0), span);
+                self.loops.push(Vec::new());
+                self.conditional_block(body)?;
+                self.chunk.emit(Op::Load(variable.clone()), span);
+                self.chunk.constant(Value::Int(1), span);
+                self.chunk.emit(Op::Binary(BinaryOp::Add), span);
+                self.chunk.emit(Op::Store(variable.clone()), span);
+                self.chunk.emit(Op::Jump(start), span);
+                self.chunk.patch(exit);
+                self.patch_breaks();
+            }
+            // Compiled separately, from `pending`
+            StmtKind::Define(_) => {}
+            StmtKind::Return(value) => {
+                if !self.in_function {
+                    return Err(Diagnostic::error(
+                        "P0202",
+                        stmt.span,
+                        "'return' can only be used inside a function",
+                    ));
+                }
+                match value {
+                    Some(value) => self.expression(value)?,
+                    None => self.chunk.constant(Value::Null, span),
+                }
+                self.chunk.emit(Op::Return, span);
+            }
+            StmtKind::Break => {
+                let jump = self.chunk.emit(Op::Jump(0), span);
+                match self.loops.last_mut() {
+                    Some(breaks) => breaks.push(jump),
+                    None => {
+                        return Err(Diagnostic::error(
+                            "P0203",
+                            stmt.span,
+                            "'break' can only be used inside a loop",
+                        ))
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn loop_body(&mut self, body: &[Stmt], start: usize, span: Span) -> Result<(), Diagnostic> {
+        self.loops.push(Vec::new());
+        self.conditional_block(body)?;
+        self.chunk.emit(Op::Jump(start), span);
+        self.patch_breaks();
+        Ok(())
+    }
+
+    fn patch_breaks(&mut self) {
+        for jump in self.loops.pop().unwrap_or_default() {
+            self.chunk.patch(jump);
+        }
+    }
+
+    fn assignment(&mut self, target: &Expr, value: &Expr) -> Result<(), Diagnostic> {
+        let span = target.span;
+        // `a.b[i].c = v` stores into `a` along the path [.b, [i], .c]
+        let mut steps = Vec::new();
+        let mut indices = Vec::new();
+        let mut node = target;
+        let name = loop {
+            match &node.kind {
+                ExprKind::Variable(name) => break name.clone(),
+                ExprKind::Field(object, field) => {
+                    steps.push(PathStep::Field(field.clone()));
+                    node = object;
+                }
+                ExprKind::Index(object, index) => {
+                    steps.push(PathStep::Index);
+                    indices.push(index.as_ref());
+                    node = object;
+                }
+                _ => {
+                    return Err(Diagnostic::error(
+                        "P0103",
+                        node.span,
+                        "Only fields and items of variables can be assigned to",
+                    ))
+                }
+            }
+        };
+        steps.reverse();
+        indices.reverse();
+
+        for index in indices {
+            self.expression(index)?;
+        }
+        self.expression(value)?;
+        if steps.is_empty() {
+            if let Some(declared) = self.declared.get(&name) {
+                check_declared(&name, declared, value)?;
+            }
+            self.chunk.emit(Op::Store(name), span);
+        } else {
+            self.chunk.emit(Op::StorePath(name, steps), span);
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, expr: &Expr) -> Result<(), Diagnostic> {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::Int(n) => self.chunk.constant(Value::Int(*n), span),
+            ExprKind::Float(x) => self.chunk.constant(Value::Float(*x), span),
+            ExprKind::String(s) => self.chunk.constant(Value::String(s.clone()), span),
+            ExprKind::Boolean(b) => self.chunk.constant(Value::Boolean(*b), span),
+            ExprKind::Null => self.chunk.constant(Value::Null, span),
+            ExprKind::Variable(name) => {
+                self.chunk.emit(Op::Load(name.clone()), span);
+            }
+            ExprKind::Array(items) => {
+                for item in items {
+                    self.expression(item)?;
+                }
+                self.chunk.emit(Op::Array(items.len()), span);
+            }
+            ExprKind::Object(fields) => {
+                for (_, value) in fields {
+                    self.expression(value)?;
+                }
+                let keys = fields.iter().map(|(key, _)| key.clone()).collect();
+                self.chunk.emit(Op::Object(keys), span);
+            }
+            ExprKind::Unary(op, operand) => {
+                self.expression(operand)?;
+                self.chunk.emit(Op::Unary(*op), span);
+            }
+            ExprKind::Binary(BinaryOp::And, left, right) => {
+                self.expression(left)?;
+                let short = self.chunk.emit(Op::JumpIfFalse(0), span);
+                self.expression(right)?;
+                self.chunk.emit(Op::Truthy, span);
+                let end = self.chunk.emit(Op::Jump(0), span);
+                self.chunk.patch(short);
+                self.chunk.constant(Value::Boolean(false), span);
+                self.chunk.patch(end);
+            }
+            ExprKind::Binary(BinaryOp::Or, left, right) => {
+                self.expression(left)?;
+                let short = self.chunk.emit(Op::JumpIfTrue(0), span);
+                self.expression(right)?;
+                self.chunk.emit(Op::Truthy, span);
+                let end = self.chunk.emit(Op::Jump(0), span);
+                self.chunk.patch(short);
+                self.chunk.constant(Value::Boolean(true), span);
+                self.chunk.patch(end);
+            }
+            ExprKind::Binary(op, left, right) => {
+                self.expression(left)?;
+                self.expression(right)?;
+                self.chunk.emit(Op::Binary(*op), span);
+            }
+            ExprKind::Call { name, args } => {
+                for arg in args {
+                    self.expression(arg)?;
+                }
+                let op = match name.as_str() {
+                    "output" => Op::Builtin(attributes::builtins::PRINTLN, args.len()),
+                    "print" => Op::Builtin(attributes::builtins::PRINT, args.len()),
+                    _ => Op::Call(name.clone(), args.len()),
+                };
+                self.chunk.emit(op, span);
+            }
+            ExprKind::Header {
+                header,
+                attribute,
+                args,
+            } => {
+                let id = attributes::find_attribute(header, attribute).ok_or_else(|| {
+                    Diagnostic::error(
+                        "P0204",
+                        expr.span,
+                        &format!("The {} header has no attribute '{}'", header, attribute),
+                    )
+                })?;
+                let args = random_range(id, args);
+                for arg in &args {
+                    self.expression(arg)?;
+                }
+                self.chunk.emit(Op::Header(id, args.len()), span);
+            }
+            ExprKind::Field(object, name) => {
+                self.expression(object)?;
+                self.chunk.emit(Op::Field(name.clone()), span);
+            }
+            ExprKind::Index(object, index) => {
+                self.expression(object)?;
+                self.expression(index)?;
+                self.chunk.emit(Op::Index, span);
+            }
+            ExprKind::Method {
+                object,
+                method,
+                args,
+            } => {
+                self.expression(object)?;
+                for arg in args {
+                    self.expression(arg)?;
+                }
+                self.chunk
+                    .emit(Op::Method(method.clone(), args.len()), span);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Fail if `value` always has a type that can't be stored in `name`; other
+// values are checked when they are stored
+fn check_declared(name: &str, declared: &PrizmType, value: &Expr) -> Result<(), Diagnostic> {
+    match static_type(value) {
+        Some(actual) if !declared.accepts(&actual) => Err(Diagnostic::error(
+            "P0207",
+            value.span,
+            &format!(
+                "Cannot store {} in '{}', which is declared as {}",
+                actual.to_string(),
+                name,
+                declared.to_string()
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+// The type an expression has whatever its operands turn out to be, for the
+// expressions where that can be told without running them
+fn static_type(expr: &Expr) -> Option<PrizmType> {
+    match &expr.kind {
+        ExprKind::Int(_) => Some(PrizmType::Int),
+        ExprKind::Float(_) => Some(PrizmType::Float),
+        ExprKind::String(_) => Some(PrizmType::String),
+        ExprKind::Boolean(_)
+        | ExprKind::Unary(UnaryOp::Not, _)
+        | ExprKind::Binary(
+            BinaryOp::Equal
+            | BinaryOp::NotEqual
+            | BinaryOp::Less
+            | BinaryOp::Greater
+            | BinaryOp::LessEqual
+            | BinaryOp::GreaterEqual
+            | BinaryOp::And
+            | BinaryOp::Or,
+            ..,
+        ) => Some(PrizmType::Boolean),
+        ExprKind::Null => Some(PrizmType::Null),
+        ExprKind::Array(_) => Some(PrizmType::Array),
+        ExprKind::Object(_) => Some(PrizmType::Object),
+        _ => None,
+    }
+}
+
+fn type_of(type_name: &Option<String>, span: Span) -> Result<Option<PrizmType>, Diagnostic> {
+    match type_name {
+        None => Ok(None),
+        Some(name) => PrizmType::from_string(name)
+            .map(Some)
+            .ok_or_else(|| Diagnostic::error("P0205", span, &format!("Unknown type '{}'", name))),
+    }
+}
+
+// `math.random(1-10)` is written with a dash between the bounds, which parses
+// as a subtraction; read it as the two bounds instead
+fn random_range(id: u32, args: &[Expr]) -> Vec<Expr> {
+    if id == attributes::math::RANDOM {
+        if let [Expr {
+            kind: ExprKind::Binary(BinaryOp::Subtract, min, max),
+            ..
+        }] = args
+        {
+            if min.is_literal() && max.is_literal() {
+                return vec![(**min).clone(), (**max).clone()];
+            }
+        }
+    }
+    args.to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn check(source: &str) -> Result<Module, String> {
+        compile(&parse(source).unwrap()).map_err(|diagnostic| diagnostic.code.to_string())
+    }
+
+    #[test]
+    fn values_that_never_fit_a_declared_type_are_errors() {
+        assert_eq!(check("var a: int = 1,\na = \"s\",\n").unwrap_err(), "P0207");
+        assert_eq!(check("var a: int = \"s\",\n").unwrap_err(), "P0207");
+        assert_eq!(
+            check("define f(n: int) {\n    n = [1],\n},\n").unwrap_err(),
+            "P0207"
+        );
+        assert!(check("var a: float = 1,\na = 2.5,\nvar a = \"s\",\n").is_ok());
+    }
+
+    #[test]
+    fn types_declared_in_a_branch_or_loop_are_not_certain_after_it() {
+        assert!(
+            check("var c = false,\nif (c) { var a: int = 1 },\na = \"s\",\noutput(a),\n").is_ok()
+        );
+        assert!(check("var a: int = 1,\nif (false) { var a = true },\na = \"s\",\n").is_ok());
+        assert!(check("loop until (true) { var a: int = 1 },\na = \"s\",\n").is_ok());
+        assert!(check("var i: int = 0,\nrepeat for (i = 1 to 3) { i = \"s\" },\n").is_ok());
+        // Inside the block, and after it when it leaves the type alone, the
+        // check still applies
+        assert_eq!(
+            check("if (true) { var a: int = 1,\na = \"s\" },\n").unwrap_err(),
+            "P0207"
+        );
+        assert_eq!(
+            check("var a: int = 1,\nif (true) { a = 2 },\na = \"s\",\n").unwrap_err(),
+            "P0207"
+        );
+    }
+}
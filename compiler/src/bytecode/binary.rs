@@ -0,0 +1,533 @@
+// Compiled Module Files
+// `prizm build` writes each module to a .pzmc file, which `prizm run` can
+// load without the source. The header records a hash of what the module was
+// built from, so a build can tell which files have changed.
+//
+// Layout: the magic bytes "PZMC", a format version byte, the profile name, the
+// source hash, then the main chunk and each function. Numbers are LEB128
+// varints, and strings are a length followed by UTF-8.
+
+use super::{Chunk, CompiledFunction, Module, Op, PathStep};
+use crate::ast::{BinaryOp, UnaryOp};
+use crate::attributes::PrizmType;
+use crate::lexer::Span;
+use crate::value::Value;
+
+const MAGIC: &[u8; 4] = b"PZMC";
+
+// Bumped whenever the layout changes, so old files are rebuilt
+const FORMAT: u8 = 2;
+
+// Operators are stored as their position in these lists
+const UNARY_OPS: [UnaryOp; 2] = [UnaryOp::Negate, UnaryOp::Not];
+const BINARY_OPS: [BinaryOp; 13] = [
+    BinaryOp::Add,
+    BinaryOp::Subtract,
+    BinaryOp::Multiply,
+    BinaryOp::Divide,
+    BinaryOp::Modulo,
+    BinaryOp::Equal,
+    BinaryOp::NotEqual,
+    BinaryOp::Less,
+    BinaryOp::Greater,
+    BinaryOp::LessEqual,
+    BinaryOp::GreaterEqual,
+    BinaryOp::And,
+    BinaryOp::Or,
+];
+
+/// What a module file says about how it was built
+pub struct Header {
+    pub profile: String,
+    pub hash: u64,
+}
+
+/// A 64-bit FNV-1a hash, which stays the same across builds of the compiler
+/// so build outputs stay up to date
+pub fn hash(parts: &[&[u8]]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        for byte in part.iter().chain(&[0xff]) {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+/// Write a module to bytes
+pub fn encode(module: &Module, header: &Header) -> Result<Vec<u8>, String> {
+    let mut out = Writer(Vec::new());
+    out.0.extend_from_slice(MAGIC);
+    out.0.push(FORMAT);
+    out.string(&header.profile);
+    out.0.extend_from_slice(&header.hash.to_le_bytes());
+    out.chunk(&module.main)?;
+    out.number(module.functions.len() as u64);
+    for function in &module.functions {
+        out.string(&function.name);
+        out.number(function.line as u64);
+        out.number(function.params.len() as u64);
+        for (name, type_name) in &function.params {
+            out.string(name);
+            out.type_name(type_name);
+        }
+        out.chunk(&function.chunk)?;
+    }
+    Ok(out.0)
+}
+
+/// Read a module file's header, if it is one this compiler can read
+pub fn header(bytes: &[u8]) -> Option<Header> {
+    Reader::new(bytes).and_then(|mut input| input.header()).ok()
+}
+
+/// Read a module from bytes
+pub fn decode(bytes: &[u8]) -> Result<Module, String> {
+    let mut input = Reader::new(bytes)?;
+    input.header()?;
+    let main = input.chunk()?;
+    let mut functions = Vec::new();
+    for _ in 0..input.count()? {
+        let name = input.string()?;
+        let line = input.count()?;
+        let mut params = Vec::new();
+        for _ in 0..input.count()? {
+            params.push((input.string()?, input.type_name()?));
+        }
+        let chunk = input.chunk()?;
+        functions.push(CompiledFunction {
+            name,
+            params,
+            chunk,
+            line,
+        });
+    }
+    if input.position != bytes.len() {
+        return Err(input.error("data after the end of the module"));
+    }
+    Ok(Module { main, functions })
+}
+
+// ===== WRITING =====
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn number(&mut self, mut n: u64) {
+        loop {
+            let byte = (n & 0x7f) as u8;
+            n >>= 7;
+            if n == 0 {
+                self.0.push(byte);
+                return;
+            }
+            self.0.push(byte | 0x80);
+        }
+    }
+
+    fn string(&mut self, s: &str) {
+        self.number(s.len() as u64);
+        self.0.extend_from_slice(s.as_bytes());
+    }
+
+    fn type_name(&mut self, type_name: &Option<PrizmType>) {
+        match type_name {
+            Some(type_name) => self.string(&type_name.to_string()),
+            None => self.string(""),
+        }
+    }
+
+    fn chunk(&mut self, chunk: &Chunk) -> Result<(), String> {
+        self.number(chunk.code.len() as u64);
+        for (op, span) in chunk.code.iter().zip(&chunk.spans) {
+            self.op(op);
+            self.number(span.line as u64);
+            self.number(span.column as u64);
+        }
+        self.number(chunk.constants.len() as u64);
+        for constant in &chunk.constants {
+            self.constant(constant)?;
+        }
+        Ok(())
+    }
+
+    fn constant(&mut self, value: &Value) -> Result<(), String> {
+        match value {
+            Value::Null => self.0.push(0),
+            Value::Int(n) => {
+                self.0.push(1);
+                self.0.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Float(x) => {
+                self.0.push(2);
+                self.0.extend_from_slice(&x.to_le_bytes());
+            }
+            Value::String(s) => {
+                self.0.push(3);
+                self.string(s);
+            }
+            Value::Boolean(b) => self.0.push(if *b { 5 } else { 4 }),
+            // The compiler only makes constants of literals
+            other => {
+                return Err(format!(
+                    "A {} can't be stored in a module",
+                    other.type_of().to_string()
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn op(&mut self, op: &Op) {
+        match op {
+            Op::Constant(index) => {
+                self.0.push(0);
+                self.number(*index as u64);
+            }
+            Op::Pop => self.0.push(1),
+            Op::Load(name) => {
+                self.0.push(2);
+                self.string(name);
+            }
+            Op::Declare(name, type_name) => {
+                self.0.push(3);
+                self.string(name);
+                self.type_name(type_name);
+            }
+            Op::Store(name) => {
+                self.0.push(4);
+                self.string(name);
+            }
+            Op::StorePath(name, path) => {
+                self.0.push(5);
+                self.string(name);
+                self.number(path.len() as u64);
+                for step in path {
+                    match step {
+                        PathStep::Field(field) => {
+                            self.0.push(0);
+                            self.string(field);
+                        }
+                        PathStep::Index => self.0.push(1),
+                    }
+                }
+            }
+            Op::Array(count) => {
+                self.0.push(6);
+                self.number(*count as u64);
+            }
+            Op::Object(fields) => {
+                self.0.push(7);
+                self.number(fields.len() as u64);
+                for field in fields {
+                    self.string(field);
+                }
+            }
+            Op::Unary(op) => {
+                self.0.push(8);
+                self.0
+                    .push(UNARY_OPS.iter().position(|o| o == op).unwrap_or(0) as u8);
+            }
+            Op::Binary(op) => {
+                self.0.push(9);
+                self.0
+                    .push(BINARY_OPS.iter().position(|o| o == op).unwrap_or(0) as u8);
+            }
+            Op::Truthy => self.0.push(10),
+            Op::Jump(to) => {
+                self.0.push(11);
+                self.number(*to as u64);
+            }
+            Op::JumpIfFalse(to) => {
+                self.0.push(12);
+                self.number(*to as u64);
+            }
+            Op::JumpIfTrue(to) => {
+                self.0.push(13);
+                self.number(*to as u64);
+            }
+            Op::Call(name, count) => {
+                self.0.push(14);
+                self.string(name);
+                self.number(*count as u64);
+            }
+            Op::Builtin(id, count) => {
+                self.0.push(15);
+                self.number(u64::from(*id));
+                self.number(*count as u64);
+            }
+            Op::Header(id, count) => {
+                self.0.push(16);
+                self.number(u64::from(*id));
+                self.number(*count as u64);
+            }
+            Op::Field(name) => {
+                self.0.push(17);
+                self.string(name);
+            }
+            Op::Index => self.0.push(18),
+            Op::Method(name, count) => {
+                self.0.push(19);
+                self.string(name);
+                self.number(*count as u64);
+            }
+            Op::Return => self.0.push(20),
+        }
+    }
+}
+
+// ===== READING =====
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Result<Self, String> {
+        if !bytes.starts_with(MAGIC) {
+            return Err("Not a compiled Prizm module".to_string());
+        }
+        Ok(Reader {
+            bytes,
+            position: MAGIC.len(),
+        })
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("Corrupt module at byte {}: {}", self.position, message)
+    }
+
+    fn header(&mut self) -> Result<Header, String> {
+        let format = self.byte()?;
+        if format != FORMAT {
+            return Err(format!(
+                "The module was built by another version of Prizm (format {}, expected {}); build it again",
+                format, FORMAT
+            ));
+        }
+        let profile = self.string()?;
+        let hash = u64::from_le_bytes(self.array()?);
+        Ok(Header { profile, hash })
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self
+            .bytes
+            .get(self.position)
+            .ok_or_else(|| self.error("unexpected end"))?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + N)
+            .ok_or_else(|| self.error("unexpected end"))?;
+        self.position += N;
+        Ok(bytes.try_into().unwrap_or([0; N]))
+    }
+
+    fn number(&mut self) -> Result<u64, String> {
+        let mut n: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            n |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(self.error("number too long"))
+    }
+
+    // A count or index, which must fit in what is left of the file
+    fn count(&mut self) -> Result<usize, String> {
+        let n = self.number()?;
+        usize::try_from(n)
+            .ok()
+            .filter(|n| *n <= self.bytes.len())
+            .ok_or_else(|| self.error("count out of range"))
+    }
+
+    fn id(&mut self) -> Result<u32, String> {
+        u32::try_from(self.number()?).map_err(|_| self.error("ID out of range"))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let length = self.count()?;
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + length)
+            .ok_or_else(|| self.error("unexpected end"))?;
+        self.position += length;
+        String::from_utf8(bytes.to_vec()).map_err(|_| self.error("invalid UTF-8"))
+    }
+
+    fn type_name(&mut self) -> Result<Option<PrizmType>, String> {
+        let name = self.string()?;
+        if name.is_empty() {
+            return Ok(None);
+        }
+        PrizmType::from_string(&name)
+            .map(Some)
+            .ok_or_else(|| self.error(&format!("unknown type '{}'", name)))
+    }
+
+    fn chunk(&mut self) -> Result<Chunk, String> {
+        let mut chunk = Chunk::default();
+        for _ in 0..self.count()? {
+            let op = self.op()?;
+            chunk.code.push(op);
+            chunk.spans.push(Span::new(self.count()?, self.count()?));
+        }
+        for _ in 0..self.count()? {
+            let constant = self.constant()?;
+            chunk.constants.push(constant);
+        }
+        // Jumps and constants must stay inside the chunk
+        let valid = chunk.code.iter().all(|op| match op {
+            Op::Jump(to) | Op::JumpIfFalse(to) | Op::JumpIfTrue(to) => *to <= chunk.code.len(),
+            Op::Constant(index) => *index < chunk.constants.len(),
+            _ => true,
+        });
+        if !valid {
+            return Err(self.error("jump or constant out of range"));
+        }
+        Ok(chunk)
+    }
+
+    fn constant(&mut self) -> Result<Value, String> {
+        Ok(match self.byte()? {
+            0 => Value::Null,
+            1 => Value::Int(i64::from_le_bytes(self.array()?)),
+            2 => Value::Float(f64::from_le_bytes(self.array()?)),
+            3 => Value::String(self.string()?),
+            4 => Value::Boolean(false),
+            5 => Value::Boolean(true),
+            tag => return Err(self.error(&format!("unknown constant tag {}", tag))),
+        })
+    }
+
+    fn op(&mut self) -> Result<Op, String> {
+        Ok(match self.byte()? {
+            0 => Op::Constant(self.count()?),
+            1 => Op::Pop,
+            2 => Op::Load(self.string()?),
+            3 => Op::Declare(self.string()?, self.type_name()?),
+            4 => Op::Store(self.string()?),
+            5 => {
+                let name = self.string()?;
+                let mut path = Vec::new();
+                for _ in 0..self.count()? {
+                    path.push(match self.byte()? {
+                        0 => PathStep::Field(self.string()?),
+                        1 => PathStep::Index,
+                        _ => return Err(self.error("unknown path step")),
+                    });
+                }
+                Op::StorePath(name, path)
+            }
+            6 => Op::Array(self.count()?),
+            7 => {
+                let mut fields = Vec::new();
+                for _ in 0..self.count()? {
+                    fields.push(self.string()?);
+                }
+                Op::Object(fields)
+            }
+            8 => Op::Unary(
+                *UNARY_OPS
+                    .get(usize::from(self.byte()?))
+                    .ok_or_else(|| self.error("unknown operator"))?,
+            ),
+            9 => Op::Binary(
+                *BINARY_OPS
+                    .get(usize::from(self.byte()?))
+                    .ok_or_else(|| self.error("unknown operator"))?,
+            ),
+            10 => Op::Truthy,
+            11 => Op::Jump(self.count()?),
+            12 => Op::JumpIfFalse(self.count()?),
+            13 => Op::JumpIfTrue(self.count()?),
+            14 => Op::Call(self.string()?, self.count()?),
+            15 => Op::Builtin(self.id()?, self.count()?),
+            16 => Op::Header(self.id()?, self.count()?),
+            17 => Op::Field(self.string()?),
+            18 => Op::Index,
+            19 => Op::Method(self.string()?, self.count()?),
+            20 => Op::Return,
+            tag => return Err(self.error(&format!("unknown instruction {}", tag))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecode::compile;
+    use crate::parser::parse;
+
+    const SOURCE: &str = r#"
+var name: string = "Prizm",
+var ratio: float = -1.5,
+var items = [1, 2.25, true, null, {label: "a", nested: [name]}],
+items[4].label = "b",
+if (ratio < 0 && !false || name == "x") {
+    output(name + "!"),
+} else {
+    output(items[0] % 2),
+},
+loop until (ratio >= 3) {
+    ratio = ratio + 1,
+},
+repeat for (i = 1 to 3) {
+    output(math.power(i, 2)),
+},
+define greet(person: string, times) {
+    return person.length() * times,
+},
+output(greet("héllo", 2)),
+"#;
+
+    fn header() -> Header {
+        Header {
+            profile: "release".to_string(),
+            hash: hash(&[SOURCE.as_bytes()]),
+        }
+    }
+
+    #[test]
+    fn modules_round_trip() {
+        let module = compile(&parse(SOURCE).unwrap()).unwrap();
+        let bytes = encode(&module, &header()).unwrap();
+        assert_eq!(decode(&bytes).unwrap(), module);
+
+        let read = super::header(&bytes).unwrap();
+        assert_eq!(read.profile, "release");
+        assert_eq!(read.hash, header().hash);
+    }
+
+    #[test]
+    fn damaged_files_are_rejected() {
+        let module = compile(&parse(SOURCE).unwrap()).unwrap();
+        let bytes = encode(&module, &header()).unwrap();
+
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(decode(&extra).is_err());
+        let mut old = bytes.clone();
+        old[MAGIC.len()] = FORMAT + 1;
+        assert!(decode(&old).is_err());
+        assert!(super::header(&old).is_none());
+        assert!(super::header(b"PZM").is_none());
+    }
+
+    #[test]
+    fn hash_separates_its_parts() {
+        assert_ne!(hash(&[b"ab", b"c"]), hash(&[b"a", b"bc"]));
+        assert_eq!(hash(&[b"abc"]), hash(&[b"abc"]));
+    }
+}
@@ -1,20 +1,512 @@
-use std::env;
+// Command Line for Prizm
+// Parses `prizm <command> [argument] [options]` and writes the help for each
+// command. The commands themselves are in main.rs.
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+use prizm_lib::scaffold::{self, Template, TEMPLATES};
+use prizm_lib::stdlib::builtins::root::{Permissions, DEFAULT_AUDIT_LOG};
 
-    if args.len() < 3 {
-        eprintln!("Usage: prizm <command> <file>");
-        return;
+/// A subcommand, with the argument and options it takes
+pub struct Command {
+    pub name: &'static str,
+    // Shown in the usage line: `<file>` if it must be given, `[file]` if it
+    // can be left out, and None if the command takes no argument
+    pub argument: Option<&'static str>,
+    pub summary: &'static str,
+    pub options: &'static [&'static str],
+}
+
+pub const COMMANDS: [Command; 10] = [
+    Command {
+        name: "run",
+        argument: Some("[file]"),
+        summary: "Run a Prizm program, or the project's entry point",
+        options: &[
+            "--ui",
+            "--allow-root",
+            "--audit-log",
+            "--explain-optimizations",
+        ],
+    },
+    Command {
+        name: "new",
+        argument: Some("<name>"),
+        summary: "Create a project in a new directory",
+        options: &["--template"],
+    },
+    Command {
+        name: "init",
+        argument: Some("[name]"),
+        summary: "Create a project in the current directory, or in <name>",
+        options: &["--template"],
+    },
+    Command {
+        name: "build",
+        argument: None,
+        summary: "Compile every file in the project to build/<profile>",
+        options: &["--debug", "--release"],
+    },
+    Command {
+        name: "test",
+        argument: Some("[file]"),
+        summary: "Run the project's tests, or one test file",
+        options: &["--allow-root", "--audit-log"],
+    },
+    Command {
+        name: "check",
+        argument: Some("<file>"),
+        summary: "Report errors in a file without running it",
+        options: &["--format"],
+    },
+    Command {
+        name: "pretty",
+        argument: Some("<file>"),
+        summary: "Format a file in the canonical Prizm style",
+        options: &["--check", "--write"],
+    },
+    Command {
+        name: "lint",
+        argument: Some("<file>"),
+        summary: "Check a file against the lint rules",
+        options: &["--fix", "--format"],
+    },
+    Command {
+        name: "explain",
+        argument: Some("<code>"),
+        summary: "Explain an error code or lint rule",
+        options: &[],
+    },
+    Command {
+        name: "repl",
+        argument: None,
+        summary: "Run Prizm interactively",
+        options: &["--allow-root", "--audit-log"],
+    },
+];
+
+// Every option a command can take: its name, what follows `=`, and its help
+const OPTIONS: [(&str, &str, &str); 11] = [
+    (
+        "--ui",
+        "=terminal|html|headless",
+        "Where the ui header draws (terminal by default)",
+    ),
+    (
+        "--allow-root",
+        "[=exec,process,...]",
+        "Allow root attributes: all of them, or those listed",
+    ),
+    ("--audit-log", "=<path>", "Where root calls are logged"),
+    (
+        "--explain-optimizations",
+        "",
+        "Print what root.optimize changed",
+    ),
+    (
+        "--check",
+        "",
+        "Fail if the file isn't formatted, instead of printing it",
+    ),
+    ("--write", "", "Format the file in place"),
+    ("--fix", "", "Apply the fixes lint knows how to make"),
+    (
+        "--format",
+        "=text|json|sarif",
+        "Print diagnostics for people, as JSON lines or as SARIF",
+    ),
+    (
+        "--template",
+        "=cli|ui|http",
+        "What the project starts as (cli by default)",
+    ),
+    ("--debug", "", "Build without optimizing (the default)"),
+    ("--release", "", "Build with root.optimize hints applied"),
+];
+
+// Taken by every command, and before any command
+const GLOBAL_OPTIONS: [(&str, &str); 3] = [
+    ("-h, --help", "Print help"),
+    ("-V, --version", "Print the version"),
+    ("--no-color", "Don't colour the output"),
+];
+
+// How diagnostics are printed: for people, or as JSON lines or SARIF for tools
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    Sarif,
+}
+
+// How `prizm build` compiles: debug leaves the program as written, release
+// applies its root.optimize hints
+#[derive(Clone, Copy, PartialEq)]
+pub enum Profile {
+    Debug,
+    Release,
+}
+
+impl Profile {
+    /// The directory under build/ its modules go in
+    pub fn name(self) -> &'static str {
+        match self {
+            Profile::Debug => "debug",
+            Profile::Release => "release",
+        }
     }
+}
+
+/// A command line that asks for a command to be run
+pub struct Args {
+    pub command: &'static Command,
+    pub argument: Option<String>,
+    pub ui_backend: String,
+    pub permissions: Permissions,
+    pub audit_log: String,
+    pub explain: bool,
+    pub check: bool,
+    pub write: bool,
+    pub fix: bool,
+    pub format: Format,
+    pub template: &'static Template,
+    pub profile: Profile,
+}
+
+pub enum Parsed {
+    Command(Args),
+    // General help, or a command's
+    Help(Option<&'static Command>),
+    Version,
+}
 
-    let command = &args[1];
-    let file = &args[2];
+pub fn command(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+/// Parse the arguments after the program name
+pub fn parse(args: &[String]) -> Result<Parsed, String> {
+    let mut name = None;
+    let mut positional = Vec::new();
+    let mut options = Vec::new();
+    let mut help = false;
+    let mut args = args.iter().map(String::as_str).peekable();
+    while let Some(arg) = args.next() {
+        match arg {
+            "-h" | "--help" => help = true,
+            "-V" | "--version" => return Ok(Parsed::Version),
+            // Diagnostics leave out colour whenever NO_COLOR is set
+            "--no-color" => std::env::set_var("NO_COLOR", "1"),
+            // Values come after `=` or as the next argument
+            arg if arg.starts_with('-') => {
+                let (flag, value) = match arg.split_once('=') {
+                    Some((flag, value)) => (flag, Some(value)),
+                    None => (arg, args.next_if(|next| is_value(arg, next))),
+                };
+                if !OPTIONS.iter().any(|(name, _, _)| *name == flag) {
+                    return Err(format!("Unknown option: {}", arg));
+                }
+                options.push((flag, value));
+            }
+            arg if name.is_none() => name = Some(arg),
+            arg => positional.push(arg),
+        }
+    }
+
+    let Some(name) = name else {
+        return match options.first() {
+            Some((flag, _)) => Err(format!("prizm doesn't take {} without a command", flag)),
+            None => Ok(Parsed::Help(None)),
+        };
+    };
+    // `prizm help [command]` is the same as `--help`
+    if name == "help" {
+        return match positional.as_slice() {
+            [] => Ok(Parsed::Help(None)),
+            [name] => command(name)
+                .map(|command| Parsed::Help(Some(command)))
+                .ok_or_else(|| unknown_command(name)),
+            _ => Err("prizm help takes at most one command".to_string()),
+        };
+    }
+    let command = command(name).ok_or_else(|| unknown_command(name))?;
+    if help {
+        return Ok(Parsed::Help(Some(command)));
+    }
+
+    let argument = match (command.argument, positional.as_slice()) {
+        (_, []) => None,
+        (Some(_), [argument]) => Some(argument.to_string()),
+        (Some(_), [_, extra, ..]) | (None, [extra, ..]) => {
+            return Err(format!(
+                "Unexpected argument '{}' for prizm {}",
+                extra, command.name
+            ))
+        }
+    };
+    if argument.is_none() {
+        if let Some(expected) = command
+            .argument
+            .filter(|argument| argument.starts_with('<'))
+        {
+            return Err(format!(
+                "prizm {} needs a {}",
+                command.name,
+                expected.trim_matches(['<', '>'])
+            ));
+        }
+    }
+
+    let mut parsed = Args {
+        command,
+        argument,
+        // UI scripts draw in the terminal unless told otherwise
+        ui_backend: "terminal".to_string(),
+        // Root attributes are off unless turned on
+        permissions: Permissions::none(),
+        audit_log: DEFAULT_AUDIT_LOG.to_string(),
+        explain: false,
+        check: false,
+        write: false,
+        fix: false,
+        format: Format::Text,
+        template: &scaffold::TEMPLATES[0],
+        profile: Profile::Debug,
+    };
+    let mut profile = None;
+    for (flag, value) in options {
+        if !command.options.contains(&flag) {
+            return Err(format!("prizm {} doesn't take {}", command.name, flag));
+        }
+        match (flag, value) {
+            ("--ui", Some(name)) => parsed.ui_backend = name.to_string(),
+            ("--allow-root", value) => parsed
+                .permissions
+                .extend(Permissions::parse(value.unwrap_or(""))?),
+            ("--audit-log", Some(path)) => parsed.audit_log = path.to_string(),
+            ("--format", Some(name)) => {
+                parsed.format = match name {
+                    "text" => Format::Text,
+                    "json" => Format::Json,
+                    "sarif" => Format::Sarif,
+                    _ => {
+                        return Err(format!(
+                            "Unknown format: {} (expected text, json or sarif)",
+                            name
+                        ))
+                    }
+                }
+            }
+            ("--template", Some(name)) => {
+                parsed.template = scaffold::template(name).ok_or_else(|| {
+                    let names: Vec<&str> = TEMPLATES.iter().map(|template| template.name).collect();
+                    format!("Unknown template: {} (expected {})", name, names.join(", "))
+                })?
+            }
+            ("--explain-optimizations", None) => parsed.explain = true,
+            ("--check", None) => parsed.check = true,
+            ("--write", None) => parsed.write = true,
+            ("--fix", None) => parsed.fix = true,
+            ("--debug" | "--release", None) => {
+                if profile.is_some_and(|other| other != flag) {
+                    return Err("--debug and --release can't be used together".to_string());
+                }
+                profile = Some(flag);
+                parsed.profile = match flag {
+                    "--release" => Profile::Release,
+                    _ => Profile::Debug,
+                };
+            }
+            (_, Some(_)) => return Err(format!("{} doesn't take a value", flag)),
+            (_, None) => {
+                return Err(format!(
+                    "{} needs a value, as in {}=... or {} ...",
+                    flag, flag, flag
+                ))
+            }
+        }
+    }
+    Ok(Parsed::Command(parsed))
+}
+
+// Whether `next` is the value of `flag`, given as `--flag value`. An option
+// whose value can be left out, like --allow-root, only takes the next argument
+// if it is a valid value, so `--allow-root app.pzm` still runs app.pzm.
+fn is_value(flag: &str, next: &str) -> bool {
+    if next.starts_with('-') {
+        return false;
+    }
+    match OPTIONS.iter().find(|(name, _, _)| *name == flag) {
+        Some(("--allow-root", _, _)) => !next.is_empty() && Permissions::parse(next).is_ok(),
+        Some((_, value, _)) => value.starts_with('='),
+        None => false,
+    }
+}
+
+fn unknown_command(name: &str) -> String {
+    format!("Unknown command: {}", name)
+}
+
+// ===== HELP =====
+
+/// The help for every command, or for one
+pub fn help(command: Option<&Command>) -> String {
+    let mut text = String::new();
+    match command {
+        None => {
+            text.push_str("Usage: prizm <command> [argument] [options]\n\nCommands:\n");
+            for command in &COMMANDS {
+                text.push_str(&format!("  {:<10} {}\n", command.name, command.summary));
+            }
+            text.push_str(&format!(
+                "  {:<10} {}\n",
+                "help", "Print help for a command"
+            ));
+            text.push_str("\nOptions:\n");
+            for (flag, help) in GLOBAL_OPTIONS {
+                text.push_str(&format!("  {:<16} {}\n", flag, help));
+            }
+            text.push_str("\nRun prizm help <command> for a command's options.\n");
+        }
+        Some(command) => {
+            text.push_str(&format!(
+                "{}\n\nUsage: prizm {}",
+                command.summary, command.name
+            ));
+            if let Some(argument) = command.argument {
+                text.push_str(&format!(" {}", argument));
+            }
+            text.push_str(" [options]\n\nOptions:\n");
+            let options = OPTIONS
+                .iter()
+                .filter(|(name, _, _)| command.options.contains(name))
+                .map(|(name, value, help)| (format!("{}{}", name, value), *help));
+            let global = GLOBAL_OPTIONS
+                .iter()
+                .map(|(flag, help)| (flag.to_string(), *help));
+            let lines: Vec<(String, &str)> = options.chain(global).collect();
+            let width = lines.iter().map(|(flag, _)| flag.len()).max().unwrap_or(0);
+            for (flag, help) in lines {
+                text.push_str(&format!("  {:<width$}  {}\n", flag, help, width = width));
+            }
+            if command.options.contains(&"--template") {
+                text.push_str("\nTemplates:\n");
+                for template in &TEMPLATES {
+                    text.push_str(&format!(
+                        "  {:<6} {}\n",
+                        template.name, template.description
+                    ));
+                }
+            }
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(line: &str) -> Result<Parsed, String> {
+        let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+        parse(&args)
+    }
+
+    // The arguments of a command line that runs a command
+    fn command_line(line: &str) -> Args {
+        match parsed(line) {
+            Ok(Parsed::Command(args)) => args,
+            Ok(_) => panic!("'{}' doesn't run a command", line),
+            Err(e) => panic!("'{}' doesn't parse: {}", line, e),
+        }
+    }
+
+    #[test]
+    fn values_come_after_equals_or_as_the_next_argument() {
+        for line in [
+            "run app.pzm --ui=html --audit-log=root.log",
+            "run --ui html app.pzm --audit-log root.log",
+        ] {
+            let args = command_line(line);
+            assert_eq!(args.command.name, "run");
+            assert_eq!(args.argument.as_deref(), Some("app.pzm"));
+            assert_eq!(args.ui_backend, "html");
+            assert_eq!(args.audit_log, "root.log");
+        }
+        assert!(command_line("lint app.pzm --format sarif").format == Format::Sarif);
+        assert!(command_line("check --format=json app.pzm").format == Format::Json);
+        // A value that looks like an option isn't taken as one
+        assert_eq!(
+            parsed("run --ui --allow-root").err(),
+            Some("--ui needs a value, as in --ui=... or --ui ...".to_string())
+        );
+    }
+
+    #[test]
+    fn allow_root_only_takes_the_next_argument_if_it_lists_attributes() {
+        let args = command_line("run --allow-root app.pzm");
+        assert_eq!(args.argument.as_deref(), Some("app.pzm"));
+        assert_eq!(args.permissions, Permissions::all());
+
+        let args = command_line("run --allow-root exec,process app.pzm");
+        assert_eq!(args.argument.as_deref(), Some("app.pzm"));
+        assert_eq!(
+            args.permissions,
+            Permissions::parse("exec,process").unwrap()
+        );
+
+        let args = command_line("run app.pzm");
+        assert_eq!(args.permissions, Permissions::none());
+        assert!(parsed("run --allow-root=nope app.pzm")
+            .err()
+            .unwrap()
+            .starts_with("Unknown root attribute 'nope'"));
+    }
+
+    #[test]
+    fn help_and_version_are_found_anywhere() {
+        assert!(matches!(parsed(""), Ok(Parsed::Help(None))));
+        assert!(matches!(parsed("--help"), Ok(Parsed::Help(None))));
+        assert!(matches!(parsed("help"), Ok(Parsed::Help(None))));
+        assert!(matches!(
+            parsed("lint --help"),
+            Ok(Parsed::Help(Some(Command { name: "lint", .. })))
+        ));
+        assert!(matches!(
+            parsed("help build"),
+            Ok(Parsed::Help(Some(Command { name: "build", .. })))
+        ));
+        assert!(matches!(parsed("run app.pzm -V"), Ok(Parsed::Version)));
+        assert!(help(command("new")).contains("Templates:"));
+    }
 
-    match command.as_str() {
-        "run" => println!("Running {}...", file),
-        "pretty" => println!("Formatting {}...", file),
-        "lint" => println!("Linting {}...", file),
-        _ => eprintln!("Unknown command: {}", command),
+    #[test]
+    fn wrong_command_lines_are_errors() {
+        let error = |line| parsed(line).err().unwrap();
+        assert_eq!(error("fly"), "Unknown command: fly");
+        assert_eq!(error("help fly"), "Unknown command: fly");
+        assert_eq!(error("--fix"), "prizm doesn't take --fix without a command");
+        assert_eq!(error("run --nope"), "Unknown option: --nope");
+        assert_eq!(error("run --fix"), "prizm run doesn't take --fix");
+        assert_eq!(error("lint"), "prizm lint needs a file");
+        assert_eq!(
+            error("lint a.pzm b.pzm"),
+            "Unexpected argument 'b.pzm' for prizm lint"
+        );
+        assert_eq!(
+            error("build now"),
+            "Unexpected argument 'now' for prizm build"
+        );
+        assert_eq!(
+            error("pretty a.pzm --check=yes"),
+            "--check doesn't take a value"
+        );
+        assert_eq!(
+            error("lint a.pzm --format=xml"),
+            "Unknown format: xml (expected text, json or sarif)"
+        );
+        assert_eq!(
+            error("build --debug --release"),
+            "--debug and --release can't be used together"
+        );
+        assert!(command_line("build --release").profile == Profile::Release);
     }
-}
\ No newline at end of file
+}
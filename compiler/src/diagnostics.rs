@@ -0,0 +1,886 @@
+// Diagnostics for Prizm Language
+// Errors and warnings shown the way compilers show them: a header with a
+// stable code, the source lines they point at with the spot underlined, and
+// notes and help after. The lexer, parser and compiler return them directly;
+// runtime errors and lint findings are turned into them to be shown.
+
+use crate::lexer::Span;
+use crate::stdlib::builtins::data::json;
+use crate::value::Value;
+use std::fmt;
+use std::io::IsTerminal;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+/// Another place in the source that explains the problem, such as where a
+/// block that was never closed starts
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// A code from `CODES`, or the ID of the lint rule that found it
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    pub labels: Vec<Label>,
+    pub notes: Vec<Note>,
+    // Boxed, as most diagnostics have none and errors should stay small
+    pub fix: Option<Box<Fix>>,
+}
+
+/// A line shown after the source: a note says more about the problem, and
+/// help says how to fix it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub help: bool,
+    pub text: String,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, span: Span, message: &str) -> Self {
+        Diagnostic {
+            severity,
+            code,
+            message: message.to_string(),
+            span,
+            labels: Vec::new(),
+            notes: Vec::new(),
+            fix: None,
+        }
+    }
+
+    pub fn error(code: &'static str, span: Span, message: &str) -> Self {
+        Diagnostic::new(Severity::Error, code, span, message)
+    }
+
+    /// A runtime error, from the "Runtime error on line N, column C: ..."
+    /// text the VM returns; its code is found from the message
+    pub fn runtime(error: &str) -> Option<Self> {
+        let rest = error.strip_prefix("Runtime error on line ")?;
+        let (location, message) = rest.split_once(": ")?;
+        let (line, column) = location.split_once(", column ")?;
+        Some(Diagnostic::error(
+            runtime_code(message),
+            Span::new(line.parse().ok()?, column.parse().ok()?),
+            message,
+        ))
+    }
+
+    pub fn with_label(mut self, span: Span, text: &str) -> Self {
+        self.labels.push(Label {
+            span,
+            text: text.to_string(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.notes.push(Note {
+            help: false,
+            text: note.to_string(),
+        });
+        self
+    }
+
+    pub fn with_help(mut self, help: &str) -> Self {
+        self.notes.push(Note {
+            help: true,
+            text: help.to_string(),
+        });
+        self
+    }
+
+    pub fn with_fix(mut self, fix: Fix) -> Self {
+        self.fix = Some(Box::new(fix));
+        self
+    }
+
+    /// The first line of the diagnostic: its severity, code and message
+    pub fn header(&self, color: bool) -> String {
+        let paint = Paint { color };
+        format!(
+            "{}{}",
+            paint.on(
+                self.color(),
+                &format!("{}[{}]", self.severity.name(), self.code)
+            ),
+            paint.on(BOLD, &format!(": {}", self.message))
+        )
+    }
+
+    fn color(&self) -> &'static str {
+        match self.severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        }
+    }
+
+    /// The diagnostic with the lines of `source` it points at
+    pub fn render(&self, file: &str, source: &str, color: bool) -> String {
+        let paint = Paint { color };
+        let level = self.color();
+        let mut out = format!("{}\n", self.header(color));
+
+        // Split on every newline, so an error at the very end of a file that
+        // ends with one still has its empty last line to point at
+        let lines: Vec<&str> = source
+            .split('\n')
+            .map(|line| line.strip_suffix('\r').unwrap_or(line))
+            .collect();
+        // Every mark: where it is, the character to underline with, its colour
+        // and text
+        let mut marks = vec![(self.span, '^', level, "")];
+        for label in &self.labels {
+            marks.push((label.span, '-', BLUE, label.text.as_str()));
+        }
+        marks.retain(|(span, ..)| span.line >= 1 && span.line <= lines.len());
+        marks.sort_by_key(|(span, ..)| (span.line, span.column));
+
+        let width = marks
+            .iter()
+            .map(|(span, ..)| span.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let gutter = paint.on(BLUE, &format!("{} |", " ".repeat(width)));
+        let location = match self.span.column {
+            0 => format!("{}:{}", file, self.span.line),
+            column => format!("{}:{}:{}", file, self.span.line, column),
+        };
+        out.push_str(&format!(
+            "{}{} {}\n",
+            " ".repeat(width),
+            paint.on(BLUE, "-->"),
+            location
+        ));
+        if !marks.is_empty() {
+            out.push_str(&format!("{}\n", gutter));
+        }
+
+        let mut previous: Option<usize> = None;
+        for (span, underline, mark_color, text) in &marks {
+            let line = lines[span.line - 1];
+            if previous != Some(span.line) {
+                if previous.is_some_and(|previous| span.line > previous + 1) {
+                    out.push_str(&format!("{}\n", paint.on(BLUE, "...")));
+                }
+                let number = format!("{:>width$} |", span.line, width = width);
+                out.push_str(&format!("{} {}\n", paint.on(BLUE, &number), line));
+            }
+            previous = Some(span.line);
+
+            let (start, length) = match span.column {
+                // The whole line, without its indentation
+                0 => {
+                    let indent = line.chars().take_while(|c| c.is_whitespace()).count();
+                    (indent, line.trim().chars().count().max(1))
+                }
+                column => (column - 1, token_width(line, column - 1)),
+            };
+            // Tabs are kept, so the marks line up under them
+            let padding: String = line
+                .chars()
+                .chain(std::iter::repeat(' '))
+                .take(start)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let marks = underline.to_string().repeat(length);
+            let text = if text.is_empty() {
+                String::new()
+            } else {
+                format!(" {}", text)
+            };
+            out.push_str(&format!(
+                "{} {}{}\n",
+                gutter,
+                padding,
+                paint.on(mark_color, &format!("{}{}", marks, text))
+            ));
+        }
+
+        if !self.notes.is_empty() {
+            out.push_str(&format!("{}\n", gutter));
+        }
+        for note in &self.notes {
+            let kind = if note.help { "= help:" } else { "= note:" };
+            out.push_str(&format!(
+                "{} {} {}\n",
+                " ".repeat(width),
+                paint.on(BOLD, kind),
+                note.text
+            ));
+        }
+        out
+    }
+}
+
+/// Replace the source from `start` up to `end` with `replacement`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub start: Span,
+    pub end: Span,
+    pub replacement: String,
+}
+
+impl Edit {
+    pub fn replace(start: Span, end: Span, replacement: &str) -> Self {
+        Edit {
+            start,
+            end,
+            replacement: replacement.to_string(),
+        }
+    }
+
+    pub fn insert(at: Span, text: &str) -> Self {
+        Edit::replace(at, at, text)
+    }
+}
+
+/// Edits that fix a problem, which are only applied all together
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fix {
+    pub description: String,
+    pub edits: Vec<Edit>,
+}
+
+// The one-line form, for when the diagnostic is passed on as a String
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stage = explain(self.code).map_or("Lint", |code| code.stage);
+        match self.span.column {
+            0 => write!(
+                f,
+                "{} error on line {}: {}",
+                stage, self.span.line, self.message
+            ),
+            column => write!(
+                f,
+                "{} error on line {}, column {}: {}",
+                stage, self.span.line, column, self.message
+            ),
+        }
+    }
+}
+
+impl From<Diagnostic> for String {
+    fn from(diagnostic: Diagnostic) -> String {
+        diagnostic.to_string()
+    }
+}
+
+/// Whether to colour what is written to `stream`: only a terminal, and only
+/// when NO_COLOR isn't set
+pub fn color(stream: &impl IsTerminal) -> bool {
+    stream.is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
+
+// How many characters the token starting at `start` takes up: a whole word,
+// number or string, or else one character
+fn token_width(line: &str, start: usize) -> usize {
+    let rest: Vec<char> = line.chars().skip(start).collect();
+    match rest.first() {
+        Some(c) if c.is_alphanumeric() || *c == '_' => rest
+            .iter()
+            .take_while(|c| c.is_alphanumeric() || **c == '_' || **c == '.')
+            .count(),
+        Some('"') => {
+            let mut escaped = false;
+            for (i, c) in rest.iter().enumerate().skip(1) {
+                match c {
+                    '\\' if !escaped => escaped = true,
+                    '"' if !escaped => return i + 1,
+                    _ => escaped = false,
+                }
+            }
+            rest.len()
+        }
+        _ => 1,
+    }
+}
+
+// ===== COLOUR =====
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+
+struct Paint {
+    color: bool,
+}
+
+impl Paint {
+    fn on(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}\x1b[0m", style, text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+// ===== MACHINE-READABLE OUTPUT =====
+
+/// The diagnostic as one line of JSON, for tools that read them one at a time
+pub fn json_line(file: &str, diagnostic: &Diagnostic) -> String {
+    let labels = diagnostic
+        .labels
+        .iter()
+        .map(|label| {
+            object(vec![
+                ("span", span_value(label.span)),
+                ("text", Value::String(label.text.clone())),
+            ])
+        })
+        .collect();
+    let notes = diagnostic
+        .notes
+        .iter()
+        .map(|note| {
+            let kind = if note.help { "help" } else { "note" };
+            object(vec![
+                ("kind", Value::String(kind.to_string())),
+                ("text", Value::String(note.text.clone())),
+            ])
+        })
+        .collect();
+    let fix = match &diagnostic.fix {
+        Some(fix) => {
+            let edits = fix
+                .edits
+                .iter()
+                .map(|edit| {
+                    object(vec![
+                        ("start", span_value(edit.start)),
+                        ("end", span_value(edit.end)),
+                        ("replacement", Value::String(edit.replacement.clone())),
+                    ])
+                })
+                .collect();
+            object(vec![
+                ("description", Value::String(fix.description.clone())),
+                ("edits", Value::Array(edits)),
+            ])
+        }
+        None => Value::Null,
+    };
+    let line = object(vec![
+        ("file", Value::String(file.to_string())),
+        ("code", Value::String(diagnostic.code.to_string())),
+        (
+            "severity",
+            Value::String(diagnostic.severity.name().to_string()),
+        ),
+        ("message", Value::String(diagnostic.message.clone())),
+        ("span", span_value(diagnostic.span)),
+        ("labels", Value::Array(labels)),
+        ("notes", Value::Array(notes)),
+        ("fix", fix),
+    ]);
+    json::stringify(&line, false).expect("diagnostics only hold JSON values")
+}
+
+/// Diagnostics for one file as a SARIF 2.1.0 log, which code review tools
+/// read. `rules` gives the ID and description of every code the command can
+/// report.
+pub fn sarif(file: &str, diagnostics: &[Diagnostic], rules: &[(&str, &str)]) -> String {
+    let artifact = || object(vec![("uri", Value::String(file.to_string()))]);
+    let location = |span: Span, message: Option<&str>| {
+        let mut fields = vec![(
+            "physicalLocation",
+            object(vec![
+                ("artifactLocation", artifact()),
+                ("region", region(span, None)),
+            ]),
+        )];
+        if let Some(message) = message {
+            fields.push(("message", text(message)));
+        }
+        object(fields)
+    };
+    let results = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let mut result = vec![
+                ("ruleId", Value::String(diagnostic.code.to_string())),
+                (
+                    "level",
+                    Value::String(diagnostic.severity.name().to_string()),
+                ),
+                ("message", text(&diagnostic.message)),
+                (
+                    "locations",
+                    Value::Array(vec![location(diagnostic.span, None)]),
+                ),
+            ];
+            if !diagnostic.labels.is_empty() {
+                let related = diagnostic
+                    .labels
+                    .iter()
+                    .map(|label| location(label.span, Some(&label.text)))
+                    .collect();
+                result.push(("relatedLocations", Value::Array(related)));
+            }
+            if let Some(fix) = &diagnostic.fix {
+                let replacements = fix
+                    .edits
+                    .iter()
+                    .map(|edit| {
+                        object(vec![
+                            ("deletedRegion", region(edit.start, Some(edit.end))),
+                            ("insertedContent", text(&edit.replacement)),
+                        ])
+                    })
+                    .collect();
+                let change = object(vec![
+                    ("artifactLocation", artifact()),
+                    ("replacements", Value::Array(replacements)),
+                ]);
+                result.push((
+                    "fixes",
+                    Value::Array(vec![object(vec![
+                        ("description", text(&fix.description)),
+                        ("artifactChanges", Value::Array(vec![change])),
+                    ])]),
+                ));
+            }
+            object(result)
+        })
+        .collect();
+    let rules = rules
+        .iter()
+        .map(|(id, description)| {
+            object(vec![
+                ("id", Value::String(id.to_string())),
+                ("shortDescription", text(description)),
+            ])
+        })
+        .collect();
+    let driver = object(vec![
+        ("name", Value::String("prizm".to_string())),
+        (
+            "version",
+            Value::String(env!("CARGO_PKG_VERSION").to_string()),
+        ),
+        (
+            "informationUri",
+            Value::String("https://github.com/Seigh-sword/Prizm".to_string()),
+        ),
+        ("rules", Value::Array(rules)),
+    ]);
+    let log = object(vec![
+        (
+            "$schema",
+            Value::String("https://json.schemastore.org/sarif-2.1.0.json".to_string()),
+        ),
+        ("version", Value::String("2.1.0".to_string())),
+        (
+            "runs",
+            Value::Array(vec![object(vec![
+                ("tool", object(vec![("driver", driver)])),
+                ("results", Value::Array(results)),
+            ])]),
+        ),
+    ]);
+    json::stringify(&log, true).expect("diagnostics only hold JSON values")
+}
+
+fn object(fields: Vec<(&str, Value)>) -> Value {
+    Value::Object(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+// A line and column, or a null column for a span that has none
+fn span_value(span: Span) -> Value {
+    let column = match span.column {
+        0 => Value::Null,
+        column => Value::Int(column as i64),
+    };
+    object(vec![
+        ("line", Value::Int(span.line as i64)),
+        ("column", column),
+    ])
+}
+
+// A SARIF region, which starts at a span and may end at another
+fn region(start: Span, end: Option<Span>) -> Value {
+    let mut fields = vec![("startLine", Value::Int(start.line as i64))];
+    if start.column > 0 {
+        fields.push(("startColumn", Value::Int(start.column as i64)));
+    }
+    if let Some(end) = end {
+        fields.push(("endLine", Value::Int(end.line as i64)));
+        fields.push(("endColumn", Value::Int(end.column as i64)));
+    }
+    object(fields)
+}
+
+// A SARIF message
+fn text(text: &str) -> Value {
+    object(vec![("text", Value::String(text.to_string()))])
+}
+
+// ===== CODES =====
+
+/// What an error code means, for `prizm explain`
+pub struct Code {
+    pub code: &'static str,
+    /// "Syntax" for errors found before the program runs, "Runtime" after
+    pub stage: &'static str,
+    pub title: &'static str,
+    pub explanation: &'static str,
+}
+
+/// Every error code. Codes are never reused: P00xx are from reading the
+/// text, P01xx from parsing it, P02xx from compiling it, and P03xx from
+/// running it.
+pub const CODES: [Code; 23] = [
+    Code {
+        code: "P0001",
+        stage: "Syntax",
+        title: "Unexpected character",
+        explanation: "The file has a character that isn't part of any Prizm token, such as `@` or `$` outside a string.\n\nRemove it, or put it inside a string: `output(\"$5\")`.",
+    },
+    Code {
+        code: "P0002",
+        stage: "Syntax",
+        title: "Unterminated string",
+        explanation: "A string starts with `\"` but the file ends before the `\"` that closes it. Strings may span several lines, so the missing quote can be far from where the string starts.\n\nAdd the closing quote: `output(\"Hello\")`.",
+    },
+    Code {
+        code: "P0003",
+        stage: "Syntax",
+        title: "Invalid number",
+        explanation: "A number can't be read, because it is too large for a 64-bit integer or has digits that don't belong, as in `0xZZ`.\n\nUse a float for very large values: `var big = 1.0e20`.",
+    },
+    Code {
+        code: "P0101",
+        stage: "Syntax",
+        title: "Unexpected token",
+        explanation: "The parser found something other than what the grammar allows at this point, such as a missing `)` or a keyword where a value should be.\n\nThe message says what was expected; check the code just before the marked spot as well, since that is often where the mistake is.",
+    },
+    Code {
+        code: "P0102",
+        stage: "Syntax",
+        title: "Unclosed block",
+        explanation: "A block that starts with `{` has no `}` to close it before the end of the file.\n\nThe label shows where the block starts. Add the `}` where the block should end:\n\n    if (x > 1) {\n        output(x),\n    }",
+    },
+    Code {
+        code: "P0103",
+        stage: "Syntax",
+        title: "Invalid assignment target",
+        explanation: "Only variables, fields and array items can be assigned to, as in `x = 1`, `player.name = \"Ada\"` or `list[0] = 2`. Values such as `1 = x` or the result of a call can't be.",
+    },
+    Code {
+        code: "P0104",
+        stage: "Syntax",
+        title: "Duplicate parameter",
+        explanation: "A function lists the same parameter twice, as in `define add(a, a)`. Give each parameter its own name.",
+    },
+    Code {
+        code: "P0201",
+        stage: "Syntax",
+        title: "Function defined twice",
+        explanation: "Two functions have the same name. Functions are defined before the program runs, so there is no way to tell which one a call means.\n\nThe label shows the first definition; rename or remove one of them.",
+    },
+    Code {
+        code: "P0202",
+        stage: "Syntax",
+        title: "Return outside a function",
+        explanation: "`return` ends a function with a value, so it can only be used inside `define`. To stop a loop at the top level, use `break`.",
+    },
+    Code {
+        code: "P0203",
+        stage: "Syntax",
+        title: "Break outside a loop",
+        explanation: "`break` leaves the innermost `loop`, `loop until` or `repeat for`, so it can only be used inside one. To leave a function, use `return`.",
+    },
+    Code {
+        code: "P0204",
+        stage: "Syntax",
+        title: "Unknown header attribute",
+        explanation: "A header such as `math` or `file` is called with an attribute it doesn't have, as in `file.raed(...)`.\n\nRun `prizm lint` to get a suggestion for the closest attribute, and see the README for every header's attributes.",
+    },
+    Code {
+        code: "P0205",
+        stage: "Syntax",
+        title: "Unknown type",
+        explanation: "A type annotation names a type Prizm doesn't have. The types are int, float, string, boolean, array, object, null and function:\n\n    var count: int = 0,",
+    },
+    Code {
+        code: "P0206",
+        stage: "Syntax",
+        title: "Invalid optimization hint",
+        explanation: "`root.optimize` marks a function for the optimizer, so it takes the name of a function defined with `define`, written without quotes or a call:\n\n    define total(items) {...}\n    root.optimize(total),",
+    },
+    Code {
+        code: "P0207",
+        stage: "Syntax",
+        title: "Declared type mismatch",
+        explanation: "A variable or parameter declared with a type, as in `var count: int = 0`, is given a value that can never have that type, such as a string literal. Values whose type is only known when the program runs are checked then instead (P0304).\n\nAssign a value of the declared type, or change the annotation.",
+    },
+    Code {
+        code: "P0300",
+        stage: "Runtime",
+        title: "Runtime error",
+        explanation: "Something failed while the program ran, such as a header attribute that couldn't do its job: a file that couldn't be read, or a value it couldn't use. The message says what went wrong.",
+    },
+    Code {
+        code: "P0301",
+        stage: "Runtime",
+        title: "Undefined variable",
+        explanation: "A variable was read before anything declared it. Declare it with `var` first:\n\n    var total = 0,\n    total = total + 1,\n\nVariables declared inside a function only exist in that function.",
+    },
+    Code {
+        code: "P0302",
+        stage: "Runtime",
+        title: "Undefined function",
+        explanation: "A function was called that is never defined with `define`, and no variable of that name holds one. Check the spelling; `prizm lint` finds these before the program runs. Lines are printed with `output`, not `println`.",
+    },
+    Code {
+        code: "P0303",
+        stage: "Runtime",
+        title: "Wrong type",
+        explanation: "A value has a type the operation can't use, such as subtracting a string or reading a field of a number. The message names the types involved; convert the value first, or check where it came from.",
+    },
+    Code {
+        code: "P0304",
+        stage: "Runtime",
+        title: "Declared type violated",
+        explanation: "A variable declared with a type, as in `var count: int = 0`, was given a value of another type. Assign a value of the declared type, or remove the annotation.",
+    },
+    Code {
+        code: "P0305",
+        stage: "Runtime",
+        title: "Division by zero",
+        explanation: "A number was divided, or taken modulo, by zero. Check the divisor before dividing:\n\n    if (count != 0) {\n        output(total / count),\n    }",
+    },
+    Code {
+        code: "P0306",
+        stage: "Runtime",
+        title: "Index out of range",
+        explanation: "An array was read or written at an index it doesn't have. Indexes start at 0, so the last item of an array of 3 items is at index 2.",
+    },
+    Code {
+        code: "P0307",
+        stage: "Runtime",
+        title: "Too many nested calls",
+        explanation: "Functions called each other so deeply that the program stopped, which usually means a function calls itself with no case that stops it. Make sure every recursive function has a case that returns without calling itself.",
+    },
+    Code {
+        code: "P0308",
+        stage: "Runtime",
+        title: "Root attribute disabled",
+        explanation: "Root attributes such as `root.exec` can change the system, so they are off unless the program is run with `--allow-root`, either for all of them or for a list: `--allow-root=exec,process`.\n\nEvery root call that is allowed is written to the audit log.",
+    },
+];
+
+/// The code with this name
+pub fn explain(code: &str) -> Option<&'static Code> {
+    CODES
+        .iter()
+        .find(|known| known.code.eq_ignore_ascii_case(code))
+}
+
+// Runtime errors come from anywhere the program can fail, as text, so their
+// code is found from how the message starts
+fn runtime_code(message: &str) -> &'static str {
+    const PREFIXES: [(&str, &str); 13] = [
+        ("Undefined variable", "P0301"),
+        ("Undefined function", "P0302"),
+        ("Expected a function to call", "P0302"),
+        ("Argument ", "P0303"),
+        ("Cannot apply", "P0303"),
+        ("Cannot compare", "P0303"),
+        ("Cannot negate", "P0303"),
+        ("Cannot index", "P0303"),
+        ("Cannot read field", "P0303"),
+        ("Cannot set field", "P0303"),
+        ("Cannot store", "P0304"),
+        ("Division by zero", "P0305"),
+        ("Modulo by zero", "P0305"),
+    ];
+    if message.starts_with("Index ") && message.contains("out of range") {
+        return "P0306";
+    }
+    if message.starts_with("Too many nested calls") {
+        return "P0307";
+    }
+    if message.starts_with("root.") && message.contains("--allow-root") {
+        return "P0308";
+    }
+    // Numbers too large to hold, rather than values of the wrong type
+    if message.contains("too large") {
+        return "P0300";
+    }
+    PREFIXES
+        .iter()
+        .find(|(prefix, _)| message.starts_with(prefix))
+        .map_or("P0300", |(_, code)| code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(text: &str) -> Value {
+        json::parse(text).unwrap()
+    }
+
+    // A lint finding with a fix, and a parse error with a label and no column
+    fn diagnostics() -> Vec<Diagnostic> {
+        let fix = Fix {
+            description: "Add ','".to_string(),
+            edits: vec![Edit::insert(Span::new(1, 10), ",")],
+        };
+        vec![
+            Diagnostic::new(
+                Severity::Warning,
+                "missing-comma",
+                Span::new(1, 10),
+                "Missing ','",
+            )
+            .with_help("Add ',' (prizm lint --fix)")
+            .with_fix(fix),
+            Diagnostic::error("P0102", Span::new(3, 0), "Unclosed '{'")
+                .with_label(Span::new(2, 5), "opened here")
+                .with_note("Blocks end with '}'"),
+        ]
+    }
+
+    #[test]
+    fn json_lines_hold_everything_about_a_diagnostic() {
+        let lines: Vec<String> = diagnostics()
+            .iter()
+            .map(|diagnostic| json_line("app.pzm", diagnostic))
+            .collect();
+        assert!(lines.iter().all(|line| !line.contains('\n')));
+        assert_eq!(
+            value(&lines[0]),
+            value(
+                r#"{"file": "app.pzm", "code": "missing-comma", "severity": "warning",
+                    "message": "Missing ','", "span": {"line": 1, "column": 10},
+                    "labels": [], "notes": [{"kind": "help", "text": "Add ',' (prizm lint --fix)"}],
+                    "fix": {"description": "Add ','", "edits": [{"start": {"line": 1, "column": 10},
+                            "end": {"line": 1, "column": 10}, "replacement": ","}]}}"#
+            )
+        );
+        assert_eq!(
+            value(&lines[1]),
+            value(
+                r#"{"file": "app.pzm", "code": "P0102", "severity": "error",
+                    "message": "Unclosed '{'", "span": {"line": 3, "column": null},
+                    "labels": [{"span": {"line": 2, "column": 5}, "text": "opened here"}],
+                    "notes": [{"kind": "note", "text": "Blocks end with '}'"}], "fix": null}"#
+            )
+        );
+    }
+
+    #[test]
+    fn sarif_logs_have_the_rules_results_and_fixes() {
+        let rules = [
+            ("missing-comma", "A statement without a ','"),
+            ("P0102", "Unclosed block"),
+        ];
+        let log = value(&sarif("app.pzm", &diagnostics(), &rules));
+        assert_eq!(log.get("version"), Some(&value("\"2.1.0\"")));
+        let Some(Value::Array(runs)) = log.get("runs") else {
+            panic!("no runs in {}", log);
+        };
+        assert_eq!(runs.len(), 1);
+        let driver = runs[0]
+            .get("tool")
+            .and_then(|tool| tool.get("driver"))
+            .unwrap();
+        assert_eq!(driver.get("name"), Some(&value("\"prizm\"")));
+        assert_eq!(
+            driver.get("rules"),
+            Some(&value(
+                r#"[{"id": "missing-comma",
+                     "shortDescription": {"text": "A statement without a ','"}},
+                    {"id": "P0102", "shortDescription": {"text": "Unclosed block"}}]"#
+            ))
+        );
+        let Some(Value::Array(results)) = runs[0].get("results") else {
+            panic!("no results in {}", log);
+        };
+        assert_eq!(results.len(), 2);
+        let location = |start: &str| {
+            value(&format!(
+                r#"[{{"physicalLocation": {{"artifactLocation": {{"uri": "app.pzm"}},
+                                          "region": {start}}}}}]"#
+            ))
+        };
+
+        let lint = &results[0];
+        assert_eq!(lint.get("ruleId"), Some(&value("\"missing-comma\"")));
+        assert_eq!(lint.get("level"), Some(&value("\"warning\"")));
+        assert_eq!(
+            lint.get("message"),
+            Some(&value(r#"{"text": "Missing ','"}"#))
+        );
+        assert_eq!(
+            lint.get("locations"),
+            Some(&location(r#"{"startLine": 1, "startColumn": 10}"#))
+        );
+        assert_eq!(lint.get("relatedLocations"), None);
+        assert_eq!(
+            lint.get("fixes"),
+            Some(&value(
+                r#"[{"description": {"text": "Add ','"},
+                     "artifactChanges": [{
+                         "artifactLocation": {"uri": "app.pzm"},
+                         "replacements": [{
+                             "deletedRegion": {"startLine": 1, "startColumn": 10,
+                                               "endLine": 1, "endColumn": 10},
+                             "insertedContent": {"text": ","}}]}]}]"#
+            ))
+        );
+
+        let error = &results[1];
+        assert_eq!(error.get("ruleId"), Some(&value("\"P0102\"")));
+        assert_eq!(error.get("level"), Some(&value("\"error\"")));
+        assert_eq!(
+            error.get("locations"),
+            Some(&location(r#"{"startLine": 3}"#))
+        );
+        let Some(Value::Array(related)) = error.get("relatedLocations") else {
+            panic!("no related locations in {}", error);
+        };
+        assert_eq!(
+            related[0].get("message"),
+            Some(&value(r#"{"text": "opened here"}"#))
+        );
+        assert_eq!(error.get("fixes"), None);
+    }
+
+    #[test]
+    fn a_file_without_problems_is_an_empty_run() {
+        let log = value(&sarif("app.pzm", &[], &[]));
+        let Some(Value::Array(runs)) = log.get("runs") else {
+            panic!("no runs in {}", log);
+        };
+        assert_eq!(runs[0].get("results"), Some(&value("[]")));
+    }
+}
@@ -0,0 +1,264 @@
+// Event Loop for Prizm
+// Orders scheduled callbacks (`time.after`, `time.every`) and events queued by
+// other headers (UI events) into a single stream. The program's executor pulls
+// one event at a time and runs its callback before asking for the next, so
+// callbacks never overlap and may schedule more work.
+
+use crate::stdlib::builtins::time::{Clock, Duration};
+use crate::value::{Handle, HandleKind, Value};
+use std::collections::VecDeque;
+
+/// A callback that is ready to run, with the arguments to pass it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub callback: Value,
+    pub args: Vec<Value>,
+}
+
+struct Scheduled {
+    id: usize,
+    due: i64,
+    interval: Option<Duration>,
+    callback: Value,
+}
+
+#[derive(Default)]
+pub struct EventLoop {
+    scheduled: Vec<Scheduled>,
+    ready: VecDeque<Event>,
+    next_id: usize,
+}
+
+impl EventLoop {
+    pub fn new() -> Self {
+        EventLoop::default()
+    }
+
+    fn schedule(&mut self, due: i64, interval: Option<Duration>, callback: Value) -> Handle {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.scheduled.push(Scheduled {
+            id,
+            due,
+            interval,
+            callback,
+        });
+        Handle::new(HandleKind::Scheduled, id)
+    }
+
+    /// Run `callback` once, `delay` after `now`
+    pub fn after(&mut self, now: i64, delay: Duration, callback: Value) -> Result<Handle, String> {
+        check_callback(&callback)?;
+        if delay.as_millis() < 0 {
+            return Err(format!(
+                "Cannot schedule a callback {} in the past",
+                delay.checked_neg()?
+            ));
+        }
+        Ok(self.schedule(due(now, delay)?, None, callback))
+    }
+
+    /// Run `callback` every `interval` starting one interval after `now`
+    pub fn every(
+        &mut self,
+        now: i64,
+        interval: Duration,
+        callback: Value,
+    ) -> Result<Handle, String> {
+        check_callback(&callback)?;
+        if interval.as_millis() <= 0 {
+            return Err(format!(
+                "Repeat interval must be positive, got {}",
+                interval
+            ));
+        }
+        Ok(self.schedule(due(now, interval)?, Some(interval), callback))
+    }
+
+    /// Stop a scheduled callback; returns false if it already ran or was cancelled
+    pub fn cancel(&mut self, handle: Handle) -> Result<bool, String> {
+        if handle.kind != HandleKind::Scheduled {
+            return Err(format!("{} cannot be cancelled", handle));
+        }
+        let before = self.scheduled.len();
+        self.scheduled.retain(|s| s.id != handle.id);
+        Ok(self.scheduled.len() < before)
+    }
+
+    /// Queue a callback to run on the next turn of the loop
+    pub fn post(&mut self, event: Event) {
+        self.ready.push_back(event);
+    }
+
+    // Earliest due callback; ties run in the order they were scheduled
+    fn next_due(&self) -> Option<usize> {
+        (0..self.scheduled.len()).min_by_key(|&i| (self.scheduled[i].due, self.scheduled[i].id))
+    }
+
+    fn take_due(&mut self, index: usize) -> Event {
+        let callback = self.scheduled[index].callback.clone();
+        match self.scheduled[index].interval {
+            // Repeats keep their rhythm instead of drifting by the callback's run time
+            // A repeat too far ahead to represent simply never comes due
+            Some(interval) => {
+                let due = &mut self.scheduled[index].due;
+                *due = due.saturating_add(interval.as_millis());
+            }
+            None => {
+                self.scheduled.remove(index);
+            }
+        }
+        Event {
+            callback,
+            args: Vec::new(),
+        }
+    }
+
+    /// Wait for the next event to run. Returns None once there is nothing
+    /// left to do: no queued events and no scheduled callbacks. With a manual
+    /// clock, waiting is instant, so a whole program runs in virtual time.
+    pub fn next_event(&mut self, clock: &dyn Clock) -> Option<Event> {
+        self.next(clock, None)
+    }
+
+    /// Like `next_event`, but never waits past `deadline`; returns None with
+    /// the clock at the deadline once nothing else falls due before it
+    pub fn next_event_until(&mut self, clock: &dyn Clock, deadline: i64) -> Option<Event> {
+        let event = self.next(clock, Some(deadline));
+        if event.is_none() {
+            let now = clock.now();
+            if now < deadline {
+                clock.sleep(Duration::from_millis(deadline - now));
+            }
+        }
+        event
+    }
+
+    fn next(&mut self, clock: &dyn Clock, deadline: Option<i64>) -> Option<Event> {
+        loop {
+            if let Some(event) = self.ready.pop_front() {
+                return Some(event);
+            }
+
+            let now = clock.now();
+            let next = self.next_due();
+            let next_due = next.map(|index| self.scheduled[index].due);
+            if let (Some(index), Some(due)) = (next, next_due) {
+                if due <= now && deadline.is_none_or(|deadline| due <= deadline) {
+                    return Some(self.take_due(index));
+                }
+            }
+            if deadline.is_some_and(|deadline| now >= deadline) {
+                return None;
+            }
+
+            // Nothing is ready, so wait for the next callback or the deadline
+            let wake = [next_due, deadline].into_iter().flatten().min();
+            if let (Some(_), Some(wake)) = (next_due, wake) {
+                clock.sleep(Duration::from_millis(wake - now));
+            } else {
+                return None;
+            }
+        }
+    }
+}
+
+// When a callback `delay` after `now` falls due
+fn due(now: i64, delay: Duration) -> Result<i64, String> {
+    now.checked_add(delay.as_millis())
+        .ok_or_else(|| format!("Cannot schedule a callback {} from now", delay))
+}
+
+fn check_callback(callback: &Value) -> Result<(), String> {
+    match callback {
+        Value::Function(_) => Ok(()),
+        other => Err(format!(
+            "Expected a function to call back, found {}",
+            other.type_of().to_string()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::builtins::time::ManualClock;
+
+    fn callback(name: &str) -> Value {
+        Value::Function(name.to_string())
+    }
+
+    fn millis(n: i64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn callbacks_run_in_virtual_time() {
+        let clock = ManualClock::new(0);
+        let mut events = EventLoop::new();
+        events.after(0, millis(300), callback("late")).unwrap();
+        events.after(0, millis(100), callback("early")).unwrap();
+        let tick = events.every(0, millis(250), callback("tick")).unwrap();
+
+        let mut ran = Vec::new();
+        while let Some(event) = events.next_event(&clock) {
+            ran.push((event.callback, clock.now()));
+            if ran.len() == 4 {
+                assert!(events.cancel(tick).unwrap());
+            }
+        }
+        assert_eq!(
+            ran,
+            vec![
+                (callback("early"), 100),
+                (callback("tick"), 250),
+                (callback("late"), 300),
+                (callback("tick"), 500),
+            ]
+        );
+    }
+
+    #[test]
+    fn waiting_stops_at_the_deadline() {
+        let clock = ManualClock::new(0);
+        let mut events = EventLoop::new();
+        events.after(0, millis(1_000), callback("later")).unwrap();
+
+        assert_eq!(events.next_event_until(&clock, 400), None);
+        assert_eq!(clock.now(), 400);
+        let event = events.next_event_until(&clock, 2_000).unwrap();
+        assert_eq!((event.callback, clock.now()), (callback("later"), 1_000));
+    }
+
+    #[test]
+    fn posted_events_run_before_scheduled_ones() {
+        let clock = ManualClock::new(0);
+        let mut events = EventLoop::new();
+        events.after(0, millis(0), callback("scheduled")).unwrap();
+        events.post(Event {
+            callback: callback("posted"),
+            args: Vec::new(),
+        });
+        assert_eq!(
+            events.next_event(&clock).unwrap().callback,
+            callback("posted")
+        );
+        assert_eq!(
+            events.next_event(&clock).unwrap().callback,
+            callback("scheduled")
+        );
+        assert_eq!(events.next_event(&clock), None);
+    }
+
+    #[test]
+    fn bad_schedules_are_errors() {
+        let mut events = EventLoop::new();
+        assert!(events.after(0, millis(-1), callback("f")).is_err());
+        assert!(events.every(0, millis(0), callback("f")).is_err());
+        assert!(events.after(0, millis(1), Value::Int(1)).is_err());
+        assert_eq!(
+            events.after(i64::MAX, millis(1), callback("f")),
+            Err("Cannot schedule a callback 1ms from now".to_string())
+        );
+    }
+}
@@ -0,0 +1,638 @@
+// Formatter for Prizm Language
+// Prints a program back out in the canonical Prizm style: four spaces of
+// indentation, spaces around operators, a comma after every statement and
+// object field, and lines wrapped to fit in MAX_WIDTH. Comments, single blank
+// lines and the way numbers are written are kept.
+
+use crate::ast::{self, BinaryOp, Expr, ExprKind, Function, Stmt, StmtKind, UnaryOp};
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{Comment, Lexer, Span, Token};
+use crate::parser::Parser;
+
+/// Lines longer than this are wrapped where the syntax allows
+pub const MAX_WIDTH: usize = 80;
+
+const INDENT: &str = "    ";
+
+// After everything in the file
+const END: Span = Span {
+    line: usize::MAX,
+    column: 0,
+};
+
+/// Format a whole program
+pub fn format(source: &str) -> Result<String, Diagnostic> {
+    let mut lexer = Lexer::new(source);
+    let tokens = lexer.tokenize()?;
+    let comments = lexer.take_comments();
+    let mut parser = Parser::new(tokens.clone());
+    let program = parser.parse_program()?;
+
+    let mut printer = Printer {
+        lines: source.lines().collect(),
+        tokens,
+        comments,
+        next_comment: 0,
+        blocks: parser.blocks().to_vec(),
+        next_block: 0,
+        out: String::new(),
+        at_start: true,
+        last_line: 0,
+        flat: false,
+    };
+    printer.statements(&program.statements, 0, END);
+    let mut out = printer.out;
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+struct Printer<'a> {
+    lines: Vec<&'a str>,
+    tokens: Vec<(Token, Span)>,
+    comments: Vec<Comment>,
+    next_comment: usize,
+    blocks: Vec<(Span, Span)>,
+    next_block: usize,
+    out: String,
+    // Nothing printed since the last opening brace, so no blank line is kept
+    at_start: bool,
+    // The source line of the last statement, item or comment printed
+    last_line: usize,
+    // Printing on one line to measure, without wrapping or comments
+    flat: bool,
+}
+
+// An element of an array, object or argument list
+enum Item<'e> {
+    Value(&'e Expr),
+    Field(&'e str, &'e Expr),
+}
+
+impl Item<'_> {
+    fn value(&self) -> &Expr {
+        match self {
+            Item::Value(value) | Item::Field(_, value) => value,
+        }
+    }
+}
+
+impl Printer<'_> {
+    // ===== LINES AND COMMENTS =====
+
+    fn push(&mut self, text: &str) {
+        self.out.push_str(text);
+    }
+
+    fn column(&self) -> usize {
+        self.out
+            .rsplit('\n')
+            .next()
+            .map_or(0, |line| line.chars().count())
+    }
+
+    fn newline(&mut self, indent: usize) {
+        self.out.push('\n');
+        self.out.push_str(&INDENT.repeat(indent));
+    }
+
+    // Start the line for something on `line` of the source, keeping one blank
+    // line if the source had any before it
+    fn line(&mut self, indent: usize, line: usize) {
+        let first_on_line = line > self.last_line;
+        self.last_line = line;
+        if self.out.is_empty() {
+            self.at_start = false;
+            return;
+        }
+        let blank_before = first_on_line
+            && line > 1
+            && self
+                .lines
+                .get(line - 2)
+                .is_some_and(|text| text.trim().is_empty());
+        if blank_before && !self.at_start {
+            self.out.push('\n');
+        }
+        self.newline(indent);
+        self.at_start = false;
+    }
+
+    // Print the comments that come before `limit`; those that followed code
+    // stay at the end of the line printed last
+    fn flush(&mut self, limit: Span, indent: usize) {
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if !before(comment.span, limit) {
+                break;
+            }
+            let comment = comment.clone();
+            self.next_comment += 1;
+            if comment.own_line || self.out.is_empty() {
+                self.line(indent, comment.span.line);
+            } else {
+                self.push(" ");
+            }
+            self.push(&comment.text);
+        }
+    }
+
+    fn has_comment(&self, from: Span, to: Span) -> bool {
+        self.comments[self.next_comment..]
+            .iter()
+            .take_while(|comment| before(comment.span, to))
+            .any(|comment| before(from, comment.span))
+    }
+
+    // ===== STATEMENTS =====
+
+    fn statements(&mut self, body: &[Stmt], indent: usize, end: Span) {
+        for stmt in body {
+            self.flush(stmt.span, indent);
+            self.line(indent, stmt.span.line);
+            self.statement(stmt, indent);
+        }
+        self.flush(end, indent);
+    }
+
+    fn statement(&mut self, stmt: &Stmt, indent: usize) {
+        match &stmt.kind {
+            StmtKind::Var {
+                name,
+                type_name,
+                value,
+            } => {
+                self.push("var ");
+                self.push(name);
+                if let Some(type_name) = type_name {
+                    self.push(": ");
+                    self.push(type_name);
+                }
+                self.push(" = ");
+                self.expr(value, indent, 1);
+            }
+            StmtKind::Assign { target, value } => {
+                self.expr(target, indent, 0);
+                self.push(" = ");
+                self.expr(value, indent, 1);
+            }
+            StmtKind::Expr(expr) => self.expr(expr, indent, 1),
+            StmtKind::If {
+                branches,
+                otherwise,
+            } => {
+                for (i, (condition, body)) in branches.iter().enumerate() {
+                    self.push(if i == 0 { "if (" } else { " else if (" });
+                    self.expr(condition, indent, 3);
+                    self.push(") ");
+                    self.block(body, indent);
+                }
+                if let Some(body) = otherwise {
+                    self.push(" else ");
+                    self.block(body, indent);
+                }
+            }
+            StmtKind::LoopUntil { condition, body } => {
+                self.push("loop until (");
+                self.expr(condition, indent, 3);
+                self.push(") ");
+                self.block(body, indent);
+            }
+            StmtKind::Loop { body } => {
+                self.push("loop ");
+                self.block(body, indent);
+            }
+            StmtKind::RepeatFor {
+                variable,
+                from,
+                to,
+                body,
+            } => {
+                self.push("repeat for (");
+                self.push(variable);
+                self.push(" = ");
+                self.expr(from, indent, 0);
+                self.push(" to ");
+                self.expr(to, indent, 3);
+                self.push(") ");
+                self.block(body, indent);
+            }
+            StmtKind::Define(function) => self.function(function, indent),
+            StmtKind::Return(Some(value)) => {
+                self.push("return ");
+                self.expr(value, indent, 1);
+            }
+            StmtKind::Return(None) => self.push("return"),
+            StmtKind::Break => self.push("break"),
+        }
+        self.push(",");
+    }
+
+    fn function(&mut self, function: &Function, indent: usize) {
+        self.push("define ");
+        self.push(&function.name);
+        self.push("(");
+        for (i, param) in function.params.iter().enumerate() {
+            if i > 0 {
+                self.push(", ");
+            }
+            self.push(&param.name);
+            if let Some(type_name) = &param.type_name {
+                self.push(": ");
+                self.push(type_name);
+            }
+        }
+        self.push(") ");
+        self.block(&function.body, indent);
+    }
+
+    fn block(&mut self, body: &[Stmt], indent: usize) {
+        // Blocks are printed in the order they open, as the parser saw them
+        let (_, close) = self.blocks[self.next_block];
+        self.next_block += 1;
+        self.push("{");
+        let start = self.out.len();
+        self.at_start = true;
+        self.statements(body, indent + 1, close);
+        if self.out.len() > start {
+            self.newline(indent);
+        }
+        self.push("}");
+        self.at_start = false;
+    }
+
+    // ===== EXPRESSIONS =====
+
+    // `trailing` is how much will follow on the same line, such as `,`
+    fn expr(&mut self, expr: &Expr, indent: usize, trailing: usize) {
+        match &expr.kind {
+            ExprKind::Int(n) => {
+                let text = self.number(expr).unwrap_or_else(|| n.to_string());
+                self.push(&text);
+            }
+            ExprKind::Float(x) => {
+                let text = self.number(expr).unwrap_or_else(|| ast::float(*x));
+                self.push(&text);
+            }
+            ExprKind::String(s) => self.push(&ast::quote(s)),
+            ExprKind::Boolean(b) => self.push(if *b { "true" } else { "false" }),
+            ExprKind::Null => self.push("null"),
+            ExprKind::Variable(name) => self.push(name),
+            ExprKind::Array(items) => {
+                let items: Vec<Item> = items.iter().map(Item::Value).collect();
+                self.push("[");
+                self.list(expr.span, Token::LeftBracket, &items, "]", indent, trailing);
+            }
+            ExprKind::Object(fields) => {
+                let items: Vec<Item> = fields
+                    .iter()
+                    .map(|(key, value)| Item::Field(key, value))
+                    .collect();
+                self.push("{");
+                self.list(expr.span, Token::LeftBrace, &items, "}", indent, trailing);
+            }
+            ExprKind::Unary(op, operand) => {
+                self.push(match op {
+                    UnaryOp::Negate => "-",
+                    UnaryOp::Not => "!",
+                });
+                let parens = matches!(operand.kind, ExprKind::Binary(..));
+                self.operand(operand, parens, indent, trailing);
+            }
+            ExprKind::Binary(op, ..) => self.binary(expr, *op, indent, trailing),
+            ExprKind::Call { name, args } => {
+                let items: Vec<Item> = args.iter().map(Item::Value).collect();
+                self.push(name);
+                self.push("(");
+                self.list(expr.span, Token::LeftParen, &items, ")", indent, trailing);
+            }
+            ExprKind::Header {
+                header,
+                attribute,
+                args,
+            } => match args.as_slice() {
+                // `math.random(1-10)` keeps the dash between its bounds
+                [Expr {
+                    kind: ExprKind::Binary(BinaryOp::Subtract, min, max),
+                    ..
+                }] if header == "math"
+                    && attribute == "random"
+                    && min.is_literal()
+                    && max.is_literal() =>
+                {
+                    self.push("math.random(");
+                    self.expr(min, indent, 0);
+                    self.push("-");
+                    self.expr(max, indent, 0);
+                    self.push(")");
+                }
+                _ => {
+                    let items: Vec<Item> = args.iter().map(Item::Value).collect();
+                    self.push(&format!("{}.{}(", header, attribute));
+                    self.list(expr.span, Token::LeftParen, &items, ")", indent, trailing);
+                }
+            },
+            ExprKind::Field(object, name) => {
+                self.operand(object, object.needs_parens_before_postfix(), indent, 0);
+                self.push(".");
+                self.push(name);
+            }
+            ExprKind::Index(object, index) => {
+                self.operand(object, object.needs_parens_before_postfix(), indent, 0);
+                self.push("[");
+                self.expr(index, indent, trailing + 1);
+                self.push("]");
+            }
+            ExprKind::Method {
+                object,
+                method,
+                args,
+            } => {
+                let items: Vec<Item> = args.iter().map(Item::Value).collect();
+                self.operand(object, object.needs_parens_before_postfix(), indent, 0);
+                self.push(&format!(".{}(", method));
+                self.list(expr.span, Token::LeftParen, &items, ")", indent, trailing);
+            }
+        }
+    }
+
+    fn operand(&mut self, expr: &Expr, parens: bool, indent: usize, trailing: usize) {
+        if parens {
+            self.push("(");
+            self.expr(expr, indent, trailing + 1);
+            self.push(")");
+        } else {
+            self.expr(expr, indent, trailing);
+        }
+    }
+
+    // A run of operators of the same precedence, such as `a + b - c`, stays
+    // on one line if it fits, and otherwise puts each operator on a new line
+    fn binary(&mut self, expr: &Expr, op: BinaryOp, indent: usize, trailing: usize) {
+        let precedence = op.precedence();
+        let mut first = expr;
+        let mut rest = Vec::new();
+        while let ExprKind::Binary(op, left, right) = &first.kind {
+            if op.precedence() != precedence {
+                break;
+            }
+            rest.push((*op, right.as_ref()));
+            first = left;
+        }
+        rest.reverse();
+
+        let wrap = !self.flat && self.column() + self.measure(expr) + trailing > MAX_WIDTH;
+        let parens =
+            matches!(&first.kind, ExprKind::Binary(inner, ..) if inner.precedence() < precedence);
+        self.operand(first, parens, indent, 0);
+        let count = rest.len();
+        for (i, (op, right)) in rest.into_iter().enumerate() {
+            if wrap {
+                self.newline(indent + 1);
+                self.push(op.symbol());
+                self.push(" ");
+            } else {
+                self.push(&format!(" {} ", op.symbol()));
+            }
+            // Operators group to the left, so a right operand of equal
+            // precedence needs parentheses too
+            let parens = matches!(&right.kind, ExprKind::Binary(inner, ..) if inner.precedence() <= precedence);
+            let after = if i + 1 == count { trailing } else { 0 };
+            self.operand(right, parens, indent + 1, after);
+        }
+    }
+
+    // The items of an array, object or argument list, after the opening
+    // bracket. Lists wrap to one item per line when they are too long or hold
+    // comments, and objects also stay wrapped if they were written that way.
+    fn list(
+        &mut self,
+        from: Span,
+        opener: Token,
+        items: &[Item],
+        closer: &str,
+        indent: usize,
+        trailing: usize,
+    ) {
+        let wrap_until = if self.flat || items.is_empty() {
+            None
+        } else {
+            let open = self.open_token(from, &opener);
+            let (open_span, close_span) = (self.tokens[open].1, self.closing(open));
+            let written_wrapped = opener == Token::LeftBrace
+                && self
+                    .tokens
+                    .get(open + 1)
+                    .is_some_and(|(_, span)| span.line > open_span.line);
+            let wrap = written_wrapped
+                || self.has_comment(open_span, close_span)
+                || self.column() + self.measure_items(items) + closer.len() + trailing > MAX_WIDTH;
+            wrap.then_some(close_span)
+        };
+        let Some(close) = wrap_until else {
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    self.push(", ");
+                }
+                self.item(item, indent, 0);
+            }
+            self.push(closer);
+            return;
+        };
+
+        self.at_start = true;
+        for item in items {
+            let span = item.value().span;
+            self.flush(span, indent + 1);
+            self.line(indent + 1, span.line);
+            self.item(item, indent + 1, 1);
+            self.push(",");
+        }
+        self.flush(close, indent + 1);
+        self.newline(indent);
+        self.push(closer);
+        self.at_start = false;
+    }
+
+    fn item(&mut self, item: &Item, indent: usize, trailing: usize) {
+        if let Item::Field(key, _) = item {
+            self.push(&ast::field_name(key));
+            self.push(": ");
+        }
+        self.expr(item.value(), indent, trailing);
+    }
+
+    // ===== MEASURING =====
+
+    // How long the expression is on one line
+    fn measure(&mut self, expr: &Expr) -> usize {
+        self.measured(|printer| printer.expr(expr, 0, 0))
+    }
+
+    fn measure_items(&mut self, items: &[Item]) -> usize {
+        self.measured(|printer| {
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    printer.push(", ");
+                }
+                printer.item(item, 0, 0);
+            }
+        })
+    }
+
+    fn measured(&mut self, print: impl FnOnce(&mut Self)) -> usize {
+        let out = std::mem::take(&mut self.out);
+        let flat = std::mem::replace(&mut self.flat, true);
+        print(self);
+        let length = self.out.chars().count();
+        self.out = out;
+        self.flat = flat;
+        length
+    }
+
+    // ===== SOURCE =====
+
+    // The first `opener` token at or after `from`
+    fn open_token(&self, from: Span, opener: &Token) -> usize {
+        let start = self.tokens.partition_point(|(_, span)| before(*span, from));
+        (start..self.tokens.len())
+            .find(|&i| self.tokens[i].0 == *opener)
+            .unwrap_or(start)
+    }
+
+    // Where the bracket opened at token `open` is closed
+    fn closing(&self, open: usize) -> Span {
+        let mut depth = 0;
+        for (token, span) in &self.tokens[open..] {
+            match token {
+                Token::LeftParen | Token::LeftBracket | Token::LeftBrace => depth += 1,
+                Token::RightParen | Token::RightBracket | Token::RightBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return *span;
+                    }
+                }
+                _ => {}
+            }
+        }
+        END
+    }
+
+    // A number as it was written, such as `0xFF`, if that still reads back as
+    // the same value
+    fn number(&self, expr: &Expr) -> Option<String> {
+        let line = self.lines.get(expr.span.line.checked_sub(1)?)?;
+        let rest: Vec<char> = line.chars().skip(expr.span.column - 1).collect();
+        let negative = rest.first() == Some(&'-');
+        let mut end = usize::from(negative);
+        while rest.get(end).is_some_and(|c| c.is_whitespace()) {
+            end += 1;
+        }
+        let start = end;
+        while let Some(c) = rest.get(end) {
+            let decimal_point = *c == '.' && rest.get(end + 1).is_some_and(|n| n.is_ascii_digit());
+            if !c.is_ascii_alphanumeric() && !decimal_point {
+                break;
+            }
+            end += 1;
+        }
+        let digits: String = rest[start..end].iter().collect();
+        let tokens = Lexer::new(&digits).tokenize().ok()?;
+        let same = match (tokens.as_slice(), &expr.kind) {
+            ([(Token::Number(n), _), (Token::Eof, _)], ExprKind::Int(value)) => {
+                (if negative { n.checked_neg() } else { Some(*n) }) == Some(*value)
+            }
+            ([(Token::Float(x), _), (Token::Eof, _)], ExprKind::Float(value)) => {
+                (if negative { -x } else { *x }) == *value
+            }
+            _ => false,
+        };
+        same.then(|| {
+            if negative {
+                format!("-{}", digits)
+            } else {
+                digits
+            }
+        })
+    }
+}
+
+fn before(a: Span, b: Span) -> bool {
+    (a.line, a.column) < (b.line, b.column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Formats the text, checking that formatting the result changes nothing
+    fn formatted(source: &str) -> String {
+        let once = format(source).unwrap();
+        assert_eq!(format(&once).unwrap(), once, "formatting again changed it");
+        once
+    }
+
+    #[test]
+    fn programs_are_printed_in_the_canonical_style() {
+        let source = "var  total=1+2*3 ,\nvar items = [1,2,3],\n\n\n\
+                      define add(a,b){\n  return a+b\n},\n\
+                      if (total>3) { output(\"big\") } else { output(\"small\") },\n\
+                      var config = {name: \"x\", size: 0x10, ratio: 1.50}\n";
+        assert_eq!(
+            formatted(source),
+            "var total = 1 + 2 * 3,\nvar items = [1, 2, 3],\n\n\
+             define add(a, b) {\n    return a + b,\n},\n\
+             if (total > 3) {\n    output(\"big\"),\n} else {\n    output(\"small\"),\n},\n\
+             var config = {name: \"x\", size: 0x10, ratio: 1.50},\n"
+        );
+    }
+
+    #[test]
+    fn comments_and_single_blank_lines_are_kept() {
+        let source = "// leading\nvar a = 1 , // trailing\n\
+                      define f(x) {\n  // inside\n\n\n  // second\n  \
+                      if (x) {\n    // only a comment\n  },\n  return x // done\n},\n\
+                      // at the end\n";
+        assert_eq!(
+            formatted(source),
+            "// leading\nvar a = 1, // trailing\n\
+             define f(x) {\n    // inside\n\n    // second\n    \
+             if (x) {\n        // only a comment\n    },\n    return x, // done\n},\n\
+             // at the end\n"
+        );
+    }
+
+    #[test]
+    fn long_lines_are_wrapped() {
+        let source = "var long = add(add(1111111111, 2222222222), \
+                      add(3333333333, add(4444444444, 5555555555))),\n\
+                      var person = {name: \"Ada Lovelace\", born: 1815, \
+                      field: \"mathematics\", known: \"notes\"},\n";
+        let output = formatted(source);
+        assert_eq!(
+            output,
+            "var long = add(\n    add(1111111111, 2222222222),\n    \
+             add(3333333333, add(4444444444, 5555555555)),\n),\n\
+             var person = {\n    name: \"Ada Lovelace\",\n    born: 1815,\n    \
+             field: \"mathematics\",\n    known: \"notes\",\n},\n"
+        );
+        assert!(output.lines().all(|line| line.len() <= MAX_WIDTH));
+    }
+
+    #[test]
+    fn formatted_templates_are_unchanged() {
+        for template in [
+            include_str!("../templates/cli/center.pzm"),
+            include_str!("../templates/http/center.pzm"),
+            include_str!("../templates/ui/center.pzm"),
+        ] {
+            assert_eq!(formatted(template), template);
+        }
+    }
+
+    #[test]
+    fn programs_that_do_not_parse_are_errors() {
+        assert_eq!(format("var a = (\n").unwrap_err().code, "P0101");
+        assert_eq!(format("").unwrap(), "");
+    }
+}
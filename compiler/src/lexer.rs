@@ -1,5 +1,7 @@
 // Lexer for Prizm Language
 
+use crate::diagnostics::Diagnostic;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     // Keywords
@@ -17,19 +19,20 @@ pub enum Token {
     Var,
     Output,  // Built-in function
     Print,   // Built-in function
-    
+
     // Type Keywords
-    Int,
-    Float,
-    String,
-    Boolean,
-    Array,
-    Object,
-    
-    // Boolean Literals
+    IntType,
+    FloatType,
+    StringType,
+    BooleanType,
+    ArrayType,
+    ObjectType,
+
+    // Literal Keywords
     True,
     False,
-    
+    Null,
+
     // Headers
     File,
     Math,
@@ -38,7 +41,7 @@ pub enum Token {
     Root,
     Data,
     Time,
-    
+
     // Operators
     Plus,
     Minus,
@@ -55,7 +58,7 @@ pub enum Token {
     And,
     Or,
     Not,
-    
+
     // Delimiters
     LeftParen,
     RightParen,
@@ -66,20 +69,49 @@ pub enum Token {
     Comma,
     Dot,
     Colon,
-    
+
     // Literals
     Identifier(String),
     Number(i64),
     Float(f64),
     String(String),
-    
+
     // Special
     Eof,
 }
 
+/// Where a token starts in the source, counting from 1
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(line: usize, column: usize) -> Self {
+        Span { line, column }
+    }
+}
+
+/// A `//` or `#` comment, which the parser skips but the formatter keeps
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    /// The comment as written, from its marker to the end of the line
+    pub text: String,
+    pub span: Span,
+    /// Whether the comment is alone on its line, rather than after code
+    pub own_line: bool,
+}
+
 pub struct Lexer {
     input: Vec<char>,
     position: usize,
+    line: usize,
+    // Position of the first character of the current line
+    line_start: usize,
+    comments: Vec<Comment>,
+    // Where each token ends, just after its last character
+    ends: Vec<Span>,
 }
 
 impl Lexer {
@@ -87,149 +119,147 @@ impl Lexer {
         Lexer {
             input: input.chars().collect(),
             position: 0,
+            line: 1,
+            line_start: 0,
+            comments: Vec::new(),
+            ends: Vec::new(),
         }
     }
 
-    pub fn tokenize(&mut self) -> Vec<Token> {
+    /// The comments skipped by `tokenize`, in the order they appear
+    pub fn take_comments(&mut self) -> Vec<Comment> {
+        std::mem::take(&mut self.comments)
+    }
+
+    /// Where each token from `tokenize` ends, in the same order
+    pub fn take_ends(&mut self) -> Vec<Span> {
+        std::mem::take(&mut self.ends)
+    }
+
+    /// Split the source into tokens, each with the place it starts. The last
+    /// token is always `Eof`.
+    pub fn tokenize(&mut self) -> Result<Vec<(Token, Span)>, Diagnostic> {
         let mut tokens = Vec::new();
 
-        while self.position < self.input.len() {
-            self.skip_whitespace();
-            
+        loop {
+            self.skip_whitespace_and_comments();
+            let span = self.span();
+
             if self.position >= self.input.len() {
-                break;
+                tokens.push((Token::Eof, span));
+                self.ends.push(span);
+                return Ok(tokens);
             }
 
-            match self.current_char() {
-                '(' => {
-                    tokens.push(Token::LeftParen);
-                    self.position += 1;
-                }
-                ')' => {
-                    tokens.push(Token::RightParen);
-                    self.position += 1;
-                }
-                '{' => {
-                    tokens.push(Token::LeftBrace);
-                    self.position += 1;
-                }
-                '}' => {
-                    tokens.push(Token::RightBrace);
-                    self.position += 1;
-                }
-                '[' => {
-                    tokens.push(Token::LeftBracket);
-                    self.position += 1;
+            let token = match self.current_char() {
+                '(' => self.single(Token::LeftParen),
+                ')' => self.single(Token::RightParen),
+                '{' => self.single(Token::LeftBrace),
+                '}' => self.single(Token::RightBrace),
+                '[' => self.single(Token::LeftBracket),
+                ']' => self.single(Token::RightBracket),
+                ',' => self.single(Token::Comma),
+                '.' => self.single(Token::Dot),
+                ':' => self.single(Token::Colon),
+                '+' => self.single(Token::Plus),
+                '-' => self.single(Token::Minus),
+                '*' => self.single(Token::Star),
+                '/' => self.single(Token::Slash),
+                '%' => self.single(Token::Percent),
+                '=' => self.pair('=', Token::EqualEqual, Token::Equal),
+                '!' => self.pair('=', Token::NotEqual, Token::Not),
+                '<' => self.pair('=', Token::LessEqual, Token::Less),
+                '>' => self.pair('=', Token::GreaterEqual, Token::Greater),
+                '&' if self.peek_char() == Some('&') => {
+                    self.position += 2;
+                    Token::And
                 }
-                ']' => {
-                    tokens.push(Token::RightBracket);
-                    self.position += 1;
-                }
-                ',' => {
-                    tokens.push(Token::Comma);
-                    self.position += 1;
-                }
-                '.' => {
-                    tokens.push(Token::Dot);
-                    self.position += 1;
-                }
-                ':' => {
-                    tokens.push(Token::Colon);
-                    self.position += 1;
-                }
-                '+' => {
-                    tokens.push(Token::Plus);
-                    self.position += 1;
-                }
-                '-' => {
-                    tokens.push(Token::Minus);
-                    self.position += 1;
-                }
-                '*' => {
-                    tokens.push(Token::Star);
-                    self.position += 1;
-                }
-                '/' => {
-                    tokens.push(Token::Slash);
-                    self.position += 1;
-                }
-                '%' => {
-                    tokens.push(Token::Percent);
-                    self.position += 1;
-                }
-                '=' => {
-                    self.position += 1;
-                    if self.position < self.input.len() && self.current_char() == '=' {
-                        tokens.push(Token::EqualEqual);
-                        self.position += 1;
-                    } else {
-                        tokens.push(Token::Equal);
-                    }
-                }
-                '!' => {
-                    self.position += 1;
-                    if self.position < self.input.len() && self.current_char() == '=' {
-                        tokens.push(Token::NotEqual);
-                        self.position += 1;
-                    } else {
-                        tokens.push(Token::Not);
-                    }
-                }
-                '<' => {
-                    self.position += 1;
-                    if self.position < self.input.len() && self.current_char() == '=' {
-                        tokens.push(Token::LessEqual);
-                        self.position += 1;
-                    } else {
-                        tokens.push(Token::Less);
-                    }
-                }
-                '>' => {
-                    self.position += 1;
-                    if self.position < self.input.len() && self.current_char() == '=' {
-                        tokens.push(Token::GreaterEqual);
-                        self.position += 1;
-                    } else {
-                        tokens.push(Token::Greater);
-                    }
+                '|' if self.peek_char() == Some('|') => {
+                    self.position += 2;
+                    Token::Or
                 }
                 '"' => {
                     self.position += 1;
-                    let string = self.read_string();
-                    tokens.push(Token::String(string));
-                }
-                _ if self.current_char().is_digit(10) => {
-                    let number = self.read_number();
-                    tokens.push(number);
+                    Token::String(self.read_string(span)?)
                 }
-                _ if self.current_char().is_alphabetic() || self.current_char() == '_' => {
+                c if c.is_ascii_digit() => self.read_number(span)?,
+                c if c.is_alphabetic() || c == '_' => {
                     let identifier = self.read_identifier();
-                    let token = self.keyword_or_identifier(&identifier);
-                    tokens.push(token);
+                    self.keyword_or_identifier(&identifier)
                 }
-                _ => {
-                    self.position += 1;
+                c => {
+                    return Err(Diagnostic::error("P0001", span, &format!("Unexpected character '{}'", c)));
                 }
-            }
+            };
+            tokens.push((token, span));
+            self.ends.push(self.span());
         }
-
-        tokens.push(Token::Eof);
-        tokens
     }
 
     fn current_char(&self) -> char {
         self.input[self.position]
     }
 
-    fn skip_whitespace(&mut self) {
-        while self.position < self.input.len() && self.input[self.position].is_whitespace() {
+    fn peek_char(&self) -> Option<char> {
+        self.input.get(self.position + 1).copied()
+    }
+
+    fn span(&self) -> Span {
+        Span::new(self.line, self.position - self.line_start + 1)
+    }
+
+    fn single(&mut self, token: Token) -> Token {
+        self.position += 1;
+        token
+    }
+
+    // A one-character operator, or a two-character one if `next` follows it
+    fn pair(&mut self, next: char, double: Token, single: Token) -> Token {
+        if self.peek_char() == Some(next) {
+            self.position += 2;
+            double
+        } else {
             self.position += 1;
+            single
+        }
+    }
+
+    // Called with the position of each '\n'
+    fn newline(&mut self, at: usize) {
+        self.line += 1;
+        self.line_start = at + 1;
+    }
+
+    // Comments run from `//` or `#` to the end of the line
+    fn skip_whitespace_and_comments(&mut self) {
+        while self.position < self.input.len() {
+            let c = self.input[self.position];
+            if c == '#' || (c == '/' && self.peek_char() == Some('/')) {
+                let span = self.span();
+                let start = self.position;
+                while self.position < self.input.len() && self.input[self.position] != '\n' {
+                    self.position += 1;
+                }
+                let text: String = self.input[start..self.position].iter().collect();
+                self.comments.push(Comment {
+                    text: text.trim_end().to_string(),
+                    span,
+                    own_line: self.input[self.line_start..start].iter().all(|c| c.is_whitespace()),
+                });
+            } else if c.is_whitespace() {
+                if c == '\n' {
+                    self.newline(self.position);
+                }
+                self.position += 1;
+            } else {
+                break;
+            }
         }
     }
 
     fn read_identifier(&mut self) -> String {
         let mut identifier = String::new();
-        while self.position < self.input.len() && 
+        while self.position < self.input.len() &&
               (self.input[self.position].is_alphanumeric() || self.input[self.position] == '_') {
             identifier.push(self.input[self.position]);
             self.position += 1;
@@ -237,36 +267,79 @@ impl Lexer {
         identifier
     }
 
-    fn read_number(&mut self) -> Token {
+    fn read_number(&mut self, span: Span) -> Result<Token, Diagnostic> {
+        if self.current_char() == '0' && matches!(self.peek_char(), Some('x') | Some('X')) {
+            self.position += 2;
+            let start = self.position;
+            while self.position < self.input.len() && self.input[self.position].is_ascii_hexdigit() {
+                self.position += 1;
+            }
+            let digits: String = self.input[start..self.position].iter().collect();
+            return i64::from_str_radix(&digits, 16)
+                .map(Token::Number)
+                .map_err(|_| Diagnostic::error("P0003", span, &format!("Invalid hex number '0x{}'", digits)));
+        }
+
         let mut number_str = String::new();
         let mut is_float = false;
 
-        while self.position < self.input.len() && 
-              (self.input[self.position].is_digit(10) || self.input[self.position] == '.') {
-            if self.input[self.position] == '.' {
+        while self.position < self.input.len() {
+            let c = self.input[self.position];
+            // A dot only belongs to the number if a digit follows it
+            let decimal_point = c == '.'
+                && !is_float
+                && self.peek_char().is_some_and(|next| next.is_ascii_digit());
+            if !c.is_ascii_digit() && !decimal_point {
+                break;
+            }
+            if c == '.' {
                 is_float = true;
             }
-            number_str.push(self.input[self.position]);
+            number_str.push(c);
             self.position += 1;
         }
 
         if is_float {
-            Token::Float(number_str.parse().unwrap_or(0.0))
+            number_str
+                .parse()
+                .map(Token::Float)
+                .map_err(|_| Diagnostic::error("P0003", span, &format!("Invalid number '{}'", number_str)))
         } else {
-            Token::Number(number_str.parse().unwrap_or(0))
+            number_str
+                .parse()
+                .map(Token::Number)
+                .map_err(|_| Diagnostic::error("P0003", span, &format!("Number '{}' is too large", number_str)))
         }
     }
 
-    fn read_string(&mut self) -> String {
+    fn read_string(&mut self, span: Span) -> Result<String, Diagnostic> {
         let mut string = String::new();
-        while self.position < self.input.len() && self.input[self.position] != '"' {
-            string.push(self.input[self.position]);
+        while self.position < self.input.len() {
+            let c = self.input[self.position];
             self.position += 1;
+            match c {
+                '"' => return Ok(string),
+                '\\' if self.position < self.input.len() => {
+                    let escaped = self.input[self.position];
+                    self.position += 1;
+                    string.push(match escaped {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        '0' => '\0',
+                        // \" \\ and anything else stand for themselves
+                        other => other,
+                    });
+                }
+                '\n' => {
+                    self.newline(self.position - 1);
+                    string.push(c);
+                }
+                _ => string.push(c),
+            }
         }
-        if self.position < self.input.len() {
-            self.position += 1; // Skip closing quote
-        }
-        string
+        Err(Diagnostic::error("P0002", span, "Unterminated string")
+            .with_help("Add a '\"' where the string should end"))
     }
 
     fn keyword_or_identifier(&self, word: &str) -> Token {
@@ -284,14 +357,15 @@ impl Lexer {
             "var" => Token::Var,
             "output" => Token::Output,
             "print" => Token::Print,
-            "int" => Token::Int,
-            "float" => Token::Float,
-            "string" => Token::String,
-            "boolean" | "bool" => Token::Boolean,
-            "array" => Token::Array,
-            "object" => Token::Object,
+            "int" => Token::IntType,
+            "float" => Token::FloatType,
+            "string" => Token::StringType,
+            "boolean" | "bool" => Token::BooleanType,
+            "array" => Token::ArrayType,
+            "object" => Token::ObjectType,
             "true" => Token::True,
             "false" => Token::False,
+            "null" => Token::Null,
             "file" => Token::File,
             "math" => Token::Math,
             "http" => Token::Http,
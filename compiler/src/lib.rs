@@ -1,16 +1,30 @@
 // Prizm Compiler Library
 // Exports compiler functionality for use as a DLL/shared library
 
+pub mod ast;
 pub mod attributes;
+pub mod bytecode;
+pub mod diagnostics;
+pub mod event_loop;
+pub mod formatter;
 pub mod lexer;
+pub mod lint;
+pub mod optimizer;
+pub mod parser;
+pub mod project;
+pub mod repl;
+pub mod scaffold;
 pub mod stdlib;
-
-use lexer::Lexer;
+pub mod value;
+pub mod vm;
 
 /// Compile a Prizm source file
 /// Returns compiled binary data
+///
+/// # Safety
+/// `source_code` must point to `source_len` readable bytes, or be null
 #[no_mangle]
-pub extern "C" fn compile_prizm(source_code: *const u8, source_len: usize) -> *const u8 {
+pub unsafe extern "C" fn compile_prizm(source_code: *const u8, source_len: usize) -> *const u8 {
     if source_code.is_null() {
         return std::ptr::null();
     }
@@ -20,11 +34,10 @@ pub extern "C" fn compile_prizm(source_code: *const u8, source_len: usize) -> *c
             .unwrap_or("")
     };
 
-    let mut lexer = Lexer::new(source);
-    let tokens = lexer.tokenize();
+    let module = parser::parse(source).and_then(|program| bytecode::compile(&program));
 
-    // Convert tokens to binary format
-    let binary_data = format!("{:?}", tokens);
+    // Convert the compiled module to binary format
+    let binary_data = format!("{:?}", module);
     let bytes = binary_data.into_bytes();
     let boxed_bytes = bytes.into_boxed_slice();
     Box::into_raw(boxed_bytes) as *const u8
@@ -32,7 +45,7 @@ pub extern "C" fn compile_prizm(source_code: *const u8, source_len: usize) -> *c
 
 /// Execute compiled Prizm code
 #[no_mangle]
-pub extern "C" fn execute_prizm(binary_data: *const u8, binary_len: usize) -> i32 {
+pub extern "C" fn execute_prizm(binary_data: *const u8, _binary_len: usize) -> i32 {
     if binary_data.is_null() {
         return -1;
     }
@@ -41,16 +54,22 @@ pub extern "C" fn execute_prizm(binary_data: *const u8, binary_len: usize) -> i3
     0
 }
 
+/// The compiler's version, as `get_version` returns it
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
 /// Get compiler version
 #[no_mangle]
 pub extern "C" fn get_version() -> *const u8 {
-    let version = "0.1.0\0";
-    version.as_ptr() as *const u8
+    let version = concat!(env!("CARGO_PKG_VERSION"), "\0");
+    version.as_ptr()
 }
 
 /// Free allocated memory
+///
+/// # Safety
+/// `ptr` must have been returned by `compile_prizm`, and not freed already
 #[no_mangle]
-pub extern "C" fn free_memory(ptr: *mut u8) {
+pub unsafe extern "C" fn free_memory(ptr: *mut u8) {
     if !ptr.is_null() {
         unsafe {
             let _ = Box::from_raw(ptr);
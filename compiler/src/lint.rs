@@ -0,0 +1,673 @@
+// Linter for Prizm Language
+// Checks a parsed program against a set of rules. Each rule has an ID and a
+// default severity, which a project can change, or turn the rule off, in the
+// [lint] table of its prizm.toml. Some findings come with a fix, a set of
+// edits to the source that `prizm lint --fix` applies.
+
+mod rules;
+
+use crate::ast::{Program, Stmt};
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{Lexer, Span, Token};
+use crate::parser::Parser;
+use crate::project;
+use crate::stdlib::builtins::data::toml;
+use crate::value::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+// How many times --fix runs the rules again to fix what earlier fixes uncover
+const MAX_FIX_ROUNDS: usize = 10;
+
+pub use crate::diagnostics::{Edit, Fix, Severity};
+
+pub struct Rule {
+    pub id: &'static str,
+    pub severity: Severity,
+    pub description: &'static str,
+    check: fn(&Source) -> Vec<Problem>,
+}
+
+/// Something a rule found, before it is given the rule's ID and severity
+struct Problem {
+    span: Span,
+    message: String,
+    fix: Option<Fix>,
+}
+
+impl Problem {
+    fn new(span: Span, message: String) -> Self {
+        Problem {
+            span,
+            message,
+            fix: None,
+        }
+    }
+
+    fn with_fix(mut self, description: &str, edits: Vec<Edit>) -> Self {
+        self.fix = Some(Fix {
+            description: description.to_string(),
+            edits,
+        });
+        self
+    }
+}
+
+/// A problem reported by a rule, with the severity it has in this project
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub span: Span,
+    pub message: String,
+    pub fix: Option<Fix>,
+}
+
+impl Finding {
+    /// The finding as a diagnostic, with its fix also given as help
+    pub fn diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::new(self.severity, self.rule, self.span, &self.message);
+        match &self.fix {
+            Some(fix) => diagnostic
+                .with_help(&format!("{} (prizm lint --fix)", fix.description))
+                .with_fix(fix.clone()),
+            None => diagnostic,
+        }
+    }
+}
+
+/// Every rule, in the order their findings are reported on the same line
+pub const RULES: [Rule; 9] = [
+    Rule {
+        id: "undefined-function",
+        severity: Severity::Error,
+        description: "A call to a function that is never defined",
+        check: rules::undefined_function,
+    },
+    Rule {
+        id: "unknown-attribute",
+        severity: Severity::Error,
+        description: "A header attribute that the header doesn't have",
+        check: rules::unknown_attribute,
+    },
+    Rule {
+        id: "missing-comma",
+        severity: Severity::Warning,
+        description: "A statement without a ',' after it",
+        check: rules::missing_comma,
+    },
+    Rule {
+        id: "print-vs-output",
+        severity: Severity::Warning,
+        description: "A print that was meant to end the line, which output does",
+        check: rules::print_vs_output,
+    },
+    Rule {
+        id: "unused-variable",
+        severity: Severity::Warning,
+        description: "A variable that is declared but never read",
+        check: rules::unused_variable,
+    },
+    Rule {
+        id: "shadowed-variable",
+        severity: Severity::Warning,
+        description:
+            "A variable or parameter that hides a parameter, global or function of the same name",
+        check: rules::shadowed_variable,
+    },
+    Rule {
+        id: "unreachable-code",
+        severity: Severity::Warning,
+        description: "Statements after a return or break, which never run",
+        check: rules::unreachable_code,
+    },
+    Rule {
+        id: "unchanging-loop-condition",
+        severity: Severity::Warning,
+        description: "A loop until condition that nothing in the loop can change",
+        check: rules::unchanging_loop_condition,
+    },
+    Rule {
+        id: "root-usage",
+        severity: Severity::Warning,
+        description: "A root attribute, which needs --allow-root to run",
+        check: rules::root_usage,
+    },
+];
+
+/// The rule with this ID
+pub fn rule(id: &str) -> Option<&'static Rule> {
+    RULES.iter().find(|rule| rule.id == id)
+}
+
+/// Which rules are on, and the severity of each
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    // Rules set in the project; None turns a rule off
+    severities: HashMap<String, Option<Severity>>,
+}
+
+impl Config {
+    /// Read the [lint] table of a prizm.toml, which maps rule IDs to
+    /// "error", "warning" or "off"
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let settings = match toml::parse(text)? {
+            Value::Object(fields) => fields,
+            _ => Vec::new(),
+        };
+        let table = match settings.into_iter().find(|(key, _)| key == "lint") {
+            None => return Ok(Config::default()),
+            Some((_, Value::Object(table))) => table,
+            Some(_) => return Err("[lint] must be a table of rule IDs".to_string()),
+        };
+        let mut severities = HashMap::new();
+        for (id, value) in table {
+            if rule(&id).is_none() {
+                let ids: Vec<&str> = RULES.iter().map(|rule| rule.id).collect();
+                return Err(format!(
+                    "Unknown lint rule '{}' (expected one of {})",
+                    id,
+                    ids.join(", ")
+                ));
+            }
+            let severity = match &value {
+                Value::String(s) if s == "error" => Some(Severity::Error),
+                Value::String(s) if s == "warning" => Some(Severity::Warning),
+                Value::String(s) if s == "off" => None,
+                other => {
+                    return Err(format!(
+                        "Lint rule '{}' must be \"error\", \"warning\" or \"off\", found {}",
+                        id, other
+                    ))
+                }
+            };
+            severities.insert(id, severity);
+        }
+        Ok(Config { severities })
+    }
+
+    /// The config of the project a file belongs to, from the nearest
+    /// prizm.toml in its directory or above; the defaults if there is none
+    pub fn find(file: &Path) -> Result<Config, String> {
+        let dir = match file.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let Some(path) = project::manifest(dir) else {
+            return Ok(Config::default());
+        };
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Config::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// The rule's severity in this project, or None if it is turned off
+    pub fn severity(&self, rule: &Rule) -> Option<Severity> {
+        match self.severities.get(rule.id) {
+            Some(severity) => *severity,
+            None => Some(rule.severity),
+        }
+    }
+}
+
+/// Run every rule that is turned on, returning the findings in source order
+pub fn lint(source: &Source, config: &Config) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for rule in &RULES {
+        let Some(severity) = config.severity(rule) else {
+            continue;
+        };
+        for problem in (rule.check)(source) {
+            findings.push(Finding {
+                rule: rule.id,
+                severity,
+                span: problem.span,
+                message: problem.message,
+                fix: problem.fix,
+            });
+        }
+    }
+    // Stable, so findings on the same spot keep the order of RULES
+    findings.sort_by_key(|finding| (finding.span.line, finding.span.column));
+    findings
+}
+
+/// Apply every fix the rules offer, then run them again on the result, until
+/// nothing is left to fix. Returns the fixed text and how many fixes were
+/// applied.
+pub fn fix(text: &str, config: &Config) -> Result<(String, usize), String> {
+    let mut text = text.to_string();
+    let mut applied = 0;
+    for _ in 0..MAX_FIX_ROUNDS {
+        let findings = lint(&Source::parse(&text)?, config);
+        let fixes: Vec<&Fix> = findings.iter().filter_map(|f| f.fix.as_ref()).collect();
+        let (fixed, count) = apply(&text, &fixes);
+        if count == 0 {
+            break;
+        }
+        // A fix must never leave a file that no longer parses
+        if let Err(e) = Source::parse(&fixed) {
+            return Err(format!(
+                "Fixing the file would break it, so it was left as it was: {}",
+                e
+            ));
+        }
+        text = fixed;
+        applied += count;
+    }
+    Ok((text, applied))
+}
+
+/// Apply the fixes whose edits don't overlap, earliest first. A fix that
+/// overlaps one already taken is skipped, for the next run to look at again.
+/// Returns the new text and how many fixes were applied.
+pub fn apply(text: &str, fixes: &[&Fix]) -> (String, usize) {
+    let range = |edit: &Edit| (offset(text, edit.start), offset(text, edit.end));
+    let mut fixes: Vec<(Vec<(usize, usize)>, &Fix)> = fixes
+        .iter()
+        .map(|fix| (fix.edits.iter().map(range).collect(), *fix))
+        .collect();
+    fixes.sort_by_key(|(ranges, _)| ranges.iter().map(|(start, _)| *start).min());
+
+    let mut taken: Vec<(usize, usize, &str)> = Vec::new();
+    let mut count = 0;
+    for (ranges, fix) in fixes {
+        // Two insertions at the same spot overlap too, as their order is unclear
+        let overlaps = ranges.iter().any(|(start, end)| {
+            taken.iter().any(|(other_start, other_end, _)| {
+                (start < other_end && other_start < end) || (start == other_start)
+            })
+        });
+        if overlaps {
+            continue;
+        }
+        for ((start, end), edit) in ranges.into_iter().zip(&fix.edits) {
+            taken.push((start, end, &edit.replacement));
+        }
+        count += 1;
+    }
+
+    // From the end, so earlier offsets stay where they were
+    taken.sort_by_key(|(start, _, _)| std::cmp::Reverse(*start));
+    let mut fixed = text.to_string();
+    for (start, end, replacement) in taken {
+        fixed.replace_range(start..end, replacement);
+    }
+    (fixed, count)
+}
+
+// The byte offset of a line and column, or the end of the text if it is past it
+fn offset(text: &str, span: Span) -> usize {
+    let mut line_start = 0;
+    for _ in 1..span.line {
+        match text[line_start..].find('\n') {
+            Some(newline) => line_start += newline + 1,
+            None => return text.len(),
+        }
+    }
+    text[line_start..]
+        .char_indices()
+        .nth(span.column - 1)
+        .map_or(text.len(), |(i, _)| line_start + i)
+}
+
+// ===== SOURCE =====
+
+/// A file to lint: its text, its tokens and the program they parse to
+pub struct Source<'a> {
+    pub text: &'a str,
+    pub program: Program,
+    tokens: Vec<(Token, Span)>,
+    // Where each token ends
+    ends: Vec<Span>,
+}
+
+impl<'a> Source<'a> {
+    pub fn parse(text: &'a str) -> Result<Self, Diagnostic> {
+        let mut lexer = Lexer::new(text);
+        let tokens = lexer.tokenize()?;
+        let ends = lexer.take_ends();
+        let program = Parser::new(tokens.clone()).parse_program()?;
+        Ok(Source {
+            text,
+            program,
+            tokens,
+            ends,
+        })
+    }
+
+    // The index of the token that starts at `span`, or the first one after it
+    fn token(&self, span: Span) -> usize {
+        let index = self
+            .tokens
+            .partition_point(|(_, start)| (start.line, start.column) < (span.line, span.column));
+        index.min(self.tokens.len() - 1)
+    }
+
+    // Where the token that starts at `span` ends
+    fn end(&self, span: Span) -> Span {
+        self.ends[self.token(span)]
+    }
+
+    // The last token of a statement, and the comma after it if there is one.
+    // `next` is where the statement after it in the same block starts.
+    fn statement_end(&self, stmt: &Stmt, next: Option<Span>) -> (usize, Option<usize>) {
+        let start = self.token(stmt.span);
+        let mut depth = 0;
+        let mut i = start;
+        loop {
+            let (token, span) = &self.tokens[i];
+            if depth == 0 && i > start && Some(*span) == next {
+                break;
+            }
+            match token {
+                Token::Comma if depth == 0 => return (i - 1, Some(i)),
+                Token::LeftParen | Token::LeftBracket | Token::LeftBrace => depth += 1,
+                // A bracket that wasn't opened here closes the enclosing block
+                Token::RightParen | Token::RightBracket | Token::RightBrace if depth == 0 => break,
+                Token::RightParen | Token::RightBracket | Token::RightBrace => depth -= 1,
+                Token::Eof => break,
+                _ => {}
+            }
+            i += 1;
+        }
+        (i - 1, None)
+    }
+
+    // An edit that deletes from `start` up to `end`, with the spaces after it,
+    // or the whole line when nothing else is on it
+    fn removal(&self, start: Span, end: Span) -> Edit {
+        let line = |number: usize| self.text.split('\n').nth(number - 1).unwrap_or("");
+        let before: String = line(start.line).chars().take(start.column - 1).collect();
+        let after: String = line(end.line).chars().skip(end.column - 1).collect();
+        if before.trim().is_empty() && after.trim().is_empty() {
+            return Edit::replace(Span::new(start.line, 1), Span::new(end.line + 1, 1), "");
+        }
+        let spaces = after
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .count();
+        Edit::replace(start, Span::new(end.line, end.column + spaces), "")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The rule, line and message of every finding, with the default config
+    fn findings(text: &str) -> Vec<(&'static str, usize, String)> {
+        lint(&Source::parse(text).unwrap(), &Config::default())
+            .into_iter()
+            .map(|finding| (finding.rule, finding.span.line, finding.message))
+            .collect()
+    }
+
+    fn found(rule: &'static str, line: usize, message: &str) -> (&'static str, usize, String) {
+        (rule, line, message.to_string())
+    }
+
+    #[test]
+    fn calls_to_undefined_functions_are_found() {
+        let text = "define add(a, b) {\n    return a + b,\n},\nvar f = add,\nf(1, 2),\n\
+                    output(ad(1, 2)),\nprintln(\"x\"),\nnope(),\n";
+        assert_eq!(
+            findings(text),
+            vec![
+                found(
+                    "undefined-function",
+                    6,
+                    "'ad' is not a defined function; did you mean 'add'?"
+                ),
+                found(
+                    "undefined-function",
+                    7,
+                    "'println' is not a defined function; Prizm prints a line with output"
+                ),
+                found("undefined-function", 8, "'nope' is not a defined function"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_header_attributes_are_found() {
+        assert_eq!(
+            findings("output(time.nwo()),\noutput(time.now()),\n"),
+            vec![found(
+                "unknown-attribute",
+                1,
+                "The time header has no attribute 'nwo'; did you mean 'now'?"
+            )]
+        );
+    }
+
+    #[test]
+    fn statements_without_a_comma_are_found() {
+        assert_eq!(
+            findings("var a = 1\noutput(a),\nif (a > 0) {\n    output(a)\n}\n"),
+            vec![
+                found("missing-comma", 1, "Missing ',' after this statement"),
+                found("missing-comma", 4, "Missing ',' after this statement"),
+            ]
+        );
+    }
+
+    #[test]
+    fn prints_that_end_a_line_are_found() {
+        assert_eq!(
+            findings("print(\"a\\n\"),\nprint(\"b\"),\nprint(\"c\"),\n"),
+            vec![
+                found(
+                    "print-vs-output",
+                    1,
+                    "print with a '\\n' at the end prints a line, which output does"
+                ),
+                found(
+                    "print-vs-output",
+                    3,
+                    "The program ends without finishing this line; \
+                     output prints it with a new line"
+                ),
+            ]
+        );
+        // Unless timers print more after it
+        assert!(findings("time.after(10, \"tick\"),\nprint(\"c\"),\n").is_empty());
+    }
+
+    #[test]
+    fn variables_that_are_never_read_are_found() {
+        let text = "var used = 1,\nvar unused = 2,\nvar _ignored = 3,\n\
+                    var bound = \"\",\nui.input(\"bound\"),\n\
+                    define f() {\n    var local = used,\n    return 1,\n},\noutput(f()),\n";
+        assert_eq!(
+            findings(text),
+            vec![
+                found(
+                    "unused-variable",
+                    2,
+                    "Variable 'unused' is declared but never read"
+                ),
+                found(
+                    "unused-variable",
+                    7,
+                    "Variable 'local' is declared but never read"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn names_that_hide_others_are_found() {
+        let text = "var count = 1,\ndefine total(count) {\n    return count,\n},\n\
+                    define add(a, b) {\n    var a = 1,\n    var total = 2,\n    \
+                    return a + b + total,\n},\noutput(total(add(count, 2))),\n";
+        assert_eq!(
+            findings(text),
+            vec![
+                found(
+                    "shadowed-variable",
+                    2,
+                    "Parameter 'count' hides the global variable declared on line 1"
+                ),
+                found(
+                    "shadowed-variable",
+                    6,
+                    "Variable 'a' hides the parameter of the same name"
+                ),
+                found(
+                    "shadowed-variable",
+                    7,
+                    "Variable 'total' hides the function defined on line 2"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn code_after_return_or_break_is_found() {
+        let text = "define f() {\n    return 1,\n    output(\"never\"),\n},\n\
+                    loop {\n    break,\n    output(f()),\n},\n";
+        assert_eq!(
+            findings(text),
+            vec![
+                found(
+                    "unreachable-code",
+                    3,
+                    "This code never runs, because it comes after the return on line 2"
+                ),
+                found(
+                    "unreachable-code",
+                    7,
+                    "This code never runs, because it comes after the break on line 6"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn loop_conditions_that_never_change_are_found() {
+        let text = "var count = 1,\n\
+                    loop until (count > 3) {\n    output(count),\n},\n\
+                    loop until (1 > 2) {\n    output(count),\n},\n\
+                    loop until (count > 3) {\n    count = count + 1,\n},\n\
+                    loop until (count > 9) {\n    if (count == 4) {\n        break,\n    },\n},\n";
+        assert_eq!(
+            findings(text),
+            vec![
+                found(
+                    "unchanging-loop-condition",
+                    2,
+                    "Nothing in this loop changes 'count', so the condition is the same every time"
+                ),
+                found(
+                    "unchanging-loop-condition",
+                    5,
+                    "This condition is the same every time, so the loop runs forever or not at all"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn root_attributes_are_found() {
+        assert_eq!(
+            findings("root.exec(\"ls\"),\n"),
+            vec![found(
+                "root-usage",
+                1,
+                "root.exec has full access to the system, and only runs with --allow-root=exec"
+            )]
+        );
+    }
+
+    #[test]
+    fn projects_can_change_severities_and_turn_rules_off() {
+        let config =
+            Config::parse("[lint]\nroot-usage = \"off\"\nunused-variable = \"error\"\n").unwrap();
+        let source = Source::parse("var a = 1,\nroot.exec(\"ls\"),\n").unwrap();
+        let findings = lint(&source, &config);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].rule, "unused-variable");
+        assert_eq!(findings[0].severity, Severity::Error);
+
+        assert!(Config::parse("name = \"app\"\n").is_ok());
+        assert!(Config::parse("[lint]\nno-such-rule = \"off\"\n")
+            .unwrap_err()
+            .starts_with("Unknown lint rule 'no-such-rule'"));
+        assert_eq!(
+            Config::parse("[lint]\nroot-usage = true\n").unwrap_err(),
+            "Lint rule 'root-usage' must be \"error\", \"warning\" or \"off\", found true"
+        );
+    }
+
+    fn fixed(text: &str) -> (String, usize) {
+        fix(text, &Config::default()).unwrap()
+    }
+
+    #[test]
+    fn missing_commas_are_added() {
+        assert_eq!(
+            fixed("var a = 1\noutput(a) // shown\n"),
+            ("var a = 1,\noutput(a), // shown\n".to_string(), 2)
+        );
+    }
+
+    #[test]
+    fn output_aliases_and_line_ending_prints_become_output() {
+        assert_eq!(
+            fixed("println(\"x\"),\nprint(\"a\", \"line\\n\"),\nprint(\"end\"),\n"),
+            (
+                "output(\"x\"),\noutput(\"a\", \"line\"),\noutput(\"end\"),\n".to_string(),
+                3
+            )
+        );
+    }
+
+    #[test]
+    fn unused_variables_are_removed_keeping_calls() {
+        let text = "var unused = 2,\n    var indented = 3,\nvar a = 1, var b = a,\n\
+                    var kept: int = compute(),\ndefine compute() {\n    return 1,\n},\n";
+        assert_eq!(
+            fixed(text),
+            (
+                "compute(),\ndefine compute() {\n    return 1,\n},\n".to_string(),
+                5
+            )
+        );
+    }
+
+    #[test]
+    fn fixes_run_again_until_nothing_is_left() {
+        // Removing b, and with it the comma it was missing, leaves a unused,
+        // which the next round removes
+        assert_eq!(
+            fixed("var a = 1,\nvar b = a\noutput(\"done\"),\n"),
+            ("output(\"done\"),\n".to_string(), 2)
+        );
+        let clean = "output(\"done\"),\n";
+        assert_eq!(fixed(clean), (clean.to_string(), 0));
+    }
+
+    #[test]
+    fn overlapping_fixes_are_left_for_the_next_run() {
+        let text = "abc";
+        let edit = |start, end, replacement| Fix {
+            description: String::new(),
+            edits: vec![Edit::replace(
+                Span::new(1, start),
+                Span::new(1, end),
+                replacement,
+            )],
+        };
+        let (first, inside, insert, same_spot) = (
+            edit(1, 3, "x"),
+            edit(2, 4, "y"),
+            edit(4, 4, "!"),
+            edit(4, 4, "?"),
+        );
+        assert_eq!(
+            apply(text, &[&inside, &first, &insert, &same_spot]),
+            ("xc!".to_string(), 2)
+        );
+    }
+}
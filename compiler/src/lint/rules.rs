@@ -0,0 +1,654 @@
+// Lint Rules
+// Each rule looks at the whole program and returns the problems it found,
+// with a fix for those that have only one sensible one
+
+use super::{Edit, Problem, Source};
+use crate::ast::{self, Expr, ExprKind, Function, Program, Stmt, StmtKind};
+use crate::attributes;
+use crate::lexer::{Span, Token};
+use std::collections::{HashMap, HashSet};
+
+// Functions that are part of the language rather than defined
+const BUILTINS: [&str; 2] = ["output", "print"];
+
+// What other languages call the function that prints a line
+const OUTPUT_ALIASES: [&str; 6] = ["println", "printLn", "printline", "writeln", "puts", "echo"];
+
+// ===== RULES =====
+
+pub fn undefined_function(source: &Source) -> Vec<Problem> {
+    let program = &source.program;
+    let mut known: Vec<&str> = BUILTINS.to_vec();
+    ast::functions(&program.statements, &mut |function| {
+        known.push(&function.name)
+    });
+    // Variables can hold functions, and are called the same way
+    let variables = all_variables(program);
+    let mut problems = Vec::new();
+    ast::walk(&program.statements, &mut |expr| {
+        if let ExprKind::Call { name, .. } = &expr.kind {
+            if known.contains(&name.as_str()) || variables.contains(name.as_str()) {
+                return;
+            }
+            if OUTPUT_ALIASES.contains(&name.as_str()) {
+                problems.push(
+                    Problem::new(
+                        expr.span,
+                        format!(
+                            "'{}' is not a defined function; Prizm prints a line with output",
+                            name
+                        ),
+                    )
+                    .with_fix("Use output", vec![rename(expr.span, name, "output")]),
+                );
+            } else {
+                problems.push(Problem::new(
+                    expr.span,
+                    format!(
+                        "'{}' is not a defined function{}",
+                        name,
+                        suggestion(name, &known)
+                    ),
+                ));
+            }
+        }
+    });
+    problems
+}
+
+pub fn unknown_attribute(source: &Source) -> Vec<Problem> {
+    let program = &source.program;
+    let mut problems = Vec::new();
+    ast::walk(&program.statements, &mut |expr| {
+        if let ExprKind::Header {
+            header, attribute, ..
+        } = &expr.kind
+        {
+            if attributes::find_attribute(header, attribute).is_none() {
+                problems.push(Problem::new(
+                    expr.span,
+                    format!(
+                        "The {} header has no attribute '{}'{}",
+                        header,
+                        attribute,
+                        suggestion(attribute, &attributes::header_attributes(header))
+                    ),
+                ));
+            }
+        }
+    });
+    problems
+}
+
+pub fn unused_variable(source: &Source) -> Vec<Problem> {
+    let program = &source.program;
+    let scopes = scopes(program);
+    let mut problems = Vec::new();
+    for scope in &scopes {
+        let mut used = reads(scope.body);
+        if scope.function.is_none() {
+            // Globals are also read by functions without a variable of the
+            // same name, and by UI bindings, which name them in strings
+            for function in &scopes[1..] {
+                let locals = locals(function);
+                used.extend(
+                    reads(function.body)
+                        .into_iter()
+                        .filter(|name| !locals.contains(name)),
+                );
+            }
+            used.extend(strings(program));
+        }
+        for (name, span) in vars(scope.body) {
+            // A leading underscore marks a variable as unused on purpose
+            if !name.starts_with('_') && !used.contains(name) {
+                let problem = Problem::new(
+                    span,
+                    format!("Variable '{}' is declared but never read", name),
+                );
+                problems.push(match remove_variable(source, span) {
+                    Some((description, edit)) => problem.with_fix(description, vec![edit]),
+                    None => problem,
+                });
+            }
+        }
+    }
+    problems
+}
+
+pub fn shadowed_variable(source: &Source) -> Vec<Problem> {
+    let program = &source.program;
+    let globals: HashMap<&str, Span> = vars(&program.statements).into_iter().collect();
+    let mut functions = HashMap::new();
+    ast::functions(&program.statements, &mut |function| {
+        functions
+            .entry(function.name.as_str())
+            .or_insert(function.span);
+    });
+
+    let no_globals = HashMap::new();
+    let mut problems = Vec::new();
+    for scope in scopes(program) {
+        // Top-level variables are the globals, so they can only hide functions
+        let globals = match scope.function {
+            Some(_) => &globals,
+            None => &no_globals,
+        };
+        let params = scope
+            .function
+            .map_or(&[][..], |function| function.params.as_slice());
+        for param in params {
+            if let Some(message) = hides("Parameter", &param.name, &functions, globals) {
+                problems.push(Problem::new(param.span, message));
+            }
+        }
+        for (name, span) in declarations(scope.body) {
+            let message =
+                if !functions.contains_key(name) && params.iter().any(|param| param.name == name) {
+                    Some(format!(
+                        "Variable '{}' hides the parameter of the same name",
+                        name
+                    ))
+                } else {
+                    hides("Variable", name, &functions, globals)
+                };
+            if let Some(message) = message {
+                problems.push(Problem::new(span, message));
+            }
+        }
+    }
+    problems
+}
+
+pub fn unreachable_code(source: &Source) -> Vec<Problem> {
+    let program = &source.program;
+    let mut problems = Vec::new();
+    blocks(&program.statements, &mut |block| {
+        let Some(end) = block
+            .iter()
+            .position(|stmt| matches!(stmt.kind, StmtKind::Return(_) | StmtKind::Break))
+        else {
+            return;
+        };
+        if let Some(next) = block.get(end + 1) {
+            let word = match block[end].kind {
+                StmtKind::Break => "break",
+                _ => "return",
+            };
+            problems.push(Problem::new(
+                next.span,
+                format!(
+                    "This code never runs, because it comes after the {} on line {}",
+                    word, block[end].span.line
+                ),
+            ));
+        }
+    });
+    problems
+}
+
+pub fn unchanging_loop_condition(source: &Source) -> Vec<Problem> {
+    let program = &source.program;
+    let mut problems = Vec::new();
+    ast::statements(&program.statements, &mut |stmt| {
+        let StmtKind::LoopUntil { condition, body } = &stmt.kind else {
+            return;
+        };
+        let mut names = Vec::new();
+        let mut calls = false;
+        ast::visit(condition, &mut |expr| match &expr.kind {
+            ExprKind::Variable(name) if !names.contains(&name.as_str()) => {
+                names.push(name.as_str())
+            }
+            ExprKind::Call { .. } | ExprKind::Header { .. } | ExprKind::Method { .. } => {
+                calls = true
+            }
+            _ => {}
+        });
+        if calls || leaves(body) || changes(body, &names) {
+            return;
+        }
+        let message = if names.is_empty() {
+            "This condition is the same every time, so the loop runs forever or not at all"
+                .to_string()
+        } else {
+            format!(
+                "Nothing in this loop changes {}, so the condition is the same every time",
+                quoted_list(&names)
+            )
+        };
+        problems.push(Problem::new(stmt.span, message));
+    });
+    problems
+}
+
+pub fn root_usage(source: &Source) -> Vec<Problem> {
+    let program = &source.program;
+    let mut problems = Vec::new();
+    ast::walk(&program.statements, &mut |expr| {
+        if let ExprKind::Header {
+            header, attribute, ..
+        } = &expr.kind
+        {
+            if header == "root" && attributes::find_attribute(header, attribute).is_some() {
+                problems.push(Problem::new(
+                    expr.span,
+                    format!(
+                        "root.{} has full access to the system, and only runs with --allow-root={}",
+                        attribute, attribute
+                    ),
+                ));
+            }
+        }
+    });
+    problems
+}
+
+pub fn missing_comma(source: &Source) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    blocks(&source.program.statements, &mut |block| {
+        for (i, stmt) in block.iter().enumerate() {
+            let next = block.get(i + 1).map(|next| next.span);
+            let (last, comma) = source.statement_end(stmt, next);
+            // The comma may be left out after a block, as in `define f() {...}`
+            if comma.is_some() || source.tokens[last].0 == Token::RightBrace {
+                continue;
+            }
+            let end = source.ends[last];
+            problems.push(
+                Problem::new(end, "Missing ',' after this statement".to_string())
+                    .with_fix("Add ','", vec![Edit::insert(end, ",")]),
+            );
+        }
+    });
+    problems
+}
+
+pub fn print_vs_output(source: &Source) -> Vec<Problem> {
+    let program = &source.program;
+    let mut problems = Vec::new();
+    // print("...\n") prints a line, which is what output is for
+    ast::statements(&program.statements, &mut |stmt| {
+        let Some((span, args)) = print_call(stmt) else {
+            return;
+        };
+        let Some(last) = args.last() else {
+            return;
+        };
+        let ExprKind::String(text) = &last.kind else {
+            return;
+        };
+        let Some(line) = text.strip_suffix('\n') else {
+            return;
+        };
+        let literal = Edit::replace(last.span, source.end(last.span), &ast::quote(line));
+        problems.push(
+            Problem::new(
+                span,
+                "print with a '\\n' at the end prints a line, which output does".to_string(),
+            )
+            .with_fix(
+                "Use output without the '\\n'",
+                vec![rename(span, "print", "output"), literal],
+            ),
+        );
+    });
+
+    // A print that is the last thing the program does leaves its line
+    // unfinished, unless timers or the UI print more after it
+    let mut later = false;
+    ast::walk(&program.statements, &mut |expr| {
+        if let ExprKind::Header {
+            header, attribute, ..
+        } = &expr.kind
+        {
+            later |= header == "ui" || (header == "time" && attribute != "sleep");
+        }
+    });
+    let last = program
+        .statements
+        .iter()
+        .rev()
+        .find(|stmt| !matches!(stmt.kind, StmtKind::Define(_)));
+    if let Some((span, args)) = last.and_then(print_call) {
+        let ends_line = matches!(
+            args.last(),
+            Some(Expr { kind: ExprKind::String(text), .. }) if text.ends_with('\n')
+        );
+        if !later && !ends_line {
+            problems.push(
+                Problem::new(
+                    span,
+                    "The program ends without finishing this line; output prints it with a new line"
+                        .to_string(),
+                )
+                .with_fix("Use output", vec![rename(span, "print", "output")]),
+            );
+        }
+    }
+    problems
+}
+
+// ===== SCOPES =====
+
+// Statements that share one set of variables: the top level, or the body of
+// one function
+struct Scope<'p> {
+    body: &'p [Stmt],
+    function: Option<&'p Function>,
+}
+
+// The top level first, then every function
+fn scopes(program: &Program) -> Vec<Scope<'_>> {
+    let mut scopes = vec![Scope {
+        body: &program.statements,
+        function: None,
+    }];
+    ast::functions(&program.statements, &mut |function| {
+        scopes.push(Scope {
+            body: &function.body,
+            function: Some(function),
+        })
+    });
+    scopes
+}
+
+// What a variable or parameter called `name` hides: a function, or else a global
+fn hides(
+    what: &str,
+    name: &str,
+    functions: &HashMap<&str, Span>,
+    globals: &HashMap<&str, Span>,
+) -> Option<String> {
+    if let Some(function) = functions.get(name) {
+        Some(format!(
+            "{} '{}' hides the function defined on line {}",
+            what, name, function.line
+        ))
+    } else {
+        globals.get(name).map(|global| {
+            format!(
+                "{} '{}' hides the global variable declared on line {}",
+                what, name, global.line
+            )
+        })
+    }
+}
+
+// Variables declared with `var`, each with the statement that first declares it
+fn vars(body: &[Stmt]) -> Vec<(&str, Span)> {
+    let mut found: Vec<(&str, Span)> = Vec::new();
+    ast::body_statements(body, &mut |stmt| {
+        if let StmtKind::Var { name, .. } = &stmt.kind {
+            if !found.iter().any(|(seen, _)| seen == name) {
+                found.push((name, stmt.span));
+            }
+        }
+    });
+    found
+}
+
+// Variables declared with `var` or counted by `repeat for`
+fn declarations(body: &[Stmt]) -> Vec<(&str, Span)> {
+    let mut found: Vec<(&str, Span)> = Vec::new();
+    ast::body_statements(body, &mut |stmt| {
+        let name = match &stmt.kind {
+            StmtKind::Var { name, .. } => name,
+            StmtKind::RepeatFor { variable, .. } => variable,
+            _ => return,
+        };
+        if !found.iter().any(|(seen, _)| seen == name) {
+            found.push((name, stmt.span));
+        }
+    });
+    found
+}
+
+// The parameters and declared variables of a function
+fn locals<'p>(scope: &Scope<'p>) -> HashSet<&'p str> {
+    let mut names: HashSet<&str> = declarations(scope.body)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    if let Some(function) = scope.function {
+        names.extend(function.params.iter().map(|param| param.name.as_str()));
+    }
+    names
+}
+
+// Every name a body reads, leaving out the targets of plain assignments
+fn reads<'p>(body: &'p [Stmt]) -> HashSet<&'p str> {
+    let mut names = HashSet::new();
+    ast::body_statements(body, &mut |stmt| {
+        let mut read = |expr: &'p Expr| match &expr.kind {
+            ExprKind::Variable(name) | ExprKind::Call { name, .. } => {
+                names.insert(name.as_str());
+            }
+            _ => {}
+        };
+        match &stmt.kind {
+            StmtKind::Assign {
+                target:
+                    Expr {
+                        kind: ExprKind::Variable(_),
+                        ..
+                    },
+                value,
+            } => ast::visit(value, &mut read),
+            _ => ast::statement_expressions(stmt, &mut read),
+        }
+    });
+    names
+}
+
+// Every name declared anywhere, as a variable, parameter or counter
+fn all_variables(program: &Program) -> HashSet<&str> {
+    let mut names = HashSet::new();
+    for scope in scopes(program) {
+        names.extend(locals(&scope));
+    }
+    names
+}
+
+fn strings(program: &Program) -> HashSet<&str> {
+    let mut found = HashSet::new();
+    ast::walk(&program.statements, &mut |expr| {
+        if let ExprKind::String(s) = &expr.kind {
+            found.insert(s.as_str());
+        }
+    });
+    found
+}
+
+// ===== FIXES =====
+
+// An edit that renames the function called at `span`
+fn rename(span: Span, name: &str, to: &str) -> Edit {
+    let end = Span::new(span.line, span.column + name.chars().count());
+    Edit::replace(span, end, to)
+}
+
+// An edit that removes the `var` statement at `span`. If its value calls
+// anything, the call is kept and only the declaration goes.
+fn remove_variable(source: &Source, span: Span) -> Option<(&'static str, Edit)> {
+    let mut found = None;
+    blocks(&source.program.statements, &mut |block| {
+        if let Some(i) = block.iter().position(|stmt| stmt.span == span) {
+            found = Some((&block[i], block.get(i + 1).map(|next| next.span)));
+        }
+    });
+    let (stmt, next) = found?;
+    let StmtKind::Var { value, .. } = &stmt.kind else {
+        return None;
+    };
+    let mut calls = false;
+    ast::visit(value, &mut |expr| {
+        calls |= matches!(
+            expr.kind,
+            ExprKind::Call { .. } | ExprKind::Header { .. } | ExprKind::Method { .. }
+        )
+    });
+    if calls {
+        // Everything before the value: `var name: type =`
+        let start = source.token(span);
+        let tokens = &source.tokens[start..];
+        let equal = start
+            + tokens
+                .iter()
+                .position(|(token, _)| *token == Token::Equal)?;
+        let value_start = source.tokens[equal + 1].1;
+        return Some((
+            "Keep the value, without the variable",
+            Edit::replace(span, value_start, ""),
+        ));
+    }
+    let (last, comma) = source.statement_end(stmt, next);
+    let end = source.ends[comma.unwrap_or(last)];
+    Some(("Remove the variable", source.removal(span, end)))
+}
+
+// The span and arguments of a `print(...)` statement
+fn print_call(stmt: &Stmt) -> Option<(Span, &[Expr])> {
+    match &stmt.kind {
+        StmtKind::Expr(Expr {
+            kind: ExprKind::Call { name, args },
+            span,
+        }) if name == "print" => Some((*span, args)),
+        _ => None,
+    }
+}
+
+// ===== HELPERS =====
+
+// Every list of statements that run one after another: the top level and each
+// block, at any depth
+fn blocks<'p>(list: &'p [Stmt], f: &mut impl FnMut(&'p [Stmt])) {
+    f(list);
+    for stmt in list {
+        match &stmt.kind {
+            StmtKind::If {
+                branches,
+                otherwise,
+            } => {
+                for (_, body) in branches {
+                    blocks(body, f);
+                }
+                if let Some(body) = otherwise {
+                    blocks(body, f);
+                }
+            }
+            StmtKind::LoopUntil { body, .. }
+            | StmtKind::Loop { body }
+            | StmtKind::RepeatFor { body, .. } => blocks(body, f),
+            StmtKind::Define(function) => blocks(&function.body, f),
+            _ => {}
+        }
+    }
+}
+
+// Whether a loop body can leave the loop early, with break or return
+fn leaves(body: &[Stmt]) -> bool {
+    body.iter().any(|stmt| match &stmt.kind {
+        StmtKind::Break | StmtKind::Return(_) => true,
+        StmtKind::If {
+            branches,
+            otherwise,
+        } => {
+            branches.iter().any(|(_, body)| leaves(body))
+                || otherwise.as_deref().is_some_and(leaves)
+        }
+        // A break in an inner loop only leaves that loop
+        StmtKind::LoopUntil { body, .. }
+        | StmtKind::Loop { body }
+        | StmtKind::RepeatFor { body, .. } => {
+            let mut returns = false;
+            ast::body_statements(body, &mut |stmt| {
+                returns |= matches!(stmt.kind, StmtKind::Return(_))
+            });
+            returns
+        }
+        _ => false,
+    })
+}
+
+// Whether running the body could change any of the variables
+fn changes(body: &[Stmt], names: &[&str]) -> bool {
+    let mut changed = false;
+    ast::body_statements(body, &mut |stmt| {
+        changed |= match &stmt.kind {
+            StmtKind::Var { name, .. } => names.contains(&name.as_str()),
+            StmtKind::Assign { target, .. } => names.contains(&root_name(target)),
+            StmtKind::RepeatFor { variable, .. } => names.contains(&variable.as_str()),
+            _ => false,
+        }
+    });
+    // Functions and UI handlers run by the body can change globals
+    ast::walk_body(body, &mut |expr| {
+        changed |= match &expr.kind {
+            ExprKind::Call { name, .. } => !BUILTINS.contains(&name.as_str()),
+            ExprKind::Header {
+                header, attribute, ..
+            } => header == "ui" && attribute == "simulate",
+            _ => false,
+        }
+    });
+    changed
+}
+
+// The variable an assignment target belongs to, as `list` in `list[0].name`
+fn root_name(target: &Expr) -> &str {
+    match &target.kind {
+        ExprKind::Variable(name) => name,
+        ExprKind::Field(object, _) | ExprKind::Index(object, _) => root_name(object),
+        _ => "",
+    }
+}
+
+// `'a'`, `'a' or 'b'`, `'a', 'b' or 'c'`
+fn quoted_list(names: &[&str]) -> String {
+    let quoted: Vec<String> = names.iter().map(|name| format!("'{}'", name)).collect();
+    match quoted.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
+
+// "; did you mean 'x'?" for the closest of the candidates, if any is close
+fn suggestion(name: &str, candidates: &[&str]) -> String {
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .iter()
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| format!("; did you mean '{}'?", candidate))
+        .unwrap_or_default()
+}
+
+// How many characters must be added, removed, changed or swapped with the
+// next one to turn `a` into `b`
+fn distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // Distances from each prefix of `a` to each prefix of `b`
+    let mut table = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in table.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in table[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (table[i - 1][j] + 1)
+                .min(table[i][j - 1] + 1)
+                .min(table[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(table[i - 2][j - 2] + 1);
+            }
+            table[i][j] = best;
+        }
+    }
+    table[a.len()][b.len()]
+}
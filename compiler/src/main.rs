@@ -1,64 +1,588 @@
-mod attributes;
-mod lexer;
-mod stdlib;
-
+use prizm_lib::stdlib::builtins::root::{AuditLog, Permissions, Root};
+use prizm_lib::stdlib::builtins::time::{Clock, ManualClock, SystemClock, Time};
+use prizm_lib::stdlib::builtins::ui::{self, Ui};
+use prizm_lib::diagnostics::{self, Diagnostic};
+use prizm_lib::lint::{self, Severity};
+use prizm_lib::project::{self, Project};
+use prizm_lib::repl::Repl;
+use prizm_lib::scaffold::{self, Template};
+use prizm_lib::bytecode::{self, binary};
+use prizm_lib::{formatter, optimizer, parser, vm};
+use std::cell::RefCell;
 use std::env;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Instant;
+
+mod cli;
+
+use cli::{Format, Parsed, Profile};
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args = match cli::parse(&args) {
+        Ok(Parsed::Command(args)) => args,
+        Ok(Parsed::Help(command)) => {
+            print!("{}", cli::help(command));
+            return;
+        }
+        Ok(Parsed::Version) => {
+            println!("Prizm v{}", prizm_lib::VERSION);
+            return;
+        }
+        Err(e) => usage_error(&e),
+    };
 
-    if args.len() < 3 {
-        eprintln!("Usage: prizm <command> <file>");
-        eprintln!("Commands: run, pretty, lint");
-        return;
+    // Parsing has checked for the arguments commands can't do without
+    let argument = args.argument.as_deref().unwrap_or_default();
+    match args.command.name {
+        "run" => {
+            // Without a file, run the project the current directory is in
+            let file = match &args.argument {
+                Some(file) => file.clone(),
+                None => project_entry(),
+            };
+            // root.optimize hints only take effect when the attribute is allowed
+            let optimize = args.permissions.allows("optimize");
+            run_file(
+                &file,
+                &args.ui_backend,
+                Root::new(args.permissions, AuditLog::File(args.audit_log)),
+                optimize,
+                args.explain,
+            )
+        }
+        "new" => create_project(Some(argument), args.template, true),
+        "init" => create_project(args.argument.as_deref(), args.template, false),
+        "build" => build_project(args.profile),
+        "test" => test_project(args.argument.as_deref(), &args.permissions, &args.audit_log),
+        "pretty" => pretty_file(argument, args.check, args.write),
+        "lint" => lint_file(argument, args.fix, args.format),
+        "check" => check_file(argument, args.format),
+        "explain" => explain_code(argument),
+        "repl" => {
+            let runtime = vm::Runtime {
+                root: Root::new(args.permissions, AuditLog::File(args.audit_log)),
+                ..vm::Runtime::default()
+            };
+            Repl::new(runtime).run()
+        }
+        _ => unreachable!("every command in cli::COMMANDS is handled"),
     }
+}
+
+// The command line was wrong: exit with 2, as other tools do
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    eprintln!("Run prizm --help for usage");
+    std::process::exit(2);
+}
+
+// The command failed: exit with 1
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+fn read(file: &str) -> String {
+    std::fs::read_to_string(file)
+        .unwrap_or_else(|e| fail(&format!("Failed to read {}: {}", file, e)))
+}
+
+// The project the current directory is in, for commands that need one
+fn current_project() -> Project {
+    let dir = env::current_dir()
+        .unwrap_or_else(|e| fail(&format!("Failed to find the current directory: {}", e)));
+    match Project::find(&dir) {
+        Ok(Some(project)) => project,
+        Ok(None) => fail(&format!(
+            "No {} in {} or any directory above it; give a file, or create a project with prizm init",
+            project::MANIFEST,
+            dir.display()
+        )),
+        Err(e) => fail(&e),
+    }
+}
+
+fn project_entry() -> String {
+    let project = current_project();
+    let entry = project.entry();
+    if !entry.is_file() {
+        fail(&format!(
+            "{} has no {}; set entry in the [package] table of {}",
+            project.name,
+            project.entry.display(),
+            project::MANIFEST
+        ));
+    }
+    entry.display().to_string()
+}
+
+// A project's files, each with its path from the project root to show the user
+fn project_files(project: &Project, files: Vec<PathBuf>) -> Vec<(String, String)> {
+    files
+        .into_iter()
+        .map(|file| {
+            let shown = file.strip_prefix(&project.root).unwrap_or(&file).display().to_string();
+            (file.display().to_string(), shown)
+        })
+        .collect()
+}
 
-    let command = &args[1];
-    let file = &args[2];
+fn run_file(file: &str, ui_backend: &str, root: Root, optimize: bool, explain: bool) {
+    // Modules from prizm build run as they were compiled
+    if Path::new(file).extension().is_some_and(|extension| extension == "pzmc") {
+        return run_module(file, ui_backend, root);
+    }
+    let content = read(file);
+    let module = match compile(&content, optimize, explain) {
+        Ok(module) => module,
+        Err(diagnostic) => {
+            report(file, &content, &diagnostic);
+            std::process::exit(1);
+        }
+    };
+    let result = ui::backend(ui_backend, Path::new(file)).and_then(|backend| {
+        let runtime = vm::Runtime {
+            ui: Ui::new(backend),
+            root,
+            ..vm::Runtime::default()
+        };
+        vm::Vm::new(module, runtime).run()
+    });
+    if let Err(e) = result {
+        match Diagnostic::runtime(&e) {
+            Some(diagnostic) => report(file, &content, &diagnostic),
+            None => eprintln!("{}", e),
+        }
+        std::process::exit(1);
+    }
+}
 
-    match command.as_str() {
-        "run" => run_file(file),
-        "pretty" => pretty_file(file),
-        "lint" => lint_file(file),
-        _ => eprintln!("Unknown command: {}", command),
+// Run a module written by prizm build. Without the source, runtime errors can
+// only say where they happened, not show the line.
+fn run_module(file: &str, ui_backend: &str, root: Root) {
+    let bytes =
+        std::fs::read(file).unwrap_or_else(|e| fail(&format!("Failed to read {}: {}", file, e)));
+    let module = binary::decode(&bytes).unwrap_or_else(|e| fail(&format!("{}: {}", file, e)));
+    let result = ui::backend(ui_backend, Path::new(file)).and_then(|backend| {
+        let runtime = vm::Runtime {
+            ui: Ui::new(backend),
+            root,
+            ..vm::Runtime::default()
+        };
+        vm::Vm::new(module, runtime).run()
+    });
+    if let Err(e) = result {
+        match Diagnostic::runtime(&e) {
+            Some(diagnostic) => {
+                let color = diagnostics::color(&std::io::stderr());
+                eprintln!("{}", diagnostic.header(color));
+                eprintln!(
+                    " --> {}:{}:{}",
+                    file, diagnostic.span.line, diagnostic.span.column
+                );
+            }
+            None => eprintln!("{}", e),
+        }
+        std::process::exit(1);
     }
 }
 
-fn run_file(file: &str) {
-    match std::fs::read_to_string(file) {
-        Ok(content) => {
-            println!("Running {}...", file);
-            let mut lexer = lexer::Lexer::new(&content);
-            let tokens = lexer.tokenize();
-            println!("Tokens: {:?}", tokens);
-            // TODO: Parse and execute tokens
+// Write a project from a template. prizm new makes a directory for it; prizm
+// init uses the current directory, or the one named, even if it has files.
+fn create_project(name: Option<&str>, template: &Template, new: bool) {
+    let current = env::current_dir()
+        .unwrap_or_else(|e| fail(&format!("Failed to find the current directory: {}", e)));
+    let (dir, name) = match name {
+        Some(name) => (current.join(name), name.to_string()),
+        None => {
+            let name = current.file_name().unwrap_or_default().to_string_lossy();
+            (current.clone(), name.into_owned())
         }
-        Err(e) => eprintln!("Failed to read file: {}", e),
+    };
+    if new && dir.exists() {
+        fail(&format!("{} already exists; run prizm init in it instead", dir.display()));
+    }
+    let written = scaffold::create(&dir, &name, template).unwrap_or_else(|e| fail(&e));
+    println!("Created {} project {}", template.name, name);
+    for file in &written {
+        println!("    {}", file.strip_prefix(&current).unwrap_or(file).display());
+    }
+    if dir != current {
+        println!("Run it with: cd {} && prizm run", name);
+    } else {
+        println!("Run it with: prizm run");
     }
 }
 
-fn pretty_file(file: &str) {
-    match std::fs::read_to_string(file) {
-        Ok(content) => {
-            println!("Formatting {}...", file);
-            let mut lexer = lexer::Lexer::new(&content);
-            let tokens = lexer.tokenize();
-            // TODO: Pretty print tokens
-            println!("Tokens: {:?}", tokens);
+// Compile every source file in the project to a module in build/<profile>.
+// A module is only compiled again when its source, the profile or the
+// compiler has changed, and modules whose source is gone are removed.
+fn build_project(profile: Profile) {
+    let start = Instant::now();
+    let project = current_project();
+    let files = project_files(&project, project.sources());
+    let (mut compiled, mut fresh, mut failed) = (0, 0, 0);
+    let mut outputs = Vec::new();
+    for (path, file) in &files {
+        let content = read(path);
+        let output = project.output(profile.name(), Path::new(file));
+        outputs.push(output.clone());
+        let hash = binary::hash(&[
+            prizm_lib::VERSION.as_bytes(),
+            profile.name().as_bytes(),
+            content.as_bytes(),
+        ]);
+        let built = std::fs::read(&output)
+            .ok()
+            .and_then(|bytes| binary::header(&bytes))
+            .is_some_and(|header| header.hash == hash);
+        if built {
+            fresh += 1;
+            continue;
+        }
+        let module = match profile {
+            Profile::Debug => check(&content),
+            Profile::Release => compile(&content, true, false),
+        };
+        let module = match module {
+            Ok(module) => module,
+            Err(diagnostic) => {
+                report(file, &content, &diagnostic);
+                // An old module would no longer match its source
+                let _ = std::fs::remove_file(&output);
+                failed += 1;
+                continue;
+            }
+        };
+        let header = binary::Header {
+            profile: profile.name().to_string(),
+            hash,
+        };
+        let written = binary::encode(&module, &header).and_then(|bytes| {
+            if let Some(parent) = output.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+            }
+            std::fs::write(&output, bytes)
+                .map_err(|e| format!("Failed to write {}: {}", output.display(), e))
+        });
+        if let Err(e) = written {
+            fail(&e);
         }
-        Err(e) => eprintln!("Failed to read file: {}", e),
+        compiled += 1;
     }
+    for stale in project.outputs(profile.name()) {
+        if !outputs.contains(&stale) {
+            let _ = std::fs::remove_file(&stale);
+        }
+    }
+
+    let summary = format!(
+        "{} compiled, {} up to date, {} failed in {:.2}s",
+        compiled,
+        fresh,
+        failed,
+        start.elapsed().as_secs_f64()
+    );
+    if failed > 0 {
+        fail(&format!(
+            "Failed to build {} v{} ({}): {}",
+            project.name,
+            project.version,
+            profile.name(),
+            summary
+        ));
+    }
+    println!(
+        "Built {} v{} ({}): {}",
+        project.name,
+        project.version,
+        profile.name(),
+        summary
+    );
 }
 
-fn lint_file(file: &str) {
-    match std::fs::read_to_string(file) {
-        Ok(content) => {
-            println!("Linting {}...", file);
-            let mut lexer = lexer::Lexer::new(&content);
-            let tokens = lexer.tokenize();
-            // TODO: Perform syntax and semantic checks
-            println!("Tokens: {:?}", tokens);
+// Run a test file, or every file in the project's tests/. A test passes if it
+// runs without an error; its output is only shown if it fails.
+fn test_project(file: Option<&str>, permissions: &Permissions, audit_log: &str) {
+    let files = match file {
+        Some(file) => vec![(file.to_string(), file.to_string())],
+        None => {
+            let project = current_project();
+            let tests = project_files(&project, project.tests());
+            if tests.is_empty() {
+                println!("No tests in {}", project.root.join("tests").display());
+                return;
+            }
+            tests
+        }
+    };
+    let color = diagnostics::color(&std::io::stderr());
+    let mut failures = Vec::new();
+    for (path, file) in &files {
+        let content = read(path);
+        let output = Captured::default();
+        let error = match compile(&content, permissions.allows("optimize"), false) {
+            Err(diagnostic) => Some(diagnostic.render(file, &content, color)),
+            Ok(module) => {
+                // Waiting is instant in tests: the clock starts at the real
+                // time and only moves when the test sleeps or waits for a
+                // callback
+                let clock = ManualClock::new(SystemClock.now());
+                let runtime = vm::Runtime {
+                    time: Time::new(Box::new(clock)),
+                    root: Root::new(permissions.clone(), AuditLog::File(audit_log.to_string())),
+                    output: Box::new(output.clone()),
+                    ..vm::Runtime::default()
+                };
+                vm::Vm::new(module, runtime).run().err().map(|e| match Diagnostic::runtime(&e) {
+                    Some(diagnostic) => diagnostic.render(file, &content, color),
+                    None => format!("{}\n", e),
+                })
+            }
+        };
+        match error {
+            None => println!("test {} ... ok", file),
+            Some(error) => {
+                println!("test {} ... FAILED", file);
+                failures.push((file, output, error));
+            }
         }
-        Err(e) => eprintln!("Failed to read file: {}", e),
+    }
+    for (file, output, error) in &failures {
+        eprintln!("\n---- {} ----", file);
+        eprint!("{}", String::from_utf8_lossy(&output.0.borrow()));
+        eprint!("{}", error);
+    }
+    let passed = files.len() - failures.len();
+    println!("\n{} passed, {} failed", passed, failures.len());
+    if !failures.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+// Output kept in memory, for tests
+#[derive(Clone, Default)]
+struct Captured(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// Parse, check root.optimize hints and compile, without optimizing
+fn check(source: &str) -> Result<bytecode::Module, Diagnostic> {
+    let program = parser::parse(source)?;
+    optimizer::hints(&program)?;
+    bytecode::compile(&program)
+}
+
+// Parse, apply root.optimize hints and compile to bytecode
+fn compile(source: &str, optimize: bool, explain: bool) -> Result<bytecode::Module, Diagnostic> {
+    let mut program = parser::parse(source)?;
+    if optimize {
+        let report = optimizer::optimize(&mut program)?;
+        if explain {
+            eprint!("{}", report.explain());
+        }
+    } else if explain {
+        eprintln!("root.optimize is disabled, so nothing was optimized; run with --allow-root=optimize");
+    }
+    bytecode::compile(&program)
+}
+
+// Show a diagnostic with the lines of the file it points at
+fn report(file: &str, source: &str, diagnostic: &Diagnostic) {
+    let color = diagnostics::color(&std::io::stderr());
+    eprint!("{}", diagnostic.render(file, source, color));
+}
+
+// Print the formatted file, or with --write replace it. --check only reports
+// whether the file needs formatting, and fails if it does.
+fn pretty_file(file: &str, check: bool, write: bool) {
+    if check && write {
+        usage_error("--check and --write can't be used together");
+    }
+    let content = read(file);
+    let formatted = match formatter::format(&content) {
+        Ok(formatted) => formatted,
+        Err(diagnostic) => {
+            report(file, &content, &diagnostic);
+            std::process::exit(1);
+        }
+    };
+    if check {
+        if formatted != content {
+            fail(&format!("{} is not formatted; run prizm pretty --write {}", file, file));
+        }
+    } else if write {
+        if formatted != content {
+            if let Err(e) = std::fs::write(file, &formatted) {
+                fail(&format!("Failed to write file: {}", e));
+            }
+            println!("Formatted {}", file);
+        }
+    } else {
+        print!("{}", formatted);
+    }
+}
+
+// Report what the lint rules find, failing if any finding is an error. With
+// --fix, the fixes are written to the file first, and only what is left is
+// reported.
+fn lint_file(file: &str, fix: bool, format: Format) {
+    // Every code lint can report: a rule, or a syntax error
+    let mut rules: Vec<(&str, &str)> = lint::RULES
+        .iter()
+        .map(|rule| (rule.id, rule.description))
+        .collect();
+    rules.extend(syntax_codes());
+
+    let mut content = read(file);
+    if let Err(diagnostic) = lint::Source::parse(&content) {
+        emit(file, &content, format, &[diagnostic], &rules);
+        std::process::exit(1);
+    }
+    let config = lint::Config::find(Path::new(file)).unwrap_or_else(|e| fail(&e));
+    if fix {
+        let (fixed, count) = lint::fix(&content, &config).unwrap_or_else(|e| fail(&e));
+        if count > 0 {
+            std::fs::write(file, &fixed)
+                .unwrap_or_else(|e| fail(&format!("Failed to write file: {}", e)));
+            let message = format!("Fixed {} problem{} in {}", count, plural(count), file);
+            // Machine-readable output keeps stdout to itself
+            match format {
+                Format::Text => println!("{}", message),
+                _ => eprintln!("{}", message),
+            }
+        }
+        content = fixed;
+    }
+    let source = lint::Source::parse(&content).unwrap_or_else(|e| fail(&e.to_string()));
+    let findings = lint::lint(&source, &config);
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    if format != Format::Text {
+        let diagnostics: Vec<Diagnostic> =
+            findings.iter().map(|finding| finding.diagnostic()).collect();
+        emit(file, &content, format, &diagnostics, &rules);
+        if errors > 0 {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let color = diagnostics::color(&std::io::stdout());
+    for finding in &findings {
+        println!("{}", finding.diagnostic().render(file, &content, color));
+    }
+    let fixable = findings.iter().filter(|finding| finding.fix.is_some()).count();
+    let warnings = findings.len() - errors;
+    if findings.is_empty() {
+        println!("No problems found in {}", file);
+    } else {
+        println!(
+            "{} problem{} ({} error{}, {} warning{})",
+            findings.len(),
+            plural(findings.len()),
+            errors,
+            plural(errors),
+            warnings,
+            plural(warnings)
+        );
+        if fixable > 0 {
+            println!(
+                "{} of them can be fixed with prizm lint --fix {}",
+                fixable, file
+            );
+        }
+    }
+    if errors > 0 {
+        std::process::exit(1);
+    }
+}
+
+// Report whatever stops a file from compiling, without running it
+fn check_file(file: &str, format: Format) {
+    let content = read(file);
+    let diagnostics: Vec<Diagnostic> = check(&content).err().into_iter().collect();
+    match (format, diagnostics.first()) {
+        (Format::Text, Some(diagnostic)) => report(file, &content, diagnostic),
+        (Format::Text, None) => println!("No errors in {}", file),
+        _ => emit(file, &content, format, &diagnostics, &syntax_codes()),
+    }
+    if !diagnostics.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+// Print diagnostics in the format asked for. `rules` describes every code the
+// command can report, for SARIF.
+fn emit(
+    file: &str,
+    source: &str,
+    format: Format,
+    found: &[Diagnostic],
+    rules: &[(&str, &str)],
+) {
+    match format {
+        Format::Text => {
+            for diagnostic in found {
+                report(file, source, diagnostic);
+            }
+        }
+        Format::Json => {
+            for diagnostic in found {
+                println!("{}", diagnostics::json_line(file, diagnostic));
+            }
+        }
+        Format::Sarif => println!("{}", diagnostics::sarif(file, found, rules)),
+    }
+}
+
+// The codes for errors found before a program runs
+fn syntax_codes() -> Vec<(&'static str, &'static str)> {
+    diagnostics::CODES
+        .iter()
+        .filter(|code| code.stage == "Syntax")
+        .map(|code| (code.code, code.title))
+        .collect()
+}
+
+// Print what an error code or a lint rule means
+fn explain_code(code: &str) {
+    if let Some(code) = diagnostics::explain(code) {
+        println!("{}: {}\n\n{}", code.code, code.title, code.explanation);
+    } else if let Some(rule) = lint::rule(code) {
+        println!(
+            "{}: {}\n\nA lint rule, reported as {} by default. Change its severity in the [lint] table of prizm.toml.",
+            rule.id,
+            rule.description,
+            match rule.severity {
+                Severity::Error => "an error",
+                Severity::Warning => "a warning",
+            }
+        );
+    } else {
+        fail(&format!("Unknown error code: {}", code));
+    }
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 {
+        ""
+    } else {
+        "s"
     }
 }
\ No newline at end of file
@@ -0,0 +1,667 @@
+// Optimizer for Prizm
+// `root.optimize(name)` marks a function for aggressive optimization. Before
+// the program is compiled, marked functions get small callees inlined,
+// constant expressions folded and code that can never run or has no effect
+// removed. Unmarked code is left exactly as written.
+
+use crate::ast::{
+    functions, statements, visit, walk, Expr, ExprKind, Function, Program, Stmt, StmtKind,
+};
+use crate::attributes::PrizmType;
+use crate::diagnostics::Diagnostic;
+use crate::lexer::Span;
+use crate::value::Value;
+use crate::vm;
+use std::collections::{HashMap, HashSet};
+
+// Passes repeat until nothing changes, up to this many times
+const MAX_ROUNDS: usize = 8;
+
+/// One change made to a marked function
+#[derive(Debug, Clone, PartialEq)]
+pub struct Applied {
+    pub line: usize,
+    pub description: String,
+}
+
+/// What `optimize` did to each marked function, in the order they were marked
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    pub functions: Vec<(String, usize, Vec<Applied>)>,
+}
+
+impl Report {
+    /// The report printed by `--explain-optimizations`
+    pub fn explain(&self) -> String {
+        if self.functions.is_empty() {
+            return "No functions are marked with root.optimize\n".to_string();
+        }
+        let mut out = String::new();
+        for (name, line, applied) in &self.functions {
+            out.push_str(&format!("optimize {} (line {}):\n", name, line));
+            if applied.is_empty() {
+                out.push_str("  nothing to optimize\n");
+            }
+            for change in applied {
+                out.push_str(&format!("  line {}: {}\n", change.line, change.description));
+            }
+        }
+        out
+    }
+}
+
+/// The functions marked with `root.optimize`, with the line of each mark
+pub fn hints(program: &Program) -> Result<Vec<(String, usize)>, Diagnostic> {
+    let mut defined = Vec::new();
+    functions(&program.statements, &mut |function| {
+        defined.push(function.name.clone())
+    });
+    let mut marked: Vec<(String, usize)> = Vec::new();
+    let mut error = None;
+    walk(&program.statements, &mut |expr| {
+        let args = match &expr.kind {
+            ExprKind::Header {
+                header,
+                attribute,
+                args,
+            } if header == "root" && attribute == "optimize" => args,
+            _ => return,
+        };
+        let name = match args.as_slice() {
+            [Expr {
+                kind: ExprKind::Variable(name),
+                ..
+            }] if defined.contains(name) => name,
+            _ => {
+                error.get_or_insert(Diagnostic::error(
+                    "P0206",
+                    expr.span,
+                    "root.optimize expects the name of a function defined with define",
+                ));
+                return;
+            }
+        };
+        if !marked.iter().any(|(marked, _)| marked == name) {
+            marked.push((name.clone(), expr.span.line));
+        }
+    });
+    match error {
+        Some(error) => Err(error),
+        None => Ok(marked),
+    }
+}
+
+/// Optimize every function marked with `root.optimize`
+pub fn optimize(program: &mut Program) -> Result<Report, Diagnostic> {
+    let marked = hints(program)?;
+    let mut callees = HashMap::new();
+    functions(&program.statements, &mut |function| {
+        callees.insert(function.name.clone(), function.clone());
+    });
+    let constants = constants(program);
+
+    let mut report = Report::default();
+    for (name, _) in marked {
+        let mut applied = Vec::new();
+        let mut line = 0;
+        functions_mut(&mut program.statements, &mut |function| {
+            if function.name == name {
+                line = function.span.line;
+                let mut pass = Pass {
+                    callees: &callees,
+                    constants: &constants,
+                    locals: locals(function),
+                    applied: Vec::new(),
+                };
+                pass.run(function);
+                applied = pass.applied;
+            }
+        });
+        report.functions.push((name, line, applied));
+    }
+    Ok(report)
+}
+
+struct Pass<'a> {
+    callees: &'a HashMap<String, Function>,
+    // Top-level variables that always hold the same literal
+    constants: &'a HashMap<String, Expr>,
+    // Parameters and variables declared in the function being optimized
+    locals: HashSet<String>,
+    applied: Vec<Applied>,
+}
+
+impl Pass<'_> {
+    fn note(&mut self, line: usize, description: String) {
+        self.applied.push(Applied { line, description });
+    }
+
+    fn run(&mut self, function: &mut Function) {
+        for _ in 0..MAX_ROUNDS {
+            let before = self.applied.len();
+            self.block(&mut function.body);
+            self.prune(&mut function.body);
+            let mut used = Vec::new();
+            for stmt in &function.body {
+                names_in(stmt, &mut used);
+            }
+            let params = param_names(function);
+            self.remove_unused(&mut function.body, &params, &used);
+            if self.applied.len() == before {
+                break;
+            }
+        }
+    }
+
+    // ===== EXPRESSIONS =====
+
+    fn block(&mut self, statements: &mut [Stmt]) {
+        for stmt in statements {
+            match &mut stmt.kind {
+                StmtKind::Var { value, .. } | StmtKind::Expr(value) => self.expression(value),
+                StmtKind::Assign { target, value } => {
+                    self.expression(target);
+                    self.expression(value);
+                }
+                StmtKind::If {
+                    branches,
+                    otherwise,
+                } => {
+                    for (condition, body) in branches {
+                        self.expression(condition);
+                        self.block(body);
+                    }
+                    if let Some(body) = otherwise {
+                        self.block(body);
+                    }
+                }
+                StmtKind::LoopUntil { condition, body } => {
+                    self.expression(condition);
+                    self.block(body);
+                }
+                StmtKind::Loop { body } => self.block(body),
+                StmtKind::RepeatFor { from, to, body, .. } => {
+                    self.expression(from);
+                    self.expression(to);
+                    self.block(body);
+                }
+                StmtKind::Return(Some(value)) => self.expression(value),
+                // Nested functions are only optimized when marked themselves
+                StmtKind::Define(_) | StmtKind::Return(None) | StmtKind::Break => {}
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &mut Expr) {
+        match &mut expr.kind {
+            ExprKind::Array(items) => items.iter_mut().for_each(|item| self.expression(item)),
+            ExprKind::Object(fields) => fields
+                .iter_mut()
+                .for_each(|(_, value)| self.expression(value)),
+            ExprKind::Unary(_, operand) => self.expression(operand),
+            ExprKind::Binary(_, left, right) => {
+                self.expression(left);
+                self.expression(right);
+            }
+            ExprKind::Call { args, .. } | ExprKind::Header { args, .. } => {
+                args.iter_mut().for_each(|arg| self.expression(arg))
+            }
+            ExprKind::Method { object, args, .. } => {
+                self.expression(object);
+                args.iter_mut().for_each(|arg| self.expression(arg));
+            }
+            ExprKind::Field(object, _) => self.expression(object),
+            ExprKind::Index(object, index) => {
+                self.expression(object);
+                self.expression(index);
+            }
+            ExprKind::Variable(name) if !self.locals.contains(name.as_str()) => {
+                if let Some(constant) = self.constants.get(name.as_str()) {
+                    let description =
+                        format!("replaced {} with its constant value {}", name, constant);
+                    expr.kind = constant.kind.clone();
+                    self.note(expr.span.line, description);
+                }
+            }
+            _ => {}
+        }
+        if let Some((inlined, description)) = self.inline(expr) {
+            *expr = inlined;
+            self.note(expr.span.line, description);
+            // The arguments may now fold with the callee's body
+            self.expression(expr);
+        }
+        if let Some(folded) = fold(expr) {
+            let description = format!("folded {} into {}", expr, folded);
+            *expr = folded;
+            self.note(expr.span.line, description);
+        }
+    }
+
+    // The callee's returned expression with the arguments put in place of
+    // its parameters, if the call is small and safe to inline
+    fn inline(&self, expr: &Expr) -> Option<(Expr, String)> {
+        let (name, args) = match &expr.kind {
+            ExprKind::Call { name, args } => (name, args),
+            _ => return None,
+        };
+        let callee = self.callees.get(name)?;
+        let body = match callee.body.as_slice() {
+            [Stmt {
+                kind: StmtKind::Return(Some(body)),
+                ..
+            }] => body,
+            _ => return None,
+        };
+        // Calls could recurse or have effects; keep them where they are
+        if args.len() != callee.params.len() || has_calls(body) {
+            return None;
+        }
+        let mut substitutions = HashMap::new();
+        for (param, arg) in callee.params.iter().zip(args) {
+            let simple = match &arg.kind {
+                ExprKind::Variable(_) => param.type_name.is_none(),
+                // Typed parameters are checked when called; only inline when
+                // the check is known to pass
+                _ if arg.is_literal() => match param.type_name.as_deref() {
+                    Some(type_name) => literal(arg).is_some_and(|value| {
+                        PrizmType::from_string(type_name)
+                            .is_some_and(|ty| vm::type_matches(&value, &ty))
+                    }),
+                    None => true,
+                },
+                _ => false,
+            };
+            if !simple {
+                return None;
+            }
+            substitutions.insert(param.name.as_str(), arg);
+        }
+        // The callee's own free names would mean something else here if the
+        // caller has locals by those names
+        let mut free = Vec::new();
+        variables(body, &mut free);
+        if free
+            .iter()
+            .any(|name| !substitutions.contains_key(name.as_str()) && self.locals.contains(name))
+        {
+            return None;
+        }
+
+        let mut inlined = body.clone();
+        substitute(&mut inlined, &substitutions, expr.span);
+        Some((inlined, format!("inlined call to {}", name)))
+    }
+
+    // ===== DEAD CODE =====
+
+    fn prune(&mut self, statements: &mut Vec<Stmt>) {
+        let mut i = 0;
+        while i < statements.len() {
+            let line = statements[i].span.line;
+            let exit = match statements[i].kind {
+                StmtKind::Return(_) => Some("return"),
+                StmtKind::Break => Some("break"),
+                _ => None,
+            };
+            if let Some(exit) = exit {
+                let removed = statements.len() - i - 1;
+                if removed > 0 {
+                    statements.truncate(i + 1);
+                    self.note(
+                        line,
+                        format!(
+                            "removed {} unreachable statement{} after {}",
+                            removed,
+                            plural(removed),
+                            exit
+                        ),
+                    );
+                }
+                break;
+            }
+            let replacement: Option<Vec<Stmt>> = match &mut statements[i].kind {
+                StmtKind::Expr(expr) if expr.is_pure() => {
+                    let description = format!("removed expression {} which has no effect", expr);
+                    self.note(line, description);
+                    Some(Vec::new())
+                }
+                StmtKind::If {
+                    branches,
+                    otherwise,
+                } => {
+                    let mut kept = Vec::new();
+                    let mut always = None;
+                    for (condition, mut body) in std::mem::take(branches) {
+                        self.prune(&mut body);
+                        match literal(&condition).map(|value| value.is_truthy()) {
+                            Some(false) => self.note(
+                                condition.span.line,
+                                format!(
+                                    "removed branch whose condition {} is always false",
+                                    condition
+                                ),
+                            ),
+                            Some(true) => {
+                                self.note(
+                                    condition.span.line,
+                                    format!(
+                                        "removed check of condition {} which is always true",
+                                        condition
+                                    ),
+                                );
+                                always = Some(body);
+                                break;
+                            }
+                            None => kept.push((condition, body)),
+                        }
+                    }
+                    if let Some(body) = always {
+                        *otherwise = Some(body);
+                    } else if let Some(body) = otherwise {
+                        self.prune(body);
+                    }
+                    if kept.is_empty() {
+                        Some(otherwise.take().unwrap_or_default())
+                    } else {
+                        *branches = kept;
+                        None
+                    }
+                }
+                StmtKind::LoopUntil { condition, body } => {
+                    if literal(condition).is_some_and(|value| value.is_truthy()) {
+                        let description =
+                            format!("removed loop that never runs: {} is always true", condition);
+                        self.note(line, description);
+                        Some(Vec::new())
+                    } else {
+                        self.prune(body);
+                        None
+                    }
+                }
+                StmtKind::Loop { body } => {
+                    self.prune(body);
+                    None
+                }
+                StmtKind::RepeatFor { from, to, body, .. } => match (&from.kind, &to.kind) {
+                    (ExprKind::Int(from), ExprKind::Int(to)) if from > to => {
+                        self.note(
+                            line,
+                            format!("removed loop over the empty range {} to {}", from, to),
+                        );
+                        Some(Vec::new())
+                    }
+                    _ => {
+                        self.prune(body);
+                        None
+                    }
+                },
+                _ => None,
+            };
+            match replacement {
+                Some(inner) => {
+                    let count = inner.len();
+                    statements.splice(i..i + 1, inner);
+                    i += count;
+                }
+                None => i += 1,
+            }
+        }
+    }
+
+    // Drop `var` declarations of pure values that nothing in the function
+    // reads. Blocks do not start a new scope, so `used` covers the whole body.
+    fn remove_unused(&mut self, statements: &mut Vec<Stmt>, params: &[String], used: &[String]) {
+        statements.retain(|stmt| match &stmt.kind {
+            StmtKind::Var { name, value, .. }
+                if value.is_pure() && !params.contains(name) && !used.contains(name) =>
+            {
+                self.applied.push(Applied {
+                    line: stmt.span.line,
+                    description: format!("removed unused variable {}", name),
+                });
+                false
+            }
+            _ => true,
+        });
+        for stmt in statements {
+            match &mut stmt.kind {
+                StmtKind::If {
+                    branches,
+                    otherwise,
+                } => {
+                    for (_, body) in branches {
+                        self.remove_unused(body, params, used);
+                    }
+                    if let Some(body) = otherwise {
+                        self.remove_unused(body, params, used);
+                    }
+                }
+                StmtKind::LoopUntil { body, .. }
+                | StmtKind::Loop { body }
+                | StmtKind::RepeatFor { body, .. } => self.remove_unused(body, params, used),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn param_names(function: &Function) -> Vec<String> {
+    function
+        .params
+        .iter()
+        .map(|param| param.name.clone())
+        .collect()
+}
+
+// ===== FOLDING =====
+
+/// The value of a literal expression
+pub fn literal(expr: &Expr) -> Option<Value> {
+    match &expr.kind {
+        ExprKind::Int(n) => Some(Value::Int(*n)),
+        ExprKind::Float(x) => Some(Value::Float(*x)),
+        ExprKind::String(s) => Some(Value::String(s.clone())),
+        ExprKind::Boolean(b) => Some(Value::Boolean(*b)),
+        ExprKind::Null => Some(Value::Null),
+        _ => None,
+    }
+}
+
+fn to_literal(value: Value, expr: &Expr) -> Option<Expr> {
+    let kind = match value {
+        Value::Int(n) => ExprKind::Int(n),
+        Value::Float(x) if x.is_finite() => ExprKind::Float(x),
+        Value::String(s) => ExprKind::String(s),
+        Value::Boolean(b) => ExprKind::Boolean(b),
+        Value::Null => ExprKind::Null,
+        _ => return None,
+    };
+    Some(Expr::new(kind, expr.span))
+}
+
+// Work out an operator applied to literals. Anything that would fail is left
+// for the program to report when it runs.
+fn fold(expr: &Expr) -> Option<Expr> {
+    let value = match &expr.kind {
+        ExprKind::Unary(op, operand) => vm::unary(*op, literal(operand)?).ok()?,
+        ExprKind::Binary(op, left, right) => {
+            let (left, right) = (literal(left)?, literal(right)?);
+            match op {
+                crate::ast::BinaryOp::And => Value::Boolean(left.is_truthy() && right.is_truthy()),
+                crate::ast::BinaryOp::Or => Value::Boolean(left.is_truthy() || right.is_truthy()),
+                op => vm::binary(*op, left, right).ok()?,
+            }
+        }
+        _ => return None,
+    };
+    to_literal(value, expr)
+}
+
+// ===== HELPERS =====
+
+// Top-level `var name = literal` declarations never assigned again. Names
+// that appear in strings are left alone, since widgets bound to them can
+// change the variable.
+fn constants(program: &Program) -> HashMap<String, Expr> {
+    let mut declared: HashMap<String, usize> = HashMap::new();
+    let mut assigned = HashSet::new();
+    let mut strings = HashSet::new();
+    statements(&program.statements, &mut |stmt| match &stmt.kind {
+        StmtKind::Var { name, .. } => *declared.entry(name.clone()).or_default() += 1,
+        StmtKind::Assign { target, .. } => {
+            assigned.insert(root_name(target).to_string());
+        }
+        StmtKind::RepeatFor { variable, .. } => {
+            assigned.insert(variable.clone());
+        }
+        _ => {}
+    });
+    walk(&program.statements, &mut |expr| {
+        if let ExprKind::String(s) = &expr.kind {
+            strings.insert(s.clone());
+        }
+    });
+
+    let mut constants = HashMap::new();
+    for stmt in &program.statements {
+        if let StmtKind::Var {
+            name,
+            type_name: None,
+            value,
+        } = &stmt.kind
+        {
+            if value.is_literal()
+                && declared.get(name) == Some(&1)
+                && !assigned.contains(name)
+                && !strings.contains(name)
+            {
+                constants.insert(name.clone(), value.clone());
+            }
+        }
+    }
+    constants
+}
+
+// Parameters and every variable the function declares, at any depth
+fn locals(function: &Function) -> HashSet<String> {
+    let mut locals: HashSet<String> = param_names(function).into_iter().collect();
+    statements(&function.body, &mut |stmt| match &stmt.kind {
+        StmtKind::Var { name, .. } | StmtKind::RepeatFor { variable: name, .. } => {
+            locals.insert(name.clone());
+        }
+        StmtKind::Assign { target, .. } => {
+            locals.insert(root_name(target).to_string());
+        }
+        _ => {}
+    });
+    locals
+}
+
+fn root_name(target: &Expr) -> &str {
+    match &target.kind {
+        ExprKind::Variable(name) => name,
+        ExprKind::Field(object, _) | ExprKind::Index(object, _) => root_name(object),
+        _ => "",
+    }
+}
+
+fn has_calls(expr: &Expr) -> bool {
+    let mut calls = false;
+    visit(expr, &mut |expr| {
+        if matches!(
+            expr.kind,
+            ExprKind::Call { .. } | ExprKind::Header { .. } | ExprKind::Method { .. }
+        ) {
+            calls = true;
+        }
+    });
+    calls
+}
+
+fn variables(expr: &Expr, names: &mut Vec<String>) {
+    visit(expr, &mut |expr| {
+        if let ExprKind::Variable(name) = &expr.kind {
+            names.push(name.clone());
+        }
+    });
+}
+
+// Every variable a statement reads, and every function it refers to by name
+fn names_in(stmt: &Stmt, names: &mut Vec<String>) {
+    walk(std::slice::from_ref(stmt), &mut |expr| match &expr.kind {
+        ExprKind::Variable(name) => names.push(name.clone()),
+        ExprKind::Call { name, .. } => names.push(name.clone()),
+        _ => {}
+    });
+    statements(std::slice::from_ref(stmt), &mut |stmt| {
+        if let StmtKind::Assign { target, .. } = &stmt.kind {
+            names.push(root_name(target).to_string());
+        }
+    });
+}
+
+// Replace parameters with their arguments, moving the expression to the
+// call's line so errors and notes point at the caller
+fn substitute(expr: &mut Expr, substitutions: &HashMap<&str, &Expr>, span: Span) {
+    expr.span = span;
+    if let ExprKind::Variable(name) = &expr.kind {
+        if let Some(arg) = substitutions.get(name.as_str()) {
+            expr.kind = arg.kind.clone();
+        }
+        return;
+    }
+    match &mut expr.kind {
+        ExprKind::Array(items) => items
+            .iter_mut()
+            .for_each(|item| substitute(item, substitutions, span)),
+        ExprKind::Object(fields) => fields
+            .iter_mut()
+            .for_each(|(_, value)| substitute(value, substitutions, span)),
+        ExprKind::Unary(_, operand) => substitute(operand, substitutions, span),
+        ExprKind::Binary(_, left, right) => {
+            substitute(left, substitutions, span);
+            substitute(right, substitutions, span);
+        }
+        ExprKind::Field(object, _) => substitute(object, substitutions, span),
+        ExprKind::Index(object, index) => {
+            substitute(object, substitutions, span);
+            substitute(index, substitutions, span);
+        }
+        _ => {}
+    }
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 {
+        ""
+    } else {
+        "s"
+    }
+}
+
+fn functions_mut(list: &mut [Stmt], f: &mut impl FnMut(&mut Function)) {
+    for stmt in list {
+        match &mut stmt.kind {
+            StmtKind::Define(function) => {
+                f(function);
+                functions_mut(&mut function.body, f);
+            }
+            StmtKind::If {
+                branches,
+                otherwise,
+            } => {
+                for (_, body) in branches {
+                    functions_mut(body, f);
+                }
+                if let Some(body) = otherwise {
+                    functions_mut(body, f);
+                }
+            }
+            StmtKind::LoopUntil { body, .. }
+            | StmtKind::Loop { body }
+            | StmtKind::RepeatFor { body, .. } => functions_mut(body, f),
+            _ => {}
+        }
+    }
+}
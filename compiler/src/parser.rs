@@ -0,0 +1,720 @@
+// Parser for Prizm Language
+// Turns tokens into a syntax tree by recursive descent. Statements are
+// separated by commas, which may be left out after a closing brace or at the
+// end of a line.
+
+use crate::ast::{BinaryOp, Expr, ExprKind, Function, Param, Program, Stmt, StmtKind, UnaryOp};
+use crate::attributes::PrizmType;
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{Lexer, Span, Token};
+
+/// Lex and parse a whole program
+pub fn parse(source: &str) -> Result<Program, Diagnostic> {
+    let tokens = Lexer::new(source).tokenize()?;
+    Parser::new(tokens).parse_program()
+}
+
+pub struct Parser {
+    tokens: Vec<(Token, Span)>,
+    position: usize,
+    // Where each block's braces are, in the order the blocks open
+    blocks: Vec<(Span, Span)>,
+}
+
+impl Parser {
+    /// `tokens` must end with `Token::Eof`, as `Lexer::tokenize` returns them
+    pub fn new(tokens: Vec<(Token, Span)>) -> Self {
+        Parser {
+            tokens,
+            position: 0,
+            blocks: Vec::new(),
+        }
+    }
+
+    /// The `{` and `}` of every block parsed so far, in the order the blocks
+    /// open. The syntax tree leaves them out, but the formatter places
+    /// comments by them.
+    pub fn blocks(&self) -> &[(Span, Span)] {
+        &self.blocks
+    }
+
+    pub fn parse_program(&mut self) -> Result<Program, Diagnostic> {
+        let mut statements = Vec::new();
+        while !self.check(&Token::Eof) {
+            statements.push(self.statement()?);
+            self.separator();
+        }
+        Ok(Program { statements })
+    }
+
+    // ===== TOKENS =====
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].0
+    }
+
+    fn span(&self) -> Span {
+        self.tokens[self.position].1
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.position].0.clone();
+        if self.position < self.tokens.len() - 1 {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn check(&self, token: &Token) -> bool {
+        self.peek() == token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.check(token) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &Token, context: &str) -> Result<(), Diagnostic> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(&format!(
+                "Expected {} {}, found {}",
+                describe(token),
+                context,
+                describe(self.peek())
+            )))
+        }
+    }
+
+    fn error(&self, message: &str) -> Diagnostic {
+        Diagnostic::error("P0101", self.span(), message)
+    }
+
+    // Commas between statements are optional
+    fn separator(&mut self) {
+        while self.eat(&Token::Comma) {}
+    }
+
+    fn identifier(&mut self, context: &str) -> Result<String, Diagnostic> {
+        match self.peek().clone() {
+            Token::Identifier(name) => {
+                self.advance();
+                Ok(name)
+            }
+            other => Err(self.error(&format!(
+                "Expected a name {}, found {}",
+                context,
+                describe(&other)
+            ))),
+        }
+    }
+
+    // Field, method and attribute names may be keywords, as in `state.count`
+    // or `data.parse`
+    fn name_after_dot(&mut self) -> Result<String, Diagnostic> {
+        let token = self.peek().clone();
+        match token {
+            Token::Identifier(name) => {
+                self.advance();
+                Ok(name)
+            }
+            other => match keyword_text(&other) {
+                Some(word) => {
+                    self.advance();
+                    Ok(word.to_string())
+                }
+                None => Err(self.error(&format!(
+                    "Expected a name after '.', found {}",
+                    describe(&other)
+                ))),
+            },
+        }
+    }
+
+    // ===== STATEMENTS =====
+
+    fn statement(&mut self) -> Result<Stmt, Diagnostic> {
+        let span = self.span();
+        let kind = match self.peek() {
+            Token::Var => self.var_statement()?,
+            Token::If => self.if_statement()?,
+            Token::Loop => self.loop_statement()?,
+            Token::Repeat => self.repeat_statement()?,
+            Token::Define | Token::Function => StmtKind::Define(self.function()?),
+            Token::Return => {
+                self.advance();
+                if matches!(self.peek(), Token::Comma | Token::RightBrace | Token::Eof) {
+                    StmtKind::Return(None)
+                } else {
+                    StmtKind::Return(Some(self.expression()?))
+                }
+            }
+            Token::Break => {
+                self.advance();
+                StmtKind::Break
+            }
+            _ => {
+                let expr = self.expression()?;
+                if self.check(&Token::Equal) {
+                    if !matches!(
+                        expr.kind,
+                        ExprKind::Variable(_) | ExprKind::Field(..) | ExprKind::Index(..)
+                    ) {
+                        return Err(Diagnostic::error(
+                            "P0103",
+                            expr.span,
+                            "Only variables, fields and array items can be assigned to",
+                        ));
+                    }
+                    self.advance();
+                    let value = self.expression()?;
+                    StmtKind::Assign {
+                        target: expr,
+                        value,
+                    }
+                } else {
+                    StmtKind::Expr(expr)
+                }
+            }
+        };
+        Ok(Stmt::new(kind, span))
+    }
+
+    fn var_statement(&mut self) -> Result<StmtKind, Diagnostic> {
+        self.advance();
+        let name = self.identifier("after 'var'")?;
+        let type_name = self.type_annotation()?;
+        self.expect(&Token::Equal, &format!("after 'var {}'", name))?;
+        let value = self.expression()?;
+        Ok(StmtKind::Var {
+            name,
+            type_name,
+            value,
+        })
+    }
+
+    // `: type`, if there is one
+    fn type_annotation(&mut self) -> Result<Option<String>, Diagnostic> {
+        if !self.eat(&Token::Colon) {
+            return Ok(None);
+        }
+        let span = self.span();
+        let name = match self.advance() {
+            Token::IntType => "int".to_string(),
+            Token::FloatType => "float".to_string(),
+            Token::StringType => "string".to_string(),
+            Token::BooleanType => "boolean".to_string(),
+            Token::ArrayType => "array".to_string(),
+            Token::ObjectType => "object".to_string(),
+            Token::Null => "null".to_string(),
+            Token::Function => "function".to_string(),
+            Token::Identifier(name) if PrizmType::from_string(&name).is_some() => name,
+            other => {
+                return Err(Diagnostic::error(
+                    "P0101",
+                    span,
+                    &format!("Expected a type after ':', found {}", describe(&other)),
+                ))
+            }
+        };
+        Ok(Some(name))
+    }
+
+    fn block(&mut self, context: &str) -> Result<Vec<Stmt>, Diagnostic> {
+        let open = self.span();
+        self.expect(&Token::LeftBrace, context)?;
+        let index = self.blocks.len();
+        self.blocks.push((open, open));
+        let mut statements = Vec::new();
+        self.separator();
+        while !self.check(&Token::RightBrace) {
+            if self.check(&Token::Eof) {
+                return Err(Diagnostic::error(
+                    "P0102",
+                    self.span(),
+                    &format!("Missing '}}' to close the block {}", context),
+                )
+                .with_label(open, "the block starts here"));
+            }
+            statements.push(self.statement()?);
+            self.separator();
+        }
+        self.blocks[index].1 = self.span();
+        self.advance();
+        Ok(statements)
+    }
+
+    fn if_statement(&mut self) -> Result<StmtKind, Diagnostic> {
+        self.advance();
+        let mut branches = Vec::new();
+        let condition = self.expression()?;
+        branches.push((condition, self.block("after the if condition")?));
+        let mut otherwise = None;
+        while self.eat(&Token::Else) {
+            if self.eat(&Token::If) {
+                let condition = self.expression()?;
+                branches.push((condition, self.block("after the else if condition")?));
+            } else {
+                otherwise = Some(self.block("after 'else'")?);
+                break;
+            }
+        }
+        Ok(StmtKind::If {
+            branches,
+            otherwise,
+        })
+    }
+
+    fn loop_statement(&mut self) -> Result<StmtKind, Diagnostic> {
+        self.advance();
+        if self.eat(&Token::LoopUntil) {
+            let condition = self.expression()?;
+            let body = self.block("after the loop until condition")?;
+            Ok(StmtKind::LoopUntil { condition, body })
+        } else {
+            let body = self.block("after 'loop'")?;
+            Ok(StmtKind::Loop { body })
+        }
+    }
+
+    fn repeat_statement(&mut self) -> Result<StmtKind, Diagnostic> {
+        self.advance();
+        self.expect(&Token::For, "after 'repeat'")?;
+        let parenthesized = self.eat(&Token::LeftParen);
+        let variable = self.identifier("to count with in 'repeat for'")?;
+        self.expect(&Token::Equal, &format!("after '{}'", variable))?;
+        let from = self.expression()?;
+        match self.peek() {
+            Token::Identifier(word) if word == "to" => {
+                self.advance();
+            }
+            other => {
+                return Err(self.error(&format!(
+                    "Expected 'to' in 'repeat for', found {}",
+                    describe(other)
+                )))
+            }
+        }
+        let to = self.expression()?;
+        if parenthesized {
+            self.expect(&Token::RightParen, "to close 'repeat for'")?;
+        }
+        let body = self.block("after 'repeat for'")?;
+        Ok(StmtKind::RepeatFor {
+            variable,
+            from,
+            to,
+            body,
+        })
+    }
+
+    fn function(&mut self) -> Result<Function, Diagnostic> {
+        let span = self.span();
+        self.advance();
+        let name = self.identifier("for the function")?;
+        self.expect(&Token::LeftParen, &format!("after '{}'", name))?;
+        let mut params = Vec::new();
+        while !self.check(&Token::RightParen) {
+            let param_span = self.span();
+            let param = self.identifier("for a parameter")?;
+            if params.iter().any(|p: &Param| p.name == param) {
+                return Err(Diagnostic::error(
+                    "P0104",
+                    param_span,
+                    &format!("Parameter '{}' is listed twice", param),
+                ));
+            }
+            params.push(Param {
+                name: param,
+                type_name: self.type_annotation()?,
+                span: param_span,
+            });
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RightParen, "after the parameters")?;
+        let body = self.block(&format!("for the body of '{}'", name))?;
+        Ok(Function {
+            name,
+            params,
+            body,
+            span,
+        })
+    }
+
+    // ===== EXPRESSIONS =====
+
+    pub fn expression(&mut self) -> Result<Expr, Diagnostic> {
+        self.or()
+    }
+
+    fn binary(
+        &mut self,
+        operand: fn(&mut Self) -> Result<Expr, Diagnostic>,
+        operators: &[(Token, BinaryOp)],
+    ) -> Result<Expr, Diagnostic> {
+        let mut left = operand(self)?;
+        loop {
+            let op = match operators.iter().find(|(token, _)| self.check(token)) {
+                Some((_, op)) => *op,
+                None => return Ok(left),
+            };
+            self.advance();
+            let right = operand(self)?;
+            let span = left.span;
+            left = Expr::new(ExprKind::Binary(op, Box::new(left), Box::new(right)), span);
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, Diagnostic> {
+        self.binary(Self::and, &[(Token::Or, BinaryOp::Or)])
+    }
+
+    fn and(&mut self) -> Result<Expr, Diagnostic> {
+        self.binary(Self::equality, &[(Token::And, BinaryOp::And)])
+    }
+
+    fn equality(&mut self) -> Result<Expr, Diagnostic> {
+        self.binary(
+            Self::comparison,
+            &[
+                (Token::EqualEqual, BinaryOp::Equal),
+                (Token::NotEqual, BinaryOp::NotEqual),
+            ],
+        )
+    }
+
+    fn comparison(&mut self) -> Result<Expr, Diagnostic> {
+        self.binary(
+            Self::additive,
+            &[
+                (Token::Less, BinaryOp::Less),
+                (Token::Greater, BinaryOp::Greater),
+                (Token::LessEqual, BinaryOp::LessEqual),
+                (Token::GreaterEqual, BinaryOp::GreaterEqual),
+            ],
+        )
+    }
+
+    fn additive(&mut self) -> Result<Expr, Diagnostic> {
+        self.binary(
+            Self::multiplicative,
+            &[
+                (Token::Plus, BinaryOp::Add),
+                (Token::Minus, BinaryOp::Subtract),
+            ],
+        )
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, Diagnostic> {
+        self.binary(
+            Self::unary,
+            &[
+                (Token::Star, BinaryOp::Multiply),
+                (Token::Slash, BinaryOp::Divide),
+                (Token::Percent, BinaryOp::Modulo),
+            ],
+        )
+    }
+
+    fn unary(&mut self) -> Result<Expr, Diagnostic> {
+        let span = self.span();
+        let op = match self.peek() {
+            Token::Minus => UnaryOp::Negate,
+            Token::Not => UnaryOp::Not,
+            _ => return self.postfix(),
+        };
+        self.advance();
+        let operand = self.unary()?;
+        // Negative literals are literals, so `-5` needs no folding
+        Ok(match (op, operand.kind) {
+            (UnaryOp::Negate, ExprKind::Int(n)) => Expr::new(ExprKind::Int(-n), span),
+            (UnaryOp::Negate, ExprKind::Float(x)) => Expr::new(ExprKind::Float(-x), span),
+            (op, kind) => Expr::new(
+                ExprKind::Unary(op, Box::new(Expr::new(kind, operand.span))),
+                span,
+            ),
+        })
+    }
+
+    fn postfix(&mut self) -> Result<Expr, Diagnostic> {
+        let mut expr = self.primary()?;
+        loop {
+            let span = self.span();
+            if self.eat(&Token::Dot) {
+                let name = self.name_after_dot()?;
+                expr = if self.check(&Token::LeftParen) {
+                    let args = self.arguments()?;
+                    Expr::new(
+                        ExprKind::Method {
+                            object: Box::new(expr),
+                            method: name,
+                            args,
+                        },
+                        span,
+                    )
+                } else {
+                    Expr::new(ExprKind::Field(Box::new(expr), name), span)
+                };
+            } else if self.eat(&Token::LeftBracket) {
+                let index = self.expression()?;
+                self.expect(&Token::RightBracket, "after the index")?;
+                expr = Expr::new(ExprKind::Index(Box::new(expr), Box::new(index)), span);
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn arguments(&mut self) -> Result<Vec<Expr>, Diagnostic> {
+        self.expect(&Token::LeftParen, "before the arguments")?;
+        let mut args = Vec::new();
+        while !self.check(&Token::RightParen) {
+            args.push(self.expression()?);
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RightParen, "after the arguments")?;
+        Ok(args)
+    }
+
+    fn primary(&mut self) -> Result<Expr, Diagnostic> {
+        let span = self.span();
+        let kind = match self.peek().clone() {
+            Token::Number(n) => {
+                self.advance();
+                ExprKind::Int(n)
+            }
+            Token::Float(x) => {
+                self.advance();
+                ExprKind::Float(x)
+            }
+            Token::String(s) => {
+                self.advance();
+                ExprKind::String(s)
+            }
+            Token::True => {
+                self.advance();
+                ExprKind::Boolean(true)
+            }
+            Token::False => {
+                self.advance();
+                ExprKind::Boolean(false)
+            }
+            Token::Null => {
+                self.advance();
+                ExprKind::Null
+            }
+            Token::Identifier(name) => {
+                self.advance();
+                if self.check(&Token::LeftParen) {
+                    ExprKind::Call {
+                        name,
+                        args: self.arguments()?,
+                    }
+                } else {
+                    ExprKind::Variable(name)
+                }
+            }
+            Token::Output | Token::Print => {
+                let name = keyword_text(&self.advance())
+                    .unwrap_or_default()
+                    .to_string();
+                ExprKind::Call {
+                    name,
+                    args: self.arguments()?,
+                }
+            }
+            Token::LeftParen => {
+                self.advance();
+                let expr = self.expression()?;
+                self.expect(&Token::RightParen, "to close the parenthesis")?;
+                return Ok(expr);
+            }
+            Token::LeftBracket => {
+                self.advance();
+                let mut items = Vec::new();
+                while !self.check(&Token::RightBracket) {
+                    items.push(self.expression()?);
+                    if !self.eat(&Token::Comma) {
+                        break;
+                    }
+                }
+                self.expect(&Token::RightBracket, "to close the array")?;
+                ExprKind::Array(items)
+            }
+            Token::LeftBrace => {
+                self.advance();
+                ExprKind::Object(self.object_fields()?)
+            }
+            token => match header_name(&token) {
+                Some(header) => {
+                    self.advance();
+                    if !self.check(&Token::Dot) {
+                        return Err(self.error(&format!(
+                            "Expected '.' and an attribute after the {} header, as in {}.{}(...)",
+                            header,
+                            header,
+                            example_attribute(header)
+                        )));
+                    }
+                    self.advance();
+                    let attribute = self.name_after_dot()?;
+                    if !self.check(&Token::LeftParen) {
+                        return Err(self.error(&format!(
+                            "Expected '(' after {}.{}; header attributes are called like functions",
+                            header, attribute
+                        )));
+                    }
+                    ExprKind::Header {
+                        header: header.to_string(),
+                        attribute,
+                        args: self.arguments()?,
+                    }
+                }
+                None => {
+                    return Err(self.error(&format!("Expected a value, found {}", describe(&token))))
+                }
+            },
+        };
+        Ok(Expr::new(kind, span))
+    }
+
+    // After the '{' of an object literal
+    fn object_fields(&mut self) -> Result<Vec<(String, Expr)>, Diagnostic> {
+        let mut fields: Vec<(String, Expr)> = Vec::new();
+        while !self.check(&Token::RightBrace) {
+            let key = match self.peek().clone() {
+                Token::String(key) => {
+                    self.advance();
+                    key
+                }
+                _ => self.name_after_dot().map_err(|_| {
+                    self.error(&format!(
+                        "Expected a field name in the object, found {}",
+                        describe(self.peek())
+                    ))
+                })?,
+            };
+            self.expect(&Token::Colon, &format!("after the field name '{}'", key))?;
+            let value = self.expression()?;
+            match fields.iter_mut().find(|(k, _)| *k == key) {
+                Some(field) => field.1 = value,
+                None => fields.push((key, value)),
+            }
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RightBrace, "to close the object")?;
+        Ok(fields)
+    }
+}
+
+fn header_name(token: &Token) -> Option<&'static str> {
+    match token {
+        Token::File => Some("file"),
+        Token::Math => Some("math"),
+        Token::Http => Some("http"),
+        Token::Ui => Some("ui"),
+        Token::Root => Some("root"),
+        Token::Data => Some("data"),
+        Token::Time => Some("time"),
+        _ => None,
+    }
+}
+
+fn example_attribute(header: &str) -> &'static str {
+    match header {
+        "file" => "read",
+        "math" => "add",
+        "http" => "get",
+        "ui" => "window",
+        "root" => "exec",
+        "data" => "parse",
+        _ => "now",
+    }
+}
+
+/// The word a keyword token was written as
+pub fn keyword_text(token: &Token) -> Option<&'static str> {
+    let word = match token {
+        Token::If => "if",
+        Token::Else => "else",
+        Token::ElseIf => "else if",
+        Token::Loop => "loop",
+        Token::LoopUntil => "until",
+        Token::Repeat => "repeat",
+        Token::For => "for",
+        Token::Define => "define",
+        Token::Function => "function",
+        Token::Return => "return",
+        Token::Break => "break",
+        Token::Var => "var",
+        Token::Output => "output",
+        Token::Print => "print",
+        Token::IntType => "int",
+        Token::FloatType => "float",
+        Token::StringType => "string",
+        Token::BooleanType => "boolean",
+        Token::ArrayType => "array",
+        Token::ObjectType => "object",
+        Token::True => "true",
+        Token::False => "false",
+        Token::Null => "null",
+        other => return header_name(other),
+    };
+    Some(word)
+}
+
+/// A token as it would be named in an error message
+pub fn describe(token: &Token) -> String {
+    match token {
+        Token::Identifier(name) => format!("'{}'", name),
+        Token::Number(n) => format!("the number {}", n),
+        Token::Float(x) => format!("the number {}", x),
+        Token::String(s) => format!("the string \"{}\"", s),
+        Token::Eof => "the end of the file".to_string(),
+        other => match keyword_text(other) {
+            Some(word) => format!("'{}'", word),
+            None => format!("'{}'", symbol(other)),
+        },
+    }
+}
+
+fn symbol(token: &Token) -> &'static str {
+    match token {
+        Token::Plus => "+",
+        Token::Minus => "-",
+        Token::Star => "*",
+        Token::Slash => "/",
+        Token::Percent => "%",
+        Token::Equal => "=",
+        Token::EqualEqual => "==",
+        Token::NotEqual => "!=",
+        Token::Less => "<",
+        Token::Greater => ">",
+        Token::LessEqual => "<=",
+        Token::GreaterEqual => ">=",
+        Token::And => "&&",
+        Token::Or => "||",
+        Token::Not => "!",
+        Token::LeftParen => "(",
+        Token::RightParen => ")",
+        Token::LeftBrace => "{",
+        Token::RightBrace => "}",
+        Token::LeftBracket => "[",
+        Token::RightBracket => "]",
+        Token::Comma => ",",
+        Token::Dot => ".",
+        Token::Colon => ":",
+        _ => "?",
+    }
+}
@@ -0,0 +1,374 @@
+// Projects for Prizm
+// A project is a directory with a prizm.toml in it. The manifest names the
+// project and its entry point and lists its dependencies; tools keep their
+// settings in it too, like the [lint] table. Commands run anywhere inside a
+// project find it by looking in each directory above the current one.
+
+use crate::stdlib::builtins::data::toml;
+use crate::value::Value;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST: &str = "prizm.toml";
+
+// Where `prizm run` starts when the manifest doesn't say
+const DEFAULT_ENTRY: &str = "src/center.pzm";
+
+// Where tests are kept, relative to the project root
+const TESTS: &str = "tests";
+
+// Where `prizm build` writes modules, relative to the project root
+const BUILD: &str = "build";
+
+// Directories that hold build output rather than sources
+const OUTPUT_DIRS: [&str; 3] = [BUILD, "bin", "target"];
+
+pub struct Project {
+    pub root: PathBuf,
+    pub name: String,
+    pub version: String,
+    // Relative to the root
+    pub entry: PathBuf,
+    pub dependencies: Vec<Dependency>,
+}
+
+pub struct Dependency {
+    pub name: String,
+    pub source: Source,
+}
+
+pub enum Source {
+    Version(String),
+    // Another project on disk, relative to this project's root
+    Path(PathBuf),
+}
+
+/// The nearest prizm.toml in `dir` or a directory above it
+pub fn manifest(dir: &Path) -> Option<PathBuf> {
+    let dir = std::fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    dir.ancestors()
+        .map(|dir| dir.join(MANIFEST))
+        .find(|path| path.is_file())
+}
+
+impl Project {
+    /// The project `dir` is in, if it is in one
+    pub fn find(dir: &Path) -> Result<Option<Project>, String> {
+        manifest(dir).map(|path| Project::load(&path)).transpose()
+    }
+
+    /// Read a project from its manifest, checking that its path
+    /// dependencies are projects too
+    pub fn load(manifest: &Path) -> Result<Project, String> {
+        let text = std::fs::read_to_string(manifest)
+            .map_err(|e| format!("Failed to read {}: {}", manifest.display(), e))?;
+        let root = manifest.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let error = |e: String| format!("{}: {}", manifest.display(), e);
+        let project = Project::parse(&text, root).map_err(error)?;
+        for dependency in &project.dependencies {
+            if let Source::Path(path) = &dependency.source {
+                if !project.root.join(path).join(MANIFEST).is_file() {
+                    return Err(error(format!(
+                        "Dependency '{}' has no {} at {}",
+                        dependency.name,
+                        MANIFEST,
+                        path.display()
+                    )));
+                }
+            }
+        }
+        Ok(project)
+    }
+
+    /// Parse a manifest for the project at `root`. Every key of [package] is
+    /// optional, so a prizm.toml with only tool settings still marks a project.
+    pub fn parse(text: &str, root: PathBuf) -> Result<Project, String> {
+        let tables = match toml::parse(text)? {
+            Value::Object(fields) => fields,
+            _ => Vec::new(),
+        };
+        let mut project = Project {
+            name: root
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            version: "0.1.0".to_string(),
+            entry: PathBuf::new(),
+            dependencies: Vec::new(),
+            root,
+        };
+        let mut entry = None;
+        for (table, value) in tables {
+            match (table.as_str(), value) {
+                ("package", Value::Object(fields)) => {
+                    for (key, value) in fields {
+                        let Value::String(value) = value else {
+                            return Err(format!("package.{} must be a string", key));
+                        };
+                        match key.as_str() {
+                            "name" => project.name = value,
+                            "version" => project.version = value,
+                            "entry" => entry = Some(PathBuf::from(value)),
+                            _ => {
+                                return Err(format!(
+                                    "Unknown key package.{} (expected name, version or entry)",
+                                    key
+                                ))
+                            }
+                        }
+                    }
+                }
+                ("dependencies", Value::Object(fields)) => {
+                    for (name, value) in fields {
+                        project.dependencies.push(Dependency::parse(name, value)?);
+                    }
+                }
+                ("package" | "dependencies", _) => {
+                    return Err(format!("[{}] must be a table", table))
+                }
+                // [lint] and the like belong to the tools
+                _ => {}
+            }
+        }
+        // Projects made before src/ was used keep center.pzm at the root
+        project.entry = entry.unwrap_or_else(|| {
+            if !project.root.join(DEFAULT_ENTRY).exists()
+                && project.root.join("center.pzm").exists()
+            {
+                PathBuf::from("center.pzm")
+            } else {
+                PathBuf::from(DEFAULT_ENTRY)
+            }
+        });
+        Ok(project)
+    }
+
+    /// The file `prizm run` starts from
+    pub fn entry(&self) -> PathBuf {
+        self.root.join(&self.entry)
+    }
+
+    /// Every .pzm file in the project outside tests/, in path order
+    pub fn sources(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        collect(&self.root, &self.root, "pzm", &mut files);
+        let tests = self.root.join(TESTS);
+        files.retain(|file| !file.starts_with(&tests));
+        files
+    }
+
+    /// Every .pzm file in tests/, in path order
+    pub fn tests(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        collect(&self.root, &self.root.join(TESTS), "pzm", &mut files);
+        files
+    }
+
+    /// Where `prizm build` writes the modules of a profile
+    pub fn output_dir(&self, profile: &str) -> PathBuf {
+        self.root.join(BUILD).join(profile)
+    }
+
+    /// The compiled module for a source file, given relative to the root
+    pub fn output(&self, profile: &str, source: &Path) -> PathBuf {
+        self.output_dir(profile).join(source).with_extension("pzmc")
+    }
+
+    /// Every module built for a profile, in path order
+    pub fn outputs(&self, profile: &str) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        collect(&self.root, &self.output_dir(profile), "pzmc", &mut files);
+        files
+    }
+}
+
+impl Dependency {
+    // `name = "1.0"`, or `name = { path = "../name" }`
+    fn parse(name: String, value: Value) -> Result<Dependency, String> {
+        let source = match value {
+            Value::String(version) => Source::Version(version),
+            Value::Object(fields) => match fields.as_slice() {
+                [(key, Value::String(path))] if key == "path" => Source::Path(PathBuf::from(path)),
+                _ => {
+                    return Err(format!(
+                        "Dependency '{}' must be {{ path = \"...\" }}",
+                        name
+                    ))
+                }
+            },
+            _ => {
+                return Err(format!(
+                    "Dependency '{}' must be a version or {{ path = \"...\" }}",
+                    name
+                ))
+            }
+        };
+        Ok(Dependency { name, source })
+    }
+}
+
+// Add the files with `extension` under `dir` to `files`, leaving out hidden
+// directories, build output and nested projects
+fn collect(root: &Path, dir: &Path, extension: &str, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut paths: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+    paths.sort();
+    for path in paths {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_dir() {
+            let output = dir == root && OUTPUT_DIRS.contains(&name.as_ref());
+            if !name.starts_with('.') && !output && !path.join(MANIFEST).is_file() {
+                collect(root, &path, extension, files);
+            }
+        } else if path.extension().is_some_and(|found| found == extension) {
+            files.push(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A directory of its own for each test, with these files in it
+    fn directory(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("prizm-project-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (name, text) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, text).unwrap();
+        }
+        std::fs::canonicalize(&dir).unwrap()
+    }
+
+    fn relative(project: &Project, files: Vec<PathBuf>) -> Vec<String> {
+        files
+            .iter()
+            .map(|file| {
+                file.strip_prefix(&project.root)
+                    .unwrap()
+                    .display()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn projects_are_found_from_any_directory_inside_them() {
+        let dir = directory(
+            "find",
+            &[
+                ("app/prizm.toml", "[package]\nname = \"app\"\n"),
+                ("app/src/deep/x.pzm", ""),
+                ("app/vendor/prizm.toml", ""),
+            ],
+        );
+        let project = Project::find(&dir.join("app/src/deep")).unwrap().unwrap();
+        assert_eq!(project.root, dir.join("app"));
+        assert_eq!(project.name, "app");
+        // A project inside another is a project of its own
+        let nested = Project::find(&dir.join("app/vendor")).unwrap().unwrap();
+        assert_eq!(nested.root, dir.join("app/vendor"));
+        assert_eq!(nested.name, "vendor");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn sources_leave_out_tests_build_output_and_nested_projects() {
+        let dir = directory(
+            "sources",
+            &[
+                ("prizm.toml", ""),
+                ("src/center.pzm", ""),
+                ("src/lib/util.pzm", ""),
+                ("src/notes.txt", ""),
+                ("tests/center.pzm", ""),
+                ("build/debug/src/center.pzmc", ""),
+                ("bin/tool.pzm", ""),
+                (".hidden/x.pzm", ""),
+                ("vendor/prizm.toml", ""),
+                ("vendor/src/center.pzm", ""),
+            ],
+        );
+        let project = Project::find(&dir).unwrap().unwrap();
+        assert_eq!(project.entry(), dir.join("src/center.pzm"));
+        assert_eq!(
+            relative(&project, project.sources()),
+            ["src/center.pzm", "src/lib/util.pzm"]
+        );
+        assert_eq!(relative(&project, project.tests()), ["tests/center.pzm"]);
+        assert_eq!(
+            relative(&project, project.outputs("debug")),
+            ["build/debug/src/center.pzmc"]
+        );
+        assert_eq!(
+            project.output("release", Path::new("src/lib/util.pzm")),
+            dir.join("build/release/src/lib/util.pzmc")
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn manifests_set_the_entry_and_dependencies() {
+        let dir = directory("manifest", &[("old/center.pzm", "")]);
+        let project = Project::parse(
+            "[package]\nname = \"app\"\nversion = \"1.2.0\"\nentry = \"main.pzm\"\n\
+             [dependencies]\nlib = { path = \"../lib\" }\njson = \"1.0\"\n\
+             [lint]\nroot-usage = \"off\"\n",
+            dir.join("app"),
+        )
+        .unwrap();
+        assert_eq!(
+            (project.name.as_str(), project.version.as_str()),
+            ("app", "1.2.0")
+        );
+        assert_eq!(project.entry, PathBuf::from("main.pzm"));
+        assert!(matches!(
+            project.dependencies.as_slice(),
+            [
+                Dependency { name: lib, source: Source::Path(path) },
+                Dependency { name: json, source: Source::Version(version) },
+            ] if lib == "lib" && path == Path::new("../lib") && json == "json" && version == "1.0"
+        ));
+
+        // Older projects keep center.pzm at the root
+        let project = Project::parse("", dir.join("old")).unwrap();
+        assert_eq!(
+            (project.name.as_str(), project.version.as_str()),
+            ("old", "0.1.0")
+        );
+        assert_eq!(project.entry, PathBuf::from("center.pzm"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn malformed_manifests_are_errors() {
+        let error = |text| Project::parse(text, PathBuf::from("app")).err().unwrap();
+        assert_eq!(
+            error("[package]\nname = 1\n"),
+            "package.name must be a string"
+        );
+        assert_eq!(
+            error("[package]\nmain = \"x\"\n"),
+            "Unknown key package.main (expected name, version or entry)"
+        );
+        assert_eq!(error("package = 1\n"), "[package] must be a table");
+        assert_eq!(
+            error("[dependencies]\nlib = { git = \"x\" }\n"),
+            "Dependency 'lib' must be { path = \"...\" }"
+        );
+
+        let dir = directory(
+            "missing",
+            &[("prizm.toml", "[dependencies]\nlib = { path = \"lib\" }\n")],
+        );
+        assert!(Project::find(&dir)
+            .err()
+            .unwrap()
+            .ends_with("Dependency 'lib' has no prizm.toml at lib"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
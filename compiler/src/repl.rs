@@ -0,0 +1,335 @@
+// REPL for Prizm Language
+// Runs Prizm an input at a time. Variables and functions persist from one
+// input to the next, an input that leaves a block, list or string open
+// continues on the next line, and an expression on its own is printed and
+// kept in `_`.
+
+mod line;
+
+use crate::ast::{self, Expr, ExprKind, Program, Stmt, StmtKind};
+use crate::attributes;
+use crate::bytecode::{self, Module};
+use crate::diagnostics::{self, Diagnostic};
+use crate::lexer::{Lexer, Token};
+use crate::parser;
+use crate::value::Value;
+use crate::vm::{Runtime, Vm};
+use line::{Editor, Line};
+use std::path::PathBuf;
+
+// Where the value of an expression on its own is kept, as in Python
+const RESULT: &str = "_";
+
+// Kept in the home directory, one line per entry
+const HISTORY_FILE: &str = ".prizm_history";
+
+// Values and lists wider than this are printed over several lines
+const WIDTH: usize = 80;
+
+const HELP: &str = "\
+Enter Prizm to run it; blocks continue on the next line until they are closed.
+
+:type <expression>   Show the type of an expression's value
+:headers [header]    List the headers, or one header's attributes
+:history             Show the lines entered so far
+:help                Show this help
+:quit                Leave (Ctrl+D does too)";
+
+pub struct Repl {
+    vm: Vm,
+    // Every function defined so far, compiled again with each input
+    definitions: Vec<Stmt>,
+    editor: Editor,
+    color: bool,
+}
+
+impl Repl {
+    pub fn new(runtime: Runtime) -> Self {
+        let history = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+        Repl {
+            vm: Vm::new(Module::default(), runtime),
+            definitions: Vec::new(),
+            editor: Editor::new(history),
+            color: diagnostics::color(&std::io::stderr()),
+        }
+    }
+
+    /// Read and run inputs until the user leaves or the input ends
+    pub fn run(&mut self) {
+        if self.editor.interactive() {
+            println!(
+                "Prizm {} (:help for commands, :quit to leave)",
+                env!("CARGO_PKG_VERSION")
+            );
+        }
+        while let Some(input) = self.read() {
+            let input = input.trim();
+            if let Some(command) = input.strip_prefix(':') {
+                if !self.command(command) {
+                    break;
+                }
+            } else if !input.is_empty() {
+                self.eval(input);
+            }
+        }
+    }
+
+    // One whole input, over as many lines as it takes to close what it opens
+    fn read(&mut self) -> Option<String> {
+        let mut input = String::new();
+        loop {
+            let prompt = if input.is_empty() {
+                "prizm> "
+            } else {
+                "   ... "
+            };
+            match self.editor.read(prompt) {
+                Line::Text(line) => {
+                    self.editor.add(&line);
+                    input.push_str(&line);
+                    input.push('\n');
+                    if input.trim_start().starts_with(':') || complete(&input) {
+                        return Some(input);
+                    }
+                }
+                // Ctrl+C drops what has been typed so far
+                Line::Interrupted => input.clear(),
+                Line::End if input.is_empty() => return None,
+                Line::End => return Some(input),
+            }
+        }
+    }
+
+    // Run a `:command`, returning false to leave
+    fn command(&mut self, command: &str) -> bool {
+        let (name, rest) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+        match name {
+            "quit" | "exit" | "q" => return false,
+            "help" => println!("{}", HELP),
+            "type" => {
+                if let Some(value) = self.evaluate(rest) {
+                    println!("{}", value.type_of().to_string());
+                }
+            }
+            "headers" => self.headers(rest.trim()),
+            "history" => {
+                for (i, line) in self.editor.history().iter().enumerate() {
+                    println!("{:>5}  {}", i + 1, line);
+                }
+            }
+            _ => eprintln!("Unknown command ':{}' (:help lists them)", name),
+        }
+        true
+    }
+
+    fn eval(&mut self, input: &str) {
+        match self.evaluate(input) {
+            Some(Value::Null) | None => {}
+            Some(value) => println!("{}", show(&value)),
+        }
+    }
+
+    // Run an input, returning its value if it ends with an expression on its own
+    fn evaluate(&mut self, input: &str) -> Option<Value> {
+        let mut program = match parser::parse(input) {
+            Ok(program) => program,
+            Err(diagnostic) => return self.report(input, &diagnostic),
+        };
+        let kept = keep_result(&mut program);
+
+        // Functions defined now replace those of the same name, and all of
+        // them are compiled again so the rest of the input can call them
+        let (defined, statements): (Vec<Stmt>, Vec<Stmt>) = program
+            .statements
+            .into_iter()
+            .partition(|stmt| defines(stmt).is_some());
+        let mut definitions = self.definitions.clone();
+        definitions.retain(|old| !defined.iter().any(|new| defines(new) == defines(old)));
+        definitions.extend(defined);
+        let program = Program {
+            statements: definitions.iter().cloned().chain(statements).collect(),
+        };
+        let module = match bytecode::compile(&program) {
+            Ok(module) => module,
+            Err(diagnostic) => return self.report(input, &diagnostic),
+        };
+        self.definitions = definitions;
+
+        if let Err(e) = self.vm.run_next(module) {
+            match Diagnostic::runtime(&e) {
+                // Its line may be in an earlier input, so only the message is shown
+                Some(diagnostic) => eprintln!("{}", diagnostic.header(self.color)),
+                None => eprintln!("{}", e),
+            }
+            return None;
+        }
+        if kept {
+            self.vm.global(RESULT).cloned()
+        } else {
+            None
+        }
+    }
+
+    fn report(&self, input: &str, diagnostic: &Diagnostic) -> Option<Value> {
+        eprint!("{}", diagnostic.render("<repl>", input, self.color));
+        None
+    }
+
+    fn headers(&self, name: &str) {
+        if name.is_empty() {
+            for (header, _) in attributes::HEADERS {
+                let attributes = attributes::header_attributes(header);
+                println!("{}", wrap(&format!("{:<6}", header), &attributes));
+            }
+            return;
+        }
+        let attributes = attributes::header_attributes(name);
+        if attributes.is_empty() {
+            eprintln!("Unknown header '{}'", name);
+            return;
+        }
+        for attribute in attributes {
+            println!("{}.{}", name, attribute);
+        }
+    }
+}
+
+// ===== INPUT =====
+
+// Whether an input closes every string, block, list and call it opens
+fn complete(input: &str) -> bool {
+    let tokens = match Lexer::new(input).tokenize() {
+        Ok(tokens) => tokens,
+        Err(diagnostic) => return diagnostic.code != "P0002",
+    };
+    let mut depth = 0i32;
+    for (token, _) in tokens {
+        match token {
+            Token::LeftBrace | Token::LeftBracket | Token::LeftParen => depth += 1,
+            Token::RightBrace | Token::RightBracket | Token::RightParen => depth -= 1,
+            _ => {}
+        }
+    }
+    // Too many closers is an error for the parser to report, not a reason to wait
+    depth <= 0
+}
+
+// Turn a trailing expression into `var _ = ...`, unless it prints by itself
+fn keep_result(program: &mut Program) -> bool {
+    let Some(stmt) = program.statements.pop() else {
+        return false;
+    };
+    let (kind, kept) = match stmt.kind {
+        StmtKind::Expr(expr) if !prints(&expr) => (
+            StmtKind::Var {
+                name: RESULT.to_string(),
+                type_name: None,
+                value: expr,
+            },
+            true,
+        ),
+        kind => (kind, false),
+    };
+    program.statements.push(Stmt::new(kind, stmt.span));
+    kept
+}
+
+fn prints(expr: &Expr) -> bool {
+    matches!(&expr.kind, ExprKind::Call { name, .. } if name == "output" || name == "print")
+}
+
+fn defines(stmt: &Stmt) -> Option<&str> {
+    match &stmt.kind {
+        StmtKind::Define(function) => Some(&function.name),
+        _ => None,
+    }
+}
+
+// ===== OUTPUT =====
+
+// A header's attributes after its name, wrapped to the width
+fn wrap(name: &str, attributes: &[&str]) -> String {
+    let indent = " ".repeat(name.len() + 1);
+    let mut text = name.to_string();
+    let mut column = text.len();
+    for (i, attribute) in attributes.iter().enumerate() {
+        let word = if i + 1 < attributes.len() {
+            format!("{},", attribute)
+        } else {
+            attribute.to_string()
+        };
+        if column + 1 + word.len() > WIDTH && column > indent.len() {
+            text.push('\n');
+            text.push_str(&indent);
+            column = indent.len();
+        } else {
+            text.push(' ');
+            column += 1;
+        }
+        column += word.len();
+        text.push_str(&word);
+    }
+    text
+}
+
+// A value as it would be written in Prizm, one item or field per line when
+// it doesn't fit on one
+fn show(value: &Value) -> String {
+    let mut text = String::new();
+    write_value(&mut text, value, 0, 0);
+    text
+}
+
+fn write_value(text: &mut String, value: &Value, indent: usize, column: usize) {
+    let flat = flat(value);
+    if column + flat.len() <= WIDTH {
+        text.push_str(&flat);
+        return;
+    }
+    let inner = "    ".repeat(indent + 1);
+    match value {
+        Value::Array(items) if !items.is_empty() => {
+            text.push_str("[\n");
+            for item in items {
+                text.push_str(&inner);
+                write_value(text, item, indent + 1, inner.len());
+                text.push_str(",\n");
+            }
+            text.push_str(&"    ".repeat(indent));
+            text.push(']');
+        }
+        Value::Object(fields) if !fields.is_empty() => {
+            text.push_str("{\n");
+            for (key, field) in fields {
+                let key = format!("{}{}: ", inner, ast::field_name(key));
+                text.push_str(&key);
+                write_value(text, field, indent + 1, key.len());
+                text.push_str(",\n");
+            }
+            text.push_str(&"    ".repeat(indent));
+            text.push('}');
+        }
+        _ => text.push_str(&flat),
+    }
+}
+
+fn flat(value: &Value) -> String {
+    match value {
+        Value::String(s) => ast::quote(s),
+        Value::Float(x) => ast::float(*x),
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(flat).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Object(fields) => {
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(key, field)| format!("{}: {}", ast::field_name(key), flat(field)))
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        other => other.to_string(),
+    }
+}
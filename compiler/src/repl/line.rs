@@ -0,0 +1,188 @@
+// Line Editing for the REPL
+// When stdin is a terminal, lines are edited in place: the arrow keys move
+// along the line and through the history, Ctrl+C drops the line and Ctrl+D
+// on an empty line ends the input. Otherwise lines are read as they come.
+
+use crate::stdlib::builtins::ui::terminal;
+use std::fs::OpenOptions;
+use std::io::{BufRead, IsTerminal, Read, Write};
+use std::path::PathBuf;
+
+// How many lines the history file keeps
+const HISTORY_SIZE: usize = 1000;
+
+pub enum Line {
+    Text(String),
+    Interrupted,
+    End,
+}
+
+pub struct Editor {
+    history: Vec<String>,
+    file: Option<PathBuf>,
+    interactive: bool,
+}
+
+impl Editor {
+    /// An editor whose history is kept in `file`, if there is one
+    pub fn new(file: Option<PathBuf>) -> Self {
+        let mut history: Vec<String> = file
+            .as_ref()
+            .and_then(|file| std::fs::read_to_string(file).ok())
+            .map(|text| text.lines().map(String::from).collect())
+            .unwrap_or_default();
+        if history.len() > HISTORY_SIZE {
+            history.drain(..history.len() - HISTORY_SIZE);
+            if let Some(file) = &file {
+                // The history is a convenience, so failing to save it is not an error
+                let _ = std::fs::write(file, history.join("\n") + "\n");
+            }
+        }
+        Editor {
+            history,
+            file,
+            interactive: std::io::stdin().is_terminal(),
+        }
+    }
+
+    /// Whether lines are typed by someone, rather than piped in
+    pub fn interactive(&self) -> bool {
+        self.interactive
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Add a line to the history, unless it is blank or repeats the last one
+    pub fn add(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(file) {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+
+    /// Read one line, showing the prompt if someone is typing
+    pub fn read(&mut self, prompt: &str) -> Line {
+        if self.interactive {
+            if let Some(mode) = terminal::raw_mode(&["-isig"]) {
+                let line = self.edit(prompt);
+                terminal::restore_mode(&mode);
+                return line;
+            }
+            print!("{}", prompt);
+            let _ = std::io::stdout().flush();
+        }
+        let mut line = String::new();
+        match std::io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => Line::End,
+            Ok(_) => Line::Text(line.trim_end_matches(['\n', '\r']).to_string()),
+        }
+    }
+
+    // Read a line key by key, with the terminal in raw mode
+    fn edit(&mut self, prompt: &str) -> Line {
+        let mut line: Vec<char> = Vec::new();
+        let mut cursor = 0;
+        // How far back Up has gone; history.len() is the line being typed
+        let mut position = self.history.len();
+        let mut typed = Vec::new();
+        let mut stdin = std::io::stdin().lock();
+        let mut buffer = [0u8; 64];
+        redraw(prompt, &line, cursor);
+        loop {
+            let count = match stdin.read(&mut buffer) {
+                Ok(0) | Err(_) => return Line::End,
+                Ok(count) => count,
+            };
+            let keys = String::from_utf8_lossy(&buffer[..count]).into_owned();
+            let mut keys = keys.chars();
+            while let Some(key) = keys.next() {
+                match key {
+                    '\r' | '\n' => {
+                        redraw(prompt, &line, line.len());
+                        finish("");
+                        return Line::Text(line.into_iter().collect());
+                    }
+                    '\x03' => {
+                        finish("^C");
+                        return Line::Interrupted;
+                    }
+                    '\x04' if line.is_empty() => {
+                        finish("");
+                        return Line::End;
+                    }
+                    '\x7f' | '\x08' if cursor > 0 => {
+                        cursor -= 1;
+                        line.remove(cursor);
+                    }
+                    '\x01' => cursor = 0,
+                    '\x05' => cursor = line.len(),
+                    '\t' => {
+                        line.splice(cursor..cursor, "    ".chars());
+                        cursor += 4;
+                    }
+                    // Escape sequences: arrows, Home, End and Delete
+                    '\x1b' => {
+                        if !matches!(keys.next(), Some('[' | 'O')) {
+                            continue;
+                        }
+                        match keys.next() {
+                            Some('A') if position > 0 => {
+                                if position == self.history.len() {
+                                    typed = line.clone();
+                                }
+                                position -= 1;
+                                line = self.history[position].chars().collect();
+                                cursor = line.len();
+                            }
+                            Some('B') if position < self.history.len() => {
+                                position += 1;
+                                line = match self.history.get(position) {
+                                    Some(entry) => entry.chars().collect(),
+                                    None => typed.clone(),
+                                };
+                                cursor = line.len();
+                            }
+                            Some('C') => cursor = (cursor + 1).min(line.len()),
+                            Some('D') => cursor = cursor.saturating_sub(1),
+                            Some('H') => cursor = 0,
+                            Some('F') => cursor = line.len(),
+                            Some('3') if keys.next() == Some('~') && cursor < line.len() => {
+                                line.remove(cursor);
+                            }
+                            _ => {}
+                        }
+                    }
+                    key if key.is_control() => {}
+                    key => {
+                        line.insert(cursor, key);
+                        cursor += 1;
+                    }
+                }
+            }
+            redraw(prompt, &line, cursor);
+        }
+    }
+}
+
+// Draw the prompt and line again, with the cursor in its place
+fn redraw(prompt: &str, line: &[char], cursor: usize) {
+    let text: String = line.iter().collect();
+    print!("\r\x1b[K{}{}", prompt, text);
+    if cursor < line.len() {
+        print!("\x1b[{}D", line.len() - cursor);
+    }
+    let _ = std::io::stdout().flush();
+}
+
+// Move to the next line, spelling out the "\r" in case the terminal doesn't add it
+fn finish(mark: &str) {
+    print!("{}\r\n", mark);
+    let _ = std::io::stdout().flush();
+}
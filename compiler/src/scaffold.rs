@@ -0,0 +1,132 @@
+// Project Templates for Prizm
+// `prizm init` and `prizm new` write a project from one of these templates.
+// The files are embedded in the binary, so nothing has to be installed beside
+// it; `{{name}}` in them is replaced by the project's name.
+
+use crate::project::MANIFEST;
+use std::path::{Path, PathBuf};
+
+/// A kind of project, and the files it starts with
+pub struct Template {
+    pub name: &'static str,
+    pub description: &'static str,
+    // Paths in the project, with their contents
+    files: &'static [(&'static str, &'static str)],
+}
+
+// The first is used when no template is asked for
+pub const TEMPLATES: [Template; 3] = [
+    Template {
+        name: "cli",
+        description: "A command line program",
+        files: &[
+            (
+                "src/center.pzm",
+                include_str!("../templates/cli/center.pzm"),
+            ),
+            ("tests/greet.pzm", include_str!("../templates/cli/test.pzm")),
+        ],
+    },
+    Template {
+        name: "ui",
+        description: "A window with a button, drawn by the ui header",
+        files: &[
+            ("src/center.pzm", include_str!("../templates/ui/center.pzm")),
+            (
+                "tests/counter.pzm",
+                include_str!("../templates/ui/test.pzm"),
+            ),
+        ],
+    },
+    Template {
+        name: "http",
+        description: "A client for a JSON API",
+        files: &[
+            (
+                "src/center.pzm",
+                include_str!("../templates/http/center.pzm"),
+            ),
+            (
+                "tests/describe.pzm",
+                include_str!("../templates/http/test.pzm"),
+            ),
+        ],
+    },
+];
+
+// Every project gets these as well as its template's files
+const COMMON: [(&str, &str); 3] = [
+    (MANIFEST, include_str!("../templates/prizm.toml")),
+    (".gitignore", include_str!("../templates/gitignore")),
+    ("README.md", include_str!("../templates/README.md")),
+];
+
+pub fn template(name: &str) -> Option<&'static Template> {
+    TEMPLATES.iter().find(|template| template.name == name)
+}
+
+/// Whether `name` can name a project: letters, digits, `-` and `_`
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Write a project called `name` into `dir` from `template`, creating the
+/// directory if needed. Files that already exist are left alone; returns the
+/// files written.
+pub fn create(dir: &Path, name: &str, template: &Template) -> Result<Vec<PathBuf>, String> {
+    if !valid_name(name) {
+        return Err(format!(
+            "'{}' can't name a project; use letters, digits, - and _",
+            name
+        ));
+    }
+    if dir.join(MANIFEST).exists() {
+        return Err(format!("{} is already a Prizm project", dir.display()));
+    }
+    let mut written = Vec::new();
+    for (path, contents) in COMMON.iter().chain(template.files) {
+        let path = dir.join(path);
+        if path.exists() {
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        std::fs::write(&path, contents.replace("{{name}}", name))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lint::{lint, Config, Source};
+
+    #[test]
+    fn template_programs_compile_and_lint_clean() {
+        for template in &TEMPLATES {
+            for (path, contents) in template.files {
+                let text = contents.replace("{{name}}", "app");
+                let source = Source::parse(&text).unwrap();
+                let findings: Vec<_> = lint(&source, &Config::default())
+                    .into_iter()
+                    .map(|finding| finding.message)
+                    .collect();
+                assert!(
+                    findings.is_empty(),
+                    "{} in the {} template: {:?}",
+                    path,
+                    template.name,
+                    findings
+                );
+                crate::bytecode::compile(&crate::parser::parse(&text).unwrap()).unwrap();
+            }
+        }
+    }
+}
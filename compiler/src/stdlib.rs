@@ -37,6 +37,17 @@ pub mod builtins {
                 Err(e) => Err(format!("Failed to write file: {}", e)),
             }
         }
+
+        /// Replace every occurrence of `old` in a file, returning how many there were
+        pub fn replace(path: &str, old: &str, new: &str) -> Result<usize, String> {
+            if old.is_empty() {
+                return Err("Failed to replace in file: the text to find is empty".to_string());
+            }
+            let content = read(path)?;
+            let count = content.matches(old).count();
+            write(path, &content.replace(old, new))?;
+            Ok(count)
+        }
     }
 
     // Math Operations
@@ -110,4 +121,16 @@ pub mod builtins {
             s.to_lowercase()
         }
     }
+
+    // Data/JSON Operations
+    pub mod data;
+
+    // Time Operations
+    pub mod time;
+
+    // UI Operations
+    pub mod ui;
+
+    // Root Operations
+    pub mod root;
 }
\ No newline at end of file
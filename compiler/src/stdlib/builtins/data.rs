@@ -0,0 +1,737 @@
+// Data/JSON Operations
+// Implements the `data` header on top of Prizm values
+
+use crate::attributes::PrizmType;
+use crate::value::{ArrayStrategy, MergeOptions, Value};
+use std::fs::File;
+use std::io::BufReader;
+
+pub mod csv;
+pub mod ini;
+pub mod json;
+pub mod toml;
+pub mod yaml;
+
+// ===== PRIZM JSON =====
+
+/// Read Prizm JSON: standard JSON, or a Prizm object literal as written
+pub fn parse(text: &str) -> Result<Value, String> {
+    json::parse(text)
+}
+
+/// Write a value as standard JSON, on one line unless `pretty`
+pub fn stringify(value: &Value, pretty: bool) -> Result<String, String> {
+    json::stringify(value, pretty)
+}
+
+// ===== VALIDATION =====
+
+/// A single schema mismatch, located by its path from the root (`$.tags[2]`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub path: String,
+    pub message: String,
+}
+
+impl Violation {
+    fn new(path: &str, message: String) -> Self {
+        Violation {
+            path: path.to_string(),
+            message,
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        Value::Object(vec![
+            ("path".to_string(), Value::String(self.path.clone())),
+            ("message".to_string(), Value::String(self.message.clone())),
+        ])
+    }
+}
+
+/// Validate a value, returning every violation found (empty means valid).
+///
+/// Without a schema the value only has to be representable as Prizm JSON.
+/// A schema is either written in Prizm (`{name: "string", tags: ["string"]}`)
+/// or is a JSON Schema subset, recognised by a `$schema` key or by `type`
+/// alongside `properties`/`items`. Errors are reserved for malformed schemas.
+pub fn validate(value: &Value, schema: Option<&Value>) -> Result<Vec<Violation>, String> {
+    let mut violations = Vec::new();
+    match schema {
+        None => check_plain_data(value, "$", &mut violations),
+        Some(schema) if is_json_schema(schema) => {
+            check_json_schema(value, schema, "$", &mut violations)?
+        }
+        Some(schema) => check_prizm_schema(value, schema, "$", &mut violations)?,
+    }
+    Ok(violations)
+}
+
+fn field_path(path: &str, key: &str) -> String {
+    format!("{}.{}", path, key)
+}
+
+fn index_path(path: &str, index: usize) -> String {
+    format!("{}[{}]", path, index)
+}
+
+fn type_matches(value: &Value, expected: &PrizmType) -> bool {
+    match (expected, value) {
+        (PrizmType::Any, _) => true,
+        // Integers widen to floats, as they do in arithmetic
+        (PrizmType::Float, Value::Int(_)) => true,
+        (expected, value) => value.type_of() == *expected,
+    }
+}
+
+fn mismatch(expected: &str, value: &Value) -> String {
+    format!(
+        "expected {}, found {}",
+        expected,
+        value.type_of().to_string()
+    )
+}
+
+fn check_plain_data(value: &Value, path: &str, out: &mut Vec<Violation>) {
+    match value {
+        Value::Float(f) if !f.is_finite() => out.push(Violation::new(
+            path,
+            format!("{} cannot be stored as data", f),
+        )),
+        Value::Duration(_) | Value::Function(_) | Value::Handle(_) => out.push(Violation::new(
+            path,
+            format!("a {} cannot be stored as data", value.type_of().to_string()),
+        )),
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                check_plain_data(item, &index_path(path, i), out);
+            }
+        }
+        Value::Object(fields) => {
+            for (i, (key, field)) in fields.iter().enumerate() {
+                if fields[..i].iter().any(|(k, _)| k == key) {
+                    out.push(Violation::new(
+                        &field_path(path, key),
+                        "duplicate field".to_string(),
+                    ));
+                }
+                check_plain_data(field, &field_path(path, key), out);
+            }
+        }
+        _ => {}
+    }
+}
+
+// ----- Prizm schemas -----
+// "int"          -> leaf type, a trailing "?" also allows null or a missing field
+// ["string"]     -> array whose items all match the inner schema
+// ["int", "int"] -> fixed-length array matched position by position
+// {port: "int"}  -> object whose listed fields match; extra fields are allowed
+
+fn parse_leaf(type_name: &str, path: &str) -> Result<(PrizmType, bool), String> {
+    let (name, optional) = match type_name.strip_suffix('?') {
+        Some(name) => (name, true),
+        None => (type_name, false),
+    };
+    match PrizmType::from_string(name) {
+        Some(t) => Ok((t, optional)),
+        None => Err(format!(
+            "Unknown type '{}' in schema at {}",
+            type_name, path
+        )),
+    }
+}
+
+fn is_optional(schema: &Value) -> bool {
+    matches!(schema, Value::String(s) if s.ends_with('?'))
+}
+
+fn check_prizm_schema(
+    value: &Value,
+    schema: &Value,
+    path: &str,
+    out: &mut Vec<Violation>,
+) -> Result<(), String> {
+    match schema {
+        Value::String(type_name) => {
+            let (expected, optional) = parse_leaf(type_name, path)?;
+            let allowed = type_matches(value, &expected) || (optional && *value == Value::Null);
+            if !allowed {
+                out.push(Violation::new(path, mismatch(&expected.to_string(), value)));
+            }
+        }
+        Value::Array(item_schemas) => {
+            let items = match value {
+                Value::Array(items) => items,
+                other => {
+                    out.push(Violation::new(path, mismatch("array", other)));
+                    return Ok(());
+                }
+            };
+            match item_schemas.len() {
+                0 => {}
+                1 => {
+                    for (i, item) in items.iter().enumerate() {
+                        check_prizm_schema(item, &item_schemas[0], &index_path(path, i), out)?;
+                    }
+                }
+                n => {
+                    if items.len() != n {
+                        out.push(Violation::new(
+                            path,
+                            format!("expected {} items, found {}", n, items.len()),
+                        ));
+                    }
+                    for (i, (item, item_schema)) in items.iter().zip(item_schemas).enumerate() {
+                        check_prizm_schema(item, item_schema, &index_path(path, i), out)?;
+                    }
+                }
+            }
+        }
+        Value::Object(field_schemas) => {
+            if !matches!(value, Value::Object(_)) {
+                out.push(Violation::new(path, mismatch("object", value)));
+                return Ok(());
+            }
+            for (key, field_schema) in field_schemas {
+                let field = field_path(path, key);
+                match value.get(key) {
+                    Some(field_value) => {
+                        check_prizm_schema(field_value, field_schema, &field, out)?
+                    }
+                    None if is_optional(field_schema) => {}
+                    None => out.push(Violation::new(&field, "missing field".to_string())),
+                }
+            }
+        }
+        other => {
+            return Err(format!(
+                "Invalid schema at {}: expected a type name, array or object, found {}",
+                path,
+                other.type_of().to_string()
+            ))
+        }
+    }
+    Ok(())
+}
+
+// ----- JSON Schema subset -----
+// Supported keywords: type, enum, const, properties, required,
+// additionalProperties, items, minItems, maxItems, minLength, maxLength,
+// minimum, maximum
+
+fn is_json_schema(schema: &Value) -> bool {
+    if schema.get("$schema").is_some() {
+        return true;
+    }
+    // A Prizm schema may have fields called `type` and `items` too, so both
+    // keywords have to look like JSON Schema before the subset is used
+    matches!(schema.get("type"), Some(Value::String(_)))
+        && (schema.get("properties").is_some() || schema.get("items").is_some())
+}
+
+fn json_type_matches(value: &Value, type_name: &str, path: &str) -> Result<bool, String> {
+    let expected = match type_name {
+        "integer" => PrizmType::Int,
+        "number" => PrizmType::Float,
+        other => match PrizmType::from_string(other) {
+            Some(t) => t,
+            None => return Err(format!("Unknown type '{}' in schema at {}", other, path)),
+        },
+    };
+    Ok(type_matches(value, &expected))
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Int(n) => Some(*n as f64),
+        Value::Float(f) => Some(*f),
+        _ => None,
+    }
+}
+
+fn schema_count(schema: &Value, keyword: &str, path: &str) -> Result<Option<usize>, String> {
+    match schema.get(keyword) {
+        None => Ok(None),
+        Some(Value::Int(n)) if *n >= 0 => Ok(Some(*n as usize)),
+        Some(_) => Err(format!(
+            "Invalid schema at {}: '{}' must be a non-negative int",
+            path, keyword
+        )),
+    }
+}
+
+fn check_json_schema(
+    value: &Value,
+    schema: &Value,
+    path: &str,
+    out: &mut Vec<Violation>,
+) -> Result<(), String> {
+    if !matches!(schema, Value::Object(_)) {
+        return Err(format!("Invalid schema at {}: expected an object", path));
+    }
+
+    if let Some(types) = schema.get("type") {
+        let names: Vec<&str> = match types {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names
+                .iter()
+                .filter_map(|n| match n {
+                    Value::String(s) => Some(s.as_str()),
+                    _ => None,
+                })
+                .collect(),
+            _ => {
+                return Err(format!(
+                    "Invalid schema at {}: 'type' must be a string",
+                    path
+                ))
+            }
+        };
+        let mut matched = false;
+        for name in &names {
+            matched |= json_type_matches(value, name, path)?;
+        }
+        if !matched {
+            out.push(Violation::new(path, mismatch(&names.join(" or "), value)));
+            // Further keywords would only repeat the type error
+            return Ok(());
+        }
+    }
+
+    if let Some(Value::Array(options)) = schema.get("enum") {
+        if !options.contains(value) {
+            out.push(Violation::new(
+                path,
+                format!("{} is not one of {}", value, Value::Array(options.clone())),
+            ));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if value != expected {
+            out.push(Violation::new(
+                path,
+                format!("expected {}, found {}", expected, value),
+            ));
+        }
+    }
+
+    if let Some(number) = as_number(value) {
+        if let Some(min) = schema.get("minimum").and_then(as_number) {
+            if number < min {
+                out.push(Violation::new(
+                    path,
+                    format!("{} is less than {}", value, min),
+                ));
+            }
+        }
+        if let Some(max) = schema.get("maximum").and_then(as_number) {
+            if number > max {
+                out.push(Violation::new(
+                    path,
+                    format!("{} is greater than {}", value, max),
+                ));
+            }
+        }
+    }
+
+    if let Value::String(s) = value {
+        let length = s.chars().count();
+        if let Some(min) = schema_count(schema, "minLength", path)? {
+            if length < min {
+                out.push(Violation::new(
+                    path,
+                    format!("shorter than {} characters", min),
+                ));
+            }
+        }
+        if let Some(max) = schema_count(schema, "maxLength", path)? {
+            if length > max {
+                out.push(Violation::new(
+                    path,
+                    format!("longer than {} characters", max),
+                ));
+            }
+        }
+    }
+
+    if let Value::Array(items) = value {
+        if let Some(min) = schema_count(schema, "minItems", path)? {
+            if items.len() < min {
+                out.push(Violation::new(path, format!("fewer than {} items", min)));
+            }
+        }
+        if let Some(max) = schema_count(schema, "maxItems", path)? {
+            if items.len() > max {
+                out.push(Violation::new(path, format!("more than {} items", max)));
+            }
+        }
+        if let Some(item_schema) = schema.get("items") {
+            for (i, item) in items.iter().enumerate() {
+                check_json_schema(item, item_schema, &index_path(path, i), out)?;
+            }
+        }
+    }
+
+    if let Value::Object(fields) = value {
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for key in required {
+                if let Value::String(key) = key {
+                    if value.get(key).is_none() {
+                        out.push(Violation::new(
+                            &field_path(path, key),
+                            "missing field".to_string(),
+                        ));
+                    }
+                }
+            }
+        }
+        let properties = schema.get("properties");
+        for (key, field) in fields {
+            match properties.and_then(|p| p.get(key)) {
+                Some(field_schema) => {
+                    check_json_schema(field, field_schema, &field_path(path, key), out)?
+                }
+                None => match schema.get("additionalProperties") {
+                    Some(Value::Boolean(false)) => out.push(Violation::new(
+                        &field_path(path, key),
+                        "unexpected field".to_string(),
+                    )),
+                    Some(extra @ Value::Object(_)) => {
+                        check_json_schema(field, extra, &field_path(path, key), out)?
+                    }
+                    _ => {}
+                },
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// ===== MERGING =====
+
+/// Deep-merge `overlay` on top of `base`, for layering configs.
+///
+/// `options` is an optional object such as
+/// `{arrays: "union", key: "name", strict: true}` where `arrays` is one of
+/// "replace" (default), "append" or "union".
+pub fn merge(base: &Value, overlay: &Value, options: Option<&Value>) -> Result<Value, String> {
+    let options = match options {
+        Some(options) => merge_options(options)?,
+        None => MergeOptions::default(),
+    };
+    let mut merged = base.clone();
+    merged.merge(overlay.clone(), &options, "$")?;
+    Ok(merged)
+}
+
+fn merge_options(options: &Value) -> Result<MergeOptions, String> {
+    if !matches!(options, Value::Object(_)) {
+        return Err(format!(
+            "Merge options must be an object, found {}",
+            options.type_of().to_string()
+        ));
+    }
+    let key = match options.get("key") {
+        None | Some(Value::Null) => None,
+        Some(Value::String(key)) => Some(key.clone()),
+        Some(other) => {
+            return Err(format!(
+                "Merge option 'key' must be a string, found {}",
+                other.type_of().to_string()
+            ))
+        }
+    };
+    let arrays = match options.get("arrays") {
+        None => ArrayStrategy::Replace,
+        Some(Value::String(strategy)) => match strategy.as_str() {
+            "replace" => ArrayStrategy::Replace,
+            "append" => ArrayStrategy::Append,
+            "union" => ArrayStrategy::Union(key.clone()),
+            other => return Err(format!("Unknown array merge strategy '{}'", other)),
+        },
+        Some(other) => {
+            return Err(format!(
+                "Merge option 'arrays' must be a string, found {}",
+                other.type_of().to_string()
+            ))
+        }
+    };
+    if key.is_some() && arrays != ArrayStrategy::Union(key) {
+        return Err("Merge option 'key' only applies to arrays: \"union\"".to_string());
+    }
+    let strict = match options.get("strict") {
+        None => false,
+        Some(Value::Boolean(strict)) => *strict,
+        Some(other) => {
+            return Err(format!(
+                "Merge option 'strict' must be a boolean, found {}",
+                other.type_of().to_string()
+            ))
+        }
+    };
+    Ok(MergeOptions { arrays, strict })
+}
+
+// ===== OTHER FORMATS =====
+// Every format reads into and writes from the same values as Prizm JSON
+
+/// `options` is an optional object such as `{delimiter: ";", header: false}`.
+/// With a header row each row is an object keyed by column name, otherwise
+/// an array of strings.
+pub fn parse_csv(text: &str, options: Option<&Value>) -> Result<Value, String> {
+    csv::parse(text, csv_options(options)?)
+}
+
+pub fn stringify_csv(value: &Value, options: Option<&Value>) -> Result<String, String> {
+    csv::stringify(value, &csv_options(options)?)
+}
+
+/// Stream the rows of a CSV file one at a time instead of loading it whole
+pub fn csv_rows(path: &str, options: Option<&Value>) -> Result<csv::Rows<BufReader<File>>, String> {
+    let options = csv_options(options)?;
+    match File::open(path) {
+        Ok(file) => Ok(csv::Rows::new(BufReader::new(file), options)),
+        Err(e) => Err(format!("Failed to open CSV file: {}", e)),
+    }
+}
+
+fn csv_options(options: Option<&Value>) -> Result<csv::CsvOptions, String> {
+    let mut parsed = csv::CsvOptions::default();
+    let options = match options {
+        Some(options) => options,
+        None => return Ok(parsed),
+    };
+    match options.get("delimiter") {
+        None => {}
+        Some(Value::String(d)) if d.chars().count() == 1 => {
+            parsed.delimiter = d.chars().next().unwrap()
+        }
+        Some(_) => return Err("CSV option 'delimiter' must be a single character".to_string()),
+    }
+    match options.get("header") {
+        None => {}
+        Some(Value::Boolean(header)) => parsed.header = *header,
+        Some(_) => return Err("CSV option 'header' must be a boolean".to_string()),
+    }
+    Ok(parsed)
+}
+
+pub fn parse_toml(text: &str) -> Result<Value, String> {
+    toml::parse(text)
+}
+
+pub fn stringify_toml(value: &Value) -> Result<String, String> {
+    toml::stringify(value)
+}
+
+pub fn parse_ini(text: &str) -> Result<Value, String> {
+    ini::parse(text)
+}
+
+pub fn stringify_ini(value: &Value) -> Result<String, String> {
+    ini::stringify(value)
+}
+
+pub fn parse_yaml(text: &str) -> Result<Value, String> {
+    yaml::parse(text)
+}
+
+pub fn stringify_yaml(value: &Value) -> Result<String, String> {
+    yaml::stringify(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(text: &str) -> Value {
+        parse(text).unwrap()
+    }
+
+    // Violations as "path: message", in the order they were found
+    fn violations(data: &str, schema: &str) -> Vec<String> {
+        validate(&value(data), Some(&value(schema)))
+            .unwrap()
+            .iter()
+            .map(|v| format!("{}: {}", v.path, v.message))
+            .collect()
+    }
+
+    #[test]
+    fn prizm_schemas_check_types_fields_and_items() {
+        let schema = r#"{name: "string", port: "int", ratio: "float", tags: ["string"],
+                         pair: ["int", "bool"], note: "string?"}"#;
+        assert!(violations(
+            r#"{name: "a", port: 80, ratio: 1, tags: [], pair: [1, true], extra: null}"#,
+            schema
+        )
+        .is_empty());
+        assert_eq!(
+            violations(
+                r#"{name: 1, ratio: "x", tags: ["a", 2], pair: [1], note: null}"#,
+                schema
+            ),
+            [
+                "$.name: expected string, found int",
+                "$.port: missing field",
+                "$.ratio: expected float, found string",
+                "$.tags[1]: expected string, found int",
+                "$.pair: expected 2 items, found 1",
+            ]
+        );
+        assert_eq!(
+            violations("[1]", r#"{a: "int"}"#),
+            ["$: expected object, found array"]
+        );
+    }
+
+    #[test]
+    fn json_schemas_are_recognised_and_checked() {
+        let schema = r#"{
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "type": "object",
+            "required": ["id", "tags"],
+            "additionalProperties": false,
+            "properties": {
+                "id": {"type": "integer", "minimum": 1},
+                "name": {"type": "string", "minLength": 2, "maxLength": 4},
+                "level": {"enum": ["low", "high"]},
+                "tags": {"type": "array", "maxItems": 2, "items": {"type": "string"}}
+            }
+        }"#;
+        assert!(violations(r#"{"id": 3, "name": "abc", "tags": ["x"]}"#, schema).is_empty());
+        assert_eq!(
+            violations(
+                r#"{"id": 0, "name": "a", "level": "mid", "tags": ["x", 1, "z"], "other": 1}"#,
+                schema
+            ),
+            [
+                "$.id: 0 is less than 1",
+                "$.name: shorter than 2 characters",
+                "$.level: mid is not one of [\"low\", \"high\"]",
+                "$.tags: more than 2 items",
+                "$.tags[1]: expected string, found int",
+                "$.other: unexpected field",
+            ]
+        );
+        assert_eq!(
+            violations(r#"{"name": "abcde"}"#, schema),
+            [
+                "$.id: missing field",
+                "$.tags: missing field",
+                "$.name: longer than 4 characters",
+            ]
+        );
+    }
+
+    #[test]
+    fn plain_data_rejects_values_json_cannot_hold() {
+        let data = Value::Array(vec![
+            Value::Float(f64::NAN),
+            Value::Function("main".to_string()),
+            Value::Object(vec![
+                ("a".to_string(), Value::Int(1)),
+                ("a".to_string(), Value::Int(2)),
+            ]),
+        ]);
+        let found: Vec<_> = validate(&data, None)
+            .unwrap()
+            .into_iter()
+            .map(|v| v.path)
+            .collect();
+        assert_eq!(found, ["$[0]", "$[1]", "$[2].a"]);
+    }
+
+    #[test]
+    fn malformed_schemas_are_errors() {
+        assert_eq!(
+            validate(&Value::Int(1), Some(&value(r#""integer""#))),
+            Err("Unknown type 'integer' in schema at $".to_string())
+        );
+        assert!(validate(&Value::Int(1), Some(&Value::Int(3))).is_err());
+        assert!(validate(
+            &value("[1]"),
+            Some(&value(
+                r#"{"type": "array", "items": {"type": "int"}, "minItems": -1}"#
+            ))
+        )
+        .is_err());
+    }
+
+    fn merged(base: &str, overlay: &str, options: &str) -> Result<Value, String> {
+        merge(&value(base), &value(overlay), Some(&value(options)))
+    }
+
+    #[test]
+    fn merge_is_deep_and_replaces_arrays_by_default() {
+        assert_eq!(
+            merge(
+                &value(r#"{"server": {"host": "a", "port": 80}, "tags": [1, 2], "keep": true}"#),
+                &value(r#"{"server": {"port": 8080, "tls": null}, "tags": [3]}"#),
+                None
+            )
+            .unwrap(),
+            value(
+                r#"{"server": {"host": "a", "port": 8080, "tls": null}, "tags": [3], "keep": true}"#
+            )
+        );
+    }
+
+    #[test]
+    fn array_strategies() {
+        assert_eq!(
+            merged("[1, 2]", "[2, 3]", r#"{"arrays": "append"}"#).unwrap(),
+            value("[1, 2, 2, 3]")
+        );
+        assert_eq!(
+            merged("[1, 2]", "[2, 3]", r#"{"arrays": "union"}"#).unwrap(),
+            value("[1, 2, 3]")
+        );
+        assert_eq!(
+            merged(
+                r#"[{"name": "a", "size": 1}, {"name": "b"}]"#,
+                r#"[{"name": "a", "size": 2, "new": true}, {"name": "c"}, 5]"#,
+                r#"{"arrays": "union", "key": "name"}"#
+            )
+            .unwrap(),
+            value(r#"[{"name": "a", "size": 2, "new": true}, {"name": "b"}, {"name": "c"}, 5]"#)
+        );
+    }
+
+    #[test]
+    fn strict_merges_refuse_changes_of_type() {
+        assert_eq!(
+            merged(
+                r#"{"a": {"b": 1}}"#,
+                r#"{"a": {"b": "x"}}"#,
+                r#"{"strict": true}"#
+            ),
+            Err("Merge conflict at $.a.b: cannot merge string into int".to_string())
+        );
+        assert!(merged(
+            r#"{"a": 1, "b": null}"#,
+            r#"{"a": 2.5, "b": [1]}"#,
+            r#"{"strict": true}"#
+        )
+        .is_ok());
+        assert_eq!(
+            merged(r#"{"a": 1}"#, r#"{"a": "x"}"#, "{}").unwrap(),
+            value(r#"{"a": "x"}"#)
+        );
+    }
+
+    #[test]
+    fn bad_merge_options_are_errors() {
+        for options in [
+            r#"{"arrays": "zip"}"#,
+            r#"{"key": "id"}"#,
+            r#"{"arrays": "append", "key": "id"}"#,
+            r#"{"strict": "yes"}"#,
+            "[]",
+        ] {
+            assert!(merged("{}", "{}", options).is_err(), "{}", options);
+        }
+    }
+}
@@ -0,0 +1,344 @@
+// CSV Format
+// Records are read lazily so large files can be iterated row by row
+
+use crate::value::Value;
+use std::io::BufRead;
+
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    /// Treat the first record as column names and yield objects
+    pub header: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: ',',
+            header: true,
+        }
+    }
+}
+
+/// Iterator over the rows of a CSV source, one Prizm value per record
+pub struct Rows<R: BufRead> {
+    reader: R,
+    options: CsvOptions,
+    columns: Option<Vec<String>>,
+    line: usize,
+    done: bool,
+}
+
+impl<R: BufRead> Rows<R> {
+    pub fn new(reader: R, options: CsvOptions) -> Self {
+        Rows {
+            reader,
+            options,
+            columns: None,
+            line: 0,
+            done: false,
+        }
+    }
+
+    fn read_line(&mut self, buf: &mut String) -> Result<bool, String> {
+        match self.reader.read_line(buf) {
+            Ok(0) => Ok(false),
+            Ok(_) => {
+                self.line += 1;
+                Ok(true)
+            }
+            Err(e) => Err(format!("Failed to read CSV: {}", e)),
+        }
+    }
+
+    // Read one record, pulling in more lines while a quoted field is open
+    fn read_record(&mut self) -> Result<Option<Vec<String>>, String> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if !self.read_line(&mut line)? {
+                return Ok(None);
+            }
+            if !line.trim_end_matches(['\r', '\n']).is_empty() {
+                break;
+            }
+        }
+
+        let start_line = self.line;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut in_quotes = false;
+        let mut quoted = false;
+        loop {
+            let mut chars = line.chars().peekable();
+            while let Some(c) = chars.next() {
+                if in_quotes {
+                    if c == '"' {
+                        if chars.peek() == Some(&'"') {
+                            chars.next();
+                            field.push('"');
+                        } else {
+                            in_quotes = false;
+                        }
+                    } else {
+                        field.push(c);
+                    }
+                } else if c == '"' && field.is_empty() && !quoted {
+                    in_quotes = true;
+                    quoted = true;
+                } else if c == self.options.delimiter {
+                    fields.push(std::mem::take(&mut field));
+                    quoted = false;
+                } else if c == '\n' || c == '\r' {
+                    // Line endings outside quotes end the record
+                } else {
+                    field.push(c);
+                }
+            }
+            if !in_quotes {
+                break;
+            }
+            line.clear();
+            if !self.read_line(&mut line)? {
+                return Err(format!(
+                    "CSV error on line {}: unterminated quoted field",
+                    start_line
+                ));
+            }
+        }
+        fields.push(field);
+        Ok(Some(fields))
+    }
+
+    fn next_row(&mut self) -> Result<Option<Value>, String> {
+        if self.options.header && self.columns.is_none() {
+            match self.read_record()? {
+                Some(columns) => self.columns = Some(columns),
+                None => return Ok(None),
+            }
+        }
+        let record = match self.read_record()? {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        match &self.columns {
+            Some(columns) => {
+                if record.len() > columns.len() {
+                    return Err(format!(
+                        "CSV error on line {}: expected {} fields, found {}",
+                        self.line,
+                        columns.len(),
+                        record.len()
+                    ));
+                }
+                // Short rows are padded with nulls
+                let fields = columns
+                    .iter()
+                    .enumerate()
+                    .map(|(i, column)| {
+                        let value = match record.get(i) {
+                            Some(field) => Value::String(field.clone()),
+                            None => Value::Null,
+                        };
+                        (column.clone(), value)
+                    })
+                    .collect();
+                Ok(Some(Value::Object(fields)))
+            }
+            None => Ok(Some(Value::Array(
+                record.into_iter().map(Value::String).collect(),
+            ))),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Rows<R> {
+    type Item = Result<Value, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_row() {
+            Ok(Some(row)) => Some(Ok(row)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+pub fn parse(text: &str, options: CsvOptions) -> Result<Value, String> {
+    let rows = Rows::new(text.as_bytes(), options).collect::<Result<Vec<_>, _>>()?;
+    Ok(Value::Array(rows))
+}
+
+/// Write an array of objects (with a header row) or an array of arrays
+pub fn stringify(value: &Value, options: &CsvOptions) -> Result<String, String> {
+    let rows = match value {
+        Value::Array(rows) => rows,
+        other => {
+            return Err(format!(
+                "CSV data must be an array of rows, found {}",
+                other.type_of().to_string()
+            ))
+        }
+    };
+
+    let mut out = String::new();
+    let objects = rows.iter().any(|row| matches!(row, Value::Object(_)));
+    if objects {
+        // Columns are every field name, in the order they first appear
+        let mut columns: Vec<&str> = Vec::new();
+        for row in rows {
+            if let Value::Object(fields) = row {
+                for (key, _) in fields {
+                    if !columns.contains(&key.as_str()) {
+                        columns.push(key);
+                    }
+                }
+            }
+        }
+        if options.header {
+            let header: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+            write_record(&mut out, &header, options.delimiter);
+        }
+        for (i, row) in rows.iter().enumerate() {
+            if !matches!(row, Value::Object(_)) {
+                return Err(format!("CSV row {} is not an object", i + 1));
+            }
+            let mut record = Vec::new();
+            for column in &columns {
+                record.push(match row.get(column) {
+                    Some(field) => cell(field, i)?,
+                    None => String::new(),
+                });
+            }
+            write_record(&mut out, &record, options.delimiter);
+        }
+    } else {
+        for (i, row) in rows.iter().enumerate() {
+            let record = match row {
+                Value::Array(fields) => fields
+                    .iter()
+                    .map(|field| cell(field, i))
+                    .collect::<Result<Vec<_>, _>>()?,
+                _ => return Err(format!("CSV row {} is not an array", i + 1)),
+            };
+            write_record(&mut out, &record, options.delimiter);
+        }
+    }
+    Ok(out)
+}
+
+fn cell(value: &Value, row: usize) -> Result<String, String> {
+    match value {
+        Value::Null => Ok(String::new()),
+        Value::Array(_) | Value::Object(_) => Err(format!(
+            "CSV row {} contains a nested {}",
+            row + 1,
+            value.type_of().to_string()
+        )),
+        other => Ok(other.to_string()),
+    }
+}
+
+fn write_record(out: &mut String, record: &[String], delimiter: char) {
+    for (i, field) in record.iter().enumerate() {
+        if i > 0 {
+            out.push(delimiter);
+        }
+        let needs_quotes = field.contains(delimiter)
+            || field.contains(['"', '\n', '\r'])
+            || field.starts_with(' ')
+            || field.ends_with(' ');
+        if needs_quotes {
+            out.push('"');
+            out.push_str(&field.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(field);
+        }
+    }
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::builtins::data::json;
+
+    fn value(text: &str) -> Value {
+        json::parse(text).unwrap()
+    }
+
+    fn no_header() -> CsvOptions {
+        CsvOptions {
+            header: false,
+            ..CsvOptions::default()
+        }
+    }
+
+    #[test]
+    fn quoted_fields_keep_delimiters_quotes_and_newlines() {
+        let text = "name,note\nAda,\"says \"\"hi\"\", then\nleaves\"\nGrace,plain\n";
+        assert_eq!(
+            parse(text, CsvOptions::default()).unwrap(),
+            value(
+                r#"[{"name": "Ada", "note": "says \"hi\", then\nleaves"},
+                    {"name": "Grace", "note": "plain"}]"#
+            )
+        );
+    }
+
+    #[test]
+    fn rows_stream_and_report_their_lines() {
+        let text = "a;b\r\n\r\n1;2\r\n\"3\n\";4\r\n5\r\n";
+        let options = CsvOptions {
+            delimiter: ';',
+            header: true,
+        };
+        let rows: Vec<_> = Rows::new(text.as_bytes(), options).collect();
+        assert_eq!(
+            rows,
+            vec![
+                Ok(value(r#"{"a": "1", "b": "2"}"#)),
+                Ok(value(r#"{"a": "3\n", "b": "4"}"#)),
+                Ok(value(r#"{"a": "5", "b": null}"#)),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_input_is_an_error() {
+        assert_eq!(
+            parse("a,b\n1,2,3\n", CsvOptions::default()),
+            Err("CSV error on line 2: expected 2 fields, found 3".to_string())
+        );
+        assert_eq!(
+            parse("a\n\"open\nstill open\n", CsvOptions::default()),
+            Err("CSV error on line 2: unterminated quoted field".to_string())
+        );
+        assert!(stringify(&value("[[1, [2]]]"), &no_header()).is_err());
+        assert!(stringify(&value("[{\"a\": 1}, [1]]"), &no_header()).is_err());
+        assert!(stringify(&value("{\"a\": 1}"), &no_header()).is_err());
+    }
+
+    #[test]
+    fn written_rows_read_back_the_same() {
+        let rows =
+            value(r#"[{"id": "1", "text": "a, \"b\"\nc"}, {"id": "2", "text": " padded "}]"#);
+        let text = stringify(&rows, &CsvOptions::default()).unwrap();
+        assert_eq!(text, "id,text\n1,\"a, \"\"b\"\"\nc\"\n2,\" padded \"\n");
+        assert_eq!(parse(&text, CsvOptions::default()).unwrap(), rows);
+
+        let arrays = value(r#"[["x", "y"], ["1", ""]]"#);
+        let text = stringify(&arrays, &no_header()).unwrap();
+        assert_eq!(parse(&text, no_header()).unwrap(), arrays);
+    }
+}
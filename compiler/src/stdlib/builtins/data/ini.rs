@@ -0,0 +1,213 @@
+// INI Format
+// `[section]` headers become nested objects; keys before the first section
+// stay at the top level. Values are kept as strings, as INI has no types.
+
+use crate::value::Value;
+
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut root = Value::Object(Vec::new());
+    let mut section: Option<String> = None;
+
+    for (index, raw) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(rest) = line.strip_prefix('[') {
+            let name = match rest.strip_suffix(']') {
+                Some(name) => name.trim(),
+                None => {
+                    return Err(format!(
+                        "INI error on line {}: missing ']' after section name",
+                        line_number
+                    ))
+                }
+            };
+            match root.get(name) {
+                Some(Value::Object(_)) => {}
+                Some(_) => {
+                    return Err(format!(
+                        "INI error on line {}: '{}' is already a key",
+                        line_number, name
+                    ))
+                }
+                None => root.set(name, Value::Object(Vec::new()))?,
+            }
+            section = Some(name.to_string());
+            continue;
+        }
+
+        let split = match line.find(['=', ':']) {
+            Some(split) => split,
+            None => {
+                return Err(format!(
+                    "INI error on line {}: expected 'key = value'",
+                    line_number
+                ))
+            }
+        };
+        let key = line[..split].trim();
+        if key.is_empty() {
+            return Err(format!("INI error on line {}: missing key", line_number));
+        }
+        let value = Value::String(unquote(line[split + 1..].trim()));
+
+        let table = match &section {
+            Some(name) => root.get_mut(name).unwrap(),
+            None => &mut root,
+        };
+        // Later duplicates win, as most INI readers do
+        table.set(key, value)?;
+    }
+
+    Ok(root)
+}
+
+fn unquote(value: &str) -> String {
+    for quote in ['"', '\''] {
+        if value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote) {
+            return value[1..value.len() - 1].to_string();
+        }
+    }
+    // Inline comments need a space before them so URLs with '#' survive
+    match value.find(" ;").or_else(|| value.find(" #")) {
+        Some(comment) => value[..comment].trim_end().to_string(),
+        None => value.to_string(),
+    }
+}
+
+pub fn stringify(value: &Value) -> Result<String, String> {
+    let fields = match value {
+        Value::Object(fields) => fields,
+        other => {
+            return Err(format!(
+                "INI documents must be objects, found {}",
+                other.type_of().to_string()
+            ))
+        }
+    };
+
+    let mut out = String::new();
+    for (key, field) in fields {
+        if !matches!(field, Value::Object(_)) {
+            write_entry(&mut out, key, field, key)?;
+        }
+    }
+    for (name, field) in fields {
+        if let Value::Object(entries) = field {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&format!("[{}]\n", name));
+            for (key, entry) in entries {
+                write_entry(&mut out, key, entry, &format!("{}.{}", name, key))?;
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn write_entry(out: &mut String, key: &str, value: &Value, location: &str) -> Result<(), String> {
+    let text = match value {
+        Value::Null => String::new(),
+        Value::Array(_) | Value::Object(_) => {
+            return Err(format!(
+                "INI cannot store a nested {} (at {})",
+                value.type_of().to_string(),
+                location
+            ))
+        }
+        other => other.to_string(),
+    };
+    let needs_quotes =
+        text.starts_with(' ') || text.ends_with(' ') || text.contains(" ;") || text.contains(" #");
+    if needs_quotes {
+        out.push_str(&format!("{} = \"{}\"\n", key, text));
+    } else {
+        out.push_str(&format!("{} = {}\n", key, text));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::builtins::data::json;
+
+    fn value(text: &str) -> Value {
+        json::parse(text).unwrap()
+    }
+
+    #[test]
+    fn sections_keys_and_comments() {
+        let text = "\
+; global settings
+name = app
+url: http://example.com/#top ; the home page
+
+[server]
+host = \" padded \"
+port = 8080
+# the same key again wins
+port = 9090
+";
+        assert_eq!(
+            parse(text).unwrap(),
+            value(
+                r#"{
+                    "name": "app",
+                    "url": "http://example.com/#top",
+                    "server": {"host": " padded ", "port": "9090"}
+                }"#
+            )
+        );
+    }
+
+    #[test]
+    fn duplicate_sections_are_merged() {
+        let text = "[db]\nhost = a\n[cache]\nsize = 1\n[db]\nport = 5432\nhost = b\n";
+        assert_eq!(
+            parse(text).unwrap(),
+            value(r#"{"db": {"host": "b", "port": "5432"}, "cache": {"size": "1"}}"#)
+        );
+    }
+
+    #[test]
+    fn malformed_documents_are_errors() {
+        for (text, expected) in [
+            (
+                "[open\n",
+                "INI error on line 1: missing ']' after section name",
+            ),
+            (
+                "a = 1\nnot a pair\n",
+                "INI error on line 2: expected 'key = value'",
+            ),
+            ("= 1\n", "INI error on line 1: missing key"),
+            (
+                "db = x\n[db]\n",
+                "INI error on line 2: 'db' is already a key",
+            ),
+        ] {
+            assert_eq!(parse(text), Err(expected.to_string()));
+        }
+        assert!(stringify(&value(r#"{"a": {"b": [1]}}"#)).is_err());
+        assert!(stringify(&value("[1]")).is_err());
+    }
+
+    #[test]
+    fn written_documents_read_back_the_same() {
+        let document = value(
+            r#"{
+                "name": "app",
+                "empty": "",
+                "server": {"host": " spaced ", "note": "a ; b"},
+                "paths": {"home": "/srv/app"}
+            }"#,
+        );
+        let text = stringify(&document).unwrap();
+        assert_eq!(parse(&text).unwrap(), document, "{}", text);
+    }
+}
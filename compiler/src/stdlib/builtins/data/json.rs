@@ -0,0 +1,318 @@
+// Prizm JSON Format
+// Standard JSON, read leniently: keys may be left unquoted, trailing commas
+// are allowed and `//` comments are skipped, so Prizm object literals parse
+// as written. Output is always standard JSON.
+
+use crate::value::Value;
+
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        input: text.chars().collect(),
+        position: 0,
+        line: 1,
+    };
+    parser.skip_whitespace();
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    match parser.peek() {
+        None => Ok(value),
+        Some(c) => Err(parser.error(&format!("unexpected '{}' after the value", c))),
+    }
+}
+
+struct Parser {
+    input: Vec<char>,
+    position: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> String {
+        format!("JSON error on line {}: {}", self.line, message)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input.get(self.position).copied()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.advance() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(&format!("expected '{}', found '{}'", expected, c))),
+            None => Err(self.error(&format!("expected '{}', found end of input", expected))),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.advance();
+                }
+                Some('/') if self.input.get(self.position + 1) == Some(&'/') => {
+                    while !matches!(self.peek(), None | Some('\n')) {
+                        self.advance();
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('{') => self.parse_object(),
+            Some('[') => self.parse_array(),
+            Some('"') => Ok(Value::String(self.parse_string()?)),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.parse_number(),
+            Some(c) if c.is_alphabetic() => match self.parse_word().as_str() {
+                "true" => Ok(Value::Boolean(true)),
+                "false" => Ok(Value::Boolean(false)),
+                "null" => Ok(Value::Null),
+                word => Err(self.error(&format!("unexpected word '{}'", word))),
+            },
+            Some(c) => Err(self.error(&format!("unexpected '{}'", c))),
+            None => Err(self.error("expected a value, found end of input")),
+        }
+    }
+
+    fn parse_word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            if !(c.is_alphanumeric() || c == '_' || c == '$') {
+                break;
+            }
+            word.push(c);
+            self.advance();
+        }
+        word
+    }
+
+    fn parse_object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut fields: Vec<(String, Value)> = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let key = match self.peek() {
+                Some('}') => break,
+                Some('"') => self.parse_string()?,
+                Some(c) if c.is_alphabetic() || c == '_' || c == '$' => self.parse_word(),
+                Some(c) => return Err(self.error(&format!("expected a key, found '{}'", c))),
+                None => return Err(self.error("unterminated object")),
+            };
+            self.skip_whitespace();
+            self.expect(':')?;
+            self.skip_whitespace();
+            let value = self.parse_value()?;
+            // A repeated key keeps its first position and its last value
+            match fields.iter_mut().find(|(k, _)| *k == key) {
+                Some(field) => field.1 = value,
+                None => fields.push((key, value)),
+            }
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => {
+                    self.advance();
+                }
+                Some('}') => break,
+                Some(c) => return Err(self.error(&format!("expected ',' or '}}', found '{}'", c))),
+                None => return Err(self.error("unterminated object")),
+            }
+        }
+        self.expect('}')?;
+        Ok(Value::Object(fields))
+    }
+
+    fn parse_array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(']') {
+                break;
+            }
+            items.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => {
+                    self.advance();
+                }
+                Some(']') => break,
+                Some(c) => return Err(self.error(&format!("expected ',' or ']', found '{}'", c))),
+                None => return Err(self.error("unterminated array")),
+            }
+        }
+        self.expect(']')?;
+        Ok(Value::Array(items))
+    }
+
+    fn parse_number(&mut self) -> Result<Value, String> {
+        let mut number = String::new();
+        let mut is_float = false;
+        while let Some(c) = self.peek() {
+            match c {
+                '0'..='9' | '-' | '+' => {}
+                '.' | 'e' | 'E' => is_float = true,
+                _ => break,
+            }
+            number.push(c);
+            self.advance();
+        }
+        if !is_float {
+            if let Ok(n) = number.parse() {
+                return Ok(Value::Int(n));
+            }
+        }
+        // Integers too large for an int are kept as floats
+        number
+            .parse()
+            .map(Value::Float)
+            .map_err(|_| self.error(&format!("invalid number '{}'", number)))
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.advance() {
+                Some('"') => return Ok(s),
+                Some('\\') => match self.advance() {
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('r') => s.push('\r'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('u') => {
+                        let high = self.parse_hex()?;
+                        let c = if (0xD800..0xDC00).contains(&high) {
+                            // A surrogate pair spells one character outside the BMP
+                            self.expect('\\')?;
+                            self.expect('u')?;
+                            let low = self.parse_hex()?;
+                            0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF)
+                        } else {
+                            high
+                        };
+                        match char::from_u32(c) {
+                            Some(c) => s.push(c),
+                            None => return Err(self.error("invalid unicode escape")),
+                        }
+                    }
+                    Some(c) => s.push(c),
+                    None => return Err(self.error("unterminated string")),
+                },
+                Some(c) => s.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn parse_hex(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            match self.advance().and_then(|c| c.to_digit(16)) {
+                Some(digit) => code = code * 16 + digit,
+                None => return Err(self.error("expected four hex digits after \\u")),
+            }
+        }
+        Ok(code)
+    }
+}
+
+// ===== WRITING =====
+
+/// Standard JSON; with `pretty`, one field or item per line indented by two
+/// spaces
+pub fn stringify(value: &Value, pretty: bool) -> Result<String, String> {
+    let mut out = String::new();
+    write_value(&mut out, value, pretty, 0, "$")?;
+    Ok(out)
+}
+
+fn write_value(
+    out: &mut String,
+    value: &Value,
+    pretty: bool,
+    depth: usize,
+    path: &str,
+) -> Result<(), String> {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Int(n) => out.push_str(&n.to_string()),
+        Value::Float(f) if !f.is_finite() => {
+            return Err(format!("{} cannot be stored as JSON (at {})", f, path))
+        }
+        Value::Float(f) if f.fract() == 0.0 => out.push_str(&format!("{:.1}", f)),
+        Value::Float(f) => out.push_str(&f.to_string()),
+        Value::String(s) => out.push_str(&quote(s)),
+        Value::Boolean(b) => out.push_str(&b.to_string()),
+        Value::Duration(d) => out.push_str(&quote(&d.to_string())),
+        Value::Function(_) | Value::Handle(_) => {
+            return Err(format!("JSON cannot store {} (at {})", value, path))
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(out, pretty, depth + 1);
+                write_value(out, item, pretty, depth + 1, &format!("{}[{}]", path, i))?;
+            }
+            if !items.is_empty() {
+                newline(out, pretty, depth);
+            }
+            out.push(']');
+        }
+        Value::Object(fields) => {
+            out.push('{');
+            for (i, (key, field)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(out, pretty, depth + 1);
+                out.push_str(&quote(key));
+                out.push_str(if pretty { ": " } else { ":" });
+                write_value(out, field, pretty, depth + 1, &format!("{}.{}", path, key))?;
+            }
+            if !fields.is_empty() {
+                newline(out, pretty, depth);
+            }
+            out.push('}');
+        }
+    }
+    Ok(())
+}
+
+fn newline(out: &mut String, pretty: bool, depth: usize) {
+    if pretty {
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth));
+    }
+}
+
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,707 @@
+// TOML Format
+// Covers tables, arrays of tables, dotted keys, inline tables and arrays,
+// and string/int/float/boolean values. Dates and times are kept as strings.
+
+use crate::value::Value;
+
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        input: text.chars().collect(),
+        position: 0,
+        line: 1,
+    };
+    parser.parse_document()
+}
+
+struct Parser {
+    input: Vec<char>,
+    position: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> String {
+        format!("TOML error on line {}: {}", self.line, message)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input.get(self.position).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.input.get(self.position + offset).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(i, c)| self.peek_at(i) == Some(c))
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    // A mismatch is left unread, so the error names the line it is on
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.advance();
+                Ok(())
+            }
+            Some('\n') | Some('\r') => Err(self.error(&format!(
+                "expected '{}', found the end of the line",
+                expected
+            ))),
+            Some(c) => Err(self.error(&format!("expected '{}', found '{}'", expected, c))),
+            None => Err(self.error(&format!("expected '{}', found end of input", expected))),
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.advance();
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.advance();
+            }
+        }
+    }
+
+    // Whitespace, comments and newlines, as allowed between array items
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') | Some('\r') => {
+                    self.advance();
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_spaces();
+        self.skip_comment();
+        if self.peek() == Some('\r') {
+            self.advance();
+        }
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.advance();
+                Ok(())
+            }
+            Some(c) => Err(self.error(&format!("unexpected '{}' after value", c))),
+        }
+    }
+
+    fn parse_document(&mut self) -> Result<Value, String> {
+        let mut root = Value::Object(Vec::new());
+        let mut current: Vec<String> = Vec::new();
+
+        loop {
+            self.skip_blank();
+            match self.peek() {
+                None => break,
+                Some('[') => {
+                    let array = self.starts_with("[[");
+                    self.advance();
+                    if array {
+                        self.advance();
+                    }
+                    self.skip_spaces();
+                    let path = self.parse_key()?;
+                    self.skip_spaces();
+                    self.expect(']')?;
+                    if array {
+                        self.expect(']')?;
+                        let (last, parents) = path.split_last().unwrap();
+                        let parent = self.table_mut(&mut root, parents)?;
+                        let line = self.line;
+                        match parent.get_mut(last) {
+                            Some(Value::Array(tables)) => tables.push(Value::Object(Vec::new())),
+                            Some(_) => {
+                                return Err(format!(
+                                    "TOML error on line {}: '{}' is not an array of tables",
+                                    line, last
+                                ))
+                            }
+                            None => parent
+                                .set(last, Value::Array(vec![Value::Object(Vec::new())]))
+                                .map_err(|e| self.error(&e))?,
+                        }
+                    } else {
+                        self.table_mut(&mut root, &path)?;
+                    }
+                    current = path;
+                    self.end_of_line()?;
+                }
+                Some(_) => {
+                    let key = self.parse_key()?;
+                    self.skip_spaces();
+                    self.expect('=')?;
+                    self.skip_spaces();
+                    let value = self.parse_value()?;
+                    let (last, parents) = key.split_last().unwrap();
+                    let mut path = current.clone();
+                    path.extend_from_slice(parents);
+                    let table = self.table_mut(&mut root, &path)?;
+                    if table.get(last).is_some() {
+                        return Err(self.error(&format!("duplicate key '{}'", last)));
+                    }
+                    table.set(last, value).map_err(|e| self.error(&e))?;
+                    self.end_of_line()?;
+                }
+            }
+        }
+        Ok(root)
+    }
+
+    // Walk (and create) tables along a path; arrays of tables resolve to their last entry
+    fn table_mut<'a>(&self, root: &'a mut Value, path: &[String]) -> Result<&'a mut Value, String> {
+        let mut table = root;
+        for key in path {
+            if table.get(key).is_none() {
+                table
+                    .set(key, Value::Object(Vec::new()))
+                    .map_err(|e| self.error(&e))?;
+            }
+            let next = table.get_mut(key).unwrap();
+            table = match next {
+                Value::Object(_) => next,
+                Value::Array(items) if matches!(items.last(), Some(Value::Object(_))) => {
+                    items.last_mut().unwrap()
+                }
+                _ => return Err(self.error(&format!("'{}' is not a table", key))),
+            };
+        }
+        Ok(table)
+    }
+
+    fn parse_key(&mut self) -> Result<Vec<String>, String> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_spaces();
+            let part = match self.peek() {
+                Some('"') => {
+                    self.advance();
+                    self.parse_basic_string()?
+                }
+                Some('\'') => {
+                    self.advance();
+                    self.parse_literal_string()?
+                }
+                _ => {
+                    let mut part = String::new();
+                    while let Some(c) = self.peek() {
+                        if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                            part.push(c);
+                            self.advance();
+                        } else {
+                            break;
+                        }
+                    }
+                    if part.is_empty() {
+                        return Err(self.error("expected a key"));
+                    }
+                    part
+                }
+            };
+            parts.push(part);
+            self.skip_spaces();
+            if self.peek() == Some('.') {
+                self.advance();
+            } else {
+                return Ok(parts);
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') if self.starts_with("\"\"\"") => {
+                self.position += 3;
+                self.parse_multiline_string('"')
+            }
+            Some('\'') if self.starts_with("'''") => {
+                self.position += 3;
+                self.parse_multiline_string('\'')
+            }
+            Some('"') => {
+                self.advance();
+                Ok(Value::String(self.parse_basic_string()?))
+            }
+            Some('\'') => {
+                self.advance();
+                Ok(Value::String(self.parse_literal_string()?))
+            }
+            Some('[') => {
+                self.advance();
+                let mut items = Vec::new();
+                loop {
+                    self.skip_blank();
+                    if self.peek() == Some(']') {
+                        self.advance();
+                        break;
+                    }
+                    items.push(self.parse_value()?);
+                    self.skip_blank();
+                    match self.advance() {
+                        Some(',') => {}
+                        Some(']') => break,
+                        _ => return Err(self.error("expected ',' or ']' in array")),
+                    }
+                }
+                Ok(Value::Array(items))
+            }
+            Some('{') => {
+                self.advance();
+                let mut table = Value::Object(Vec::new());
+                self.skip_spaces();
+                if self.peek() == Some('}') {
+                    self.advance();
+                    return Ok(table);
+                }
+                loop {
+                    let key = self.parse_key()?;
+                    self.skip_spaces();
+                    self.expect('=')?;
+                    self.skip_spaces();
+                    let value = self.parse_value()?;
+                    let (last, parents) = key.split_last().unwrap();
+                    let target = self.table_mut(&mut table, parents)?;
+                    if target.get(last).is_some() {
+                        return Err(self.error(&format!("duplicate key '{}'", last)));
+                    }
+                    target.set(last, value).map_err(|e| self.error(&e))?;
+                    self.skip_spaces();
+                    match self.peek() {
+                        Some(',') => self.advance(),
+                        Some('}') => {
+                            self.advance();
+                            break;
+                        }
+                        _ => return Err(self.error("expected ',' or '}' in inline table")),
+                    };
+                }
+                Ok(table)
+            }
+            Some(_) => {
+                let mut word = String::new();
+                while let Some(c) = self.peek() {
+                    if matches!(c, ',' | ']' | '}' | '#' | '\n' | '\r')
+                        || (c == ' ' && !self.is_date_time_space(&word))
+                    {
+                        break;
+                    }
+                    word.push(c);
+                    self.advance();
+                }
+                self.parse_word(word.trim_end())
+            }
+            None => Err(self.error("expected a value")),
+        }
+    }
+
+    // `1979-05-27 07:32:00` may use a space between date and time
+    fn is_date_time_space(&self, word: &str) -> bool {
+        word.len() == 10
+            && word.as_bytes()[4] == b'-'
+            && self.peek_at(1).is_some_and(|c| c.is_ascii_digit())
+    }
+
+    fn parse_word(&self, word: &str) -> Result<Value, String> {
+        match word {
+            "true" => return Ok(Value::Boolean(true)),
+            "false" => return Ok(Value::Boolean(false)),
+            "inf" | "+inf" => return Ok(Value::Float(f64::INFINITY)),
+            "-inf" => return Ok(Value::Float(f64::NEG_INFINITY)),
+            "nan" | "+nan" | "-nan" => return Ok(Value::Float(f64::NAN)),
+            "" => return Err(self.error("expected a value")),
+            _ => {}
+        }
+        let digits = word.replace('_', "");
+        let radix = match digits.get(..2) {
+            Some("0x") => Some(16),
+            Some("0o") => Some(8),
+            Some("0b") => Some(2),
+            _ => None,
+        };
+        if let Some(radix) = radix {
+            return i64::from_str_radix(&digits[2..], radix)
+                .map(Value::Int)
+                .map_err(|_| self.error(&format!("invalid integer '{}'", word)));
+        }
+        if let Ok(n) = digits.parse::<i64>() {
+            return Ok(Value::Int(n));
+        }
+        let looks_like_float = digits.contains(['.', 'e', 'E'])
+            && digits
+                .trim_start_matches(['+', '-'])
+                .starts_with(|c: char| c.is_ascii_digit());
+        if looks_like_float {
+            if let Ok(f) = digits.parse::<f64>() {
+                return Ok(Value::Float(f));
+            }
+        }
+        let looks_like_date = word.starts_with(|c: char| c.is_ascii_digit())
+            && (word.contains('-') || word.contains(':'));
+        if looks_like_date {
+            return Ok(Value::String(word.to_string()));
+        }
+        Err(self.error(&format!("invalid value '{}'", word)))
+    }
+
+    fn parse_escape(&mut self) -> Result<char, String> {
+        let c = match self.advance() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some(u @ ('u' | 'U')) => {
+                let len = if u == 'u' { 4 } else { 8 };
+                let mut hex = String::new();
+                for _ in 0..len {
+                    hex.extend(self.advance());
+                }
+                return u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| self.error(&format!("invalid unicode escape '{}'", hex)));
+            }
+            Some(c) => return Err(self.error(&format!("invalid escape '\\{}'", c))),
+            None => return Err(self.error("unterminated string")),
+        };
+        Ok(c)
+    }
+
+    fn parse_basic_string(&mut self) -> Result<String, String> {
+        let mut s = String::new();
+        loop {
+            if matches!(self.peek(), Some('\n') | None) {
+                return Err(self.error("unterminated string"));
+            }
+            match self.advance() {
+                Some('"') => return Ok(s),
+                Some('\\') => s.push(self.parse_escape()?),
+                Some(c) => s.push(c),
+                None => unreachable!(),
+            }
+        }
+    }
+
+    fn parse_literal_string(&mut self) -> Result<String, String> {
+        let mut s = String::new();
+        loop {
+            if matches!(self.peek(), Some('\n') | None) {
+                return Err(self.error("unterminated string"));
+            }
+            match self.advance() {
+                Some('\'') => return Ok(s),
+                Some(c) => s.push(c),
+                None => unreachable!(),
+            }
+        }
+    }
+
+    fn parse_multiline_string(&mut self, quote: char) -> Result<Value, String> {
+        let closing: String = std::iter::repeat_n(quote, 3).collect();
+        // A newline straight after the opening quotes is trimmed
+        if self.peek() == Some('\r') && self.peek_at(1) == Some('\n') {
+            self.position += 1;
+        }
+        if self.peek() == Some('\n') {
+            self.advance();
+        }
+        let mut s = String::new();
+        loop {
+            if self.starts_with(&closing) {
+                self.position += 3;
+                return Ok(Value::String(s));
+            }
+            match self.advance() {
+                Some('\\') if quote == '"' => {
+                    // A trailing backslash joins the next non-blank line
+                    if matches!(self.peek(), Some('\n') | Some('\r') | Some(' ')) {
+                        while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+                            self.advance();
+                        }
+                    } else {
+                        s.push(self.parse_escape()?);
+                    }
+                }
+                Some(c) => s.push(c),
+                None => return Err(self.error("unterminated multi-line string")),
+            }
+        }
+    }
+}
+
+// ===== WRITING =====
+
+pub fn stringify(value: &Value) -> Result<String, String> {
+    let fields = match value {
+        Value::Object(fields) => fields,
+        other => {
+            return Err(format!(
+                "TOML documents must be objects, found {}",
+                other.type_of().to_string()
+            ))
+        }
+    };
+    let mut out = String::new();
+    write_table(&mut out, &[], fields)?;
+    Ok(out)
+}
+
+fn is_table(value: &Value) -> bool {
+    matches!(value, Value::Object(_))
+}
+
+fn is_table_array(value: &Value) -> bool {
+    matches!(value, Value::Array(items) if !items.is_empty() && items.iter().all(is_table))
+}
+
+fn write_table(
+    out: &mut String,
+    path: &[String],
+    fields: &[(String, Value)],
+) -> Result<(), String> {
+    for (key, value) in fields {
+        if !is_table(value) && !is_table_array(value) {
+            let location = dotted(path, key);
+            out.push_str(&format!(
+                "{} = {}\n",
+                write_key(key),
+                inline(value, &location)?
+            ));
+        }
+    }
+    for (key, value) in fields {
+        let mut child = path.to_vec();
+        child.push(key.clone());
+        match value {
+            Value::Object(child_fields) => {
+                // Tables holding only subtables get their header implicitly
+                let has_values = child_fields.is_empty()
+                    || child_fields
+                        .iter()
+                        .any(|(_, v)| !is_table(v) && !is_table_array(v));
+                if has_values {
+                    separate(out);
+                    out.push_str(&format!("[{}]\n", header(&child)));
+                }
+                write_table(out, &child, child_fields)?;
+            }
+            Value::Array(items) if is_table_array(value) => {
+                for item in items {
+                    if let Value::Object(item_fields) = item {
+                        separate(out);
+                        out.push_str(&format!("[[{}]]\n", header(&child)));
+                        write_table(out, &child, item_fields)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn separate(out: &mut String) {
+    if !out.is_empty() {
+        out.push('\n');
+    }
+}
+
+fn dotted(path: &[String], key: &str) -> String {
+    let mut parts = path.to_vec();
+    parts.push(key.to_string());
+    parts.join(".")
+}
+
+fn header(path: &[String]) -> String {
+    path.iter()
+        .map(|k| write_key(k))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn write_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        key.to_string()
+    } else {
+        quote(key)
+    }
+}
+
+fn quote(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn inline(value: &Value, location: &str) -> Result<String, String> {
+    Ok(match value {
+        Value::Null => return Err(format!("TOML has no null value (at {})", location)),
+        Value::Int(n) => n.to_string(),
+        Value::Float(f) if f.is_nan() => "nan".to_string(),
+        Value::Float(f) if f.is_infinite() => if *f > 0.0 { "inf" } else { "-inf" }.to_string(),
+        Value::Float(f) if f.fract() == 0.0 => format!("{:.1}", f),
+        Value::Float(f) => f.to_string(),
+        Value::String(s) => quote(s),
+        Value::Boolean(b) => b.to_string(),
+        Value::Duration(d) => quote(&d.to_string()),
+        Value::Function(_) | Value::Handle(_) => {
+            return Err(format!("TOML cannot store {} (at {})", value, location))
+        }
+        Value::Array(items) => {
+            let items = items
+                .iter()
+                .map(|item| inline(item, location))
+                .collect::<Result<Vec<_>, _>>()?;
+            format!("[{}]", items.join(", "))
+        }
+        Value::Object(fields) => {
+            if fields.is_empty() {
+                return Ok("{}".to_string());
+            }
+            let fields = fields
+                .iter()
+                .map(|(k, v)| {
+                    Ok(format!(
+                        "{} = {}",
+                        write_key(k),
+                        inline(v, &dotted(&[location.to_string()], k))?
+                    ))
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            format!("{{ {} }}", fields.join(", "))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::builtins::data::json;
+
+    fn value(text: &str) -> Value {
+        json::parse(text).unwrap()
+    }
+
+    #[test]
+    fn inline_tables_and_arrays_of_tables() {
+        let text = r#"
+title = "Prizm" # trailing comment
+owner = { name = "Ada", "e-mail" = 'ada@example.com', age = 36 }
+ports = [8000, 8001,
+         8002]
+
+[[servers]]
+name = "alpha"
+ratio = 0.5
+tags = ["a", "b"]
+
+[[servers]]
+name = "beta"
+enabled = false
+
+[servers.limits]
+cpu.cores = 4
+"#;
+        assert_eq!(
+            parse(text).unwrap(),
+            value(
+                r#"{
+                    "title": "Prizm",
+                    "owner": {"name": "Ada", "e-mail": "ada@example.com", "age": 36},
+                    "ports": [8000, 8001, 8002],
+                    "servers": [
+                        {"name": "alpha", "ratio": 0.5, "tags": ["a", "b"]},
+                        {"name": "beta", "enabled": false, "limits": {"cpu": {"cores": 4}}}
+                    ]
+                }"#
+            )
+        );
+    }
+
+    #[test]
+    fn strings_and_dates() {
+        let text = "a = \"tab\\there \\u00e9\"\nb = '''\nraw \\n'''\nc = \"\"\"\nline\"\"\"\nd = 1979-05-27T07:32:00Z\n";
+        assert_eq!(
+            parse(text).unwrap(),
+            value(
+                r#"{"a": "tab\there é", "b": "raw \\n", "c": "line", "d": "1979-05-27T07:32:00Z"}"#
+            )
+        );
+    }
+
+    #[test]
+    fn malformed_documents_are_errors() {
+        for (text, line) in [
+            ("a = 1\na = 2\n", 2),
+            ("a = \n", 1),
+            ("[table\nb = 1\n", 1),
+            ("x = { y = 1\n", 1),
+            ("s = \"open\n", 1),
+            ("a = 1\n[a]\n", 2),
+        ] {
+            let error = parse(text).unwrap_err();
+            assert!(
+                error.starts_with(&format!("TOML error on line {}:", line)),
+                "{:?} gave {}",
+                text,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn written_documents_read_back_the_same() {
+        let document = value(
+            r#"{
+                "name": "app",
+                "version": 2,
+                "weights": [1.5, -2.0],
+                "quoted key": "line\nbreak \"quoted\"",
+                "build": {"release": true, "flags": {"opt": 3}},
+                "targets": [{"os": "linux"}, {"os": "mac", "arch": ["arm64"]}]
+            }"#,
+        );
+        let text = stringify(&document).unwrap();
+        assert_eq!(parse(&text).unwrap(), document, "{}", text);
+        assert!(stringify(&value(r#"{"a": null}"#)).is_err());
+        assert!(stringify(&value("[1]")).is_err());
+    }
+}
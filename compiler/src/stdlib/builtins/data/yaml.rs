@@ -0,0 +1,709 @@
+// YAML Format (subset)
+// Block mappings and sequences, flow `[...]`/`{...}` collections, quoted and
+// plain scalars, and `|`/`>` block strings. Anchors, aliases, tags and
+// multiple documents are not supported.
+
+use crate::value::Value;
+
+struct Line {
+    number: usize,
+    indent: usize,
+    // Content with the indentation and any trailing comment removed
+    text: String,
+    raw: String,
+}
+
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut lines = Vec::new();
+    for (index, raw) in text.lines().enumerate() {
+        let trimmed = raw.trim_start();
+        let indent = raw.len() - trimmed.len();
+        if raw.starts_with("---") || raw.starts_with("...") {
+            if lines.iter().any(|l: &Line| !l.text.is_empty()) {
+                return Err(format!(
+                    "YAML error on line {}: multiple documents are not supported",
+                    index + 1
+                ));
+            }
+            continue;
+        }
+        if raw[..indent].contains('\t') {
+            return Err(format!(
+                "YAML error on line {}: tabs cannot be used for indentation",
+                index + 1
+            ));
+        }
+        lines.push(Line {
+            number: index + 1,
+            indent,
+            text: strip_comment(trimmed).trim_end().to_string(),
+            raw: raw.to_string(),
+        });
+    }
+
+    let mut parser = Parser { lines, index: 0 };
+    parser.skip_blank();
+    if parser.index >= parser.lines.len() {
+        return Ok(Value::Null);
+    }
+    let indent = parser.lines[parser.index].indent;
+    let value = parser.parse_block(indent)?;
+    parser.skip_blank();
+    if let Some(line) = parser.lines.get(parser.index) {
+        return Err(format!(
+            "YAML error on line {}: unexpected indentation",
+            line.number
+        ));
+    }
+    Ok(value)
+}
+
+fn strip_comment(text: &str) -> &str {
+    let mut quote: Option<char> = None;
+    let mut previous = ' ';
+    for (i, c) in text.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' && previous.is_whitespace() => return &text[..i],
+            None => {}
+        }
+        previous = c;
+    }
+    text
+}
+
+fn is_sequence_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+// Position of the `:` separating a mapping key from its value, if any
+fn mapping_colon(text: &str) -> Option<usize> {
+    let mut quote: Option<char> = None;
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' if i == 0 => quote = Some(c),
+                '[' | '{' => depth += 1,
+                ']' | '}' => depth -= 1,
+                ':' if depth == 0 => {
+                    let rest = &text[i + 1..];
+                    if rest.is_empty() || rest.starts_with(' ') {
+                        return Some(i);
+                    }
+                }
+                _ => {}
+            },
+        }
+    }
+    None
+}
+
+struct Parser {
+    lines: Vec<Line>,
+    index: usize,
+}
+
+impl Parser {
+    fn skip_blank(&mut self) {
+        while self.index < self.lines.len() && self.lines[self.index].text.is_empty() {
+            self.index += 1;
+        }
+    }
+
+    fn current(&self) -> Option<&Line> {
+        self.lines.get(self.index)
+    }
+
+    fn error(&self, message: &str) -> String {
+        let number = self
+            .current()
+            .or(self.lines.last())
+            .map(|l| l.number)
+            .unwrap_or(1);
+        format!("YAML error on line {}: {}", number, message)
+    }
+
+    fn parse_block(&mut self, indent: usize) -> Result<Value, String> {
+        let text = self.lines[self.index].text.clone();
+        if is_sequence_item(&text) {
+            self.parse_sequence(indent)
+        } else if mapping_colon(&text).is_some() {
+            self.parse_mapping(indent)
+        } else {
+            let value = parse_scalar(&text).map_err(|e| self.error(&e))?;
+            self.index += 1;
+            Ok(value)
+        }
+    }
+
+    fn parse_sequence(&mut self, indent: usize) -> Result<Value, String> {
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank();
+            let line = match self.current() {
+                Some(line) if line.indent == indent && is_sequence_item(&line.text) => line,
+                _ => break,
+            };
+            let rest = line.text[1..].trim_start().to_string();
+            if rest.is_empty() {
+                self.index += 1;
+                items.push(self.parse_nested(indent)?);
+            } else {
+                // `- key: value` and `- - item` open a collection inside the item
+                let offset = line.text.len() - rest.len();
+                let opens_block = is_sequence_item(&rest) || mapping_colon(&rest).is_some();
+                if opens_block && !rest.starts_with(['[', '{', '"', '\'']) {
+                    let line = &mut self.lines[self.index];
+                    line.indent += offset;
+                    line.text = rest;
+                    let child = line.indent;
+                    items.push(self.parse_block(child)?);
+                } else {
+                    items.push(self.parse_inline(&rest, indent)?);
+                }
+            }
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn parse_mapping(&mut self, indent: usize) -> Result<Value, String> {
+        let mut fields: Vec<(String, Value)> = Vec::new();
+        loop {
+            self.skip_blank();
+            let line = match self.current() {
+                Some(line) if line.indent == indent && !is_sequence_item(&line.text) => line,
+                Some(line) if line.indent > indent => {
+                    return Err(
+                        self.error(&format!("unexpected indentation of {} spaces", line.indent))
+                    )
+                }
+                _ => break,
+            };
+            let text = line.text.clone();
+            let colon = match mapping_colon(&text) {
+                Some(colon) => colon,
+                None => return Err(self.error("expected 'key: value'")),
+            };
+            let key = match parse_scalar(text[..colon].trim()).map_err(|e| self.error(&e))? {
+                Value::String(key) => key,
+                other => other.to_string(),
+            };
+            if fields.iter().any(|(k, _)| *k == key) {
+                return Err(self.error(&format!("duplicate key '{}'", key)));
+            }
+            let rest = text[colon + 1..].trim().to_string();
+            let value = if rest.is_empty() {
+                self.index += 1;
+                self.parse_nested(indent)?
+            } else {
+                self.parse_inline(&rest, indent)?
+            };
+            fields.push((key, value));
+        }
+        Ok(Value::Object(fields))
+    }
+
+    // The value of an empty `key:` or `-` is whatever block is indented below it
+    fn parse_nested(&mut self, indent: usize) -> Result<Value, String> {
+        self.skip_blank();
+        match self.current() {
+            Some(line) if line.indent > indent => {
+                let child = line.indent;
+                self.parse_block(child)
+            }
+            // Sequences may sit at the same indentation as their key
+            Some(line) if line.indent == indent && is_sequence_item(&line.text) => {
+                self.parse_sequence(indent)
+            }
+            _ => Ok(Value::Null),
+        }
+    }
+
+    fn parse_inline(&mut self, text: &str, indent: usize) -> Result<Value, String> {
+        if text.starts_with('|') || text.starts_with('>') {
+            return self.parse_block_string(text, indent);
+        }
+        let value = parse_scalar(text).map_err(|e| self.error(&e))?;
+        self.index += 1;
+        Ok(value)
+    }
+
+    fn parse_block_string(&mut self, header: &str, indent: usize) -> Result<Value, String> {
+        let folded = header.starts_with('>');
+        let chomp = &header[1..];
+        self.index += 1;
+
+        let mut body: Vec<&str> = Vec::new();
+        let mut block_indent = None;
+        while let Some(line) = self.lines.get(self.index) {
+            if line.raw.trim().is_empty() {
+                body.push("");
+                self.index += 1;
+                continue;
+            }
+            if line.indent <= indent {
+                break;
+            }
+            let start = *block_indent.get_or_insert(line.indent);
+            if line.indent < start {
+                break;
+            }
+            body.push(&line.raw[start..]);
+            self.index += 1;
+        }
+        // Trailing blank lines belong to whatever follows, not to the string
+        let mut trailing = 0;
+        while body.last() == Some(&"") {
+            body.pop();
+            trailing += 1;
+        }
+
+        let mut s = if folded {
+            let mut s = String::new();
+            for (i, line) in body.iter().enumerate() {
+                // Lines fold into one with a space; each blank line between
+                // them stands for a single line break
+                if line.is_empty() {
+                    s.push('\n');
+                } else if i > 0 && !body[i - 1].is_empty() {
+                    s.push(' ');
+                }
+                s.push_str(line);
+            }
+            s
+        } else {
+            body.join("\n")
+        };
+        match chomp {
+            "-" => {}
+            "+" => s.push_str(&"\n".repeat(trailing + 1)),
+            _ => s.push('\n'),
+        }
+        Ok(Value::String(s))
+    }
+}
+
+fn parse_scalar(text: &str) -> Result<Value, String> {
+    let mut flow = Flow {
+        input: text.chars().collect(),
+        position: 0,
+    };
+    let value = flow.parse_value(false)?;
+    flow.skip_spaces();
+    if flow.position < flow.input.len() {
+        return Err(format!("unexpected text after value: '{}'", text));
+    }
+    Ok(value)
+}
+
+// Scalars and `[...]` / `{...}` flow collections
+struct Flow {
+    input: Vec<char>,
+    position: usize,
+}
+
+impl Flow {
+    fn peek(&self) -> Option<char> {
+        self.input.get(self.position).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(c) if c.is_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn parse_value(&mut self, in_flow: bool) -> Result<Value, String> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('[') => {
+                self.position += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.skip_spaces();
+                    if self.peek() == Some(']') {
+                        self.position += 1;
+                        return Ok(Value::Array(items));
+                    }
+                    items.push(self.parse_value(true)?);
+                    self.skip_spaces();
+                    match self.peek() {
+                        Some(',') => self.position += 1,
+                        Some(']') => {}
+                        _ => return Err("expected ',' or ']' in flow sequence".to_string()),
+                    }
+                }
+            }
+            Some('{') => {
+                self.position += 1;
+                let mut fields = Vec::new();
+                loop {
+                    self.skip_spaces();
+                    if self.peek() == Some('}') {
+                        self.position += 1;
+                        return Ok(Value::Object(fields));
+                    }
+                    let key = match self.parse_value(true)? {
+                        Value::String(key) => key,
+                        other => other.to_string(),
+                    };
+                    self.skip_spaces();
+                    let value = if self.peek() == Some(':') {
+                        self.position += 1;
+                        self.parse_value(true)?
+                    } else {
+                        Value::Null
+                    };
+                    fields.push((key, value));
+                    self.skip_spaces();
+                    match self.peek() {
+                        Some(',') => self.position += 1,
+                        Some('}') => {}
+                        _ => return Err("expected ',' or '}' in flow mapping".to_string()),
+                    }
+                }
+            }
+            Some('"') => {
+                self.position += 1;
+                let mut s = String::new();
+                loop {
+                    match self.peek() {
+                        Some('"') => {
+                            self.position += 1;
+                            return Ok(Value::String(s));
+                        }
+                        Some('\\') => {
+                            self.position += 1;
+                            let c = self.peek().ok_or("unterminated string")?;
+                            self.position += 1;
+                            s.push(match c {
+                                'n' => '\n',
+                                't' => '\t',
+                                'r' => '\r',
+                                '0' => '\0',
+                                'x' | 'u' | 'U' => {
+                                    let len = match c {
+                                        'x' => 2,
+                                        'u' => 4,
+                                        _ => 8,
+                                    };
+                                    let end = (self.position + len).min(self.input.len());
+                                    let hex: String =
+                                        self.input[self.position..end].iter().collect();
+                                    self.position = end;
+                                    u32::from_str_radix(&hex, 16)
+                                        .ok()
+                                        .and_then(char::from_u32)
+                                        .ok_or(format!("invalid escape '\\{}{}'", c, hex))?
+                                }
+                                other => other,
+                            });
+                        }
+                        Some(c) => {
+                            s.push(c);
+                            self.position += 1;
+                        }
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+            }
+            Some('\'') => {
+                self.position += 1;
+                let mut s = String::new();
+                loop {
+                    match self.peek() {
+                        Some('\'') => {
+                            self.position += 1;
+                            if self.peek() == Some('\'') {
+                                s.push('\'');
+                                self.position += 1;
+                            } else {
+                                return Ok(Value::String(s));
+                            }
+                        }
+                        Some(c) => {
+                            s.push(c);
+                            self.position += 1;
+                        }
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+            }
+            Some('&') | Some('*') | Some('!') => {
+                Err("anchors, aliases and tags are not supported".to_string())
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(c) = self.peek() {
+                    let ends_flow = in_flow && matches!(c, ',' | ']' | '}');
+                    let ends_key = in_flow
+                        && c == ':'
+                        && matches!(self.input.get(self.position + 1), None | Some(' '));
+                    if ends_flow || ends_key {
+                        break;
+                    }
+                    word.push(c);
+                    self.position += 1;
+                }
+                Ok(plain_scalar(word.trim()))
+            }
+        }
+    }
+}
+
+fn plain_scalar(word: &str) -> Value {
+    match word {
+        "" | "~" | "null" | "Null" | "NULL" => return Value::Null,
+        "true" | "True" | "TRUE" => return Value::Boolean(true),
+        "false" | "False" | "FALSE" => return Value::Boolean(false),
+        ".inf" | "+.inf" | ".Inf" | ".INF" => return Value::Float(f64::INFINITY),
+        "-.inf" | "-.Inf" | "-.INF" => return Value::Float(f64::NEG_INFINITY),
+        ".nan" | ".NaN" | ".NAN" => return Value::Float(f64::NAN),
+        _ => {}
+    }
+    if let Some(hex) = word.strip_prefix("0x") {
+        if let Ok(n) = i64::from_str_radix(hex, 16) {
+            return Value::Int(n);
+        }
+    }
+    if let Some(octal) = word.strip_prefix("0o") {
+        if let Ok(n) = i64::from_str_radix(octal, 8) {
+            return Value::Int(n);
+        }
+    }
+    if let Ok(n) = word.parse::<i64>() {
+        return Value::Int(n);
+    }
+    let numeric = word
+        .trim_start_matches(['+', '-'])
+        .starts_with(|c: char| c.is_ascii_digit() || c == '.');
+    if numeric {
+        if let Ok(f) = word.parse::<f64>() {
+            return Value::Float(f);
+        }
+    }
+    Value::String(word.to_string())
+}
+
+// ===== WRITING =====
+
+pub fn stringify(value: &Value) -> Result<String, String> {
+    let mut out = String::new();
+    match value {
+        Value::Array(items) if !items.is_empty() => write_sequence(&mut out, items, 0),
+        Value::Object(fields) if !fields.is_empty() => write_mapping(&mut out, fields, 0),
+        scalar => {
+            out.push_str(&inline(scalar));
+            out.push('\n');
+        }
+    }
+    Ok(out)
+}
+
+fn write_mapping(out: &mut String, fields: &[(String, Value)], indent: usize) {
+    for (key, value) in fields {
+        out.push_str(&" ".repeat(indent));
+        out.push_str(&scalar_string(key));
+        out.push(':');
+        write_child(out, value, indent);
+    }
+}
+
+fn write_sequence(out: &mut String, items: &[Value], indent: usize) {
+    for item in items {
+        out.push_str(&" ".repeat(indent));
+        out.push('-');
+        match item {
+            // Nested collections start on the dash's line: `- key: value`
+            Value::Object(fields) if !fields.is_empty() => {
+                let mut nested = String::new();
+                write_mapping(&mut nested, fields, indent + 2);
+                out.push(' ');
+                out.push_str(&nested[indent + 2..]);
+            }
+            Value::Array(items) if !items.is_empty() => {
+                let mut nested = String::new();
+                write_sequence(&mut nested, items, indent + 2);
+                out.push(' ');
+                out.push_str(&nested[indent + 2..]);
+            }
+            scalar => {
+                out.push(' ');
+                out.push_str(&inline(scalar));
+                out.push('\n');
+            }
+        }
+    }
+}
+
+fn write_child(out: &mut String, value: &Value, indent: usize) {
+    match value {
+        Value::Object(fields) if !fields.is_empty() => {
+            out.push('\n');
+            write_mapping(out, fields, indent + 2);
+        }
+        Value::Array(items) if !items.is_empty() => {
+            out.push('\n');
+            write_sequence(out, items, indent + 2);
+        }
+        scalar => {
+            out.push(' ');
+            out.push_str(&inline(scalar));
+            out.push('\n');
+        }
+    }
+}
+
+fn inline(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Float(f) if f.is_nan() => ".nan".to_string(),
+        Value::Float(f) if f.is_infinite() => if *f > 0.0 { ".inf" } else { "-.inf" }.to_string(),
+        Value::Float(f) if f.fract() == 0.0 => format!("{:.1}", f),
+        Value::String(s) => scalar_string(s),
+        Value::Array(_) => "[]".to_string(),
+        Value::Object(_) => "{}".to_string(),
+        other => other.to_string(),
+    }
+}
+
+// Strings are left plain unless they would read back as something else
+fn scalar_string(s: &str) -> String {
+    let ambiguous = s.is_empty()
+        || !matches!(plain_scalar(s), Value::String(_))
+        || s.starts_with(|c: char| "-?:,[]{}#&*!|>'\"%@` ".contains(c))
+        || s.ends_with(' ')
+        || s.contains(": ")
+        || s.ends_with(':')
+        || s.contains(" #")
+        || s.contains(|c: char| c.is_control());
+    if !ambiguous {
+        return s.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::builtins::data::json;
+
+    fn value(text: &str) -> Value {
+        json::parse(text).unwrap()
+    }
+
+    #[test]
+    fn block_mappings_sequences_and_flow_collections() {
+        let text = "\
+# settings
+name: app   # trailing comment
+version: 1.5
+debug: ~
+hosts:
+  - alpha
+  - name: beta
+    port: 0x1F90
+limits: {cpu: 2, tags: [a, 'b c']}
+";
+        assert_eq!(
+            parse(text).unwrap(),
+            value(
+                r#"{
+                    "name": "app",
+                    "version": 1.5,
+                    "debug": null,
+                    "hosts": ["alpha", {"name": "beta", "port": 8080}],
+                    "limits": {"cpu": 2, "tags": ["a", "b c"]}
+                }"#
+            )
+        );
+    }
+
+    #[test]
+    fn block_scalars_keep_or_fold_their_lines() {
+        let text = "\
+literal: |
+  one
+    two
+
+folded: >
+  one
+  two
+
+  three
+strip: |-
+  end
+keep: |+
+  end
+
+last: x
+";
+        assert_eq!(
+            parse(text).unwrap(),
+            value(
+                r#"{
+                    "literal": "one\n  two\n",
+                    "folded": "one two\nthree\n",
+                    "strip": "end",
+                    "keep": "end\n\n",
+                    "last": "x"
+                }"#
+            )
+        );
+    }
+
+    #[test]
+    fn malformed_documents_are_errors() {
+        for (text, expected) in [
+            ("a: 1\na: 2\n", "YAML error on line 2: duplicate key 'a'"),
+            (
+                "a: 1\n\tb: 2\n",
+                "YAML error on line 2: tabs cannot be used for indentation",
+            ),
+            (
+                "a: 1\n---\nb: 2\n",
+                "YAML error on line 2: multiple documents are not supported",
+            ),
+        ] {
+            assert_eq!(parse(text), Err(expected.to_string()));
+        }
+        assert!(parse("a: [1, 2\n").is_err());
+        assert!(parse("just text\n  indented: 1\n").is_err());
+    }
+
+    #[test]
+    fn written_documents_read_back_the_same() {
+        let document = value(
+            r#"{
+                "name": "app",
+                "empty": "",
+                "looks like a number": "42",
+                "yes": "true",
+                "multi": "line one\nline two",
+                "ratio": 2.0,
+                "items": [1, [2, 3], {"k": "v", "list": []}, {}],
+                "nested": {"deep": {"deeper": null}}
+            }"#,
+        );
+        let text = stringify(&document).unwrap();
+        assert_eq!(parse(&text).unwrap(), document, "{}", text);
+    }
+}
@@ -0,0 +1,459 @@
+// Root Header for Prizm
+// Low-level operations that can change the machine a script runs on. Every
+// attribute is off unless the user turns it on with `--allow-root`, and every
+// call to one, allowed or not, is written to the audit log.
+
+use crate::stdlib::builtins::time::{self, Clock, Duration, SystemClock, TimeZone};
+use crate::value::{Handle, HandleKind, Value};
+use std::fs::OpenOptions;
+use std::io::Write;
+
+pub mod memory;
+pub mod process;
+
+/// Every attribute of the header, by name
+pub const ATTRIBUTES: [&str; 6] = [
+    "exec",
+    "system",
+    "memory",
+    "process",
+    "interrupt",
+    "optimize",
+];
+
+// ===== PERMISSIONS =====
+
+/// Which root attributes a run may use
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Permissions {
+    allowed: Vec<String>,
+}
+
+impl Permissions {
+    /// Nothing allowed, the default
+    pub fn none() -> Self {
+        Permissions::default()
+    }
+
+    pub fn all() -> Self {
+        Permissions {
+            allowed: ATTRIBUTES.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    /// Read the value of `--allow-root`: empty for everything, or a comma
+    /// separated list of attributes such as "exec,process"
+    pub fn parse(list: &str) -> Result<Self, String> {
+        if list.is_empty() {
+            return Ok(Permissions::all());
+        }
+        let mut allowed = Vec::new();
+        for name in list.split(',').map(str::trim) {
+            if !ATTRIBUTES.contains(&name) {
+                return Err(format!(
+                    "Unknown root attribute '{}' (expected one of {})",
+                    name,
+                    ATTRIBUTES.join(", ")
+                ));
+            }
+            allowed.push(name.to_string());
+        }
+        Ok(Permissions { allowed })
+    }
+
+    /// Allow more attributes, for when `--allow-root` is given more than once
+    pub fn extend(&mut self, other: Permissions) {
+        for name in other.allowed {
+            if !self.allowed.contains(&name) {
+                self.allowed.push(name);
+            }
+        }
+    }
+
+    pub fn allows(&self, attribute: &str) -> bool {
+        self.allowed.iter().any(|name| name == attribute)
+    }
+}
+
+// ===== AUDIT LOG =====
+
+/// Where each root call is recorded, one line per call:
+/// `<time> root.<attribute>(<arguments>) allowed|denied`
+pub enum AuditLog {
+    Off,
+    File(String),
+    // Kept in memory, for tests and embedders
+    Memory(Vec<String>),
+}
+
+/// The default log file, in the directory the script runs from
+pub const DEFAULT_AUDIT_LOG: &str = "prizm-audit.log";
+
+impl AuditLog {
+    fn record(&mut self, line: String) -> Result<(), String> {
+        match self {
+            AuditLog::Off => Ok(()),
+            AuditLog::Memory(lines) => {
+                lines.push(line);
+                Ok(())
+            }
+            AuditLog::File(path) => OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&*path)
+                .and_then(|mut file| writeln!(file, "{}", line))
+                .map_err(|e| format!("Failed to write audit log {}: {}", path, e)),
+        }
+    }
+
+    /// Lines recorded so far, for a log kept in memory
+    pub fn lines(&self) -> &[String] {
+        match self {
+            AuditLog::Memory(lines) => lines,
+            _ => &[],
+        }
+    }
+}
+
+// ===== RUNTIME =====
+
+/// State behind the `root` header for one running program
+pub struct Root {
+    permissions: Permissions,
+    audit: AuditLog,
+    // Processes started with `root.process`, indexed by handle id
+    children: Vec<process::Child>,
+    // Buffers from `root.memory`, indexed by handle id
+    buffers: Vec<memory::Buffer>,
+}
+
+impl Default for Root {
+    fn default() -> Self {
+        Root::new(
+            Permissions::none(),
+            AuditLog::File(DEFAULT_AUDIT_LOG.to_string()),
+        )
+    }
+}
+
+impl Root {
+    pub fn new(permissions: Permissions, audit: AuditLog) -> Self {
+        Root {
+            permissions,
+            audit,
+            children: Vec::new(),
+            buffers: Vec::new(),
+        }
+    }
+
+    pub fn audit_log(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    /// Record a call to `root.<attribute>` and fail unless it is allowed.
+    /// Every root attribute calls this before doing anything.
+    pub fn authorize(&mut self, attribute: &str, args: &[Value]) -> Result<(), String> {
+        let allowed = self.permissions.allows(attribute);
+        let args: Vec<String> = args.iter().map(describe).collect();
+        let stamp = time::format(SystemClock.now(), "%Y-%m-%dT%H:%M:%S%z", TimeZone::UTC)?;
+        self.audit.record(format!(
+            "{} root.{}({}) {}",
+            stamp,
+            attribute,
+            args.join(", "),
+            if allowed { "allowed" } else { "denied" }
+        ))?;
+
+        if allowed {
+            Ok(())
+        } else {
+            Err(format!(
+                "root.{} is disabled. Root attributes can change the system, so they \
+                 must be turned on: run with --allow-root={} (or --allow-root for all of them)",
+                attribute, attribute
+            ))
+        }
+    }
+
+    /// `root.system(instruction)` is reserved; commands run through `root.exec`
+    pub fn system(&mut self, instruction: &Value) -> Result<Value, String> {
+        self.authorize("system", std::slice::from_ref(instruction))?;
+        Err("root.system is not available yet; run commands with root.exec".to_string())
+    }
+
+    /// `root.exec(command, args, options)`: run a command to completion and
+    /// return `{stdout, stderr, code, timed_out}`
+    pub fn exec(
+        &mut self,
+        command: &Value,
+        args: Option<&Value>,
+        options: Option<&Value>,
+    ) -> Result<Value, String> {
+        self.authorize("exec", &call_args(command, args, options))?;
+        let spec = process::CommandSpec::from_values(command, args, options)?;
+        Ok(process::exec(&spec)?.to_value())
+    }
+
+    /// `root.process(command, args, options)` starts a command in the
+    /// background and returns a process handle; `root.process()` lists the
+    /// processes the script started
+    pub fn process(
+        &mut self,
+        command: Option<&Value>,
+        args: Option<&Value>,
+        options: Option<&Value>,
+    ) -> Result<Value, String> {
+        let command = match command {
+            Some(command) => command,
+            None => {
+                self.authorize("process", &[])?;
+                let mut list = Vec::new();
+                for (id, child) in self.children.iter_mut().enumerate() {
+                    list.push(Value::Object(vec![
+                        (
+                            "process".to_string(),
+                            Value::Handle(Handle::new(HandleKind::Process, id)),
+                        ),
+                        ("pid".to_string(), Value::Int(child.pid() as i64)),
+                        (
+                            "command".to_string(),
+                            Value::String(child.command().to_string()),
+                        ),
+                        ("running".to_string(), Value::Boolean(child.is_running())),
+                    ]));
+                }
+                return Ok(Value::Array(list));
+            }
+        };
+        self.authorize("process", &call_args(command, args, options))?;
+        let spec = process::CommandSpec::from_values(command, args, options)?;
+        self.children.push(process::spawn(&spec)?);
+        Ok(Value::Handle(Handle::new(
+            HandleKind::Process,
+            self.children.len() - 1,
+        )))
+    }
+
+    fn child(&mut self, handle: Handle) -> Result<&mut process::Child, String> {
+        match self.children.get_mut(handle.id) {
+            Some(child) if handle.kind == HandleKind::Process => Ok(child),
+            _ => Err(format!("{} is not a process", handle)),
+        }
+    }
+
+    /// Methods on process handles: pid, running, wait(timeout) and kill
+    pub fn process_method(
+        &mut self,
+        handle: Handle,
+        method: &str,
+        args: Vec<Value>,
+    ) -> Result<Value, String> {
+        let mut logged = vec![Value::Handle(handle), Value::String(method.to_string())];
+        logged.extend(args.iter().cloned());
+        self.authorize("process", &logged)?;
+        let child = self.child(handle)?;
+        match (method, args.as_slice()) {
+            ("pid", []) => Ok(Value::Int(child.pid() as i64)),
+            ("running", []) => Ok(Value::Boolean(child.is_running())),
+            ("wait", []) => Ok(child.wait()?.to_value()),
+            ("wait", [timeout]) => Ok(child
+                .wait_timeout(Duration::from_value(timeout)?)?
+                .map_or(Value::Null, |output| output.to_value())),
+            ("kill", []) => {
+                child.kill()?;
+                Ok(Value::Null)
+            }
+            (other, _) => Err(format!(
+                "Processes have no method '{}' taking {} arguments",
+                other,
+                args.len()
+            )),
+        }
+    }
+
+    /// `root.interrupt(process, signal)`: send a signal such as "SIGINT" or
+    /// "SIGTERM" to a process the script started
+    pub fn interrupt(&mut self, target: &Value, signal: &Value) -> Result<Value, String> {
+        self.authorize("interrupt", &[target.clone(), signal.clone()])?;
+        let handle = match target {
+            Value::Handle(handle) => *handle,
+            other => {
+                return Err(format!(
+                    "Expected a process from root.process, found {}",
+                    other.type_of().to_string()
+                ))
+            }
+        };
+        let signal = match signal {
+            Value::String(signal) => signal.clone(),
+            other => {
+                return Err(format!(
+                    "Expected a signal name, found {}",
+                    other.type_of().to_string()
+                ))
+            }
+        };
+        self.child(handle)?.signal(&signal)?;
+        Ok(Value::Null)
+    }
+
+    /// `root.memory(size)` allocates a zeroed buffer; `root.memory(text)` and
+    /// `root.memory([bytes])` copy their contents into a new one
+    pub fn memory(&mut self, contents: &Value) -> Result<Value, String> {
+        self.authorize("memory", std::slice::from_ref(contents))?;
+        let buffer = memory::Buffer::from_value(contents)?;
+        Ok(self.add_buffer(buffer))
+    }
+
+    fn add_buffer(&mut self, buffer: memory::Buffer) -> Value {
+        self.buffers.push(buffer);
+        Value::Handle(Handle::new(HandleKind::Buffer, self.buffers.len() - 1))
+    }
+
+    /// Methods on buffer handles: size, read_u8 ... read_u64, read_f64, the
+    /// matching writes, slice, fill, bytes, text and hex
+    pub fn buffer_method(
+        &mut self,
+        handle: Handle,
+        method: &str,
+        args: Vec<Value>,
+    ) -> Result<Value, String> {
+        // Only the `root.memory` call that made the buffer is checked and
+        // logged; using the buffer touches nothing outside it
+        let buffer = match self.buffers.get_mut(handle.id) {
+            Some(buffer) if handle.kind == HandleKind::Buffer => buffer,
+            _ => return Err(format!("{} is not a buffer", handle)),
+        };
+        match (method, args.as_slice()) {
+            ("slice", [Value::Int(start), Value::Int(end)]) => {
+                let slice = buffer.slice(*start, *end)?;
+                Ok(self.add_buffer(slice))
+            }
+            ("slice", [Value::Int(start)]) => {
+                let slice = buffer.slice(*start, buffer.len() as i64)?;
+                Ok(self.add_buffer(slice))
+            }
+            _ => buffer.method(method, &args),
+        }
+    }
+
+    /// `root.optimize(function)` marks a function for the optimizer, which
+    /// reads the marks before the program starts. At run time the call is
+    /// only checked and logged.
+    pub fn optimize(&mut self, function: &Value) -> Result<Value, String> {
+        self.authorize("optimize", std::slice::from_ref(function))?;
+        match function {
+            Value::Function(_) => Ok(Value::Null),
+            other => Err(format!(
+                "Expected a function to optimize, found {}",
+                other.type_of().to_string()
+            )),
+        }
+    }
+}
+
+fn call_args(command: &Value, args: Option<&Value>, options: Option<&Value>) -> Vec<Value> {
+    let mut logged = vec![command.clone()];
+    logged.extend(args.cloned());
+    logged.extend(options.cloned());
+    logged
+}
+
+// Arguments as they would be written in Prizm, so log lines read like the call
+fn describe(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", s),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root(allow: &str) -> Root {
+        Root::new(
+            Permissions::parse(allow).unwrap(),
+            AuditLog::Memory(Vec::new()),
+        )
+    }
+
+    // Logged calls without their timestamps
+    fn calls(root: &Root) -> Vec<&str> {
+        root.audit_log()
+            .lines()
+            .iter()
+            .map(|line| line.split_once(' ').unwrap().1)
+            .collect()
+    }
+
+    #[test]
+    fn parse_reads_allow_root_lists() {
+        assert_eq!(Permissions::parse(""), Ok(Permissions::all()));
+        let some = Permissions::parse("exec, memory").unwrap();
+        assert!(some.allows("exec") && some.allows("memory"));
+        assert!(!some.allows("process"));
+        assert_eq!(
+            Permissions::parse("exec,shell"),
+            Err(
+                "Unknown root attribute 'shell' (expected one of exec, system, memory, \
+                 process, interrupt, optimize)"
+                    .to_string()
+            )
+        );
+        assert!(Permissions::parse("exec,").is_err());
+    }
+
+    #[test]
+    fn extend_adds_attributes_once() {
+        let mut permissions = Permissions::parse("exec").unwrap();
+        permissions.extend(Permissions::parse("exec,process").unwrap());
+        assert_eq!(permissions, Permissions::parse("exec,process").unwrap());
+        permissions.extend(Permissions::none());
+        assert!(!permissions.allows("memory"));
+    }
+
+    #[test]
+    fn audit_log_records_allowed_and_denied_calls() {
+        let mut root = root("memory");
+        root.memory(&Value::Int(4)).unwrap();
+        let denied = root.optimize(&Value::Function("main".to_string()));
+        assert!(denied.unwrap_err().contains("--allow-root=optimize"));
+        root.memory(&Value::String("hi".to_string())).unwrap();
+        assert_eq!(
+            calls(&root),
+            vec![
+                "root.memory(4) allowed",
+                "root.optimize(<function main>) denied",
+                "root.memory(\"hi\") allowed",
+            ]
+        );
+    }
+
+    #[test]
+    fn buffer_use_is_not_logged() {
+        let mut root = root("memory");
+        let Value::Handle(buffer) = root.memory(&Value::Int(8)).unwrap() else {
+            panic!("root.memory should return a buffer");
+        };
+        let write = vec![Value::Int(0), Value::Int(7)];
+        root.buffer_method(buffer, "write_u8", write).unwrap();
+        root.buffer_method(buffer, "slice", vec![Value::Int(0), Value::Int(4)])
+            .unwrap();
+        assert_eq!(
+            root.buffer_method(buffer, "read_u8", vec![Value::Int(0)]),
+            Ok(Value::Int(7))
+        );
+        assert_eq!(calls(&root), vec!["root.memory(8) allowed"]);
+    }
+
+    #[test]
+    fn audit_lines_start_with_a_utc_timestamp() {
+        let mut root = root("");
+        root.optimize(&Value::Function("main".to_string())).unwrap();
+        let line = &root.audit_log().lines()[0];
+        let (stamp, _) = line.split_once(' ').unwrap();
+        assert_eq!(stamp.len(), "2024-01-01T00:00:00+0000".len());
+        assert!(stamp.ends_with("+0000"));
+    }
+}
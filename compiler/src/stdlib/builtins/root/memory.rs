@@ -0,0 +1,378 @@
+// Byte Buffers for the Root Header
+// `root.memory` hands out buffers the script owns instead of raw addresses.
+// Every read and write is checked against the buffer's size, so binary
+// protocol work cannot touch memory outside it.
+
+use crate::value::Value;
+
+/// Largest buffer a script may allocate (256 MiB)
+pub const MAX_SIZE: usize = 256 * 1024 * 1024;
+
+// Bytes per line of `hex()`
+const HEX_WIDTH: usize = 16;
+
+/// Byte order for multi-byte reads and writes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    /// "little" (the default) or "big"
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "little" | "le" => Ok(Endian::Little),
+            "big" | "be" => Ok(Endian::Big),
+            other => Err(format!(
+                "Unknown byte order '{}' (expected \"little\" or \"big\")",
+                other
+            )),
+        }
+    }
+}
+
+/// A fixed-size, zero-initialised block of bytes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Buffer {
+    bytes: Vec<u8>,
+}
+
+impl Buffer {
+    pub fn new(size: usize) -> Result<Self, String> {
+        check_size(size)?;
+        Ok(Buffer {
+            bytes: vec![0; size],
+        })
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, String> {
+        check_size(bytes.len())?;
+        Ok(Buffer { bytes })
+    }
+
+    /// A buffer from `root.memory`'s argument: a size, a string (its UTF-8
+    /// bytes) or an array of byte values
+    pub fn from_value(value: &Value) -> Result<Self, String> {
+        match value {
+            Value::Int(size) if *size >= 0 => Buffer::new(*size as usize),
+            Value::Int(size) => Err(format!("Buffer size must not be negative, got {}", size)),
+            Value::String(text) => Buffer::from_bytes(text.as_bytes().to_vec()),
+            Value::Array(items) => Buffer::from_bytes(
+                items
+                    .iter()
+                    .map(byte)
+                    .collect::<Result<Vec<u8>, String>>()?,
+            ),
+            other => Err(format!(
+                "Expected a size, string or array of bytes, found {}",
+                other.type_of().to_string()
+            )),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    // The range `offset..offset + width`, or an error if any of it falls
+    // outside the buffer
+    fn range(&self, offset: i64, width: usize) -> Result<std::ops::Range<usize>, String> {
+        let start = usize::try_from(offset).ok();
+        match start.and_then(|start| start.checked_add(width).map(|end| (start, end))) {
+            Some((start, end)) if end <= self.bytes.len() => Ok(start..end),
+            _ => Err(format!(
+                "Offset {} is out of bounds: {} byte access in a buffer of {} bytes",
+                offset,
+                width,
+                self.bytes.len()
+            )),
+        }
+    }
+
+    /// Read an unsigned integer of `width` bytes (1, 2, 4 or 8)
+    pub fn read_uint(&self, offset: i64, width: usize, endian: Endian) -> Result<u64, String> {
+        let range = self.range(offset, width)?;
+        let bytes = &self.bytes[range];
+        let fold = |value: u64, byte: &u8| (value << 8) | *byte as u64;
+        Ok(match endian {
+            Endian::Big => bytes.iter().fold(0, fold),
+            Endian::Little => bytes.iter().rev().fold(0, fold),
+        })
+    }
+
+    /// Write the low `width` bytes of `value`; values that do not fit are an
+    /// error rather than being truncated
+    pub fn write_uint(
+        &mut self,
+        offset: i64,
+        width: usize,
+        value: u64,
+        endian: Endian,
+    ) -> Result<(), String> {
+        if width < 8 && value >> (width * 8) != 0 {
+            return Err(format!(
+                "{} does not fit in a u{} (0 to {})",
+                value,
+                width * 8,
+                (1u64 << (width * 8)) - 1
+            ));
+        }
+        let range = self.range(offset, width)?;
+        let bytes = &mut self.bytes[range];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            let shift = match endian {
+                Endian::Little => i,
+                Endian::Big => width - 1 - i,
+            };
+            *byte = (value >> (shift * 8)) as u8;
+        }
+        Ok(())
+    }
+
+    pub fn read_f64(&self, offset: i64, endian: Endian) -> Result<f64, String> {
+        Ok(f64::from_bits(self.read_uint(offset, 8, endian)?))
+    }
+
+    pub fn write_f64(&mut self, offset: i64, value: f64, endian: Endian) -> Result<(), String> {
+        self.write_uint(offset, 8, value.to_bits(), endian)
+    }
+
+    /// A copy of the bytes from `start` up to, not including, `end`
+    pub fn slice(&self, start: i64, end: i64) -> Result<Buffer, String> {
+        if end < start {
+            return Err(format!(
+                "Slice end {} comes before its start {}",
+                end, start
+            ));
+        }
+        let width = end
+            .checked_sub(start)
+            .and_then(|width| usize::try_from(width).ok())
+            .ok_or_else(|| format!("Slice {}..{} is too large", start, end))?;
+        let range = self.range(start, width).map_err(|_| {
+            format!(
+                "Slice {}..{} is out of bounds in a buffer of {} bytes",
+                start,
+                end,
+                self.bytes.len()
+            )
+        })?;
+        Buffer::from_bytes(self.bytes[range].to_vec())
+    }
+
+    /// Offset, hex bytes and printable characters, 16 bytes per line:
+    /// `00000000  48 69 0a                                          |Hi.|`
+    pub fn hex_dump(&self) -> String {
+        let mut out = String::new();
+        for (line, chunk) in self.bytes.chunks(HEX_WIDTH).enumerate() {
+            let mut hex = String::new();
+            for (i, byte) in chunk.iter().enumerate() {
+                if i == HEX_WIDTH / 2 {
+                    hex.push(' ');
+                }
+                hex.push_str(&format!("{:02x} ", byte));
+            }
+            let text: String = chunk
+                .iter()
+                .map(|&byte| {
+                    if byte.is_ascii_graphic() || byte == b' ' {
+                        byte as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            out.push_str(&format!(
+                "{:08x}  {:<width$} |{}|\n",
+                line * HEX_WIDTH,
+                hex,
+                text,
+                width = HEX_WIDTH * 3 + 1
+            ));
+        }
+        out
+    }
+
+    /// Methods on buffer handles, apart from `slice` which makes a new handle
+    pub fn method(&mut self, method: &str, args: &[Value]) -> Result<Value, String> {
+        let width = match method {
+            "read_u8" | "write_u8" => 1,
+            "read_u16" | "write_u16" => 2,
+            "read_u32" | "write_u32" => 4,
+            "read_u64" | "write_u64" | "read_f64" | "write_f64" => 8,
+            _ => 0,
+        };
+        match (method, args) {
+            ("size", []) => Ok(Value::Int(self.len() as i64)),
+            ("bytes", []) => Ok(Value::Array(
+                self.bytes.iter().map(|&b| Value::Int(b as i64)).collect(),
+            )),
+            ("text", []) => Ok(Value::String(
+                String::from_utf8_lossy(&self.bytes).to_string(),
+            )),
+            ("hex", []) => Ok(Value::String(self.hex_dump())),
+            ("fill", [value]) => {
+                let value = byte(value)?;
+                self.bytes.iter_mut().for_each(|b| *b = value);
+                Ok(Value::Null)
+            }
+            ("read_f64", [offset, rest @ ..]) if rest.len() <= 1 => {
+                Ok(Value::Float(self.read_f64(int(offset)?, endian(rest)?)?))
+            }
+            ("write_f64", [offset, value, rest @ ..]) if rest.len() <= 1 => {
+                let value = match value {
+                    Value::Float(f) => *f,
+                    Value::Int(n) => *n as f64,
+                    other => {
+                        return Err(format!(
+                            "Expected a number, found {}",
+                            other.type_of().to_string()
+                        ))
+                    }
+                };
+                self.write_f64(int(offset)?, value, endian(rest)?)?;
+                Ok(Value::Null)
+            }
+            (_, [offset, rest @ ..])
+                if width > 0 && method.starts_with("read_u") && rest.len() <= 1 =>
+            {
+                let value = self.read_uint(int(offset)?, width, endian(rest)?)?;
+                // Prizm ints are signed 64-bit
+                i64::try_from(value).map(Value::Int).map_err(|_| {
+                    format!(
+                        "u64 value {} is too large for a Prizm int; read it as two u32s",
+                        value
+                    )
+                })
+            }
+            (_, [offset, value, rest @ ..])
+                if width > 0 && method.starts_with("write_u") && rest.len() <= 1 =>
+            {
+                let value = int(value)?;
+                let value = u64::try_from(value)
+                    .map_err(|_| format!("Cannot write negative value {} as unsigned", value))?;
+                self.write_uint(int(offset)?, width, value, endian(rest)?)?;
+                Ok(Value::Null)
+            }
+            _ => Err(format!(
+                "Buffers have no method '{}' taking {} arguments",
+                method,
+                args.len()
+            )),
+        }
+    }
+}
+
+fn check_size(size: usize) -> Result<(), String> {
+    if size > MAX_SIZE {
+        return Err(format!(
+            "Failed to allocate {} bytes: buffers are limited to {} bytes",
+            size, MAX_SIZE
+        ));
+    }
+    Ok(())
+}
+
+fn int(value: &Value) -> Result<i64, String> {
+    match value {
+        Value::Int(n) => Ok(*n),
+        other => Err(format!(
+            "Expected an int, found {}",
+            other.type_of().to_string()
+        )),
+    }
+}
+
+fn byte(value: &Value) -> Result<u8, String> {
+    let n = int(value)?;
+    u8::try_from(n).map_err(|_| format!("{} is not a byte (0 to 255)", n))
+}
+
+// The optional byte order argument that ends read and write calls
+fn endian(rest: &[Value]) -> Result<Endian, String> {
+    match rest {
+        [] => Ok(Endian::Little),
+        [Value::String(name)] => Endian::parse(name),
+        [other, ..] => Err(format!(
+            "Expected a byte order, found {}",
+            other.type_of().to_string()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_and_writes_in_either_byte_order() {
+        let mut buffer = Buffer::new(8).unwrap();
+        buffer.write_uint(0, 2, 0xCAFE, Endian::Big).unwrap();
+        assert_eq!(&buffer.as_bytes()[..2], &[0xCA, 0xFE]);
+        assert_eq!(buffer.read_uint(0, 2, Endian::Little).unwrap(), 0xFECA);
+        buffer.write_f64(0, 1.5, Endian::Little).unwrap();
+        assert_eq!(buffer.read_f64(0, Endian::Little).unwrap(), 1.5);
+    }
+
+    #[test]
+    fn accesses_outside_the_buffer_fail() {
+        let mut buffer = Buffer::new(4).unwrap();
+        assert!(buffer.read_uint(0, 4, Endian::Little).is_ok());
+        assert_eq!(
+            buffer.read_uint(1, 4, Endian::Little),
+            Err("Offset 1 is out of bounds: 4 byte access in a buffer of 4 bytes".to_string())
+        );
+        assert!(buffer.read_uint(-1, 1, Endian::Little).is_err());
+        assert!(buffer.write_uint(4, 1, 0, Endian::Little).is_err());
+        assert!(buffer.read_uint(i64::MAX, 8, Endian::Little).is_err());
+        assert_eq!(buffer.as_bytes(), &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn slices_are_checked() {
+        let buffer = Buffer::from_bytes(b"prizm".to_vec()).unwrap();
+        assert_eq!(buffer.slice(1, 3).unwrap().as_bytes(), b"ri");
+        assert!(buffer.slice(5, 5).unwrap().is_empty());
+        assert_eq!(
+            buffer.slice(3, 6),
+            Err("Slice 3..6 is out of bounds in a buffer of 5 bytes".to_string())
+        );
+        assert!(buffer.slice(-1, 2).is_err());
+        assert_eq!(
+            buffer.slice(3, 1),
+            Err("Slice end 1 comes before its start 3".to_string())
+        );
+        assert_eq!(
+            buffer.slice(i64::MIN, i64::MAX),
+            Err(format!("Slice {}..{} is too large", i64::MIN, i64::MAX))
+        );
+    }
+
+    #[test]
+    fn buffers_are_limited_in_size() {
+        assert!(Buffer::new(MAX_SIZE + 1).is_err());
+        assert!(Buffer::from_value(&Value::Int(-1)).is_err());
+        assert!(Buffer::from_value(&Value::Array(vec![Value::Int(256)])).is_err());
+    }
+
+    #[test]
+    fn methods_check_their_arguments() {
+        let mut buffer = Buffer::new(2).unwrap();
+        let write = |value| vec![Value::Int(0), Value::Int(value)];
+        assert!(buffer.method("write_u8", &write(255)).is_ok());
+        assert!(buffer.method("write_u8", &write(-1)).is_err());
+        assert!(buffer.method("write_u16", &write(1)).is_ok());
+        assert!(buffer.method("write_u32", &write(1)).is_err());
+        assert_eq!(buffer.method("size", &[]), Ok(Value::Int(2)));
+        assert!(buffer.method("grow", &[]).is_err());
+    }
+}
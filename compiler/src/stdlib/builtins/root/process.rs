@@ -0,0 +1,542 @@
+// Subprocesses for the Root Header
+// Commands run directly, never through a shell, so arguments cannot smuggle in
+// extra commands. Output is collected on background threads so a child that
+// writes a lot cannot fill its pipe and stall. Each command runs in its own
+// process group, so a timeout or a kill ends everything it started, and
+// whatever is still running when the script ends is killed with it.
+
+use crate::stdlib::builtins::time::Duration;
+use crate::value::Value;
+use std::io::{ErrorKind, Read, Write};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+// How often a wait with a timeout checks on the child
+const WAIT_STEP: std::time::Duration = std::time::Duration::from_millis(5);
+
+// How long output keeps being read after a timed wait ends
+const READ_GRACE: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// What to run and how, read from `(command, args, options)`
+#[derive(Debug, Clone, Default)]
+pub struct CommandSpec {
+    pub program: String,
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub cwd: Option<String>,
+    pub stdin: Option<String>,
+    pub timeout: Option<Duration>,
+}
+
+impl CommandSpec {
+    /// `args` is an array of strings; `options` is an optional object such as
+    /// `{env: {LANG: "C"}, cwd: "build", stdin: "input", timeout: "5s"}`
+    pub fn from_values(
+        command: &Value,
+        args: Option<&Value>,
+        options: Option<&Value>,
+    ) -> Result<Self, String> {
+        let program = match command {
+            Value::String(program) if !program.is_empty() => program.clone(),
+            other => {
+                return Err(format!(
+                    "Expected a command name, found {}",
+                    describe(other)
+                ))
+            }
+        };
+        let args = match args {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Array(items)) => items
+                .iter()
+                .map(|item| match item {
+                    Value::String(s) => Ok(s.clone()),
+                    Value::Int(_) | Value::Float(_) | Value::Boolean(_) => Ok(item.to_string()),
+                    other => Err(format!(
+                        "Command arguments must be strings, found {}",
+                        describe(other)
+                    )),
+                })
+                .collect::<Result<_, _>>()?,
+            Some(other) => {
+                return Err(format!(
+                    "Command arguments must be an array, found {}",
+                    describe(other)
+                ))
+            }
+        };
+        let mut spec = CommandSpec {
+            program,
+            args,
+            ..CommandSpec::default()
+        };
+
+        let options = match options {
+            None | Some(Value::Null) => return Ok(spec),
+            Some(options @ Value::Object(_)) => options,
+            Some(other) => {
+                return Err(format!(
+                    "Command options must be an object, found {}",
+                    describe(other)
+                ))
+            }
+        };
+        match options.get("env") {
+            None => {}
+            Some(Value::Object(vars)) => {
+                for (key, value) in vars {
+                    let value = match value {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    spec.env.push((key.clone(), value));
+                }
+            }
+            Some(_) => return Err("Command option 'env' must be an object".to_string()),
+        }
+        match options.get("cwd") {
+            None => {}
+            Some(Value::String(cwd)) => spec.cwd = Some(cwd.clone()),
+            Some(_) => return Err("Command option 'cwd' must be a string".to_string()),
+        }
+        match options.get("stdin") {
+            None => {}
+            Some(Value::String(stdin)) => spec.stdin = Some(stdin.clone()),
+            Some(_) => return Err("Command option 'stdin' must be a string".to_string()),
+        }
+        if let Some(timeout) = options.get("timeout") {
+            spec.timeout = Some(Duration::from_value(timeout)?);
+        }
+        Ok(spec)
+    }
+
+    /// The command line, for listings and error messages
+    pub fn display(&self) -> String {
+        let mut parts = vec![self.program.clone()];
+        parts.extend(self.args.iter().cloned());
+        parts.join(" ")
+    }
+}
+
+fn describe(value: &Value) -> String {
+    value.type_of().to_string()
+}
+
+/// How a finished command went
+#[derive(Debug, Clone, PartialEq)]
+pub struct Output {
+    pub stdout: String,
+    pub stderr: String,
+    // None when the process was ended by a signal
+    pub code: Option<i32>,
+    // The signal that ended the process, such as "SIGTERM"
+    pub signal: Option<String>,
+    pub timed_out: bool,
+}
+
+impl Output {
+    /// `{stdout, stderr, code, signal, timed_out}`
+    pub fn to_value(&self) -> Value {
+        Value::Object(vec![
+            ("stdout".to_string(), Value::String(self.stdout.clone())),
+            ("stderr".to_string(), Value::String(self.stderr.clone())),
+            (
+                "code".to_string(),
+                self.code
+                    .map_or(Value::Null, |code| Value::Int(code as i64)),
+            ),
+            (
+                "signal".to_string(),
+                self.signal.clone().map_or(Value::Null, Value::String),
+            ),
+            ("timed_out".to_string(), Value::Boolean(self.timed_out)),
+        ])
+    }
+}
+
+/// A process started by the script
+pub struct Child {
+    process: std::process::Child,
+    command: String,
+    stdout: Option<Reader>,
+    stderr: Option<Reader>,
+    // Set once the process has been waited on
+    output: Option<Output>,
+}
+
+// One of the child's output pipes, read on a background thread
+struct Reader {
+    bytes: Arc<Mutex<Vec<u8>>>,
+    thread: JoinHandle<()>,
+}
+
+fn collect<R: Read + Send + 'static>(pipe: Option<R>) -> Option<Reader> {
+    pipe.map(|mut pipe| {
+        let bytes = Arc::new(Mutex::new(Vec::new()));
+        let read = Arc::clone(&bytes);
+        let thread = std::thread::spawn(move || {
+            let mut chunk = [0; 4096];
+            loop {
+                match pipe.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(n) => read
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .extend_from_slice(&chunk[..n]),
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(_) => break,
+                }
+            }
+        });
+        Reader { bytes, thread }
+    })
+}
+
+// The output read so far. Without a deadline this waits for the pipe to
+// close; with one it gives up then, since a process the child started can
+// keep the pipe open long after the child has gone.
+fn finish(reader: Option<Reader>, deadline: Option<Instant>) -> String {
+    let reader = match reader {
+        Some(reader) => reader,
+        None => return String::new(),
+    };
+    match deadline {
+        None => {
+            let _ = reader.thread.join();
+        }
+        Some(deadline) => {
+            while !reader.thread.is_finished() && Instant::now() < deadline {
+                std::thread::sleep(WAIT_STEP);
+            }
+        }
+    }
+    let bytes = reader.bytes.lock().unwrap_or_else(|e| e.into_inner());
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+/// Start a command without waiting for it
+pub fn spawn(spec: &CommandSpec) -> Result<Child, String> {
+    let mut command = Command::new(&spec.program);
+    command
+        .args(&spec.args)
+        .envs(spec.env.iter().map(|(k, v)| (k, v)))
+        .stdin(if spec.stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(cwd) = &spec.cwd {
+        command.current_dir(cwd);
+    }
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let mut process = command
+        .spawn()
+        .map_err(|e| format!("Failed to run '{}': {}", spec.program, e))?;
+
+    if let (Some(input), Some(mut stdin)) = (spec.stdin.clone(), process.stdin.take()) {
+        // Written from a thread so a child that does not read cannot block us;
+        // dropping the pipe afterwards closes the child's stdin
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(input.as_bytes());
+        });
+    }
+    let stdout = collect(process.stdout.take());
+    let stderr = collect(process.stderr.take());
+    Ok(Child {
+        process,
+        command: spec.display(),
+        stdout,
+        stderr,
+        output: None,
+    })
+}
+
+/// Run a command to completion, as `root.exec` does. A command still running
+/// when its timeout runs out is killed and reported as `timed_out`.
+pub fn exec(spec: &CommandSpec) -> Result<Output, String> {
+    let mut child = spawn(spec)?;
+    let timeout = match spec.timeout {
+        Some(timeout) => timeout,
+        None => return child.wait(),
+    };
+    let deadline = deadline(timeout);
+    if let Some(status) = child.wait_until(deadline)? {
+        return Ok(child.collect_output(status, Some(deadline), false));
+    }
+    let _ = child.kill();
+    let status = child
+        .process
+        .wait()
+        .map_err(|e| format!("Failed to wait for '{}': {}", child.command, e))?;
+    Ok(child.collect_output(status, Some(deadline), true))
+}
+
+fn deadline(timeout: Duration) -> Instant {
+    Instant::now() + std::time::Duration::from_millis(timeout.as_millis().max(0) as u64)
+}
+
+impl Child {
+    pub fn pid(&self) -> u32 {
+        self.process.id()
+    }
+
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    pub fn is_running(&mut self) -> bool {
+        self.output.is_none() && matches!(self.process.try_wait(), Ok(None))
+    }
+
+    /// Wait for the process to exit
+    pub fn wait(&mut self) -> Result<Output, String> {
+        if let Some(output) = &self.output {
+            return Ok(output.clone());
+        }
+        let status = self
+            .process
+            .wait()
+            .map_err(|e| format!("Failed to wait for '{}': {}", self.command, e))?;
+        Ok(self.collect_output(status, None, false))
+    }
+
+    /// Wait at most `timeout` for the process to exit. A process still
+    /// running then is left alone and the result is None.
+    pub fn wait_timeout(&mut self, timeout: Duration) -> Result<Option<Output>, String> {
+        if let Some(output) = &self.output {
+            return Ok(Some(output.clone()));
+        }
+        let deadline = deadline(timeout);
+        Ok(self
+            .wait_until(deadline)?
+            .map(|status| self.collect_output(status, Some(deadline), false)))
+    }
+
+    // The exit status, or None if the process is still running at `deadline`
+    fn wait_until(&mut self, deadline: Instant) -> Result<Option<ExitStatus>, String> {
+        loop {
+            match self.process.try_wait() {
+                Ok(Some(status)) => return Ok(Some(status)),
+                Ok(None) if Instant::now() >= deadline => return Ok(None),
+                Ok(None) => std::thread::sleep(WAIT_STEP),
+                Err(e) => return Err(format!("Failed to wait for '{}': {}", self.command, e)),
+            }
+        }
+    }
+
+    // Collect the output of a process that has exited and keep it for later
+    // waits. After a timed wait, output is read only a little past the
+    // deadline.
+    fn collect_output(
+        &mut self,
+        status: ExitStatus,
+        deadline: Option<Instant>,
+        timed_out: bool,
+    ) -> Output {
+        let read_deadline = deadline.map(|deadline| deadline.max(Instant::now()) + READ_GRACE);
+        let output = Output {
+            stdout: finish(self.stdout.take(), read_deadline),
+            stderr: finish(self.stderr.take(), read_deadline),
+            code: status.code(),
+            signal: exit_signal(&status),
+            timed_out,
+        };
+        self.output = Some(output.clone());
+        output
+    }
+
+    /// Kill the process and anything it started
+    pub fn kill(&mut self) -> Result<(), String> {
+        // The group can outlive the child, so it is signalled either way
+        #[cfg(unix)]
+        match send_signal(-(self.pid() as i32), "KILL") {
+            Ok(()) => {}
+            // No process is left in the group
+            Err(e) if e.raw_os_error() == Some(libc::ESRCH) => {}
+            Err(e) => return Err(format!("Failed to kill '{}': {}", self.command, e)),
+        }
+        if !self.is_running() {
+            return Ok(());
+        }
+        self.process
+            .kill()
+            .map_err(|e| format!("Failed to kill '{}': {}", self.command, e))
+    }
+
+    /// Send a signal by name ("SIGINT", "TERM", ...) to the process and
+    /// anything it started. Only KILL works outside Unix.
+    pub fn signal(&mut self, name: &str) -> Result<(), String> {
+        let signal = signal_name(name)?;
+        if !self.is_running() {
+            return Err(format!("'{}' is not running", self.command));
+        }
+        if signal == "KILL" {
+            return self.kill();
+        }
+        #[cfg(unix)]
+        return send_signal(-(self.pid() as i32), signal)
+            .map_err(|e| format!("Failed to send SIG{} to '{}': {}", signal, self.command, e));
+        #[cfg(not(unix))]
+        Err(format!("SIG{} is only supported on Unix", signal))
+    }
+}
+
+// A process the script leaves running is killed with everything it started
+// when its handle goes away, which at the latest is when the script ends
+impl Drop for Child {
+    fn drop(&mut self) {
+        if self.output.is_none() {
+            let _ = self.kill();
+            let _ = self.process.wait();
+        }
+    }
+}
+
+const SIGNALS: [&str; 9] = [
+    "HUP", "INT", "QUIT", "KILL", "TERM", "USR1", "USR2", "STOP", "CONT",
+];
+
+fn signal_name(name: &str) -> Result<&'static str, String> {
+    let upper = name.to_uppercase();
+    let short = upper.strip_prefix("SIG").unwrap_or(&upper);
+    SIGNALS
+        .iter()
+        .find(|signal| **signal == short)
+        .copied()
+        .ok_or_else(|| {
+            format!(
+                "Unknown signal '{}' (expected one of SIG{})",
+                name,
+                SIGNALS.join(", SIG")
+            )
+        })
+}
+
+#[cfg(unix)]
+fn signal_number(signal: &str) -> libc::c_int {
+    match signal {
+        "HUP" => libc::SIGHUP,
+        "INT" => libc::SIGINT,
+        "QUIT" => libc::SIGQUIT,
+        "KILL" => libc::SIGKILL,
+        "TERM" => libc::SIGTERM,
+        "USR1" => libc::SIGUSR1,
+        "USR2" => libc::SIGUSR2,
+        "STOP" => libc::SIGSTOP,
+        "CONT" => libc::SIGCONT,
+        other => unreachable!("unknown signal {}", other),
+    }
+}
+
+// A negative target signals the whole process group
+#[cfg(unix)]
+fn send_signal(target: i32, signal: &str) -> std::io::Result<()> {
+    // SAFETY: kill takes no pointers and only reports failure through errno
+    if unsafe { libc::kill(target, signal_number(signal)) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+// "SIGTERM" for the signals `signal` understands, "SIG<number>" for others
+#[cfg(unix)]
+fn exit_signal(status: &ExitStatus) -> Option<String> {
+    let number = std::os::unix::process::ExitStatusExt::signal(status)?;
+    Some(
+        match SIGNALS
+            .iter()
+            .find(|signal| signal_number(signal) == number)
+        {
+            Some(signal) => format!("SIG{}", signal),
+            None => format!("SIG{}", number),
+        },
+    )
+}
+
+#[cfg(not(unix))]
+fn exit_signal(_status: &ExitStatus) -> Option<String> {
+    None
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn spec(program: &str, args: &[&str]) -> CommandSpec {
+        CommandSpec {
+            program: program.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            ..CommandSpec::default()
+        }
+    }
+
+    fn millis(n: i64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn exec_reports_exit_codes_and_output() {
+        let output = exec(&spec("sh", &["-c", "echo out; echo err >&2; exit 3"])).unwrap();
+        assert_eq!(
+            output,
+            Output {
+                stdout: "out\n".to_string(),
+                stderr: "err\n".to_string(),
+                code: Some(3),
+                signal: None,
+                timed_out: false,
+            }
+        );
+    }
+
+    #[test]
+    fn exec_kills_commands_that_time_out() {
+        let mut spec = spec("sleep", &["5"]);
+        spec.timeout = Some(millis(50));
+        let output = exec(&spec).unwrap();
+        assert!(output.timed_out);
+        assert_eq!(output.code, None);
+        assert_eq!(output.signal.as_deref(), Some("SIGKILL"));
+    }
+
+    #[test]
+    fn timed_wait_leaves_the_process_running() {
+        let mut child = spawn(&spec("sleep", &["5"])).unwrap();
+        assert_eq!(child.wait_timeout(millis(20)), Ok(None));
+        assert!(child.is_running());
+        child.signal("SIGTERM").unwrap();
+        let output = child.wait().unwrap();
+        assert_eq!(output.signal.as_deref(), Some("SIGTERM"));
+        assert!(!output.timed_out);
+        assert_eq!(child.wait_timeout(millis(20)), Ok(Some(output)));
+    }
+
+    #[test]
+    fn kill_ends_what_the_process_started() {
+        // The background sleep holds the output pipes, so the wait below
+        // only returns once it is gone too
+        let mut child = spawn(&spec("sh", &["-c", "sleep 30 & wait"])).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let started = Instant::now();
+        child.kill().unwrap();
+        assert_eq!(child.wait().unwrap().signal.as_deref(), Some("SIGKILL"));
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+    }
+
+    #[test]
+    fn dropping_a_handle_kills_the_process() {
+        let child = spawn(&spec("sleep", &["30"])).unwrap();
+        let pid = child.pid() as i32;
+        drop(child);
+        assert_eq!(
+            send_signal(pid, "CONT").unwrap_err().raw_os_error(),
+            Some(libc::ESRCH)
+        );
+    }
+}
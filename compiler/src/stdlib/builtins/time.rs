@@ -0,0 +1,943 @@
+// Time Operations
+// Timestamps are milliseconds since the Unix epoch. Every read of the
+// current time goes through a Clock so tests can swap in a manual one.
+
+use crate::value::{Handle, HandleKind, Value};
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
+
+// ===== CLOCKS =====
+
+pub trait Clock {
+    /// Milliseconds since the Unix epoch
+    fn now(&self) -> i64;
+    fn sleep(&self, duration: Duration);
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        match std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+            Ok(since) => since.as_millis() as i64,
+            Err(before) => -(before.duration().as_millis() as i64),
+        }
+    }
+
+    fn sleep(&self, duration: Duration) {
+        if duration.millis > 0 {
+            std::thread::sleep(std::time::Duration::from_millis(duration.millis as u64));
+        }
+    }
+}
+
+/// A clock that only moves when told to; sleeping advances it instantly.
+/// Clones share the same time, so a test can keep one and hand out another.
+#[derive(Clone, Default)]
+pub struct ManualClock {
+    now: Rc<Cell<i64>>,
+}
+
+impl ManualClock {
+    pub fn new(start: i64) -> Self {
+        ManualClock {
+            now: Rc::new(Cell::new(start)),
+        }
+    }
+
+    pub fn set(&self, now: i64) {
+        self.now.set(now);
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get().saturating_add(duration.millis));
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> i64 {
+        self.now.get()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        if duration.millis > 0 {
+            self.advance(duration);
+        }
+    }
+}
+
+// ===== DURATIONS =====
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Duration {
+    millis: i64,
+}
+
+const UNITS: [(&str, i64); 5] = [
+    ("d", 86_400_000),
+    ("h", 3_600_000),
+    ("m", 60_000),
+    ("s", 1_000),
+    ("ms", 1),
+];
+
+impl Duration {
+    pub fn from_millis(millis: i64) -> Self {
+        Duration { millis }
+    }
+
+    pub fn as_millis(&self) -> i64 {
+        self.millis
+    }
+
+    pub fn as_seconds(&self) -> f64 {
+        self.millis as f64 / 1_000.0
+    }
+
+    /// Parse strings such as "1h30m", "2.5s" or "250ms"
+    pub fn parse(text: &str) -> Result<Duration, String> {
+        let trimmed = text.trim();
+        let (negative, mut rest) = match trimmed.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, trimmed),
+        };
+        if rest.is_empty() {
+            return Err(format!("Invalid duration '{}'", text));
+        }
+        let mut total = 0.0;
+        while !rest.is_empty() {
+            let number_len = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            let number: f64 = rest[..number_len]
+                .parse()
+                .map_err(|_| format!("Invalid duration '{}'", text))?;
+            rest = rest[number_len..].trim_start();
+            let unit_len = rest
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(rest.len());
+            let unit = &rest[..unit_len];
+            let scale = match UNITS.iter().find(|(name, _)| *name == unit) {
+                Some((_, scale)) => *scale,
+                None => {
+                    return Err(format!(
+                        "Invalid duration '{}': unknown unit '{}' (use d, h, m, s or ms)",
+                        text, unit
+                    ))
+                }
+            };
+            total += number * scale as f64;
+            rest = rest[unit_len..].trim_start();
+        }
+        let millis =
+            round_millis(total).ok_or_else(|| format!("Duration '{}' is out of range", text))?;
+        Ok(Duration::from_millis(if negative {
+            -millis
+        } else {
+            millis
+        }))
+    }
+
+    /// Accept a duration, a number of milliseconds or a duration string
+    pub fn from_value(value: &Value) -> Result<Duration, String> {
+        match value {
+            Value::Duration(duration) => Ok(*duration),
+            Value::Int(millis) => Ok(Duration::from_millis(*millis)),
+            Value::Float(millis) => round_millis(*millis)
+                .map(Duration::from_millis)
+                .ok_or_else(|| format!("Duration of {}ms is out of range", millis)),
+            Value::String(text) => Duration::parse(text),
+            other => Err(format!(
+                "Expected a duration, found {}",
+                other.type_of().to_string()
+            )),
+        }
+    }
+
+    // Arithmetic on durations fails instead of wrapping around when the
+    // result does not fit
+    pub fn checked_add(&self, other: Duration) -> Result<Duration, String> {
+        checked(self.millis.checked_add(other.millis))
+    }
+
+    pub fn checked_sub(&self, other: Duration) -> Result<Duration, String> {
+        checked(self.millis.checked_sub(other.millis))
+    }
+
+    pub fn checked_mul(&self, factor: i64) -> Result<Duration, String> {
+        checked(self.millis.checked_mul(factor))
+    }
+
+    pub fn checked_neg(&self) -> Result<Duration, String> {
+        checked(self.millis.checked_neg())
+    }
+
+    pub fn divide(&self, divisor: i64) -> Result<Duration, String> {
+        if divisor == 0 {
+            Err("Division by zero".to_string())
+        } else {
+            checked(self.millis.checked_div(divisor))
+        }
+    }
+}
+
+/// Round a float number of milliseconds, refusing NaN, infinities and
+/// values outside the range of an i64
+fn round_millis(millis: f64) -> Option<i64> {
+    let millis = millis.round();
+    // i64::MAX as f64 rounds up to 2^63, which is already too large
+    if millis.is_finite() && millis >= i64::MIN as f64 && millis < i64::MAX as f64 {
+        Some(millis as i64)
+    } else {
+        None
+    }
+}
+
+fn checked(millis: Option<i64>) -> Result<Duration, String> {
+    millis
+        .map(Duration::from_millis)
+        .ok_or_else(|| "Duration overflow".to_string())
+}
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.millis == 0 {
+            return write!(f, "0ms");
+        }
+        if self.millis < 0 {
+            write!(f, "-")?;
+        }
+        let mut rest = self.millis.unsigned_abs();
+        let mut first = true;
+        for (name, scale) in UNITS {
+            let count = rest / scale as u64;
+            if count > 0 {
+                if !first {
+                    write!(f, " ")?;
+                }
+                write!(f, "{}{}", count, name)?;
+                rest %= scale as u64;
+                first = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+// ===== TIMEZONES =====
+// Only UTC and fixed offsets; there is no timezone database
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeZone {
+    offset_minutes: i32,
+}
+
+impl TimeZone {
+    pub const UTC: TimeZone = TimeZone { offset_minutes: 0 };
+
+    pub fn from_offset(offset_minutes: i32) -> Self {
+        TimeZone { offset_minutes }
+    }
+
+    /// Parse "UTC", "Z", "+05:30", "-0800", "+02" or "UTC+2"
+    pub fn parse(text: &str) -> Result<TimeZone, String> {
+        let trimmed = text.trim();
+        let offset = match trimmed.to_ascii_uppercase().as_str() {
+            "UTC" | "GMT" | "Z" => return Ok(TimeZone::UTC),
+            upper => upper
+                .strip_prefix("UTC")
+                .or_else(|| upper.strip_prefix("GMT"))
+                .unwrap_or(upper)
+                .to_string(),
+        };
+        let invalid = || {
+            format!(
+                "Invalid timezone '{}' (use UTC or an offset like +05:30)",
+                text
+            )
+        };
+        let (sign, digits) = match offset.chars().next() {
+            Some('+') => (1, &offset[1..]),
+            Some('-') => (-1, &offset[1..]),
+            _ => return Err(invalid()),
+        };
+        let digits = digits.replace(':', "");
+        if !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let (hours, minutes) = match digits.len() {
+            1 | 2 => (digits.parse::<i32>().unwrap(), 0),
+            4 => (
+                digits[..2].parse::<i32>().unwrap(),
+                digits[2..].parse::<i32>().unwrap(),
+            ),
+            _ => return Err(invalid()),
+        };
+        if hours > 14 || minutes > 59 {
+            return Err(invalid());
+        }
+        Ok(TimeZone::from_offset(sign * (hours * 60 + minutes)))
+    }
+
+    pub fn offset_minutes(&self) -> i32 {
+        self.offset_minutes
+    }
+
+    fn offset_string(&self, colon: bool) -> String {
+        let sign = if self.offset_minutes < 0 { '-' } else { '+' };
+        let minutes = self.offset_minutes.abs();
+        if colon {
+            format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+        } else {
+            format!("{}{:02}{:02}", sign, minutes / 60, minutes % 60)
+        }
+    }
+
+    pub fn name(&self) -> String {
+        if self.offset_minutes == 0 {
+            "UTC".to_string()
+        } else {
+            self.offset_string(true)
+        }
+    }
+}
+
+// ===== CALENDAR =====
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+const WEEKDAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+struct DateTime {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    millis: u32,
+    // 0 is Sunday
+    weekday: u32,
+    // 1 is January 1st
+    yearday: u32,
+}
+
+// Days since 1970-01-01 for a proleptic Gregorian date
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn to_date_time(timestamp: i64, zone: TimeZone) -> Result<DateTime, String> {
+    let local = timestamp
+        .checked_add(zone.offset_minutes as i64 * 60_000)
+        .ok_or_else(|| format!("Timestamp {} is out of range", timestamp))?;
+    let days = local.div_euclid(86_400_000);
+    let millis_of_day = local.rem_euclid(86_400_000);
+    let (year, month, day) = civil_from_days(days);
+    Ok(DateTime {
+        year,
+        month,
+        day,
+        hour: (millis_of_day / 3_600_000) as u32,
+        minute: (millis_of_day / 60_000 % 60) as u32,
+        second: (millis_of_day / 1_000 % 60) as u32,
+        millis: (millis_of_day % 1_000) as u32,
+        weekday: (days + 4).rem_euclid(7) as u32,
+        yearday: (days - days_from_civil(year, 1, 1) + 1) as u32,
+    })
+}
+
+// ===== FORMATTING =====
+// strftime-style directives:
+//   %Y year      %m month     %d day       %H hour (24h)  %M minute  %S second
+//   %f millis    %y 2-digit year           %e day, space padded
+//   %I hour (12h) %p AM/PM    %j day of year %b/%B month name %a/%A weekday name
+//   %z +0530     %:z +05:30   %Z zone name %s Unix seconds
+//   %F = %Y-%m-%d  %T = %H:%M:%S  %D = %m/%d/%y  %% a literal '%'
+
+pub const DEFAULT_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+pub fn format(timestamp: i64, pattern: &str, zone: TimeZone) -> Result<String, String> {
+    let t = to_date_time(timestamp, zone)?;
+    let mut out = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let directive = match chars.next() {
+            Some(':') if chars.clone().next() == Some('z') => {
+                chars.next();
+                out.push_str(&zone.offset_string(true));
+                continue;
+            }
+            Some(d) => d,
+            None => return Err("Format string ends with a lone '%'".to_string()),
+        };
+        match directive {
+            'Y' => out.push_str(&format!("{:04}", t.year)),
+            'y' => out.push_str(&format!("{:02}", t.year.rem_euclid(100))),
+            'm' => out.push_str(&format!("{:02}", t.month)),
+            'd' => out.push_str(&format!("{:02}", t.day)),
+            'e' => out.push_str(&format!("{:2}", t.day)),
+            'H' => out.push_str(&format!("{:02}", t.hour)),
+            'I' => out.push_str(&format!("{:02}", (t.hour + 11) % 12 + 1)),
+            'p' => out.push_str(if t.hour < 12 { "AM" } else { "PM" }),
+            'M' => out.push_str(&format!("{:02}", t.minute)),
+            'S' => out.push_str(&format!("{:02}", t.second)),
+            'f' => out.push_str(&format!("{:03}", t.millis)),
+            'j' => out.push_str(&format!("{:03}", t.yearday)),
+            'b' => out.push_str(&MONTHS[t.month as usize - 1][..3]),
+            'B' => out.push_str(MONTHS[t.month as usize - 1]),
+            'a' => out.push_str(&WEEKDAYS[t.weekday as usize][..3]),
+            'A' => out.push_str(WEEKDAYS[t.weekday as usize]),
+            'z' => out.push_str(&zone.offset_string(false)),
+            'Z' => out.push_str(&zone.name()),
+            's' => out.push_str(&timestamp.div_euclid(1_000).to_string()),
+            'F' => out.push_str(&format!("{:04}-{:02}-{:02}", t.year, t.month, t.day)),
+            'T' => out.push_str(&format!("{:02}:{:02}:{:02}", t.hour, t.minute, t.second)),
+            'D' => out.push_str(&format!(
+                "{:02}/{:02}/{:02}",
+                t.month,
+                t.day,
+                t.year.rem_euclid(100)
+            )),
+            '%' => out.push('%'),
+            other => return Err(format!("Unknown format directive '%{}'", other)),
+        }
+    }
+    Ok(out)
+}
+
+// ===== PARSING =====
+
+struct Scanner<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl Scanner<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.position..]
+    }
+
+    fn error(&self, expected: &str) -> String {
+        format!(
+            "Cannot parse time '{}': expected {} at position {}",
+            self.text,
+            expected,
+            self.position + 1
+        )
+    }
+
+    fn number(&mut self, max_digits: usize, what: &str) -> Result<i64, String> {
+        let rest = self.rest();
+        let sign_len = if rest.starts_with('-') || rest.starts_with('+') {
+            1
+        } else {
+            0
+        };
+        let digits = rest[sign_len..]
+            .chars()
+            .take(max_digits)
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        if digits == 0 {
+            return Err(self.error(what));
+        }
+        let value = rest[..sign_len + digits]
+            .parse()
+            .map_err(|_| self.error(what))?;
+        self.position += sign_len + digits;
+        Ok(value)
+    }
+
+    fn name(&mut self, names: &[&str], what: &str) -> Result<usize, String> {
+        let rest = self.rest().to_ascii_lowercase();
+        // Try full names first so "March" is not read as "Mar" + "ch"
+        for full in [true, false] {
+            for (i, name) in names.iter().enumerate() {
+                let candidate = if full {
+                    name.to_string()
+                } else {
+                    name[..3].to_string()
+                };
+                if rest.starts_with(&candidate.to_ascii_lowercase()) {
+                    self.position += candidate.len();
+                    return Ok(i);
+                }
+            }
+        }
+        Err(self.error(what))
+    }
+
+    fn literal(&mut self, c: char) -> Result<(), String> {
+        if c.is_whitespace() {
+            let skipped = self.rest().len() - self.rest().trim_start().len();
+            self.position += skipped;
+            return Ok(());
+        }
+        if self.rest().starts_with(c) {
+            self.position += c.len_utf8();
+            Ok(())
+        } else {
+            Err(self.error(&format!("'{}'", c)))
+        }
+    }
+
+    fn offset(&mut self) -> Result<TimeZone, String> {
+        let rest = self.rest();
+        if rest.starts_with('Z') {
+            self.position += 1;
+            return Ok(TimeZone::UTC);
+        }
+        let len = rest
+            .char_indices()
+            .skip(1)
+            .find(|(_, c)| !c.is_ascii_digit() && *c != ':')
+            .map(|(i, _)| i)
+            .unwrap_or(rest.len());
+        let zone = TimeZone::parse(&rest[..len]).map_err(|_| self.error("a UTC offset"))?;
+        self.position += len;
+        Ok(zone)
+    }
+}
+
+/// Parse `text` laid out as `pattern` (same directives as `format`).
+/// Fields missing from the pattern default to 1970-01-01 00:00:00, and
+/// `zone` applies unless the text carries its own offset via %z or %Z.
+pub fn parse(text: &str, pattern: &str, zone: TimeZone) -> Result<i64, String> {
+    let mut scanner = Scanner { text, position: 0 };
+    let (mut year, mut month, mut day) = (1970, 1, 1);
+    let (mut hour, mut minute, mut second, mut millis) = (0, 0, 0, 0);
+    let mut pm: Option<bool> = None;
+    let mut zone = zone;
+    let mut epoch: Option<i64> = None;
+
+    let expanded = pattern
+        .replace("%F", "%Y-%m-%d")
+        .replace("%T", "%H:%M:%S")
+        .replace("%D", "%m/%d/%y");
+    let mut chars = expanded.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            scanner.literal(c)?;
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => year = scanner.number(4, "a year")?,
+            Some('y') => year = 2000 + scanner.number(2, "a 2-digit year")?,
+            Some('m') => month = scanner.number(2, "a month")?,
+            Some('d') => day = scanner.number(2, "a day")?,
+            Some('e') => {
+                scanner.literal(' ')?;
+                day = scanner.number(2, "a day")?
+            }
+            Some('H') | Some('I') => hour = scanner.number(2, "an hour")?,
+            Some('M') => minute = scanner.number(2, "a minute")?,
+            Some('S') => second = scanner.number(2, "a second")?,
+            Some('f') => millis = scanner.number(3, "milliseconds")?,
+            Some('j') => {
+                let yearday = scanner.number(3, "a day of the year")?;
+                month = 1;
+                day = yearday;
+            }
+            Some('p') => {
+                let rest = scanner.rest().to_ascii_uppercase();
+                pm = Some(if rest.starts_with("AM") {
+                    false
+                } else if rest.starts_with("PM") {
+                    true
+                } else {
+                    return Err(scanner.error("AM or PM"));
+                });
+                scanner.position += 2;
+            }
+            Some('b') | Some('B') => month = scanner.name(&MONTHS, "a month name")? as i64 + 1,
+            Some('a') | Some('A') => {
+                scanner.name(&WEEKDAYS, "a weekday name")?;
+            }
+            Some(':') if chars.next() == Some('z') => zone = scanner.offset()?,
+            Some('z') => zone = scanner.offset()?,
+            Some('Z') => {
+                if scanner.rest().starts_with("UTC") || scanner.rest().starts_with("GMT") {
+                    scanner.position += 3;
+                    zone = TimeZone::UTC;
+                } else {
+                    zone = scanner.offset()?;
+                }
+            }
+            Some('s') => {
+                let seconds = scanner.number(19, "Unix seconds")?;
+                epoch = Some(
+                    seconds
+                        .checked_mul(1_000)
+                        .ok_or_else(|| out_of_range(text))?,
+                )
+            }
+            Some('%') => scanner.literal('%')?,
+            Some(other) => return Err(format!("Unknown format directive '%{}'", other)),
+            None => return Err("Format string ends with a lone '%'".to_string()),
+        }
+    }
+    if !scanner.rest().is_empty() {
+        return Err(format!(
+            "Cannot parse time '{}': unexpected '{}' at the end",
+            text,
+            scanner.rest()
+        ));
+    }
+    if let Some(epoch) = epoch {
+        return Ok(epoch);
+    }
+
+    if let Some(pm) = pm {
+        if !(1..=12).contains(&hour) {
+            return Err(format!(
+                "Cannot parse time '{}': hour {} is not on a 12-hour clock",
+                text, hour
+            ));
+        }
+        hour = hour % 12 + if pm { 12 } else { 0 };
+    }
+    if !(1..=12).contains(&month) {
+        return Err(format!(
+            "Cannot parse time '{}': month {} is out of range",
+            text, month
+        ));
+    }
+    let month = month as u32;
+    // %j counts days from January 1st and may run past the end of January
+    let max_day = if day > 31 {
+        if is_leap_year(year) {
+            366
+        } else {
+            365
+        }
+    } else {
+        days_in_month(year, month) as i64
+    };
+    if day < 1 || day > max_day {
+        return Err(format!(
+            "Cannot parse time '{}': day {} is out of range",
+            text, day
+        ));
+    }
+    if hour > 23 || minute > 59 || second > 60 {
+        return Err(format!(
+            "Cannot parse time '{}': time of day is out of range",
+            text
+        ));
+    }
+
+    let days = days_from_civil(year, month, 1) + day - 1;
+    let local = days * 86_400_000 + hour * 3_600_000 + minute * 60_000 + second * 1_000 + millis;
+    local
+        .checked_sub(zone.offset_minutes as i64 * 60_000)
+        .ok_or_else(|| out_of_range(text))
+}
+
+fn out_of_range(text: &str) -> String {
+    format!("Cannot parse time '{}': the time is out of range", text)
+}
+
+// ===== STOPWATCHES =====
+
+/// Measures elapsed time on the runtime's clock, optionally counting down
+/// from a limit; pausing stops the count without losing it
+struct Stopwatch {
+    started: i64,
+    paused_at: Option<i64>,
+    paused_total: i64,
+    limit: Option<Duration>,
+}
+
+impl Stopwatch {
+    fn elapsed(&self, now: i64) -> Duration {
+        let end = self.paused_at.unwrap_or(now);
+        Duration::from_millis(end - self.started - self.paused_total)
+    }
+}
+
+// ===== RUNTIME =====
+
+/// State behind the `time` header for one running program
+pub struct Time {
+    clock: Box<dyn Clock>,
+    stopwatches: Vec<Stopwatch>,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Time::new(Box::new(SystemClock))
+    }
+}
+
+impl Time {
+    pub fn new(clock: Box<dyn Clock>) -> Self {
+        Time {
+            clock,
+            stopwatches: Vec::new(),
+        }
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    /// Current time in milliseconds since the Unix epoch
+    pub fn now(&self) -> i64 {
+        self.clock.now()
+    }
+
+    /// Current Unix timestamp in whole seconds
+    pub fn timestamp(&self) -> i64 {
+        self.clock.now().div_euclid(1_000)
+    }
+
+    pub fn sleep(&self, duration: Duration) -> Result<(), String> {
+        if duration.as_millis() < 0 {
+            return Err(format!(
+                "Cannot sleep for a negative duration ({})",
+                duration
+            ));
+        }
+        self.clock.sleep(duration);
+        Ok(())
+    }
+
+    /// Start a stopwatch; with a limit it also reports the time remaining
+    pub fn timer(&mut self, limit: Option<Duration>) -> Handle {
+        self.stopwatches.push(Stopwatch {
+            started: self.clock.now(),
+            paused_at: None,
+            paused_total: 0,
+            limit,
+        });
+        Handle::new(HandleKind::Stopwatch, self.stopwatches.len() - 1)
+    }
+
+    /// Methods on the stopwatch object returned by `time.timer`:
+    /// elapsed, remaining, done, pause, resume and reset
+    pub fn stopwatch_method(&mut self, handle: Handle, method: &str) -> Result<Value, String> {
+        let now = self.clock.now();
+        let stopwatch = match self.stopwatches.get_mut(handle.id) {
+            Some(stopwatch) if handle.kind == HandleKind::Stopwatch => stopwatch,
+            _ => return Err(format!("{} is not a timer", handle)),
+        };
+        match method {
+            "elapsed" => Ok(Value::Duration(stopwatch.elapsed(now))),
+            "remaining" => match stopwatch.limit {
+                Some(limit) => {
+                    let remaining = limit.checked_sub(stopwatch.elapsed(now))?;
+                    Ok(Value::Duration(remaining.max(Duration::default())))
+                }
+                None => Ok(Value::Null),
+            },
+            "done" => Ok(Value::Boolean(match stopwatch.limit {
+                Some(limit) => stopwatch.elapsed(now) >= limit,
+                None => false,
+            })),
+            "pause" => {
+                stopwatch.paused_at.get_or_insert(now);
+                Ok(Value::Null)
+            }
+            "resume" => {
+                if let Some(paused_at) = stopwatch.paused_at.take() {
+                    stopwatch.paused_total += now - paused_at;
+                }
+                Ok(Value::Null)
+            }
+            "reset" => {
+                stopwatch.started = now;
+                stopwatch.paused_total = 0;
+                if stopwatch.paused_at.is_some() {
+                    stopwatch.paused_at = Some(now);
+                }
+                Ok(Value::Null)
+            }
+            other => Err(format!("Timers have no method '{}'", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(n: i64) -> Duration {
+        Duration::from_millis(n * 1_000)
+    }
+
+    fn method(time: &mut Time, timer: Handle, name: &str) -> Value {
+        time.stopwatch_method(timer, name).unwrap()
+    }
+
+    #[test]
+    fn manual_clock_moves_only_when_told() {
+        let clock = ManualClock::new(1_000);
+        let time = Time::new(Box::new(clock.clone()));
+        assert_eq!(time.now(), 1_000);
+        time.sleep(seconds(2)).unwrap();
+        assert_eq!(clock.now(), 3_000);
+        clock.set(-500);
+        assert_eq!(time.timestamp(), -1);
+        assert!(time.sleep(seconds(-1)).is_err());
+    }
+
+    #[test]
+    fn timer_counts_down_on_a_manual_clock() {
+        let clock = ManualClock::new(0);
+        let mut time = Time::new(Box::new(clock.clone()));
+        let timer = time.timer(Some(seconds(10)));
+
+        clock.advance(seconds(4));
+        assert_eq!(
+            method(&mut time, timer, "elapsed"),
+            Value::Duration(seconds(4))
+        );
+        assert_eq!(
+            method(&mut time, timer, "remaining"),
+            Value::Duration(seconds(6))
+        );
+        assert_eq!(method(&mut time, timer, "done"), Value::Boolean(false));
+
+        clock.advance(seconds(20));
+        assert_eq!(
+            method(&mut time, timer, "remaining"),
+            Value::Duration(seconds(0))
+        );
+        assert_eq!(method(&mut time, timer, "done"), Value::Boolean(true));
+
+        method(&mut time, timer, "reset");
+        assert_eq!(
+            method(&mut time, timer, "elapsed"),
+            Value::Duration(seconds(0))
+        );
+    }
+
+    #[test]
+    fn paused_timer_keeps_its_count() {
+        let clock = ManualClock::new(0);
+        let mut time = Time::new(Box::new(clock.clone()));
+        let timer = time.timer(None);
+
+        clock.advance(seconds(3));
+        method(&mut time, timer, "pause");
+        clock.advance(seconds(60));
+        assert_eq!(
+            method(&mut time, timer, "elapsed"),
+            Value::Duration(seconds(3))
+        );
+        method(&mut time, timer, "resume");
+        clock.advance(seconds(2));
+        assert_eq!(
+            method(&mut time, timer, "elapsed"),
+            Value::Duration(seconds(5))
+        );
+        assert_eq!(method(&mut time, timer, "remaining"), Value::Null);
+    }
+
+    #[test]
+    fn duration_arithmetic_reports_overflow() {
+        let max = Duration::from_millis(i64::MAX);
+        assert_eq!(seconds(1).checked_add(seconds(2)), Ok(seconds(3)));
+        assert_eq!(
+            max.checked_add(seconds(1)),
+            Err("Duration overflow".to_string())
+        );
+        assert_eq!(max.checked_mul(2), Err("Duration overflow".to_string()));
+        assert_eq!(
+            Duration::from_millis(i64::MIN).checked_neg(),
+            Err("Duration overflow".to_string())
+        );
+    }
+
+    #[test]
+    fn durations_out_of_range_are_errors() {
+        assert_eq!(
+            Duration::parse("1h30m"),
+            Ok(Duration::from_millis(5_400_000))
+        );
+        assert_eq!(
+            Duration::parse("9999999999999999999d"),
+            Err("Duration '9999999999999999999d' is out of range".to_string())
+        );
+        assert!(Duration::from_value(&Value::Float(f64::NAN)).is_err());
+        assert!(Duration::from_value(&Value::Float(i64::MAX as f64)).is_err());
+        assert!(Duration::from_value(&Value::Float(-1e300)).is_err());
+    }
+
+    #[test]
+    fn timestamps_out_of_range_are_errors() {
+        let plus_one = TimeZone::parse("+01:00").unwrap();
+        assert_eq!(
+            format(i64::MAX, "%Y", plus_one),
+            Err(format!("Timestamp {} is out of range", i64::MAX))
+        );
+        assert_eq!(format(0, "%F %T", plus_one).unwrap(), "1970-01-01 01:00:00");
+        assert_eq!(
+            parse("92233720368547758", "%s", TimeZone::UTC),
+            Err("Cannot parse time '92233720368547758': the time is out of range".to_string())
+        );
+        assert_eq!(
+            parse("1700000000", "%s", TimeZone::UTC),
+            Ok(1_700_000_000_000)
+        );
+    }
+}
@@ -0,0 +1,1086 @@
+// UI Header for Prizm
+// `ui.window`, `ui.button`, ... build a retained tree of widgets, and user input
+// is turned into calls to the handlers registered with `ui.event`. Backends
+// only draw the tree, so the same script can run with or without a display.
+
+use crate::event_loop::Event;
+use crate::value::{Handle, HandleKind, Value};
+use std::path::Path;
+
+pub mod html;
+pub mod layout;
+pub mod terminal;
+
+// Size of one character of text, in the pixel units scripts use for positions
+pub const CHAR_WIDTH: i64 = 8;
+pub const LINE_HEIGHT: i64 = 16;
+
+// ===== WIDGETS =====
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WidgetKind {
+    Window,
+    Button,
+    Text,
+    Input,
+    Label,
+    Panel,
+    Row,
+    Column,
+    Grid,
+}
+
+impl WidgetKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WidgetKind::Window => "window",
+            WidgetKind::Button => "button",
+            WidgetKind::Text => "text",
+            WidgetKind::Input => "input",
+            WidgetKind::Label => "label",
+            WidgetKind::Panel => "panel",
+            WidgetKind::Row => "row",
+            WidgetKind::Column => "column",
+            WidgetKind::Grid => "grid",
+        }
+    }
+
+    /// Rows, columns and grids, which position their children themselves
+    pub fn is_container(&self) -> bool {
+        matches!(
+            self,
+            WidgetKind::Row | WidgetKind::Column | WidgetKind::Grid
+        )
+    }
+
+    /// Widgets that can take keyboard focus
+    pub fn is_focusable(&self) -> bool {
+        matches!(self, WidgetKind::Button | WidgetKind::Input)
+    }
+}
+
+/// Position and size in pixels, relative to the widget's parent
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Rect {
+    pub x: i64,
+    pub y: i64,
+    pub width: i64,
+    pub height: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Widget {
+    pub kind: WidgetKind,
+    // Window title, button label, text content or input placeholder
+    pub text: String,
+    // What has been typed into an input
+    pub value: String,
+    // Name of the UI value this widget shows (and, for inputs, edits)
+    pub binding: Option<String>,
+    // Text or label content with `{name}` placeholders, filled in from UI values
+    pub template: Option<String>,
+    // As the script gave it; a size of 0 means "fit the content"
+    pub rect: Rect,
+    // Where the layout engine put it, which is what backends draw
+    pub layout: Rect,
+    pub options: LayoutOptions,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+/// Spacing for rows, columns and grids, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LayoutOptions {
+    // Space around the children
+    pub padding: i64,
+    // Space between neighbouring children
+    pub spacing: i64,
+    // Cells across, for grids
+    pub columns: usize,
+}
+
+/// Every widget created by a program, in creation order. Widget ids index into
+/// `widgets`; windows have no parent and everything else belongs to a window.
+#[derive(Debug, Default)]
+pub struct Tree {
+    pub widgets: Vec<Widget>,
+    pub focus: Option<usize>,
+    pub hover: Option<usize>,
+}
+
+impl Tree {
+    pub fn windows(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.widgets.len()).filter(|&id| self.widgets[id].parent.is_none())
+    }
+
+    pub fn get(&self, id: usize) -> Result<&Widget, String> {
+        self.widgets
+            .get(id)
+            .ok_or_else(|| format!("No widget with id {}", id))
+    }
+
+    /// Find a widget by its title, label, content or placeholder
+    pub fn find(&self, text: &str) -> Option<usize> {
+        self.widgets.iter().position(|widget| widget.text == text)
+    }
+
+    /// Focusable widgets in the order Tab visits them
+    pub fn focus_order(&self) -> Vec<usize> {
+        let mut order = Vec::new();
+        for window in self.windows() {
+            self.collect_focusable(window, &mut order);
+        }
+        order
+    }
+
+    fn collect_focusable(&self, id: usize, order: &mut Vec<usize>) {
+        for &child in &self.widgets[id].children {
+            if self.widgets[child].kind.is_focusable() {
+                order.push(child);
+            }
+            self.collect_focusable(child, order);
+        }
+    }
+
+    /// Text description of the tree, one widget per line, for tests to compare
+    pub fn snapshot(&self) -> String {
+        let mut out = String::new();
+        for window in self.windows() {
+            self.write_snapshot(window, 0, &mut out);
+        }
+        out
+    }
+
+    fn write_snapshot(&self, id: usize, depth: usize, out: &mut String) {
+        let widget = &self.widgets[id];
+        let rect = widget.layout;
+        out.push_str(&"  ".repeat(depth));
+        out.push_str(widget.kind.name());
+        if !widget.text.is_empty() {
+            out.push_str(&format!(" {:?}", widget.text));
+        }
+        if widget.kind != WidgetKind::Window {
+            out.push_str(&format!(" at ({}, {})", rect.x, rect.y));
+        }
+        out.push_str(&format!(" {}x{}", rect.width, rect.height));
+        if widget.kind == WidgetKind::Input {
+            out.push_str(&format!(" value={:?}", widget.value));
+        }
+        if self.focus == Some(id) {
+            out.push_str(" [focused]");
+        }
+        if self.hover == Some(id) {
+            out.push_str(" [hover]");
+        }
+        out.push('\n');
+        for &child in &widget.children {
+            self.write_snapshot(child, depth + 1, out);
+        }
+    }
+}
+
+// ===== EVENTS =====
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventType {
+    Click,
+    Submit,
+    Change,
+    Hover,
+    Focus,
+    Blur,
+    Keypress,
+}
+
+impl EventType {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "click" => Ok(EventType::Click),
+            "submit" => Ok(EventType::Submit),
+            "change" => Ok(EventType::Change),
+            "hover" => Ok(EventType::Hover),
+            "focus" => Ok(EventType::Focus),
+            "blur" => Ok(EventType::Blur),
+            "keypress" => Ok(EventType::Keypress),
+            other => Err(format!(
+                "Unknown event type '{}' (expected click, submit, change, hover, focus, blur or keypress)",
+                other
+            )),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventType::Click => "click",
+            EventType::Submit => "submit",
+            EventType::Change => "change",
+            EventType::Hover => "hover",
+            EventType::Focus => "focus",
+            EventType::Blur => "blur",
+            EventType::Keypress => "keypress",
+        }
+    }
+}
+
+// ===== BACKENDS =====
+
+/// Draws the widget tree somewhere
+pub trait Backend {
+    fn render(&mut self, tree: &Tree) -> Result<(), String>;
+
+    /// Keys the user pressed since the last poll, for interactive backends
+    fn poll_keys(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// The program keeps running while the user can still interact with it
+    fn is_open(&self) -> bool {
+        false
+    }
+}
+
+/// Draws nothing. Tests drive the UI through `Ui::simulate` and compare
+/// `Ui::snapshot` against the expected tree.
+#[derive(Default)]
+pub struct Headless;
+
+impl Backend for Headless {
+    fn render(&mut self, _tree: &Tree) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Pick a backend by name, as given to `prizm run --ui=<name>`. The HTML
+/// page for `script.pzm` is written next to it as `script.html`.
+pub fn backend(name: &str, script: &Path) -> Result<Box<dyn Backend>, String> {
+    match name {
+        "headless" => Ok(Box::new(Headless)),
+        "terminal" => Ok(Box::new(terminal::Terminal::new())),
+        "html" => Ok(Box::new(html::Html::new(script.with_extension("html")))),
+        other => Err(format!(
+            "Unknown UI backend '{}' (expected terminal, html or headless)",
+            other
+        )),
+    }
+}
+
+// ===== RUNTIME =====
+
+struct Handler {
+    event_type: EventType,
+    // Only events on this widget or inside it; None for `ui.event` handlers
+    widget: Option<usize>,
+    callback: Value,
+}
+
+/// State behind the `ui` header for one running program
+pub struct Ui {
+    tree: Tree,
+    handlers: Vec<Handler>,
+    backend: Box<dyn Backend>,
+    // Values widgets are bound to, by name: fields of `ui.state` and bound
+    // program variables
+    values: Value,
+    // Edits to bound values since the runtime last copied them to variables
+    changes: Vec<(String, Value)>,
+    rendered: bool,
+}
+
+impl Default for Ui {
+    fn default() -> Self {
+        Ui::new(Box::new(Headless))
+    }
+}
+
+impl Ui {
+    pub fn new(backend: Box<dyn Backend>) -> Self {
+        Ui {
+            tree: Tree::default(),
+            handlers: Vec::new(),
+            backend,
+            values: Value::Object(Vec::new()),
+            changes: Vec::new(),
+            rendered: false,
+        }
+    }
+
+    pub fn tree(&self) -> &Tree {
+        &self.tree
+    }
+
+    pub fn window(&mut self, title: &str, width: i64, height: i64) -> Result<Handle, String> {
+        check_size(width, height)?;
+        self.tree.widgets.push(Widget {
+            kind: WidgetKind::Window,
+            text: title.to_string(),
+            value: String::new(),
+            binding: None,
+            template: None,
+            rect: Rect {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            },
+            layout: Rect::default(),
+            options: LayoutOptions::default(),
+            parent: None,
+            children: Vec::new(),
+        });
+        layout::apply(&mut self.tree);
+        Ok(Handle::new(HandleKind::Widget, self.tree.widgets.len() - 1))
+    }
+
+    // Widgets belong to the most recently created window
+    fn add(&mut self, kind: WidgetKind, text: &str, rect: Rect) -> Result<Handle, String> {
+        check_size(rect.width, rect.height)?;
+        let window = match self.tree.windows().last() {
+            Some(window) => window,
+            None => {
+                return Err(format!(
+                    "Cannot create a {} before a window; call ui.window first",
+                    kind.name()
+                ))
+            }
+        };
+        let id = self.tree.widgets.len();
+        self.tree.widgets.push(Widget {
+            kind,
+            text: text.to_string(),
+            value: String::new(),
+            binding: None,
+            template: None,
+            rect,
+            layout: Rect::default(),
+            options: LayoutOptions::default(),
+            parent: Some(window),
+            children: Vec::new(),
+        });
+        self.tree.widgets[window].children.push(id);
+        layout::apply(&mut self.tree);
+        Ok(Handle::new(HandleKind::Widget, id))
+    }
+
+    pub fn button(
+        &mut self,
+        label: &str,
+        x: i64,
+        y: i64,
+        width: i64,
+        height: i64,
+    ) -> Result<Handle, String> {
+        self.add(
+            WidgetKind::Button,
+            label,
+            Rect {
+                x,
+                y,
+                width,
+                height,
+            },
+        )
+    }
+
+    pub fn input(
+        &mut self,
+        placeholder: &str,
+        x: i64,
+        y: i64,
+        width: i64,
+        height: i64,
+    ) -> Result<Handle, String> {
+        self.add(
+            WidgetKind::Input,
+            placeholder,
+            Rect {
+                x,
+                y,
+                width,
+                height,
+            },
+        )
+    }
+
+    /// Panels keep absolute positioning: `children` stay at the x/y they were
+    /// created with, measured from the panel's corner
+    pub fn panel(
+        &mut self,
+        x: i64,
+        y: i64,
+        width: i64,
+        height: i64,
+        children: &[Handle],
+    ) -> Result<Handle, String> {
+        let panel = self.add(
+            WidgetKind::Panel,
+            "",
+            Rect {
+                x,
+                y,
+                width,
+                height,
+            },
+        )?;
+        self.adopt(panel, children)?;
+        Ok(panel)
+    }
+
+    /// Text and labels are sized to fit their content. `{name}` in the
+    /// content shows the UI value called `name` and updates when it changes.
+    pub fn text(&mut self, content: &str, x: i64, y: i64) -> Result<Handle, String> {
+        self.text_widget(WidgetKind::Text, content, x, y)
+    }
+
+    pub fn label(&mut self, text: &str, x: i64, y: i64) -> Result<Handle, String> {
+        self.text_widget(WidgetKind::Label, text, x, y)
+    }
+
+    fn text_widget(
+        &mut self,
+        kind: WidgetKind,
+        text: &str,
+        x: i64,
+        y: i64,
+    ) -> Result<Handle, String> {
+        let handle = self.add(
+            kind,
+            text,
+            Rect {
+                x,
+                y,
+                ..Rect::default()
+            },
+        )?;
+        if text.contains('{') {
+            self.tree.widgets[handle.id].template = Some(text.to_string());
+            self.refresh()?;
+        }
+        Ok(handle)
+    }
+
+    /// `ui.row(children, options)`: children side by side, left to right
+    pub fn row(&mut self, children: &[Handle], options: Option<&Value>) -> Result<Handle, String> {
+        self.container(WidgetKind::Row, children, options)
+    }
+
+    /// `ui.column(children, options)`: children stacked top to bottom
+    pub fn column(
+        &mut self,
+        children: &[Handle],
+        options: Option<&Value>,
+    ) -> Result<Handle, String> {
+        self.container(WidgetKind::Column, children, options)
+    }
+
+    /// `ui.grid(children, options)`: children in a table, `columns` cells across
+    pub fn grid(&mut self, children: &[Handle], options: Option<&Value>) -> Result<Handle, String> {
+        self.container(WidgetKind::Grid, children, options)
+    }
+
+    // `options` is an optional object such as `{x: 20, y: 20, padding: 8,
+    // spacing: 4, columns: 2}`; x and y only matter when the container is
+    // placed directly in a window or panel
+    fn container(
+        &mut self,
+        kind: WidgetKind,
+        children: &[Handle],
+        options: Option<&Value>,
+    ) -> Result<Handle, String> {
+        let number = |name: &str| -> Result<i64, String> {
+            match options.and_then(|options| options.get(name)) {
+                None => Ok(0),
+                Some(Value::Int(n)) if *n >= 0 || name == "x" || name == "y" => Ok(*n),
+                Some(other) => Err(format!(
+                    "Layout option '{}' must be a non-negative int, found {}",
+                    name, other
+                )),
+            }
+        };
+        if let Some(options) = options {
+            if !matches!(options, Value::Object(_)) {
+                return Err(format!(
+                    "Layout options must be an object, found {}",
+                    options.type_of().to_string()
+                ));
+            }
+        }
+        let layout_options = LayoutOptions {
+            padding: number("padding")?,
+            spacing: number("spacing")?,
+            columns: match (kind, number("columns")?) {
+                (WidgetKind::Grid, 0) => 2,
+                (_, columns) => columns as usize,
+            },
+        };
+        let rect = Rect {
+            x: number("x")?,
+            y: number("y")?,
+            ..Rect::default()
+        };
+
+        let container = self.add(kind, "", rect)?;
+        self.tree.widgets[container.id].options = layout_options;
+        self.adopt(container, children)?;
+        Ok(container)
+    }
+
+    // Move `children` out of wherever they were created and into `parent`
+    fn adopt(&mut self, parent: Handle, children: &[Handle]) -> Result<(), String> {
+        for &child in children {
+            let id = self.widget_id(child)?;
+            let widget = &self.tree.widgets[id];
+            if widget.kind == WidgetKind::Window {
+                return Err("A window cannot go inside another widget".to_string());
+            }
+            if let Some(old) = widget.parent {
+                self.tree.widgets[old].children.retain(|&c| c != id);
+            }
+            self.tree.widgets[id].parent = Some(parent.id);
+            self.tree.widgets[parent.id].children.push(id);
+        }
+        layout::apply(&mut self.tree);
+        Ok(())
+    }
+
+    /// `ui.event(type, callback)`: call `callback` whenever an event of this type happens
+    pub fn event(&mut self, event_type: &str, callback: Value) -> Result<(), String> {
+        let event_type = EventType::parse(event_type)?;
+        check_callback(event_type, &callback)?;
+        self.handlers.push(Handler {
+            event_type,
+            widget: None,
+            callback,
+        });
+        Ok(())
+    }
+
+    /// The widget a handle refers to
+    pub fn widget_id(&self, handle: Handle) -> Result<usize, String> {
+        if handle.kind != HandleKind::Widget || handle.id >= self.tree.widgets.len() {
+            return Err(format!("{} is not a widget", handle));
+        }
+        Ok(handle.id)
+    }
+
+    /// Methods on the handles returned by widget constructors:
+    /// `on(type, callback)` handles events on that widget and anything inside it
+    pub fn widget_method(
+        &mut self,
+        handle: Handle,
+        method: &str,
+        args: Vec<Value>,
+    ) -> Result<Value, String> {
+        let id = self.widget_id(handle)?;
+        match (method, args.as_slice()) {
+            ("on", [Value::String(event_type), callback]) => {
+                let event_type = EventType::parse(event_type)?;
+                check_callback(event_type, callback)?;
+                self.handlers.push(Handler {
+                    event_type,
+                    widget: Some(id),
+                    callback: callback.clone(),
+                });
+                Ok(Value::Handle(handle))
+            }
+            ("on", _) => Err("Usage: widget.on(type, callback)".to_string()),
+            // Inputs edit the bound value; text and labels show it
+            ("bind", [Value::String(name)]) => {
+                let widget = &mut self.tree.widgets[id];
+                if !matches!(
+                    widget.kind,
+                    WidgetKind::Input | WidgetKind::Text | WidgetKind::Label
+                ) {
+                    return Err(format!("A {} cannot be bound", widget.kind.name()));
+                }
+                widget.binding = Some(name.clone());
+                if self.values.get(name).is_none() {
+                    let initial = match widget.kind {
+                        WidgetKind::Input => Value::String(widget.value.clone()),
+                        _ => Value::String(widget.text.clone()),
+                    };
+                    self.values.set(name, initial)?;
+                }
+                self.refresh()?;
+                Ok(Value::Handle(handle))
+            }
+            ("bind", _) => Err("Usage: widget.bind(name)".to_string()),
+            ("value", []) => match self.tree.widgets[id].kind {
+                WidgetKind::Input => Ok(Value::String(self.tree.widgets[id].value.clone())),
+                _ => Ok(Value::String(self.tree.widgets[id].text.clone())),
+            },
+            (other, _) => Err(format!(
+                "A {} has no method '{}'",
+                self.tree.widgets[id].kind.name(),
+                other
+            )),
+        }
+    }
+
+    pub fn render(&mut self) -> Result<(), String> {
+        if self.tree.widgets.is_empty() {
+            return Err("Nothing to render; create a window with ui.window first".to_string());
+        }
+        self.rendered = true;
+        self.backend.render(&self.tree)
+    }
+
+    // ===== BINDING =====
+
+    /// `ui.state(object)`: add fields to the UI's values and return a handle to
+    /// them. Reading `state.field` reads a value; assigning it updates every
+    /// widget bound to that name.
+    pub fn state(&mut self, initial: &Value) -> Result<Handle, String> {
+        let fields = match initial {
+            Value::Object(fields) => fields.clone(),
+            Value::Null => Vec::new(),
+            other => {
+                return Err(format!(
+                    "UI state must be an object, found {}",
+                    other.type_of().to_string()
+                ))
+            }
+        };
+        for (name, value) in fields {
+            self.values.set(&name, value)?;
+        }
+        self.refresh()?;
+        Ok(Handle::new(HandleKind::State, 0))
+    }
+
+    pub fn value(&self, name: &str) -> Option<&Value> {
+        self.values.get(name)
+    }
+
+    /// Change a bound value, from a state field or a program variable
+    /// assignment, and redraw whatever shows it
+    pub fn set_value(&mut self, name: &str, value: Value) -> Result<(), String> {
+        self.values.set(name, value)?;
+        self.refresh()
+    }
+
+    /// Whether a program variable with this name should be kept in sync
+    pub fn is_bound(&self, name: &str) -> bool {
+        self.values.get(name).is_some()
+    }
+
+    /// Values edited through inputs since the last call, for the runtime to
+    /// copy into the program's variables
+    pub fn take_changes(&mut self) -> Vec<(String, Value)> {
+        std::mem::take(&mut self.changes)
+    }
+
+    // Bring every bound widget up to date and lay out again, then redraw if
+    // the program has started rendering
+    fn refresh(&mut self) -> Result<(), String> {
+        self.update_bound();
+        if self.rendered {
+            self.backend.render(&self.tree)?;
+        }
+        Ok(())
+    }
+
+    fn update_bound(&mut self) {
+        for widget in &mut self.tree.widgets {
+            let bound = widget
+                .binding
+                .as_deref()
+                .and_then(|name| self.values.get(name));
+            match (widget.kind, bound, &widget.template) {
+                (WidgetKind::Input, Some(value), _) => widget.value = value.to_string(),
+                (_, Some(value), _) => widget.text = value.to_string(),
+                (_, None, Some(template)) => widget.text = fill_template(template, &self.values),
+                _ => {}
+            }
+        }
+        layout::apply(&mut self.tree);
+    }
+
+    // An input's text changed through the keyboard; drawing is left to the caller
+    fn edited(&mut self, id: usize) {
+        let widget = &self.tree.widgets[id];
+        if let Some(name) = widget.binding.clone() {
+            let value = Value::String(widget.value.clone());
+            self.changes.push((name.clone(), value.clone()));
+            if self.values.set(&name, value).is_ok() {
+                self.update_bound();
+            }
+        }
+    }
+
+    /// Apply keys pressed in an interactive backend, redrawing if there were any
+    pub fn poll(&mut self) -> Result<Vec<Event>, String> {
+        let keys = self.backend.poll_keys();
+        let mut events = Vec::new();
+        for key in &keys {
+            events.extend(self.press_key(key)?);
+        }
+        if !keys.is_empty() && self.backend.is_open() {
+            self.render()?;
+        }
+        Ok(events)
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.backend.is_open()
+    }
+
+    pub fn snapshot(&self) -> String {
+        self.tree.snapshot()
+    }
+
+    // Handlers to call for one event, in the order they were registered. Each
+    // gets an event object: {type, target, value, key}.
+    fn dispatch(
+        &self,
+        event_type: EventType,
+        target: Option<usize>,
+        key: Option<&str>,
+        events: &mut Vec<Event>,
+    ) {
+        let event = Value::Object(vec![
+            (
+                "type".to_string(),
+                Value::String(event_type.name().to_string()),
+            ),
+            (
+                "target".to_string(),
+                target.map_or(Value::Null, |id| {
+                    Value::Handle(Handle::new(HandleKind::Widget, id))
+                }),
+            ),
+            (
+                "value".to_string(),
+                match target {
+                    Some(id) if self.tree.widgets[id].kind == WidgetKind::Input => {
+                        Value::String(self.tree.widgets[id].value.clone())
+                    }
+                    _ => Value::Null,
+                },
+            ),
+            (
+                "key".to_string(),
+                key.map_or(Value::Null, |key| Value::String(key.to_string())),
+            ),
+        ]);
+        for handler in &self.handlers {
+            if handler.event_type == event_type && self.handles(handler.widget, target) {
+                events.push(Event {
+                    callback: handler.callback.clone(),
+                    args: vec![event.clone()],
+                });
+            }
+        }
+    }
+
+    // Widget handlers also see events on the widgets inside them
+    fn handles(&self, widget: Option<usize>, target: Option<usize>) -> bool {
+        let widget = match widget {
+            Some(widget) => widget,
+            None => return true,
+        };
+        let mut current = target;
+        while let Some(id) = current {
+            if id == widget {
+                return true;
+            }
+            current = self.tree.widgets[id].parent;
+        }
+        false
+    }
+
+    // ===== INPUT =====
+    // Each of these applies one piece of user input to the tree and returns the
+    // handler calls it causes, for the runtime to queue on the event loop.
+
+    pub fn click(&mut self, id: usize) -> Result<Vec<Event>, String> {
+        let kind = self.tree.get(id)?.kind;
+        let mut events = Vec::new();
+        if kind.is_focusable() {
+            self.move_focus(Some(id), &mut events);
+        }
+        self.dispatch(EventType::Click, Some(id), None, &mut events);
+        Ok(events)
+    }
+
+    pub fn hover(&mut self, id: usize) -> Result<Vec<Event>, String> {
+        self.tree.get(id)?;
+        let mut events = Vec::new();
+        if self.tree.hover != Some(id) {
+            self.tree.hover = Some(id);
+            self.dispatch(EventType::Hover, Some(id), None, &mut events);
+        }
+        Ok(events)
+    }
+
+    pub fn focus(&mut self, id: Option<usize>) -> Result<Vec<Event>, String> {
+        if let Some(id) = id {
+            let widget = self.tree.get(id)?;
+            if !widget.kind.is_focusable() {
+                return Err(format!("A {} cannot take focus", widget.kind.name()));
+            }
+        }
+        let mut events = Vec::new();
+        self.move_focus(id, &mut events);
+        Ok(events)
+    }
+
+    fn move_focus(&mut self, id: Option<usize>, events: &mut Vec<Event>) {
+        if self.tree.focus == id {
+            return;
+        }
+        if let Some(previous) = self.tree.focus {
+            self.dispatch(EventType::Blur, Some(previous), None, events);
+        }
+        self.tree.focus = id;
+        if id.is_some() {
+            self.dispatch(EventType::Focus, id, None, events);
+        }
+    }
+
+    /// Submit an input, as pressing Enter in it does
+    pub fn submit(&mut self, id: usize) -> Result<Vec<Event>, String> {
+        let widget = self.tree.get(id)?;
+        if widget.kind != WidgetKind::Input {
+            return Err(format!(
+                "Only inputs can be submitted, not a {}",
+                widget.kind.name()
+            ));
+        }
+        let mut events = Vec::new();
+        self.move_focus(Some(id), &mut events);
+        self.dispatch(EventType::Submit, Some(id), None, &mut events);
+        Ok(events)
+    }
+
+    /// Press a key: a single character, or one of Tab, Shift+Tab, Enter and
+    /// Backspace. Keys go to the focused widget.
+    pub fn press_key(&mut self, key: &str) -> Result<Vec<Event>, String> {
+        let mut events = Vec::new();
+        let focused = self.tree.focus;
+        self.dispatch(EventType::Keypress, focused, Some(key), &mut events);
+        let kind = focused.map(|id| self.tree.widgets[id].kind);
+
+        match key {
+            "Tab" | "Shift+Tab" => {
+                let order = self.tree.focus_order();
+                if !order.is_empty() {
+                    let current = focused.and_then(|id| order.iter().position(|&o| o == id));
+                    let next = match (current, key) {
+                        (None, "Tab") => 0,
+                        (None, _) => order.len() - 1,
+                        (Some(i), "Tab") => (i + 1) % order.len(),
+                        (Some(i), _) => (i + order.len() - 1) % order.len(),
+                    };
+                    self.move_focus(Some(order[next]), &mut events);
+                }
+            }
+            "Enter" => match (focused, kind) {
+                (Some(id), Some(WidgetKind::Button)) => events.extend(self.click(id)?),
+                (Some(id), Some(WidgetKind::Input)) => {
+                    self.dispatch(EventType::Submit, Some(id), None, &mut events)
+                }
+                _ => {}
+            },
+            "Backspace" => {
+                if let (Some(id), Some(WidgetKind::Input)) = (focused, kind) {
+                    if self.tree.widgets[id].value.pop().is_some() {
+                        self.edited(id);
+                        self.dispatch(EventType::Change, Some(id), None, &mut events);
+                    }
+                }
+            }
+            _ => {
+                let mut chars = key.chars();
+                let c = match (chars.next(), chars.next()) {
+                    (Some(c), None) => c,
+                    _ => return Err(format!("Unknown key '{}'", key)),
+                };
+                if let (Some(id), Some(WidgetKind::Input)) = (focused, kind) {
+                    self.tree.widgets[id].value.push(c);
+                    self.edited(id);
+                    self.dispatch(EventType::Change, Some(id), None, &mut events);
+                }
+            }
+        }
+        Ok(events)
+    }
+
+    /// Press each character of `text` in turn
+    pub fn type_text(&mut self, text: &str) -> Result<Vec<Event>, String> {
+        let mut events = Vec::new();
+        for c in text.chars() {
+            events.extend(self.press_key(&c.to_string())?);
+        }
+        Ok(events)
+    }
+
+    /// `ui.simulate(type, target)`: fake user input from a script under test.
+    /// The target is a key name for "keypress", otherwise a widget or the text
+    /// of one (or nothing, to blur the focused widget).
+    pub fn simulate(&mut self, event_type: &str, target: &Value) -> Result<Vec<Event>, String> {
+        let event_type = EventType::parse(event_type)?;
+        match event_type {
+            EventType::Keypress => match target {
+                Value::String(key) => self.press_key(key),
+                other => Err(format!(
+                    "Expected a key name to simulate 'keypress', found {}",
+                    other.type_of().to_string()
+                )),
+            },
+            EventType::Blur => self.focus(None),
+            EventType::Change => {
+                Err("Simulate typing with 'keypress' to change an input".to_string())
+            }
+            EventType::Click => self.click(self.target(event_type, target)?),
+            EventType::Hover => self.hover(self.target(event_type, target)?),
+            EventType::Focus => self.focus(Some(self.target(event_type, target)?)),
+            EventType::Submit => self.submit(self.target(event_type, target)?),
+        }
+    }
+
+    fn target(&self, event_type: EventType, target: &Value) -> Result<usize, String> {
+        match target {
+            Value::Handle(handle) => self.widget_id(*handle),
+            Value::String(text) => self
+                .tree
+                .find(text)
+                .ok_or_else(|| format!("No widget labelled '{}'", text)),
+            Value::Null => Err(format!("Simulating '{}' needs a widget", event_type.name())),
+            other => Err(format!(
+                "Expected a widget to simulate '{}' on, found {}",
+                event_type.name(),
+                other.type_of().to_string()
+            )),
+        }
+    }
+}
+
+// Replace each `{name}` with the UI value of that name; unknown names stay as written
+fn fill_template(template: &str, values: &Value) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('}') {
+            Some(end) => {
+                match values.get(after[..end].trim()) {
+                    Some(value) => out.push_str(&value.to_string()),
+                    None => out.push_str(&rest[start..start + end + 2]),
+                }
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn check_callback(event_type: EventType, callback: &Value) -> Result<(), String> {
+    match callback {
+        Value::Function(_) => Ok(()),
+        other => Err(format!(
+            "Expected a function to handle '{}' events, found {}",
+            event_type.name(),
+            other.type_of().to_string()
+        )),
+    }
+}
+
+fn check_size(width: i64, height: i64) -> Result<(), String> {
+    if width < 0 || height < 0 {
+        return Err(format!("Invalid size {}x{}", width, height));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn callback(name: &str) -> Value {
+        Value::Function(name.to_string())
+    }
+
+    fn form() -> Ui {
+        let mut ui = Ui::default();
+        ui.window("Form", 320, 200).unwrap();
+        ui.input("Name", 10, 10, 0, 0).unwrap();
+        ui.button("Save", 10, 60, 0, 0).unwrap();
+        ui
+    }
+
+    #[test]
+    fn snapshot_describes_the_tree() {
+        let ui = form();
+        assert_eq!(
+            ui.snapshot(),
+            concat!(
+                "window \"Form\" 320x200\n",
+                "  input \"Name\" at (10, 10) 160x32 value=\"\"\n",
+                "  button \"Save\" at (10, 60) 64x32\n",
+            )
+        );
+    }
+
+    #[test]
+    fn simulated_click_calls_handlers_with_an_event() {
+        let mut ui = form();
+        ui.event("click", callback("on_click")).unwrap();
+        let events = ui
+            .simulate("click", &Value::String("Save".to_string()))
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].callback, callback("on_click"));
+        match &events[0].args[..] {
+            [Value::Object(fields)] => assert_eq!(
+                fields[0],
+                ("type".to_string(), Value::String("click".to_string()))
+            ),
+            other => panic!("Expected one event object, found {:?}", other),
+        }
+        assert!(ui
+            .snapshot()
+            .contains("button \"Save\" at (10, 60) 64x32 [focused]"));
+    }
+
+    #[test]
+    fn simulated_keys_type_into_the_focused_input() {
+        let mut ui = form();
+        ui.event("change", callback("on_change")).unwrap();
+        ui.simulate("focus", &Value::String("Name".to_string()))
+            .unwrap();
+        let mut events = Vec::new();
+        for key in ["A", "d", "a", "Backspace"] {
+            events.extend(
+                ui.simulate("keypress", &Value::String(key.to_string()))
+                    .unwrap(),
+            );
+        }
+        assert_eq!(events.len(), 4);
+        assert!(ui.snapshot().contains("value=\"Ad\" [focused]"));
+
+        ui.simulate("keypress", &Value::String("Tab".to_string()))
+            .unwrap();
+        assert!(ui
+            .snapshot()
+            .contains("button \"Save\" at (10, 60) 64x32 [focused]"));
+    }
+
+    #[test]
+    fn simulate_rejects_bad_targets() {
+        let mut ui = form();
+        assert_eq!(
+            ui.simulate("click", &Value::String("Cancel".to_string())),
+            Err("No widget labelled 'Cancel'".to_string())
+        );
+        assert!(ui
+            .simulate("submit", &Value::String("Save".to_string()))
+            .is_err());
+        assert!(ui.simulate("scroll", &Value::Null).is_err());
+    }
+}
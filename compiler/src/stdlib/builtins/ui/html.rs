@@ -0,0 +1,137 @@
+// HTML Backend for the UI Header
+// Exports the widget tree as a static HTML page. Widgets are absolutely
+// positioned with the same x/y/width/height the script gave them, so the page
+// matches the layout and can be previewed in a browser or compared as text.
+
+use super::{Backend, Tree, WidgetKind};
+use std::path::PathBuf;
+
+const STYLE: &str = "\
+body { font-family: sans-serif; background: #e8e8e8; margin: 16px; }
+.prizm-window { display: inline-block; margin: 0 16px 16px 0; background: #fff; border: 1px solid #888; vertical-align: top; }
+.prizm-title { padding: 4px 8px; background: #445; color: #fff; font-weight: bold; }
+.prizm-body, .prizm-panel { position: relative; overflow: hidden; }
+.prizm-body *, .prizm-panel * { position: absolute; box-sizing: border-box; margin: 0; }
+.prizm-panel { border: 1px solid #bbb; }
+.prizm-text, .prizm-label { white-space: pre; line-height: 16px; }
+";
+
+/// The whole page for a tree, with one `<section>` per window
+pub fn export(tree: &Tree) -> String {
+    let title = tree
+        .windows()
+        .next()
+        .map(|id| tree.widgets[id].text.as_str())
+        .unwrap_or("Prizm");
+
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n");
+    out.push_str("<meta charset=\"utf-8\">\n");
+    out.push_str(&format!("<title>{}</title>\n", escape(title)));
+    out.push_str(&format!("<style>\n{}</style>\n", STYLE));
+    out.push_str("</head>\n<body>\n");
+    for id in tree.windows() {
+        let window = &tree.widgets[id];
+        out.push_str("<section class=\"prizm-window\">\n");
+        out.push_str(&format!(
+            "  <div class=\"prizm-title\">{}</div>\n",
+            escape(&window.text)
+        ));
+        out.push_str(&format!(
+            "  <div class=\"prizm-body\" style=\"width: {}px; height: {}px\">\n",
+            window.layout.width, window.layout.height
+        ));
+        write_children(tree, id, 2, &mut out);
+        out.push_str("  </div>\n</section>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn write_children(tree: &Tree, parent: usize, depth: usize, out: &mut String) {
+    for &id in &tree.widgets[parent].children {
+        let widget = &tree.widgets[id];
+        let rect = widget.layout;
+        let indent = "  ".repeat(depth);
+        let position = format!("left: {}px; top: {}px", rect.x, rect.y);
+        let size = format!("width: {}px; height: {}px", rect.width, rect.height);
+        let focus = if tree.focus == Some(id) {
+            " autofocus"
+        } else {
+            ""
+        };
+        let text = escape(&widget.text);
+
+        let element = match widget.kind {
+            WidgetKind::Text => format!(
+                "<span class=\"prizm-text\" style=\"{}\">{}</span>",
+                position, text
+            ),
+            WidgetKind::Label => format!(
+                "<label class=\"prizm-label\" style=\"{}\">{}</label>",
+                position, text
+            ),
+            WidgetKind::Button => format!(
+                "<button class=\"prizm-button\" style=\"{}; {}\"{}>{}</button>",
+                position, size, focus, text
+            ),
+            WidgetKind::Input => format!(
+                "<input class=\"prizm-input\" type=\"text\" placeholder=\"{}\" value=\"{}\" style=\"{}; {}\"{}>",
+                text,
+                escape(&widget.value),
+                position,
+                size,
+                focus
+            ),
+            WidgetKind::Panel | WidgetKind::Row | WidgetKind::Column | WidgetKind::Grid => {
+                out.push_str(&format!(
+                    "{}<div class=\"prizm-{}\" style=\"{}; {}\">\n",
+                    indent,
+                    widget.kind.name(),
+                    position,
+                    size
+                ));
+                write_children(tree, id, depth + 1, out);
+                out.push_str(&format!("{}</div>\n", indent));
+                continue;
+            }
+            WidgetKind::Window => continue,
+        };
+        out.push_str(&indent);
+        out.push_str(&element);
+        out.push('\n');
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Writes the page to a file each time the program calls `ui.render()`
+pub struct Html {
+    path: PathBuf,
+}
+
+impl Html {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Html { path: path.into() }
+    }
+}
+
+impl Backend for Html {
+    fn render(&mut self, tree: &Tree) -> Result<(), String> {
+        std::fs::write(&self.path, export(tree))
+            .map_err(|e| format!("Failed to write HTML to {}: {}", self.path.display(), e))
+    }
+}
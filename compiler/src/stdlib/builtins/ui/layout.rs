@@ -0,0 +1,181 @@
+// Layout Engine for the UI Header
+// Works out where every widget goes before a backend draws it. Windows and
+// panels place their children at the x/y the script gave; rows, columns and
+// grids place theirs one after another and grow to fit them. Widgets created
+// with no size get a natural size from their content. Sizes saturate rather
+// than overflow, since backends clip to what they can show anyway.
+
+use super::{Rect, Tree, WidgetKind, CHAR_WIDTH, LINE_HEIGHT};
+
+// Natural sizes for widgets created without one
+const CONTROL_HEIGHT: i64 = 2 * LINE_HEIGHT;
+const INPUT_CHARS: i64 = 20;
+
+/// Fill in `layout` for every widget in the tree
+pub fn apply(tree: &mut Tree) {
+    let windows: Vec<usize> = tree.windows().collect();
+    for id in windows {
+        let (width, height) = measure(tree, id);
+        tree.widgets[id].layout = Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+        place_children(tree, id);
+    }
+}
+
+/// Width and height of text drawn on a LINE_HEIGHT grid
+pub fn text_size(text: &str) -> (i64, i64) {
+    let lines = text.lines().count().max(1) as i64;
+    let longest = text
+        .lines()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0) as i64;
+    (
+        longest.saturating_mul(CHAR_WIDTH),
+        lines.saturating_mul(LINE_HEIGHT),
+    )
+}
+
+/// The size a widget wants to be, before its parent places it
+fn measure(tree: &Tree, id: usize) -> (i64, i64) {
+    let widget = &tree.widgets[id];
+    let rect = widget.rect;
+    let chars = widget.text.chars().count() as i64;
+    let or = |given: i64, natural: i64| if given > 0 { given } else { natural };
+
+    match widget.kind {
+        WidgetKind::Text | WidgetKind::Label => text_size(&widget.text),
+        // Room for "[ label ]"
+        WidgetKind::Button => (
+            or(
+                rect.width,
+                chars.saturating_add(4).saturating_mul(CHAR_WIDTH),
+            ),
+            or(rect.height, CONTROL_HEIGHT),
+        ),
+        WidgetKind::Input => (
+            or(rect.width, INPUT_CHARS * CHAR_WIDTH),
+            or(rect.height, CONTROL_HEIGHT),
+        ),
+        // Big enough to show every child where it was put
+        WidgetKind::Panel | WidgetKind::Window => {
+            let (mut right, mut bottom) = (0, 0);
+            for &child in &widget.children {
+                let (width, height) = measure(tree, child);
+                right = right.max(tree.widgets[child].rect.x.saturating_add(width));
+                bottom = bottom.max(tree.widgets[child].rect.y.saturating_add(height));
+            }
+            (or(rect.width, right), or(rect.height, bottom))
+        }
+        WidgetKind::Row | WidgetKind::Column | WidgetKind::Grid => {
+            let Cells { columns, rows, .. } = arrange(tree, id);
+            let padding = widget.options.padding;
+            (
+                span(&columns, widget.options.spacing).saturating_add(padding.saturating_mul(2)),
+                span(&rows, widget.options.spacing).saturating_add(padding.saturating_mul(2)),
+            )
+        }
+    }
+}
+
+// Total length of tracks laid end to end with `spacing` between them
+fn span(tracks: &[i64], spacing: i64) -> i64 {
+    let gaps = tracks.len().saturating_sub(1) as i64;
+    total(tracks).saturating_add(gaps.saturating_mul(spacing))
+}
+
+fn total(tracks: &[i64]) -> i64 {
+    tracks
+        .iter()
+        .fold(0, |sum, &track| sum.saturating_add(track))
+}
+
+// Which cell each child of a container goes in, and the width of every
+// column and height of every row
+struct Cells {
+    // (child, column, row)
+    cells: Vec<(usize, usize, usize)>,
+    columns: Vec<i64>,
+    rows: Vec<i64>,
+}
+
+// Rows are one row of cells, columns one column; grids fill `columns` cells
+// across and then wrap
+fn arrange(tree: &Tree, id: usize) -> Cells {
+    let widget = &tree.widgets[id];
+    let across = match widget.kind {
+        WidgetKind::Row => widget.children.len().max(1),
+        WidgetKind::Column => 1,
+        _ => widget.options.columns.max(1),
+    };
+    let mut cells = Vec::new();
+    let mut columns: Vec<i64> = Vec::new();
+    let mut rows: Vec<i64> = Vec::new();
+    for (i, &child) in widget.children.iter().enumerate() {
+        let (column, row) = (i % across, i / across);
+        let (width, height) = measure(tree, child);
+        if columns.len() <= column {
+            columns.resize(column + 1, 0);
+        }
+        if rows.len() <= row {
+            rows.resize(row + 1, 0);
+        }
+        columns[column] = columns[column].max(width);
+        rows[row] = rows[row].max(height);
+        cells.push((child, column, row));
+    }
+    Cells {
+        cells,
+        columns,
+        rows,
+    }
+}
+
+fn place_children(tree: &mut Tree, id: usize) {
+    let kind = tree.widgets[id].kind;
+    let children = tree.widgets[id].children.clone();
+
+    if matches!(
+        kind,
+        WidgetKind::Row | WidgetKind::Column | WidgetKind::Grid
+    ) {
+        let Cells {
+            cells,
+            columns,
+            rows,
+        } = arrange(tree, id);
+        let options = tree.widgets[id].options;
+        let offset = |tracks: &[i64], index: usize| {
+            options
+                .padding
+                .saturating_add(total(&tracks[..index]))
+                .saturating_add((index as i64).saturating_mul(options.spacing))
+        };
+        for (child, column, row) in cells {
+            let (width, height) = measure(tree, child);
+            tree.widgets[child].layout = Rect {
+                x: offset(&columns, column),
+                y: offset(&rows, row),
+                width,
+                height,
+            };
+            place_children(tree, child);
+        }
+    } else {
+        for child in children {
+            let (width, height) = measure(tree, child);
+            let rect = tree.widgets[child].rect;
+            tree.widgets[child].layout = Rect {
+                x: rect.x,
+                y: rect.y,
+                width,
+                height,
+            };
+            place_children(tree, child);
+        }
+    }
+}
@@ -0,0 +1,455 @@
+// Terminal Backend for the UI Header
+// Draws the widget tree as text cells with ANSI escapes and reads keys from
+// stdin, so UI scripts work over SSH. Pixel positions are scaled down to the
+// character grid: one cell is CHAR_WIDTH by LINE_HEIGHT pixels. When stdout is
+// not a terminal, each render is written as plain text instead.
+
+use super::{Backend, Tree, WidgetKind, CHAR_WIDTH, LINE_HEIGHT};
+use std::io::{IsTerminal, Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+// ===== GRID =====
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    Normal,
+    Bold,
+    // Placeholder text in empty inputs
+    Dim,
+    // The focused widget
+    Reverse,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cell {
+    pub ch: char,
+    pub style: Style,
+}
+
+const BLANK: Cell = Cell {
+    ch: ' ',
+    style: Style::Normal,
+};
+
+/// A screen's worth of cells. Anything drawn outside it is clipped.
+pub struct Grid {
+    pub width: usize,
+    pub height: usize,
+    cells: Vec<Cell>,
+}
+
+impl Grid {
+    pub fn new(width: usize, height: usize) -> Self {
+        Grid {
+            width,
+            height,
+            cells: vec![BLANK; width * height],
+        }
+    }
+
+    pub fn get(&self, col: usize, row: usize) -> Option<Cell> {
+        if col < self.width && row < self.height {
+            Some(self.cells[row * self.width + col])
+        } else {
+            None
+        }
+    }
+
+    fn put(&mut self, col: i64, row: i64, ch: char, style: Style) {
+        if col >= 0 && row >= 0 && (col as usize) < self.width && (row as usize) < self.height {
+            self.cells[row as usize * self.width + col as usize] = Cell { ch, style };
+        }
+    }
+
+    fn write(&mut self, col: i64, row: i64, text: &str, style: Style) {
+        for (i, ch) in text.chars().enumerate() {
+            self.put(col.saturating_add(i as i64), row, ch, style);
+        }
+    }
+
+    fn draw_box(&mut self, col: i64, row: i64, width: i64, height: i64, title: &str) {
+        if width < 2 || height < 2 {
+            return;
+        }
+        let right = col.saturating_add(width - 1);
+        let bottom = row.saturating_add(height - 1);
+        // Only the part of each edge that is on the grid
+        for c in col.saturating_add(1).max(0)..right.min(self.width as i64) {
+            self.put(c, row, '─', Style::Normal);
+            self.put(c, bottom, '─', Style::Normal);
+        }
+        for r in row.saturating_add(1).max(0)..bottom.min(self.height as i64) {
+            self.put(col, r, '│', Style::Normal);
+            self.put(right, r, '│', Style::Normal);
+        }
+        self.put(col, row, '┌', Style::Normal);
+        self.put(right, row, '┐', Style::Normal);
+        self.put(col, bottom, '└', Style::Normal);
+        self.put(right, bottom, '┘', Style::Normal);
+        if !title.is_empty() {
+            let title: String = title.chars().take((width - 6).max(0) as usize).collect();
+            self.write(col + 2, row, &format!(" {} ", title), Style::Bold);
+        }
+    }
+
+    /// The grid as plain text, with trailing spaces trimmed from each line
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for row in 0..self.height {
+            let line: String = (0..self.width)
+                .map(|col| self.cells[row * self.width + col].ch)
+                .collect();
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        out
+    }
+
+    /// The grid as ANSI escapes that redraw the whole screen in place
+    pub fn to_ansi(&self) -> String {
+        let mut out = String::from("\x1b[H");
+        for row in 0..self.height {
+            let mut style = Style::Normal;
+            for col in 0..self.width {
+                let cell = self.cells[row * self.width + col];
+                if cell.style != style {
+                    out.push_str(match cell.style {
+                        Style::Normal => "\x1b[0m",
+                        Style::Bold => "\x1b[0;1m",
+                        Style::Dim => "\x1b[0;2m",
+                        Style::Reverse => "\x1b[0;7m",
+                    });
+                    style = cell.style;
+                }
+                out.push(cell.ch);
+            }
+            out.push_str("\x1b[0m\x1b[K\r\n");
+        }
+        out.push_str("\x1b[J");
+        out
+    }
+}
+
+// Pixels to cells; positions round down and sizes round up so nothing shrinks away
+fn cells_at(pixels: i64, per_cell: i64) -> i64 {
+    pixels.div_euclid(per_cell)
+}
+
+fn cells_for(pixels: i64, per_cell: i64) -> i64 {
+    pixels.saturating_add(per_cell - 1).div_euclid(per_cell)
+}
+
+/// Lay the tree out on a grid. Each window is a box sized to its pixel size;
+/// several windows are stacked top to bottom. The grid is at most `max_width`
+/// by `max_height` cells, and anything beyond that is clipped.
+pub fn draw(tree: &Tree, max_width: usize, max_height: usize) -> Grid {
+    let windows: Vec<usize> = tree.windows().collect();
+    let size = |id: usize| {
+        let rect = tree.widgets[id].layout;
+        (
+            cells_for(rect.width, CHAR_WIDTH).saturating_add(2),
+            cells_for(rect.height, LINE_HEIGHT).saturating_add(2),
+        )
+    };
+    let width = windows.iter().map(|&id| size(id).0).max().unwrap_or(0);
+    let height = windows
+        .iter()
+        .fold(0i64, |height, &id| height.saturating_add(size(id).1));
+    let mut grid = Grid::new(
+        (width as usize).min(max_width),
+        (height as usize).min(max_height),
+    );
+
+    let mut top = 0;
+    for id in windows {
+        let (window_width, window_height) = size(id);
+        grid.draw_box(0, top, window_width, window_height, &tree.widgets[id].text);
+        // Content starts inside the border
+        draw_children(tree, id, 1, top.saturating_add(1), &mut grid);
+        top = top.saturating_add(window_height);
+    }
+    grid
+}
+
+fn draw_children(tree: &Tree, parent: usize, left: i64, top: i64, grid: &mut Grid) {
+    for &id in &tree.widgets[parent].children {
+        let widget = &tree.widgets[id];
+        let rect = widget.layout;
+        let col = left.saturating_add(cells_at(rect.x, CHAR_WIDTH));
+        let row = top.saturating_add(cells_at(rect.y, LINE_HEIGHT));
+        let width = cells_for(rect.width, CHAR_WIDTH);
+        let height = cells_for(rect.height, LINE_HEIGHT).max(1);
+        // Single-line widgets sit on the middle row of their box
+        let middle = row.saturating_add((height - 1) / 2);
+        let focused = tree.focus == Some(id);
+
+        match widget.kind {
+            WidgetKind::Text | WidgetKind::Label => {
+                for (i, line) in widget.text.lines().enumerate() {
+                    grid.write(col, row.saturating_add(i as i64), line, Style::Normal);
+                }
+            }
+            WidgetKind::Button => {
+                let label = format!("[ {} ]", widget.text);
+                let label_width = label.chars().count() as i64;
+                let offset = ((width - label_width) / 2).max(0);
+                let style = if focused { Style::Reverse } else { Style::Bold };
+                grid.write(col.saturating_add(offset), middle, &label, style);
+            }
+            WidgetKind::Input => {
+                let width = (width.max(1) as usize).min(grid.width.max(1));
+                let (text, style) = if widget.value.is_empty() && !focused {
+                    (widget.text.as_str(), Style::Dim)
+                } else {
+                    (widget.value.as_str(), Style::Normal)
+                };
+                // Long values scroll so the end stays visible
+                let skip = text.chars().count().saturating_sub(width - 1);
+                let shown: String = text.chars().skip(skip).collect();
+                let field = format!("{:_<width$}", shown, width = width);
+                let style = if focused { Style::Reverse } else { style };
+                grid.write(col, middle, &field, style);
+            }
+            WidgetKind::Panel => {
+                grid.draw_box(col, row, width, height, "");
+                draw_children(tree, id, col, row, grid);
+            }
+            WidgetKind::Row | WidgetKind::Column | WidgetKind::Grid => {
+                draw_children(tree, id, col, row, grid);
+            }
+            WidgetKind::Window => {}
+        }
+    }
+}
+
+// ===== KEYS =====
+
+/// Turn bytes read from the terminal into key names understood by
+/// `Ui::press_key`. Returns true if the user asked to close the UI
+/// (Escape, Ctrl+C or Ctrl+D).
+pub fn decode_keys(input: &str, keys: &mut Vec<String>) -> bool {
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\t' => keys.push("Tab".to_string()),
+            '\r' | '\n' => keys.push("Enter".to_string()),
+            '\x7f' | '\x08' => keys.push("Backspace".to_string()),
+            '\x03' | '\x04' => return true,
+            '\x1b' => {
+                if chars.peek() != Some(&'[') {
+                    return true;
+                }
+                chars.next();
+                // Arrow keys move focus like Tab; other sequences are ignored
+                match chars.next() {
+                    Some('A') | Some('Z') => keys.push("Shift+Tab".to_string()),
+                    Some('B') => keys.push("Tab".to_string()),
+                    _ => {}
+                }
+            }
+            c if c.is_control() => {}
+            c => keys.push(c.to_string()),
+        }
+    }
+    false
+}
+
+// ===== BACKEND =====
+
+// Screen size to draw for when the terminal cannot be asked
+const DEFAULT_SIZE: (usize, usize) = (80, 24);
+
+/// Draws to the terminal on stdout and reads keys from stdin
+pub struct Terminal {
+    keys: Option<Receiver<String>>,
+    // `stty` settings to restore when the UI closes
+    saved_mode: Option<String>,
+    started: bool,
+    closed: bool,
+    // Stdout is a file or pipe: write each render as text, without escapes
+    plain: bool,
+}
+
+impl Default for Terminal {
+    fn default() -> Self {
+        Terminal::new()
+    }
+}
+
+impl Terminal {
+    pub fn new() -> Self {
+        Terminal {
+            keys: None,
+            saved_mode: None,
+            started: false,
+            closed: false,
+            plain: !std::io::stdout().is_terminal(),
+        }
+    }
+
+    fn start(&mut self) {
+        // Ctrl+C arrives as a key instead of killing prizm, so the terminal
+        // is always put back
+        self.saved_mode = raw_mode(&["-isig"]);
+        // Alternate screen, hidden cursor
+        print!("\x1b[?1049h\x1b[?25l");
+
+        // Reading stdin blocks, so it happens on its own thread
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut stdin = std::io::stdin();
+            let mut buffer = [0u8; 64];
+            while let Ok(count) = stdin.read(&mut buffer) {
+                let text = String::from_utf8_lossy(&buffer[..count]).to_string();
+                if count == 0 || sender.send(text).is_err() {
+                    break;
+                }
+            }
+        });
+        self.keys = Some(receiver);
+        self.started = true;
+    }
+
+    fn stop(&mut self) {
+        if self.started {
+            print!("\x1b[0m\x1b[?25h\x1b[?1049l");
+            let _ = std::io::stdout().flush();
+            if let Some(mode) = self.saved_mode.take() {
+                restore_mode(&mode);
+            }
+            self.started = false;
+        }
+    }
+}
+
+impl Backend for Terminal {
+    fn render(&mut self, tree: &Tree) -> Result<(), String> {
+        let (width, height) = screen_size();
+        let grid = draw(tree, width, height);
+        let text = if self.plain {
+            grid.to_text() + "\n"
+        } else {
+            if !self.started {
+                self.start();
+            }
+            grid.to_ansi()
+        };
+        let mut stdout = std::io::stdout();
+        stdout
+            .write_all(text.as_bytes())
+            .and_then(|_| stdout.flush())
+            .map_err(|e| format!("Failed to draw to the terminal: {}", e))
+    }
+
+    fn poll_keys(&mut self) -> Vec<String> {
+        let mut keys = Vec::new();
+        if let Some(receiver) = &self.keys {
+            loop {
+                match receiver.try_recv() {
+                    Ok(input) => {
+                        if decode_keys(&input, &mut keys) {
+                            self.closed = true;
+                            break;
+                        }
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        self.closed = true;
+                        break;
+                    }
+                }
+            }
+        }
+        if self.closed {
+            self.stop();
+        }
+        keys
+    }
+
+    fn is_open(&self) -> bool {
+        self.started && !self.closed
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// Columns and rows of the terminal, from `stty size` or else $COLUMNS and $LINES
+fn screen_size() -> (usize, usize) {
+    let from_env = |name: &str| std::env::var(name).ok()?.trim().parse::<usize>().ok();
+    #[cfg(unix)]
+    if let Some(size) = stty(&["size"]) {
+        if let Some((rows, cols)) = size.split_once(' ') {
+            if let (Ok(rows), Ok(cols)) = (rows.parse::<usize>(), cols.parse::<usize>()) {
+                if rows > 0 && cols > 0 {
+                    return (cols, rows);
+                }
+            }
+        }
+    }
+    (
+        from_env("COLUMNS").unwrap_or(DEFAULT_SIZE.0),
+        from_env("LINES").unwrap_or(DEFAULT_SIZE.1),
+    )
+}
+
+/// Deliver keys as they are pressed, without echo, with any other `stty`
+/// settings in `extra`. Returns the previous settings, or None if stdin is
+/// not a terminal.
+#[cfg(unix)]
+pub fn raw_mode(extra: &[&str]) -> Option<String> {
+    let saved = stty(&["-g"])?;
+    let mut args = vec!["-icanon", "-echo", "min", "1"];
+    args.extend(extra);
+    stty(&args)?;
+    Some(saved)
+}
+
+/// Put back the settings `raw_mode` returned
+#[cfg(unix)]
+pub fn restore_mode(mode: &str) {
+    let _ = stty(&[mode]);
+}
+
+#[cfg(unix)]
+fn stty(args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new("stty")
+        .args(args)
+        .stdin(std::process::Stdio::inherit())
+        .output()
+        .ok()?;
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        None
+    }
+}
+
+// Other platforms deliver keys a line at a time
+#[cfg(not(unix))]
+pub fn raw_mode(_extra: &[&str]) -> Option<String> {
+    None
+}
+
+#[cfg(not(unix))]
+pub fn restore_mode(_mode: &str) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_named_and_close_keys_stop() {
+        let mut keys = Vec::new();
+        assert!(!decode_keys("a\t\x1b[Z\r\x7f", &mut keys));
+        assert_eq!(keys, ["a", "Tab", "Shift+Tab", "Enter", "Backspace"]);
+        for close in ["\x03", "\x04", "\x1b"] {
+            let mut keys = Vec::new();
+            assert!(decode_keys(&format!("x{}y", close), &mut keys));
+            assert_eq!(keys, ["x"]);
+        }
+    }
+}
@@ -0,0 +1,264 @@
+// Runtime Values for Prizm
+// Every header attribute takes and returns these values
+
+use crate::attributes::PrizmType;
+use crate::stdlib::builtins::time::Duration;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Int(i64),
+    Float(f64),
+    String(String),
+    Boolean(bool),
+    Array(Vec<Value>),
+    // Object fields keep their declaration order
+    Object(Vec<(String, Value)>),
+    Duration(Duration),
+    // A function defined with `define`, referred to by name
+    Function(String),
+    Handle(Handle),
+}
+
+/// Reference to state owned by a header at runtime (timers, widgets, ...).
+/// Handles behave as objects whose methods are answered by that header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Handle {
+    pub kind: HandleKind,
+    pub id: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HandleKind {
+    Stopwatch,
+    Scheduled,
+    Widget,
+    State,
+    Process,
+    Buffer,
+    Rows,
+}
+
+impl Handle {
+    pub fn new(kind: HandleKind, id: usize) -> Self {
+        Handle { kind, id }
+    }
+}
+
+impl fmt::Display for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            HandleKind::Stopwatch => "timer",
+            HandleKind::Scheduled => "scheduled callback",
+            HandleKind::Widget => "widget",
+            HandleKind::State => "ui state",
+            HandleKind::Process => "process",
+            HandleKind::Buffer => "buffer",
+            HandleKind::Rows => "csv rows",
+        };
+        write!(f, "<{} #{}>", kind, self.id)
+    }
+}
+
+impl Value {
+    pub fn type_of(&self) -> PrizmType {
+        match self {
+            Value::Null => PrizmType::Null,
+            Value::Int(_) => PrizmType::Int,
+            Value::Float(_) => PrizmType::Float,
+            Value::String(_) => PrizmType::String,
+            Value::Boolean(_) => PrizmType::Boolean,
+            Value::Array(_) => PrizmType::Array,
+            Value::Object(_) => PrizmType::Object,
+            Value::Duration(_) => PrizmType::Duration,
+            Value::Function(_) => PrizmType::Function,
+            Value::Handle(_) => PrizmType::Object,
+        }
+    }
+
+    /// Look up a field on an object value
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        match self {
+            Value::Object(fields) => fields.iter_mut().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Set a field on an object value, replacing it in place if it already exists
+    pub fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+        match self {
+            Value::Object(fields) => {
+                match fields.iter_mut().find(|(k, _)| k == key) {
+                    Some(field) => field.1 = value,
+                    None => fields.push((key.to_string(), value)),
+                }
+                Ok(())
+            }
+            other => Err(format!(
+                "Cannot set field '{}' on {}",
+                key,
+                other.type_of().to_string()
+            )),
+        }
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Boolean(b) => *b,
+            Value::Int(n) => *n != 0,
+            Value::Float(f) => *f != 0.0,
+            Value::String(s) => !s.is_empty(),
+            Value::Array(items) => !items.is_empty(),
+            Value::Object(_) => true,
+            Value::Duration(d) => d.as_millis() != 0,
+            Value::Function(_) => true,
+            Value::Handle(_) => true,
+        }
+    }
+
+    /// Deep-merge `other` into this value; `path` names this value in errors
+    pub fn merge(
+        &mut self,
+        other: Value,
+        options: &MergeOptions,
+        path: &str,
+    ) -> Result<(), String> {
+        match (&mut *self, other) {
+            (Value::Object(fields), Value::Object(other_fields)) => {
+                for (key, other_value) in other_fields {
+                    let field_path = format!("{}.{}", path, key);
+                    match fields.iter_mut().find(|(k, _)| *k == key) {
+                        Some((_, value)) => value.merge(other_value, options, &field_path)?,
+                        None => fields.push((key, other_value)),
+                    }
+                }
+                Ok(())
+            }
+            (Value::Array(items), Value::Array(other_items)) => {
+                match &options.arrays {
+                    ArrayStrategy::Replace => *items = other_items,
+                    ArrayStrategy::Append => items.extend(other_items),
+                    ArrayStrategy::Union(key) => {
+                        for (i, other_item) in other_items.into_iter().enumerate() {
+                            let item_path = format!("{}[{}]", path, i);
+                            // Items without the key field are compared whole
+                            let keyed = key
+                                .as_deref()
+                                .and_then(|key| other_item.get(key).map(|id| (key, id)));
+                            let existing = match keyed {
+                                Some((key, id)) => {
+                                    items.iter().position(|item| item.get(key) == Some(id))
+                                }
+                                None => items.iter().position(|item| *item == other_item),
+                            };
+                            match existing {
+                                Some(index) => {
+                                    items[index].merge(other_item, options, &item_path)?
+                                }
+                                None => items.push(other_item),
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            }
+            (this, other) => {
+                if options.strict && !same_kind(this, &other) {
+                    return Err(format!(
+                        "Merge conflict at {}: cannot merge {} into {}",
+                        path,
+                        other.type_of().to_string(),
+                        this.type_of().to_string()
+                    ));
+                }
+                *this = other;
+                Ok(())
+            }
+        }
+    }
+}
+
+// Null never conflicts, and ints and floats are both numbers
+fn same_kind(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Null, _) | (_, Value::Null) => true,
+        (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => true,
+        (a, b) => a.type_of() == b.type_of(),
+    }
+}
+
+/// How arrays found at the same path are combined by `Value::merge`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArrayStrategy {
+    Replace,
+    Append,
+    /// Keep one copy of each item; with a key, objects sharing that field's
+    /// value are merged into each other instead of being compared whole
+    Union(Option<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergeOptions {
+    pub arrays: ArrayStrategy,
+    /// Error on type conflicts instead of letting the newer value win
+    pub strict: bool,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        MergeOptions {
+            arrays: ArrayStrategy::Replace,
+            strict: false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Duration(d) => write!(f, "{}", d),
+            Value::Function(name) => write!(f, "<function {}>", name),
+            Value::Handle(h) => write!(f, "{}", h),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match item {
+                        Value::String(s) => write!(f, "\"{}\"", s)?,
+                        other => write!(f, "{}", other)?,
+                    }
+                }
+                write!(f, "]")
+            }
+            Value::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match value {
+                        Value::String(s) => write!(f, "{}: \"{}\"", key, s)?,
+                        other => write!(f, "{}: {}", key, other)?,
+                    }
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
@@ -0,0 +1,690 @@
+// Virtual Machine for Prizm
+// Runs a compiled module on a value stack. The statements outside functions
+// run first; after them the event loop runs scheduled callbacks and UI
+// events until nothing is left to wait for.
+
+mod headers;
+
+use crate::ast::{BinaryOp, UnaryOp};
+use crate::attributes::{self, PrizmType};
+use crate::bytecode::{Chunk, Module, Op, PathStep};
+use crate::event_loop::{Event, EventLoop};
+use crate::stdlib::builtins::data::csv;
+use crate::stdlib::builtins::root::Root;
+use crate::stdlib::builtins::time::Time;
+use crate::stdlib::builtins::ui::Ui;
+use crate::value::{Handle, HandleKind, Value};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Write};
+use std::rc::Rc;
+
+// Deepest chain of calls before the program is stopped
+const MAX_DEPTH: usize = 256;
+
+// How long the loop waits for timers before checking an open UI for keys
+const UI_POLL_INTERVAL: i64 = 10;
+
+/// The headers' state and where output goes, for one run of a program
+pub struct Runtime {
+    pub ui: Ui,
+    pub time: Time,
+    pub root: Root,
+    pub output: Box<dyn Write>,
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Runtime {
+            ui: Ui::default(),
+            time: Time::default(),
+            root: Root::default(),
+            output: Box::new(std::io::stdout()),
+        }
+    }
+}
+
+struct Variable {
+    value: Value,
+    declared: Option<PrizmType>,
+}
+
+pub struct Vm {
+    module: Rc<Module>,
+    globals: HashMap<String, Variable>,
+    // Local variables of each function call in progress
+    frames: Vec<HashMap<String, Variable>>,
+    ui: Ui,
+    time: Time,
+    root: Root,
+    output: Box<dyn Write>,
+    events: EventLoop,
+    // Readers from `data.csv_rows`, indexed by handle id
+    rows: Vec<csv::Rows<BufReader<File>>>,
+}
+
+impl Vm {
+    pub fn new(module: Module, runtime: Runtime) -> Self {
+        Vm {
+            module: Rc::new(module),
+            globals: HashMap::new(),
+            frames: Vec::new(),
+            ui: runtime.ui,
+            time: runtime.time,
+            root: runtime.root,
+            output: runtime.output,
+            events: EventLoop::new(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Run the program, then its callbacks until there are none left
+    pub fn run(&mut self) -> Result<(), String> {
+        let module = Rc::clone(&self.module);
+        self.execute(&module.main)?;
+        self.drive()?;
+        self.output
+            .flush()
+            .map_err(|e| format!("Failed to write output: {}", e))
+    }
+
+    /// Run another module with the globals the last one left, as the REPL
+    /// does with each input. The new module must define every function that
+    /// is still called.
+    pub fn run_next(&mut self, module: Module) -> Result<(), String> {
+        self.module = Rc::new(module);
+        self.run()
+    }
+
+    /// A global variable's value, once the program has run
+    pub fn global(&self, name: &str) -> Option<&Value> {
+        self.globals.get(name).map(|variable| &variable.value)
+    }
+
+    // ===== EVENT LOOP =====
+
+    fn drive(&mut self) -> Result<(), String> {
+        loop {
+            let event = if self.ui.is_open() {
+                let events = self.ui.poll()?;
+                self.sync_ui()?;
+                for event in events {
+                    self.events.post(event);
+                }
+                let deadline = self.time.now() + UI_POLL_INTERVAL;
+                match self.events.next_event_until(self.time.clock(), deadline) {
+                    Some(event) => event,
+                    None => continue,
+                }
+            } else {
+                match self.events.next_event(self.time.clock()) {
+                    Some(event) => event,
+                    None => return Ok(()),
+                }
+            };
+            self.dispatch(event)?;
+        }
+    }
+
+    fn dispatch(&mut self, event: Event) -> Result<(), String> {
+        self.call_value(&event.callback, event.args)?;
+        Ok(())
+    }
+
+    // Copy values typed into bound inputs into the variables of the same name
+    fn sync_ui(&mut self) -> Result<(), String> {
+        for (name, value) in self.ui.take_changes() {
+            if let Some(variable) = self.globals.get_mut(&name) {
+                if variable
+                    .declared
+                    .as_ref()
+                    .is_none_or(|declared| type_matches(&value, declared))
+                {
+                    variable.value = value;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // ===== VARIABLES =====
+
+    fn load(&self, name: &str) -> Result<Value, String> {
+        let variable = self
+            .frames
+            .last()
+            .and_then(|frame| frame.get(name))
+            .or_else(|| self.globals.get(name));
+        match variable {
+            Some(variable) => Ok(variable.value.clone()),
+            None if self.module.function(name).is_some() => Ok(Value::Function(name.to_string())),
+            None => Err(format!("Undefined variable '{}'", name)),
+        }
+    }
+
+    fn declare(
+        &mut self,
+        name: &str,
+        value: Value,
+        declared: Option<PrizmType>,
+    ) -> Result<(), String> {
+        if let Some(declared) = &declared {
+            check_type(name, &value, declared)?;
+        }
+        let variable = Variable { value, declared };
+        match self.frames.last_mut() {
+            Some(frame) => {
+                frame.insert(name.to_string(), variable);
+            }
+            None => self.set_global(name, variable)?,
+        }
+        Ok(())
+    }
+
+    // Assign to the innermost variable with this name, or declare it
+    fn store(&mut self, name: &str, value: Value) -> Result<(), String> {
+        if let Some(variable) = self.frames.last_mut().and_then(|frame| frame.get_mut(name)) {
+            if let Some(declared) = &variable.declared {
+                check_type(name, &value, declared)?;
+            }
+            variable.value = value;
+            return Ok(());
+        }
+        let declared = match self.globals.get(name) {
+            Some(variable) => variable.declared.clone(),
+            None if !self.frames.is_empty() => return self.declare(name, value, None),
+            None => None,
+        };
+        if let Some(declared) = &declared {
+            check_type(name, &value, declared)?;
+        }
+        self.set_global(name, Variable { value, declared })
+    }
+
+    fn set_global(&mut self, name: &str, variable: Variable) -> Result<(), String> {
+        // Globals named like a UI value stay in sync with the widgets showing it
+        if self.ui.is_bound(name) {
+            self.ui.set_value(name, variable.value.clone())?;
+        }
+        self.globals.insert(name.to_string(), variable);
+        Ok(())
+    }
+
+    fn store_path(
+        &mut self,
+        name: &str,
+        steps: &[PathStep],
+        indices: Vec<Value>,
+        value: Value,
+    ) -> Result<(), String> {
+        let mut target = self.load(name)?;
+        // `state.field = value` changes a UI value
+        if let (Value::Handle(handle), [PathStep::Field(field)]) = (&target, steps) {
+            if handle.kind == HandleKind::State {
+                return self.ui.set_value(field, value);
+            }
+        }
+        assign_path(&mut target, steps, &mut indices.into_iter(), value)?;
+        self.store(name, target)
+    }
+
+    // ===== CALLS =====
+
+    fn call_value(&mut self, callee: &Value, args: Vec<Value>) -> Result<Value, String> {
+        match callee {
+            Value::Function(name) => self.call_function(name, args),
+            other => Err(format!(
+                "Expected a function to call, found {}",
+                other.type_of().to_string()
+            )),
+        }
+    }
+
+    fn call_function(&mut self, name: &str, mut args: Vec<Value>) -> Result<Value, String> {
+        let module = Rc::clone(&self.module);
+        let function = module
+            .function(name)
+            .ok_or_else(|| format!("Undefined function '{}'", name))?;
+        if self.frames.len() >= MAX_DEPTH {
+            return Err(format!(
+                "Too many nested calls ({}) while calling '{}'; is it recursing forever?",
+                MAX_DEPTH, name
+            ));
+        }
+        // Extra arguments are ignored and missing ones are null, so callbacks
+        // can leave out the event they are given
+        args.resize(function.params.len(), Value::Null);
+        let mut frame = HashMap::new();
+        for ((param, declared), value) in function.params.iter().zip(args) {
+            if let Some(declared) = declared {
+                if !type_matches(&value, declared) {
+                    return Err(format!(
+                        "Argument '{}' of {} must be {}, found {}",
+                        param,
+                        name,
+                        declared.to_string(),
+                        value.type_of().to_string()
+                    ));
+                }
+            }
+            frame.insert(
+                param.clone(),
+                Variable {
+                    value,
+                    declared: declared.clone(),
+                },
+            );
+        }
+        self.frames.push(frame);
+        let result = self.execute(&function.chunk);
+        self.frames.pop();
+        result
+    }
+
+    // ===== EXECUTION =====
+
+    fn execute(&mut self, chunk: &Chunk) -> Result<Value, String> {
+        let mut stack = Vec::new();
+        let mut ip = 0;
+        while ip < chunk.code.len() {
+            let op = &chunk.code[ip];
+            ip += 1;
+            let result = self.step(op, chunk, &mut stack, &mut ip);
+            match result {
+                Ok(Some(value)) => return Ok(value),
+                Ok(None) => {}
+                // Errors from inside a call already say where they happened
+                Err(e) if e.starts_with("Runtime error") => return Err(e),
+                Err(e) => {
+                    let span = chunk.spans[ip - 1];
+                    return Err(format!(
+                        "Runtime error on line {}, column {}: {}",
+                        span.line, span.column, e
+                    ));
+                }
+            }
+        }
+        Ok(Value::Null)
+    }
+
+    // Run one instruction; returns a value when the chunk returns
+    fn step(
+        &mut self,
+        op: &Op,
+        chunk: &Chunk,
+        stack: &mut Vec<Value>,
+        ip: &mut usize,
+    ) -> Result<Option<Value>, String> {
+        match op {
+            Op::Constant(index) => stack.push(chunk.constants[*index].clone()),
+            Op::Pop => {
+                pop(stack)?;
+            }
+            Op::Load(name) => stack.push(self.load(name)?),
+            Op::Declare(name, declared) => {
+                let value = pop(stack)?;
+                self.declare(name, value, declared.clone())?;
+            }
+            Op::Store(name) => {
+                let value = pop(stack)?;
+                self.store(name, value)?;
+            }
+            Op::StorePath(name, steps) => {
+                let value = pop(stack)?;
+                let count = steps
+                    .iter()
+                    .filter(|step| **step == PathStep::Index)
+                    .count();
+                let indices = pop_many(stack, count)?;
+                self.store_path(name, steps, indices, value)?;
+            }
+            Op::Array(count) => {
+                let items = pop_many(stack, *count)?;
+                stack.push(Value::Array(items));
+            }
+            Op::Object(keys) => {
+                let values = pop_many(stack, keys.len())?;
+                let mut object = Value::Object(Vec::new());
+                for (key, value) in keys.iter().zip(values) {
+                    object.set(key, value)?;
+                }
+                stack.push(object);
+            }
+            Op::Unary(op) => {
+                let value = pop(stack)?;
+                stack.push(unary(*op, value)?);
+            }
+            Op::Binary(op) => {
+                let right = pop(stack)?;
+                let left = pop(stack)?;
+                stack.push(binary(*op, left, right)?);
+            }
+            Op::Truthy => {
+                let value = pop(stack)?;
+                stack.push(Value::Boolean(value.is_truthy()));
+            }
+            Op::Jump(target) => *ip = *target,
+            Op::JumpIfFalse(target) => {
+                if !pop(stack)?.is_truthy() {
+                    *ip = *target;
+                }
+            }
+            Op::JumpIfTrue(target) => {
+                if pop(stack)?.is_truthy() {
+                    *ip = *target;
+                }
+            }
+            Op::Call(name, argc) => {
+                let args = pop_many(stack, *argc)?;
+                // A variable holding a function is called through it
+                let callee = match self.load(name) {
+                    Ok(callee @ Value::Function(_)) => callee,
+                    _ => Value::Function(name.clone()),
+                };
+                stack.push(self.call_value(&callee, args)?);
+            }
+            Op::Builtin(id, argc) => {
+                let args = pop_many(stack, *argc)?;
+                let text: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                let text = text.join(" ");
+                let written = if *id == attributes::builtins::PRINTLN {
+                    writeln!(self.output, "{}", text)
+                } else {
+                    write!(self.output, "{}", text).and_then(|_| self.output.flush())
+                };
+                written.map_err(|e| format!("Failed to write output: {}", e))?;
+                stack.push(Value::Null);
+            }
+            Op::Header(id, argc) => {
+                let args = pop_many(stack, *argc)?;
+                stack.push(headers::call(self, *id, args)?);
+            }
+            Op::Field(name) => {
+                let object = pop(stack)?;
+                stack.push(self.field(object, name)?);
+            }
+            Op::Index => {
+                let index = pop(stack)?;
+                let object = pop(stack)?;
+                stack.push(index_value(&object, &index)?);
+            }
+            Op::Method(name, argc) => {
+                let args = pop_many(stack, *argc)?;
+                let object = pop(stack)?;
+                let result = match object {
+                    Value::Handle(handle) => headers::method(self, handle, name, args)?,
+                    other => {
+                        return Err(format!(
+                            "A {} has no method '{}'",
+                            other.type_of().to_string(),
+                            name
+                        ))
+                    }
+                };
+                stack.push(result);
+            }
+            Op::Return => return Ok(Some(pop(stack)?)),
+        }
+        Ok(None)
+    }
+
+    fn field(&self, object: Value, name: &str) -> Result<Value, String> {
+        match object {
+            // Missing fields read as null, so optional fields can be checked
+            Value::Object(_) => Ok(object.get(name).cloned().unwrap_or(Value::Null)),
+            Value::Handle(Handle {
+                kind: HandleKind::State,
+                ..
+            }) => Ok(self.ui.value(name).cloned().unwrap_or(Value::Null)),
+            other => Err(format!(
+                "Cannot read field '{}' of {}",
+                name,
+                other.type_of().to_string()
+            )),
+        }
+    }
+}
+
+fn pop(stack: &mut Vec<Value>) -> Result<Value, String> {
+    stack
+        .pop()
+        .ok_or_else(|| "Internal error: the value stack is empty".to_string())
+}
+
+fn pop_many(stack: &mut Vec<Value>, count: usize) -> Result<Vec<Value>, String> {
+    if stack.len() < count {
+        return Err("Internal error: the value stack is empty".to_string());
+    }
+    Ok(stack.split_off(stack.len() - count))
+}
+
+// ===== TYPES =====
+
+/// Whether a value can be stored in a variable or parameter declared with
+/// this type
+pub fn type_matches(value: &Value, declared: &PrizmType) -> bool {
+    declared.accepts(&value.type_of())
+}
+
+fn check_type(name: &str, value: &Value, declared: &PrizmType) -> Result<(), String> {
+    if type_matches(value, declared) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Cannot store {} in '{}', which is declared as {}",
+            value.type_of().to_string(),
+            name,
+            declared.to_string()
+        ))
+    }
+}
+
+// ===== INDEXING =====
+
+fn index_value(object: &Value, index: &Value) -> Result<Value, String> {
+    match (object, index) {
+        (Value::Array(items), Value::Int(i)) => position(*i, items.len())
+            .map(|i| items[i].clone())
+            .ok_or_else(|| out_of_range(*i, items.len())),
+        (Value::String(s), Value::Int(i)) => {
+            let count = s.chars().count();
+            position(*i, count)
+                .and_then(|i| s.chars().nth(i))
+                .map(|c| Value::String(c.to_string()))
+                .ok_or_else(|| out_of_range(*i, count))
+        }
+        (Value::Object(_), Value::String(key)) => {
+            Ok(object.get(key).cloned().unwrap_or(Value::Null))
+        }
+        (object, index) => Err(format!(
+            "Cannot index {} with {}",
+            object.type_of().to_string(),
+            index.type_of().to_string()
+        )),
+    }
+}
+
+fn position(index: i64, len: usize) -> Option<usize> {
+    usize::try_from(index).ok().filter(|&i| i < len)
+}
+
+fn out_of_range(index: i64, len: usize) -> String {
+    format!("Index {} is out of range for {} items", index, len)
+}
+
+// Set the field or item `steps` leads to inside `target`
+fn assign_path(
+    target: &mut Value,
+    steps: &[PathStep],
+    indices: &mut impl Iterator<Item = Value>,
+    value: Value,
+) -> Result<(), String> {
+    let (step, rest) = match steps.split_first() {
+        Some(split) => split,
+        None => {
+            *target = value;
+            return Ok(());
+        }
+    };
+    let key = match step {
+        PathStep::Field(name) => Value::String(name.clone()),
+        PathStep::Index => indices.next().unwrap_or(Value::Null),
+    };
+    match (&mut *target, &key) {
+        (Value::Array(items), Value::Int(i)) => {
+            let len = items.len();
+            let slot = position(*i, len).ok_or_else(|| out_of_range(*i, len))?;
+            assign_path(&mut items[slot], rest, indices, value)
+        }
+        (Value::Object(_), Value::String(name)) => {
+            if target.get(name).is_none() {
+                target.set(name, Value::Null)?;
+            }
+            let field = target.get_mut(name).expect("field was just set");
+            assign_path(field, rest, indices, value)
+        }
+        (target, Value::String(name)) => Err(format!(
+            "Cannot set field '{}' on {}",
+            name,
+            target.type_of().to_string()
+        )),
+        (target, key) => Err(format!(
+            "Cannot index {} with {}",
+            target.type_of().to_string(),
+            key.type_of().to_string()
+        )),
+    }
+}
+
+// ===== OPERATORS =====
+// Shared with the optimizer, which folds constant expressions with them
+
+pub fn unary(op: UnaryOp, value: Value) -> Result<Value, String> {
+    match (op, value) {
+        (UnaryOp::Not, value) => Ok(Value::Boolean(!value.is_truthy())),
+        (UnaryOp::Negate, Value::Int(n)) => n
+            .checked_neg()
+            .map(Value::Int)
+            .ok_or_else(|| format!("Cannot negate {}: the result is too large", n)),
+        (UnaryOp::Negate, Value::Float(x)) => Ok(Value::Float(-x)),
+        (UnaryOp::Negate, Value::Duration(d)) => d.checked_neg().map(Value::Duration),
+        (UnaryOp::Negate, other) => Err(format!("Cannot negate {}", other.type_of().to_string())),
+    }
+}
+
+pub fn binary(op: BinaryOp, left: Value, right: Value) -> Result<Value, String> {
+    use BinaryOp::*;
+    match op {
+        Equal => Ok(Value::Boolean(equal(&left, &right))),
+        NotEqual => Ok(Value::Boolean(!equal(&left, &right))),
+        And => Ok(Value::Boolean(left.is_truthy() && right.is_truthy())),
+        Or => Ok(Value::Boolean(left.is_truthy() || right.is_truthy())),
+        Less | Greater | LessEqual | GreaterEqual => {
+            let ordering = compare(&left, &right).ok_or_else(|| {
+                format!(
+                    "Cannot compare {} {} {}",
+                    left.type_of().to_string(),
+                    op.symbol(),
+                    right.type_of().to_string()
+                )
+            })?;
+            Ok(Value::Boolean(match op {
+                Less => ordering.is_lt(),
+                Greater => ordering.is_gt(),
+                LessEqual => ordering.is_le(),
+                _ => ordering.is_ge(),
+            }))
+        }
+        Add | Subtract | Multiply | Divide | Modulo => arithmetic(op, left, right),
+    }
+}
+
+fn arithmetic(op: BinaryOp, left: Value, right: Value) -> Result<Value, String> {
+    use BinaryOp::*;
+    let overflow = || format!("{} {} {} is too large for an int", left, op.symbol(), right);
+    match (op, &left, &right) {
+        // Adding anything to a string joins them
+        (Add, Value::String(a), b) => Ok(Value::String(format!("{}{}", a, b))),
+        (Add, a, Value::String(b)) => Ok(Value::String(format!("{}{}", a, b))),
+        (Add, Value::Array(a), Value::Array(b)) => {
+            Ok(Value::Array(a.iter().chain(b).cloned().collect()))
+        }
+        (Divide | Modulo, Value::Int(_), Value::Int(0)) => Err(if op == Divide {
+            "Division by zero".to_string()
+        } else {
+            "Modulo by zero".to_string()
+        }),
+        (_, Value::Int(a), Value::Int(b)) => {
+            let result = match op {
+                Add => a.checked_add(*b),
+                Subtract => a.checked_sub(*b),
+                Multiply => a.checked_mul(*b),
+                Divide => a.checked_div(*b),
+                _ => a.checked_rem(*b),
+            };
+            result.map(Value::Int).ok_or_else(overflow)
+        }
+        (_, Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+            let (a, b) = (number(&left), number(&right));
+            Ok(Value::Float(match op {
+                Add => a + b,
+                Subtract => a - b,
+                Multiply => a * b,
+                Divide => a / b,
+                _ => a % b,
+            }))
+        }
+        // Durations add to each other and to timestamps, and scale by ints
+        (Add, Value::Duration(a), Value::Duration(b)) => a.checked_add(*b).map(Value::Duration),
+        (Subtract, Value::Duration(a), Value::Duration(b)) => {
+            a.checked_sub(*b).map(Value::Duration)
+        }
+        (Add, Value::Int(t), Value::Duration(d)) | (Add, Value::Duration(d), Value::Int(t)) => t
+            .checked_add(d.as_millis())
+            .map(Value::Int)
+            .ok_or_else(overflow),
+        (Subtract, Value::Int(t), Value::Duration(d)) => t
+            .checked_sub(d.as_millis())
+            .map(Value::Int)
+            .ok_or_else(overflow),
+        (Multiply, Value::Duration(d), Value::Int(n))
+        | (Multiply, Value::Int(n), Value::Duration(d)) => d.checked_mul(*n).map(Value::Duration),
+        (Divide, Value::Duration(d), Value::Int(n)) => Ok(Value::Duration(d.divide(*n)?)),
+        _ => Err(format!(
+            "Cannot apply {} to {} and {}",
+            op.symbol(),
+            left.type_of().to_string(),
+            right.type_of().to_string()
+        )),
+    }
+}
+
+fn number(value: &Value) -> f64 {
+    match value {
+        Value::Int(n) => *n as f64,
+        Value::Float(x) => *x,
+        _ => 0.0,
+    }
+}
+
+// Ints and floats compare by value; anything else must match exactly
+fn equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Int(_), Value::Float(_)) | (Value::Float(_), Value::Int(_)) => {
+            number(left) == number(right)
+        }
+        _ => left == right,
+    }
+}
+
+fn compare(left: &Value, right: &Value) -> Option<std::cmp::Ordering> {
+    match (left, right) {
+        (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+        (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+            number(left).partial_cmp(&number(right))
+        }
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Duration(a), Value::Duration(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
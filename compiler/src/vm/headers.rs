@@ -0,0 +1,477 @@
+// Header Calls for the Virtual Machine
+// Maps attribute IDs to the header implementations in the standard library,
+// checking and converting arguments on the way in
+
+use super::{binary, Vm};
+use crate::ast::BinaryOp;
+use crate::attributes::{self, data, file, math, root, time, ui};
+use crate::stdlib::builtins;
+use crate::stdlib::builtins::time::{Duration, TimeZone};
+use crate::value::{Handle, HandleKind, Value};
+
+/// Call `header.attribute(args)` by attribute ID
+pub fn call(vm: &mut Vm, id: u32, args: Vec<Value>) -> Result<Value, String> {
+    let (header, attribute) =
+        attributes::attribute_name(id).ok_or_else(|| format!("Unknown attribute ID {}", id))?;
+    let call = Call {
+        name: format!("{}.{}", header, attribute),
+        args,
+    };
+    match header {
+        "file" => file_call(id, &call),
+        "math" => math_call(id, &call),
+        "ui" => ui_call(vm, id, &call),
+        "root" => root_call(vm, id, &call),
+        "data" => data_call(vm, id, &call),
+        "time" => time_call(vm, id, &call),
+        _ => Err(format!("{} is not available yet", call.name)),
+    }
+}
+
+/// Call a method on a handle returned by a header
+pub fn method(
+    vm: &mut Vm,
+    handle: Handle,
+    method: &str,
+    args: Vec<Value>,
+) -> Result<Value, String> {
+    match handle.kind {
+        HandleKind::Stopwatch if args.is_empty() => vm.time.stopwatch_method(handle, method),
+        HandleKind::Stopwatch => Err(format!("Timer method '{}' takes no arguments", method)),
+        HandleKind::Scheduled => match (method, args.as_slice()) {
+            ("cancel", []) => Ok(Value::Boolean(vm.events.cancel(handle)?)),
+            _ => Err(format!("Scheduled callbacks have no method '{}'", method)),
+        },
+        HandleKind::Widget => vm.ui.widget_method(handle, method, args),
+        HandleKind::State => Err(format!(
+            "UI state has no method '{}'; read and assign its fields instead",
+            method
+        )),
+        HandleKind::Process => vm.root.process_method(handle, method, args),
+        HandleKind::Buffer => vm.root.buffer_method(handle, method, args),
+        HandleKind::Rows => {
+            let rows = vm
+                .rows
+                .get_mut(handle.id)
+                .ok_or_else(|| format!("{} is not a CSV reader", handle))?;
+            match (method, args.as_slice()) {
+                // null once every row has been read
+                ("next", []) => rows.next().unwrap_or(Ok(Value::Null)),
+                _ => Err(format!("CSV rows have no method '{}'", method)),
+            }
+        }
+    }
+}
+
+struct Call {
+    name: String,
+    args: Vec<Value>,
+}
+
+impl Call {
+    // Fail unless there are between `min` and `max` arguments
+    fn expect(&self, min: usize, max: usize) -> Result<(), String> {
+        let count = self.args.len();
+        if count >= min && count <= max {
+            return Ok(());
+        }
+        let expected = match (min, max) {
+            (0, 0) => "no arguments".to_string(),
+            (1, 1) => "1 argument".to_string(),
+            (min, max) if min == max => format!("{} arguments", min),
+            (min, max) => format!("{} to {} arguments", min, max),
+        };
+        Err(format!("{} takes {}, got {}", self.name, expected, count))
+    }
+
+    fn arg(&self, index: usize) -> &Value {
+        self.args.get(index).unwrap_or(&Value::Null)
+    }
+
+    // An argument that may be left out or given as null
+    fn optional(&self, index: usize) -> Option<&Value> {
+        match self.args.get(index) {
+            None | Some(Value::Null) => None,
+            Some(value) => Some(value),
+        }
+    }
+
+    fn string(&self, index: usize) -> Result<&str, String> {
+        match self.arg(index) {
+            Value::String(s) => Ok(s),
+            other => Err(format!(
+                "Expected a string, found {}",
+                other.type_of().to_string()
+            )),
+        }
+    }
+
+    // Text to show; values other than strings are shown as they print
+    fn text(&self, index: usize) -> String {
+        self.arg(index).to_string()
+    }
+
+    fn int(&self, index: usize) -> Result<i64, String> {
+        match self.arg(index) {
+            Value::Int(n) => Ok(*n),
+            other => Err(format!(
+                "Expected an int, found {}",
+                other.type_of().to_string()
+            )),
+        }
+    }
+
+    fn int_or_zero(&self, index: usize) -> Result<i64, String> {
+        match self.optional(index) {
+            None => Ok(0),
+            Some(_) => self.int(index),
+        }
+    }
+
+    fn duration(&self, index: usize) -> Result<Duration, String> {
+        Duration::from_value(self.arg(index))
+    }
+
+    fn zone(&self, index: usize) -> Result<TimeZone, String> {
+        match self.optional(index) {
+            None => Ok(TimeZone::UTC),
+            Some(_) => TimeZone::parse(self.string(index)?),
+        }
+    }
+
+    fn widgets(&self, index: usize) -> Result<Vec<Handle>, String> {
+        match self.optional(index) {
+            None => Ok(Vec::new()),
+            Some(Value::Array(items)) => items
+                .iter()
+                .map(|item| match item {
+                    Value::Handle(handle) => Ok(*handle),
+                    other => Err(format!(
+                        "Expected a widget, found {}",
+                        other.type_of().to_string()
+                    )),
+                })
+                .collect(),
+            Some(other) => Err(format!(
+                "Expected an array of widgets, found {}",
+                other.type_of().to_string()
+            )),
+        }
+    }
+}
+
+fn widget(handle: Handle) -> Value {
+    Value::Handle(handle)
+}
+
+// ===== FILE =====
+
+fn file_call(id: u32, call: &Call) -> Result<Value, String> {
+    use builtins::file as fs;
+    match id {
+        file::CREATE | file::DELETE | file::ACCESS | file::READ => call.expect(1, 1)?,
+        file::MOVE | file::MODIFY | file::WRITE => call.expect(2, 2)?,
+        _ => call.expect(3, 3)?,
+    }
+    let path = call.string(0)?;
+    match id {
+        file::CREATE => fs::create(path).map(|_| Value::Null),
+        file::DELETE => fs::delete(path).map(|_| Value::Null),
+        file::MOVE => fs::move_file(path, call.string(1)?).map(|_| Value::Null),
+        file::REPLACE => fs::replace(path, call.string(1)?, call.string(2)?)
+            .map(|count| Value::Int(count as i64)),
+        file::MODIFY | file::WRITE => fs::write(path, &call.text(1)).map(|_| Value::Null),
+        _ => fs::read(path).map(Value::String),
+    }
+}
+
+// ===== MATH =====
+
+fn math_call(id: u32, call: &Call) -> Result<Value, String> {
+    call.expect(2, 2)?;
+    let (a, b) = (call.arg(0).clone(), call.arg(1).clone());
+    match id {
+        math::ADD => binary(BinaryOp::Add, a, b),
+        math::SUBTRACT => binary(BinaryOp::Subtract, a, b),
+        math::MULTIPLY => binary(BinaryOp::Multiply, a, b),
+        math::DIVIDE => binary(BinaryOp::Divide, a, b),
+        math::MODULO => binary(BinaryOp::Modulo, a, b),
+        math::RANDOM => {
+            let (min, max) = (call.int(0)?, call.int(1)?);
+            if min > max {
+                return Err(format!(
+                    "math.random needs min <= max, got {} and {}",
+                    min, max
+                ));
+            }
+            Ok(Value::Int(builtins::math::random(min, max)))
+        }
+        _ => match (a, b) {
+            (Value::Int(base), Value::Int(exp)) if exp >= 0 => u32::try_from(exp)
+                .ok()
+                .and_then(|exp| base.checked_pow(exp))
+                .map(Value::Int)
+                .ok_or_else(|| format!("{} to the power {} is too large for an int", base, exp)),
+            (base @ (Value::Int(_) | Value::Float(_)), exp @ (Value::Int(_) | Value::Float(_))) => {
+                Ok(Value::Float(super::number(&base).powf(super::number(&exp))))
+            }
+            (base, exp) => Err(format!(
+                "Expected numbers, found {} and {}",
+                base.type_of().to_string(),
+                exp.type_of().to_string()
+            )),
+        },
+    }
+}
+
+// ===== UI =====
+
+fn ui_call(vm: &mut Vm, id: u32, call: &Call) -> Result<Value, String> {
+    match id {
+        ui::WINDOW => {
+            call.expect(1, 3)?;
+            let window = vm
+                .ui
+                .window(&call.text(0), call.int_or_zero(1)?, call.int_or_zero(2)?)?;
+            Ok(widget(window))
+        }
+        ui::BUTTON | ui::INPUT => {
+            call.expect(1, 5)?;
+            let (text, x, y) = (call.text(0), call.int_or_zero(1)?, call.int_or_zero(2)?);
+            let (width, height) = (call.int_or_zero(3)?, call.int_or_zero(4)?);
+            let handle = if id == ui::BUTTON {
+                vm.ui.button(&text, x, y, width, height)?
+            } else {
+                vm.ui.input(&text, x, y, width, height)?
+            };
+            Ok(widget(handle))
+        }
+        ui::TEXT | ui::LABEL => {
+            call.expect(1, 3)?;
+            let (text, x, y) = (call.text(0), call.int_or_zero(1)?, call.int_or_zero(2)?);
+            let handle = if id == ui::TEXT {
+                vm.ui.text(&text, x, y)?
+            } else {
+                vm.ui.label(&text, x, y)?
+            };
+            Ok(widget(handle))
+        }
+        ui::PANEL => {
+            call.expect(4, 5)?;
+            let panel = vm.ui.panel(
+                call.int(0)?,
+                call.int(1)?,
+                call.int(2)?,
+                call.int(3)?,
+                &call.widgets(4)?,
+            )?;
+            Ok(widget(panel))
+        }
+        ui::ROW | ui::COLUMN | ui::GRID => {
+            call.expect(1, 2)?;
+            let children = call.widgets(0)?;
+            let options = call.optional(1);
+            let container = match id {
+                ui::ROW => vm.ui.row(&children, options)?,
+                ui::COLUMN => vm.ui.column(&children, options)?,
+                _ => vm.ui.grid(&children, options)?,
+            };
+            Ok(widget(container))
+        }
+        ui::EVENT => {
+            call.expect(2, 2)?;
+            vm.ui.event(call.string(0)?, call.arg(1).clone())?;
+            Ok(Value::Null)
+        }
+        ui::RENDER => {
+            call.expect(0, 0)?;
+            vm.ui.render()?;
+            Ok(Value::Null)
+        }
+        ui::SNAPSHOT => {
+            call.expect(0, 0)?;
+            Ok(Value::String(vm.ui.snapshot()))
+        }
+        ui::SIMULATE => {
+            // Handlers run straight away, so a test can check their effect
+            // on the next line
+            call.expect(1, 2)?;
+            let events = vm.ui.simulate(call.string(0)?, call.arg(1))?;
+            vm.sync_ui()?;
+            for event in events {
+                vm.dispatch(event)?;
+            }
+            Ok(Value::Null)
+        }
+        _ => {
+            call.expect(0, 1)?;
+            Ok(widget(vm.ui.state(call.arg(0))?))
+        }
+    }
+}
+
+// ===== ROOT =====
+
+fn root_call(vm: &mut Vm, id: u32, call: &Call) -> Result<Value, String> {
+    match id {
+        root::EXEC => {
+            call.expect(1, 3)?;
+            vm.root
+                .exec(call.arg(0), call.optional(1), call.optional(2))
+        }
+        root::SYSTEM => {
+            call.expect(1, 1)?;
+            vm.root.system(call.arg(0))
+        }
+        root::MEMORY => {
+            call.expect(1, 1)?;
+            vm.root.memory(call.arg(0))
+        }
+        root::PROCESS => {
+            call.expect(0, 3)?;
+            vm.root
+                .process(call.optional(0), call.optional(1), call.optional(2))
+        }
+        root::INTERRUPT => {
+            call.expect(2, 2)?;
+            vm.root.interrupt(call.arg(0), call.arg(1))
+        }
+        _ => {
+            call.expect(1, 1)?;
+            vm.root.optimize(call.arg(0))
+        }
+    }
+}
+
+// ===== DATA =====
+
+fn data_call(vm: &mut Vm, id: u32, call: &Call) -> Result<Value, String> {
+    use builtins::data as formats;
+    match id {
+        data::PARSE | data::DECODE => {
+            call.expect(1, 1)?;
+            formats::parse(call.string(0)?)
+        }
+        data::STRINGIFY | data::ENCODE => {
+            call.expect(1, 2)?;
+            let pretty = call.optional(1).is_some_and(Value::is_truthy);
+            formats::stringify(call.arg(0), pretty).map(Value::String)
+        }
+        data::VALIDATE => {
+            call.expect(1, 2)?;
+            let violations = formats::validate(call.arg(0), call.optional(1))?;
+            Ok(Value::Array(
+                violations.iter().map(|v| v.to_value()).collect(),
+            ))
+        }
+        data::MERGE => {
+            call.expect(2, 3)?;
+            formats::merge(call.arg(0), call.arg(1), call.optional(2))
+        }
+        data::PARSE_CSV => {
+            call.expect(1, 2)?;
+            formats::parse_csv(call.string(0)?, call.optional(1))
+        }
+        data::STRINGIFY_CSV => {
+            call.expect(1, 2)?;
+            formats::stringify_csv(call.arg(0), call.optional(1)).map(Value::String)
+        }
+        data::CSV_ROWS => {
+            call.expect(1, 2)?;
+            vm.rows
+                .push(formats::csv_rows(call.string(0)?, call.optional(1))?);
+            Ok(Value::Handle(Handle::new(
+                HandleKind::Rows,
+                vm.rows.len() - 1,
+            )))
+        }
+        data::PARSE_TOML | data::PARSE_INI | data::PARSE_YAML => {
+            call.expect(1, 1)?;
+            let text = call.string(0)?;
+            match id {
+                data::PARSE_TOML => formats::parse_toml(text),
+                data::PARSE_INI => formats::parse_ini(text),
+                _ => formats::parse_yaml(text),
+            }
+        }
+        _ => {
+            call.expect(1, 1)?;
+            let value = call.arg(0);
+            match id {
+                data::STRINGIFY_TOML => formats::stringify_toml(value),
+                data::STRINGIFY_INI => formats::stringify_ini(value),
+                _ => formats::stringify_yaml(value),
+            }
+            .map(Value::String)
+        }
+    }
+}
+
+// ===== TIME =====
+
+fn time_call(vm: &mut Vm, id: u32, call: &Call) -> Result<Value, String> {
+    use builtins::time as clock;
+    match id {
+        time::NOW | time::TIMESTAMP => {
+            call.expect(0, 0)?;
+            Ok(Value::Int(if id == time::NOW {
+                vm.time.now()
+            } else {
+                vm.time.timestamp()
+            }))
+        }
+        time::SLEEP => {
+            call.expect(1, 1)?;
+            vm.time.sleep(call.duration(0)?)?;
+            Ok(Value::Null)
+        }
+        time::FORMAT => {
+            call.expect(1, 3)?;
+            let pattern = match call.optional(1) {
+                None => clock::DEFAULT_FORMAT,
+                Some(_) => call.string(1)?,
+            };
+            clock::format(call.int(0)?, pattern, call.zone(2)?).map(Value::String)
+        }
+        time::PARSE => {
+            call.expect(1, 3)?;
+            let pattern = match call.optional(1) {
+                None => clock::DEFAULT_FORMAT,
+                Some(_) => call.string(1)?,
+            };
+            clock::parse(call.string(0)?, pattern, call.zone(2)?).map(Value::Int)
+        }
+        time::TIMER => {
+            call.expect(0, 1)?;
+            let limit = match call.optional(0) {
+                None => None,
+                Some(_) => Some(call.duration(0)?),
+            };
+            Ok(Value::Handle(vm.time.timer(limit)))
+        }
+        time::DURATION => {
+            call.expect(1, 1)?;
+            Ok(Value::Duration(call.duration(0)?))
+        }
+        time::AFTER | time::EVERY => {
+            call.expect(2, 2)?;
+            let (now, delay, callback) = (vm.time.now(), call.duration(0)?, call.arg(1).clone());
+            let handle = if id == time::AFTER {
+                vm.events.after(now, delay, callback)?
+            } else {
+                vm.events.every(now, delay, callback)?
+            };
+            Ok(Value::Handle(handle))
+        }
+        _ => {
+            call.expect(1, 1)?;
+            match call.arg(0) {
+                Value::Handle(handle) => Ok(Value::Boolean(vm.events.cancel(*handle)?)),
+                other => Err(format!(
+                    "Expected a scheduled callback, found {}",
+                    other.type_of().to_string()
+                )),
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+// Exit codes of the prizm command: 0 when it worked, 1 when the command
+// failed, and 2 when the command line itself was wrong
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+// A directory of its own for each test, with these files in it
+fn directory(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("prizm-cli-{}-{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for (name, text) in files {
+        std::fs::write(dir.join(name), text).unwrap();
+    }
+    dir
+}
+
+fn prizm(dir: &Path, line: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_prizm_compiler"))
+        .args(line.split_whitespace())
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+fn exit_code(dir: &Path, line: &str) -> i32 {
+    prizm(dir, line).status.code().unwrap()
+}
+
+#[test]
+fn help_and_version_exit_with_0() {
+    let dir = directory("help", &[]);
+    let version = prizm(&dir, "--version");
+    assert_eq!(version.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&version.stdout),
+        format!("Prizm v{}\n", prizm_lib::VERSION)
+    );
+    assert_eq!(exit_code(&dir, "help run"), 0);
+    assert_eq!(exit_code(&dir, ""), 0);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn wrong_command_lines_exit_with_2() {
+    let dir = directory("usage", &[("app.pzm", "output(1),\n")]);
+    for line in [
+        "fly",
+        "lint",
+        "run --nope app.pzm",
+        "lint app.pzm --format=xml",
+    ] {
+        let output = prizm(&dir, line);
+        assert_eq!(output.status.code(), Some(2), "prizm {}", line);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("Run prizm --help for usage"));
+    }
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn failed_commands_exit_with_1() {
+    let dir = directory(
+        "fail",
+        &[
+            ("ok.pzm", "output(1),\n"),
+            ("divide.pzm", "output(1 / 0),\n"),
+            ("broken.pzm", "var a = (\n"),
+            ("unformatted.pzm", "var  a=1,\noutput(a),\n"),
+            ("undefined.pzm", "nope(),\n"),
+            ("unused.pzm", "var a = 1,\noutput(2),\n"),
+        ],
+    );
+    assert_eq!(exit_code(&dir, "run ok.pzm"), 0);
+    assert_eq!(exit_code(&dir, "run missing.pzm"), 1);
+    assert_eq!(exit_code(&dir, "run divide.pzm"), 1);
+    assert_eq!(exit_code(&dir, "check ok.pzm"), 0);
+    assert_eq!(exit_code(&dir, "check broken.pzm"), 1);
+    assert_eq!(exit_code(&dir, "pretty --check ok.pzm"), 0);
+    assert_eq!(exit_code(&dir, "pretty --check unformatted.pzm"), 1);
+    // Lint fails on errors, and warnings alone don't fail it
+    assert_eq!(exit_code(&dir, "lint undefined.pzm"), 1);
+    assert_eq!(exit_code(&dir, "lint unused.pzm"), 0);
+    let _ = std::fs::remove_dir_all(&dir);
+}
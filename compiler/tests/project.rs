@@ -0,0 +1,109 @@
+// Project mode: prizm build compiles only what changed, and prizm run finds
+// the project's entry point from anywhere inside it
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn project(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("prizm-build-{}-{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    write(
+        &dir,
+        "prizm.toml",
+        "[package]\nname = \"demo\"\nversion = \"1.2.0\"\n",
+    );
+    write(&dir, "src/center.pzm", "output(\"hi\"),\n");
+    write(
+        &dir,
+        "src/lib/util.pzm",
+        "define f() {\n    return 1,\n},\n",
+    );
+    dir
+}
+
+fn write(dir: &Path, name: &str, text: &str) {
+    let path = dir.join(name);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, text).unwrap();
+}
+
+// The exit code, and what was printed to stdout then stderr
+fn prizm(dir: &Path, line: &str) -> (i32, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_prizm_compiler"))
+        .args(line.split_whitespace())
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    let text = String::from_utf8_lossy(&output.stdout).into_owned()
+        + &String::from_utf8_lossy(&output.stderr);
+    (output.status.code().unwrap(), text)
+}
+
+// The "N compiled, N up to date, N failed" part of a build's summary
+fn built(dir: &Path, line: &str) -> (i32, String) {
+    let (code, text) = prizm(dir, line);
+    let summary = text.lines().last().unwrap_or_default();
+    let counts = summary.split("): ").nth(1).unwrap_or_default();
+    let counts = counts.split(" in ").next().unwrap_or_default();
+    (code, counts.to_string())
+}
+
+#[test]
+fn builds_only_compile_files_that_changed() {
+    let dir = project("incremental");
+    let counts = |summary: &str| (0, summary.to_string());
+    assert_eq!(
+        built(&dir, "build"),
+        counts("2 compiled, 0 up to date, 0 failed")
+    );
+    assert!(dir.join("build/debug/src/center.pzmc").is_file());
+    assert!(dir.join("build/debug/src/lib/util.pzmc").is_file());
+    assert_eq!(
+        built(&dir, "build"),
+        counts("0 compiled, 2 up to date, 0 failed")
+    );
+
+    write(&dir, "src/center.pzm", "output(\"changed\"),\n");
+    assert_eq!(
+        built(&dir, "build"),
+        counts("1 compiled, 1 up to date, 0 failed")
+    );
+
+    // Each profile is built on its own
+    assert_eq!(
+        built(&dir, "build --release"),
+        counts("2 compiled, 0 up to date, 0 failed")
+    );
+    assert_eq!(
+        built(&dir, "build"),
+        counts("0 compiled, 2 up to date, 0 failed")
+    );
+
+    // Modules of deleted sources go, and failures leave no module behind
+    std::fs::remove_file(dir.join("src/lib/util.pzm")).unwrap();
+    write(&dir, "src/bad.pzm", "var a = (\n");
+    assert_eq!(
+        built(&dir, "build"),
+        (1, "0 compiled, 1 up to date, 1 failed".to_string())
+    );
+    assert!(!dir.join("build/debug/src/lib/util.pzmc").exists());
+    assert!(!dir.join("build/debug/src/bad.pzmc").exists());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn commands_work_from_anywhere_in_the_project() {
+    let dir = project("nested");
+    assert_eq!(prizm(&dir.join("src/lib"), "run"), (0, "hi\n".to_string()));
+    assert_eq!(
+        built(&dir.join("src/lib"), "build"),
+        (0, "2 compiled, 0 up to date, 0 failed".to_string())
+    );
+    assert!(dir.join("build/debug/src/center.pzmc").is_file());
+
+    let outside = std::env::temp_dir().join(format!("prizm-outside-{}", std::process::id()));
+    std::fs::create_dir_all(&outside).unwrap();
+    assert_eq!(prizm(&outside, "run").0, 1);
+    let _ = std::fs::remove_dir_all(&outside);
+    let _ = std::fs::remove_dir_all(&dir);
+}